@@ -3,8 +3,8 @@
 use gvariant::{
     aligned_bytes::{copy_to_align, AsAligned, A8},
     casting::AlignOf,
-    gv, Bool, Cast, Marker, MaybeFixedSize, MaybeNonFixedSize, NonFixedWidthArray, SerializeTo,
-    Str, Structure, Variant,
+    gv, Bool, Cast, Marker, MaybeFixedSize, MaybeNonFixedSize, NonFixedWidthArray, ObjectPath,
+    SerializeTo, Signature, Str, Structure, Variant,
 };
 use libfuzzer_sys::fuzz_target;
 use std::{
@@ -159,18 +159,6 @@ impl PartialEq<GLibVariant> for Str {
             assert_eq!(self.to_str().as_bytes(), self.as_bytes_non_conformant());
         }
 
-        let ts = unsafe {
-            std::ptr::read(glib_sys::g_variant_type_peek_string(
-                glib_sys::g_variant_get_type(rhs.variant),
-            ))
-        } as u8;
-        if (ts == b'o' || ts == b'g') && !normal_form {
-            // NON-CONFORMANT: We don't have support for validating object paths
-            // or type signatures, so they're not guaranteed to match for
-            // non-normal form data.
-            return true;
-        }
-
         let mut len: usize = 0;
         let string = unsafe {
             CStr::from_ptr(glib_sys::g_variant_get_string(
@@ -182,6 +170,18 @@ impl PartialEq<GLibVariant> for Str {
     }
 }
 
+impl PartialEq<GLibVariant> for ObjectPath {
+    fn eq(&self, rhs: &GLibVariant) -> bool {
+        *self.as_gv_str() == *rhs
+    }
+}
+
+impl PartialEq<GLibVariant> for Signature {
+    fn eq(&self, rhs: &GLibVariant) -> bool {
+        *self.as_gv_str() == *rhs
+    }
+}
+
 impl<T: ?Sized + Cast> PartialEq<GLibVariant> for NonFixedWidthArray<T>
 where
     T: PartialEq<GLibVariant>,
@@ -257,10 +257,15 @@ impl PartialEq<GLibVariant> for Variant {
         if g.is_normal_form() {
             ty == g_ty.to_bytes() && data.as_ref() as &[u8] == unsafe { g.get_data() }
         } else {
-            // NON-CONFORMANT: We don't implement precise equality for Variants
-            // as that would require run-time inspection of GVariant types, so
-            // here we just assume equality.
-            true
+            // Compare the normal forms instead.  This is the same as
+            // g_variant_equal, but doesn't rely on our own PartialEq.
+            let mut normal = vec![];
+            SerializeTo::<Variant>::serialize(self, &mut normal).unwrap();
+            let g_normal = unsafe {
+                GLibVariant::new_from_gvariant(glib_sys::g_variant_get_normal_form(rhs.variant))
+                    .unwrap()
+            };
+            normal == unsafe { g_normal.get_data() }
         }
     }
 }
@@ -301,10 +306,16 @@ fn test_cmp<'data, T: gvariant::Marker>(
     let gv = GLibVariant::new(data, &gvt);
     let v = m.cast(data);
 
+    // Our normal form checks should agree with GLib's
+    assert_eq!(m.is_normal_form(data), gv.is_normal_form());
+
     let mut reserialized = vec![];
     m.serialize(v, &mut reserialized).unwrap();
     let rs = copy_to_align(&reserialized);
 
+    // Reserialising gives normal form, even if the input wasn't
+    assert!(m.is_normal_form(rs.as_ref()));
+
     // Round-tripping serialization should give the same result:
     // println!("{:?} {:?}", reserialized.as_slice(), data.as_ref());
     if T::TYPESTR != b"d" {
@@ -316,6 +327,8 @@ fn test_cmp<'data, T: gvariant::Marker>(
     //println!("{}: {:?} == {:?}", &std::str::from_utf8(T::TYPESTR).unwrap(), gv, v);
     if gv.is_normal_form() {
         assert_eq!(*v, gv);
+        // Our printer should match `g_variant_print` for normal form data:
+        assert_eq!(m.print(v, true).to_string(), format!("{:?}", gv));
 
         if data.len() >= 256 && data.len() < 512 && T::TYPESTR == b"aay" {
             // In theory there is exactly 1 normal form for data, but
@@ -0,0 +1,709 @@
+// Implementation of `#[derive(GVariant)]`
+//
+// For a struct `Foo` with named fields we generate, in a private module, a
+// structure type for the struct's type string, in the same way as `gv!` does
+// for structures with more than 16 fields.  It's given accessor methods named
+// after the fields and exported as `FooView`.  `Foo::marker()` returns a
+// `Marker` for it and `&Foo` implements `SerializeTo<FooView>`.
+//
+// For an enum `Bar` the module instead contains a `Marker` for each variant's
+// payload type and one for the enum as a whole, which is **v**, **(sv)** or
+// **(uv)**.  `&Bar` implements `SerializeTo` for the latter.  As the data is
+// only valid for one of the variants there's no single type to cast it to, so
+// we generate a separate enum `BarView<'a>` holding a reference to the
+// payload, which implements `TryFrom` for the enum's type.
+
+use proc_macro::TokenStream;
+use syn::{
+    punctuated::Punctuated, token::Comma, Data, DeriveInput, Error, Field, Fields, GenericArgument,
+    GenericParam, Lit, LitStr, Meta, NestedMeta, PathArguments, Type, Variant,
+};
+
+use crate::generate_impl::{escape, generate_structure_and_types, generate_types};
+use crate::marker_type;
+use crate::type_parser::{one, GVariantType};
+
+// Returns the generated code and the path to the gvariant crate that it uses,
+// which is `::gvariant` unless given with `#[gvariant(crate = "...")]`.
+pub(crate) fn derive_gvariant(input: &DeriveInput) -> syn::Result<(String, TokenStream)> {
+    let krate = match str_attrs(&input.attrs, &["type", "crate"])?.remove(1) {
+        Some(lit) => {
+            lit.parse::<syn::Path>()?;
+            lit.value().parse().unwrap()
+        }
+        None => "::gvariant".parse().unwrap(),
+    };
+    let code = match &input.data {
+        Data::Struct(s) => match &s.fields {
+            Fields::Named(f) => derive_struct(input, &f.named),
+            _ => Err(Error::new_spanned(
+                &input.ident,
+                "#[derive(GVariant)] requires a struct with named fields",
+            )),
+        },
+        Data::Enum(e) => derive_enum(input, &e.variants),
+        Data::Union(_) => Err(Error::new_spanned(
+            &input.ident,
+            "#[derive(GVariant)] can only be used on structs and enums",
+        )),
+    }?;
+    Ok((code, krate))
+}
+
+fn derive_struct(input: &DeriveInput, fields: &Punctuated<Field, Comma>) -> syn::Result<String> {
+    let name = &input.ident;
+    let (impl_params, self_ty) = generics(input)?;
+
+    let field_types = fields
+        .iter()
+        .map(|field| Ok(str_attrs(&field.attrs, &["type"])?.remove(0)))
+        .collect::<syn::Result<Vec<_>>>()?;
+    let typestr = match str_attrs(&input.attrs, &["type", "crate"])?.remove(0) {
+        Some(lit) => {
+            if let Some(field_lit) = field_types.iter().flatten().next() {
+                return Err(Error::new_spanned(
+                    field_lit,
+                    "The type of each field is already given by the struct's \
+                     #[gvariant(type = \"...\")]",
+                ));
+            }
+            let typestr = lit.value();
+            crate::parse_typestr(&lit)?;
+            check_typestr(&typestr, fields.len()).map_err(|msg| Error::new_spanned(&lit, msg))?;
+            typestr
+        }
+        None => {
+            let mut typestr = "(".to_string();
+            for (field, field_type) in fields.iter().zip(&field_types) {
+                typestr += &match field_type {
+                    Some(lit) => {
+                        crate::parse_typestr(lit)?;
+                        lit.value()
+                    }
+                    None => infer_typestr(&field.ty).ok_or_else(|| {
+                        Error::new_spanned(
+                            &field.ty,
+                            "Can't determine the GVariant type of this field.  Specify it \
+                             with #[gvariant(type = \"...\")] on the field or the struct",
+                        )
+                    })?,
+                };
+            }
+            typestr.push(')');
+            check_typestr(&typestr, fields.len()).map_err(|msg| Error::new_spanned(name, msg))?;
+            typestr
+        }
+    };
+    let spec = one(typestr.as_bytes()).unwrap();
+    let children = match &spec {
+        GVariantType::Tuple(children) => children.as_slice(),
+        GVariantType::DictItem(children) => children.as_ref(),
+        _ => unreachable!("check_typestr only accepts structures"),
+    };
+
+    let escaped = escape(spec.to_string());
+    let module = format!("_gvariant_{}", name);
+    // Everything in our module is pub, it's the module itself and the
+    // visibility of `FooView` that limits where the types can be used.
+    let outer_vis = visibility(&input.vis);
+
+    let mut accessors = String::new();
+    let mut serialize_fields = String::new();
+    for (n, (field, child)) in fields.iter().zip(children).enumerate() {
+        let field_name = field.ident.as_ref().unwrap();
+        accessors += &format!(
+            "
+        pub fn {field_name}(&self) -> &{ty} {{
+            self.field_{n}()
+        }}",
+            field_name = field_name,
+            ty = marker_type(child),
+            n = n
+        );
+        serialize_fields += &format!("&self.{}, ", field_name);
+    }
+
+    Ok(format!(
+        "
+#[allow(non_snake_case, dead_code, unused_imports, clippy::unused_unit)]
+mod {module} {{
+    use __gvariant::aligned_bytes::{{
+        align_offset, empty_aligned, AlignedOffset, AlignedSlice, AsAligned, A1, A2, A4, A8,
+    }};
+    use __gvariant::casting::{{AlignOf, AllBitPatternsValid}};
+    use __gvariant::*;
+
+    {types}
+
+    impl Structure{escaped} {{
+        {accessors}
+    }}
+
+    #[derive(Copy, Clone)]
+    pub struct Marker();
+    impl __gvariant::Marker for Marker {{
+        type Type = Structure{escaped};
+        const TYPESTR: &'static [u8] = b\"{typestr}\";
+    }}
+}}
+
+/// Zero-copy view of serialised [`{name}`] data with the GVariant type **{typestr}**
+{outer_vis} type {name}View = {module}::Structure{escaped};
+
+impl<{impl_params}> {self_ty} {{
+    /// Returns the [`Marker`](gvariant::Marker) for **{typestr}**, used for
+    /// casting data to a [`{name}View`] and for serialisation.
+    #[allow(dead_code)]
+    {outer_vis} fn marker() -> {module}::Marker {{
+        {module}::Marker()
+    }}
+}}
+
+impl<'__gv, {impl_params}> __gvariant::SerializeTo<{name}View> for &'__gv {self_ty} {{
+    fn serialize(self, f: &mut impl __gvariant::write::Write) -> ::core::result::Result<usize, __gvariant::write::Error> {{
+        __gvariant::SerializeTo::<{name}View>::serialize(&({serialize_fields}), f)
+    }}
+}}
+",
+        module = module,
+        types = generate_structure_and_types(&spec, "pub").unwrap(),
+        escaped = escaped,
+        accessors = accessors,
+        typestr = typestr,
+        name = name,
+        outer_vis = outer_vis,
+        impl_params = impl_params,
+        self_ty = self_ty,
+        serialize_fields = serialize_fields,
+    ))
+}
+
+struct EnumVariant<'a> {
+    ident: &'a syn::Ident,
+    has_payload: bool,
+    typestr: String,
+    // The string for **(sv)** or the number for **(uv)**
+    tag: String,
+}
+
+fn derive_enum(input: &DeriveInput, variants: &Punctuated<Variant, Comma>) -> syn::Result<String> {
+    let name = &input.ident;
+    let (impl_params, self_ty) = generics(input)?;
+
+    let enum_typestr = match str_attrs(&input.attrs, &["type", "crate"])?.remove(0) {
+        None => "v".to_string(),
+        Some(lit) => match lit.value().as_str() {
+            x @ "v" | x @ "(sv)" | x @ "(uv)" => x.to_string(),
+            _ => {
+                return Err(Error::new_spanned(
+                    lit,
+                    "Enums can only be serialised as \"v\", \"(sv)\" or \"(uv)\"",
+                ))
+            }
+        },
+    };
+
+    if variants.is_empty() {
+        return Err(Error::new_spanned(
+            name,
+            "#[derive(GVariant)] requires an enum with at least one variant",
+        ));
+    }
+    let mut vs: Vec<EnumVariant> = vec![];
+    for (n, variant) in variants.iter().enumerate() {
+        let mut attrs = str_attrs(&variant.attrs, &["type", "name"])?;
+        let (has_payload, typestr) = match &variant.fields {
+            Fields::Unit => match &attrs[0] {
+                Some(lit) if lit.value() != "()" => {
+                    return Err(Error::new_spanned(lit, "Unit variants have type \"()\""))
+                }
+                _ => (false, "()".to_string()),
+            },
+            Fields::Unnamed(f) if f.unnamed.len() == 1 => {
+                // The type can be given on either the variant or its field
+                let field_type = str_attrs(&f.unnamed[0].attrs, &["type"])?.remove(0);
+                match (attrs[0].as_ref(), field_type.as_ref()) {
+                    (Some(_), Some(lit)) => {
+                        return Err(Error::new_spanned(
+                            lit,
+                            "The type of this field is already given on the variant",
+                        ))
+                    }
+                    (Some(lit), None) | (None, Some(lit)) => {
+                        crate::parse_typestr(lit)?;
+                        (true, lit.value())
+                    }
+                    (None, None) => {
+                        let ty = &f.unnamed[0].ty;
+                        let typestr = infer_typestr(ty).ok_or_else(|| {
+                            Error::new_spanned(
+                                ty,
+                                "Can't determine the GVariant type of this field.  Specify \
+                                 it with #[gvariant(type = \"...\")]",
+                            )
+                        })?;
+                        (true, typestr)
+                    }
+                }
+            }
+            _ => {
+                return Err(Error::new_spanned(
+                    variant,
+                    "#[derive(GVariant)] requires enum variants to be unit variants or to \
+                     have exactly one unnamed field",
+                ))
+            }
+        };
+        let tag = match (enum_typestr.as_str(), attrs.remove(1)) {
+            ("(sv)", Some(lit)) => lit.value(),
+            (_, Some(lit)) => {
+                return Err(Error::new_spanned(
+                    lit,
+                    "Variants can only be renamed in enums serialised as \"(sv)\"",
+                ))
+            }
+            ("(uv)", None) => n.to_string(),
+            _ => variant.ident.to_string(),
+        };
+        for other in &vs {
+            if enum_typestr == "v" && other.typestr == typestr {
+                return Err(Error::new_spanned(
+                    variant,
+                    format!(
+                        "{} and {} both have type {}.  Enums serialised as \"v\" need a \
+                         different type for each variant",
+                        other.ident, variant.ident, typestr
+                    ),
+                ));
+            }
+            if other.tag == tag {
+                return Err(Error::new_spanned(
+                    variant,
+                    format!("{} and {} have the same name", other.ident, variant.ident),
+                ));
+            }
+        }
+        vs.push(EnumVariant {
+            ident: &variant.ident,
+            has_payload,
+            typestr,
+            tag,
+        });
+    }
+
+    let specs: Vec<GVariantType> = Some(&enum_typestr)
+        .into_iter()
+        .chain(vs.iter().map(|v| &v.typestr))
+        .map(|t| one(t.as_bytes()).unwrap())
+        .collect();
+    let types = generate_types(&specs, "pub").unwrap();
+
+    let module = format!("_gvariant_{}", name);
+    let outer_vis = visibility(&input.vis);
+    let enum_type = marker_type(&one(enum_typestr.as_bytes()).unwrap());
+    let (tag_expr, unknown_tag) = match enum_typestr.as_str() {
+        "(sv)" => ("tag.to_str()", "UnknownVariant::Name(tag)"),
+        "(uv)" => ("*tag", "UnknownVariant::Index(tag)"),
+        _ => ("ty", "UnknownVariant::Type(ty)"),
+    };
+
+    let mut markers = String::new();
+    let mut view_variants = String::new();
+    let mut serialize_arms = String::new();
+    let mut deserialize_arms = String::new();
+    for v in &vs {
+        markers += &format!(
+            "
+    pub type Payload_{ident} = {ty};
+    #[derive(Copy, Clone)]
+    pub struct Marker_{ident}();
+    impl __gvariant::Marker for Marker_{ident} {{
+        type Type = Payload_{ident};
+        const TYPESTR: &'static [u8] = b\"{typestr}\";
+    }}",
+            ident = v.ident,
+            ty = marker_type(&one(v.typestr.as_bytes()).unwrap()),
+            typestr = v.typestr,
+        );
+        let (pattern, payload, value) = if v.has_payload {
+            (
+                format!("{}::{}(x)", name, v.ident),
+                "x",
+                format!(
+                    "{}View::{}({}::Marker_{}().cast(data.as_aligned()))",
+                    name, v.ident, module, v.ident
+                ),
+            )
+        } else {
+            (
+                format!("{}::{}", name, v.ident),
+                "&()",
+                format!("{}View::{}", name, v.ident),
+            )
+        };
+        view_variants += &if v.has_payload {
+            format!(
+                "
+    {}(&'a {}::Payload_{}),",
+                v.ident, module, v.ident
+            )
+        } else {
+            format!(
+                "
+    {},",
+                v.ident
+            )
+        };
+        let wrapped = format!(
+            "__gvariant::VariantWrap({}::Marker_{}(), {})",
+            module, v.ident, payload
+        );
+        serialize_arms += &format!(
+            "
+            {pattern} => __gvariant::SerializeTo::<{module}::Type>::serialize({value}, f),",
+            pattern = pattern,
+            module = module,
+            value = match enum_typestr.as_str() {
+                "(sv)" => format!("&({:?}, {})", v.tag, wrapped),
+                "(uv)" => format!("&({}u32, {})", v.tag, wrapped),
+                _ => wrapped,
+            }
+        );
+        deserialize_arms += &match enum_typestr.as_str() {
+            "(sv)" => format!(
+                "
+            {:?} if ty == b\"{}\" => Ok({}),",
+                v.tag, v.typestr, value
+            ),
+            "(uv)" => format!(
+                "
+            {} if ty == b\"{}\" => Ok({}),",
+                v.tag, v.typestr, value
+            ),
+            _ => format!(
+                "
+            b\"{}\" => Ok({}),",
+                v.typestr, value
+            ),
+        };
+    }
+    if enum_typestr != "v" {
+        // The tag matched but the type didn't
+        let tags: Vec<String> = vs
+            .iter()
+            .map(|v| match enum_typestr.as_str() {
+                "(sv)" => format!("{:?}", v.tag),
+                _ => v.tag.clone(),
+            })
+            .collect();
+        if !tags.is_empty() {
+            deserialize_arms += &format!(
+                "
+            {} => Err(__gvariant::UnknownVariant::Type(ty)),",
+                tags.join(" | ")
+            );
+        }
+    }
+    let split = match enum_typestr.as_str() {
+        "v" => "let (ty, data) = value.split();",
+        _ => {
+            "let (tag, variant) = __gvariant::Structure::to_tuple(value);
+        let (ty, data) = variant.split();"
+        }
+    };
+
+    Ok(format!(
+        "
+#[allow(non_snake_case, non_camel_case_types, dead_code, unused_imports, clippy::unused_unit)]
+mod {module} {{
+    use __gvariant::aligned_bytes::{{
+        align_offset, empty_aligned, AlignedOffset, AlignedSlice, AsAligned, A1, A2, A4, A8,
+    }};
+    use __gvariant::casting::{{AlignOf, AllBitPatternsValid}};
+    use __gvariant::*;
+
+    {types}
+    {markers}
+
+    pub type Type = {enum_type};
+    #[derive(Copy, Clone)]
+    pub struct Marker();
+    impl __gvariant::Marker for Marker {{
+        type Type = self::Type;
+        const TYPESTR: &'static [u8] = b\"{typestr}\";
+    }}
+}}
+
+/// Zero-copy view of serialised [`{name}`] data with the GVariant type **{typestr}**
+///
+/// Created from the data with `TryFrom`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+{outer_vis} enum {name}View<'a> {{{view_variants}
+}}
+
+impl<{impl_params}> {self_ty} {{
+    /// Returns the [`Marker`](gvariant::Marker) for **{typestr}**, used for
+    /// casting data and for serialisation.
+    #[allow(dead_code)]
+    {outer_vis} fn marker() -> {module}::Marker {{
+        {module}::Marker()
+    }}
+}}
+
+impl<'__gv, {impl_params}> __gvariant::SerializeTo<{module}::Type> for &'__gv {self_ty} {{
+    fn serialize(self, f: &mut impl __gvariant::write::Write) -> ::core::result::Result<usize, __gvariant::write::Error> {{
+        match self {{{serialize_arms}
+        }}
+    }}
+}}
+
+impl<'a> ::core::convert::TryFrom<&'a {module}::Type> for {name}View<'a> {{
+    type Error = __gvariant::UnknownVariant<'a>;
+    #[allow(unused_variables)]
+    fn try_from(value: &'a {module}::Type) -> ::core::result::Result<Self, Self::Error> {{
+        use __gvariant::{{aligned_bytes::AsAligned, Marker}};
+        {split}
+        match {tag_expr} {{{deserialize_arms}
+            tag => Err(__gvariant::{unknown_tag}),
+        }}
+    }}
+}}
+",
+        module = module,
+        types = types,
+        markers = markers,
+        enum_type = enum_type,
+        typestr = enum_typestr,
+        name = name,
+        outer_vis = outer_vis,
+        view_variants = view_variants,
+        impl_params = impl_params,
+        self_ty = self_ty,
+        serialize_arms = serialize_arms,
+        split = split,
+        tag_expr = tag_expr,
+        deserialize_arms = deserialize_arms,
+        unknown_tag = unknown_tag,
+    ))
+}
+
+// Checks the generics of the type we're deriving for and returns the
+// parameter list for the `impl` and the type with its parameters.  Only
+// lifetime parameters are supported, so types like `Foo<'a>` with fields like
+// `&'a str` can be serialised.
+fn generics(input: &DeriveInput) -> syn::Result<(String, String)> {
+    let mut lifetimes = vec![];
+    for param in &input.generics.params {
+        match param {
+            GenericParam::Lifetime(l) => lifetimes.push(l),
+            _ => {
+                return Err(Error::new_spanned(
+                    param,
+                    "#[derive(GVariant)] doesn't support type or const parameters",
+                ))
+            }
+        }
+    }
+    if let Some(w) = &input.generics.where_clause {
+        return Err(Error::new_spanned(
+            w,
+            "#[derive(GVariant)] doesn't support where clauses",
+        ));
+    }
+    let impl_params: Vec<String> = lifetimes
+        .iter()
+        .map(|l| {
+            let bounds: Vec<String> = l.bounds.iter().map(|b| b.to_string()).collect();
+            if bounds.is_empty() {
+                l.lifetime.to_string()
+            } else {
+                format!("{}: {}", l.lifetime, bounds.join(" + "))
+            }
+        })
+        .collect();
+    let ty_params: Vec<String> = lifetimes.iter().map(|l| l.lifetime.to_string()).collect();
+    Ok((
+        impl_params.join(", "),
+        format!("{}<{}>", input.ident, ty_params.join(", ")),
+    ))
+}
+
+pub(crate) fn visibility(vis: &syn::Visibility) -> String {
+    match vis {
+        syn::Visibility::Inherited => "".to_string(),
+        syn::Visibility::Public(_) => "pub".to_string(),
+        syn::Visibility::Crate(_) => "pub(crate)".to_string(),
+        syn::Visibility::Restricted(r) => format!(
+            "pub({}{})",
+            if r.in_token.is_some() { "in " } else { "" },
+            r.path
+                .segments
+                .iter()
+                .map(|s| s.ident.to_string())
+                .collect::<Vec<_>>()
+                .join("::")
+        ),
+    }
+}
+
+// Parses `#[gvariant(key = "...", ...)]`, returning the value for each of
+// `keys` in the same order.
+fn str_attrs(attrs: &[syn::Attribute], keys: &[&str]) -> syn::Result<Vec<Option<LitStr>>> {
+    let expected = keys
+        .iter()
+        .map(|k| format!("{} = \"...\"", k))
+        .collect::<Vec<_>>()
+        .join(" or ");
+    let mut out = vec![None; keys.len()];
+    for attr in attrs.iter().filter(|a| a.path.is_ident("gvariant")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            other => {
+                return Err(Error::new_spanned(
+                    other,
+                    format!("Expected #[gvariant({})]", expected),
+                ))
+            }
+        };
+        for nested in list.nested {
+            let n = match &nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) => {
+                    keys.iter().position(|k| nv.path.is_ident(k))
+                }
+                _ => None,
+            };
+            match (n, nested) {
+                (Some(n), NestedMeta::Meta(Meta::NameValue(nv))) => match nv.lit {
+                    Lit::Str(s) => out[n] = Some(s),
+                    lit => return Err(Error::new_spanned(lit, "Expected a string")),
+                },
+                (_, other) => {
+                    return Err(Error::new_spanned(
+                        other,
+                        format!("Unknown gvariant attribute.  Expected {}", expected),
+                    ))
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn check_typestr(typestr: &str, n_fields: usize) -> Result<(), String> {
+    let spec = one(typestr.as_bytes()).map_err(|e| format!("Invalid type string: {}", e))?;
+    let n_children = match &spec {
+        GVariantType::Tuple(children) => children.len(),
+        GVariantType::DictItem(_) => 2,
+        _ => {
+            return Err(format!(
+                "{} isn't a structure or dictionary entry type",
+                typestr
+            ))
+        }
+    };
+    if n_children != n_fields {
+        return Err(format!(
+            "{} has {} fields, but the struct has {}",
+            typestr, n_children, n_fields
+        ));
+    }
+    Ok(())
+}
+
+// Guess the GVariant type of a field from its rust type.  This is purely
+// syntactic as the macro doesn't have access to type information.
+fn infer_typestr(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Reference(r) => infer_typestr(&r.elem),
+        Type::Paren(p) => infer_typestr(&p.elem),
+        Type::Group(g) => infer_typestr(&g.elem),
+        Type::Slice(s) => Some(format!("a{}", infer_typestr(&s.elem)?)),
+        Type::Array(a) => Some(format!("a{}", infer_typestr(&a.elem)?)),
+        Type::Tuple(t) => {
+            let mut out = "(".to_string();
+            for elem in &t.elems {
+                out += &infer_typestr(elem)?;
+            }
+            out.push(')');
+            Some(out)
+        }
+        Type::Path(p) if p.qself.is_none() => {
+            let segment = p.path.segments.last()?;
+            // The GVariant types of the generic arguments, if there are `n`
+            let args = |n| match &segment.arguments {
+                PathArguments::AngleBracketed(a) if a.args.len() == n => a
+                    .args
+                    .iter()
+                    .map(|arg| match arg {
+                        GenericArgument::Type(t) => infer_typestr(t),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<_>>>(),
+                _ => None,
+            };
+            let arg = || Some(args(1)?.remove(0));
+            Some(match segment.ident.to_string().as_str() {
+                "bool" => "b".to_string(),
+                "u8" => "y".to_string(),
+                "i16" => "n".to_string(),
+                "u16" => "q".to_string(),
+                "i32" => "i".to_string(),
+                "u32" => "u".to_string(),
+                "i64" => "x".to_string(),
+                "u64" => "t".to_string(),
+                "f64" => "d".to_string(),
+                "str" | "String" => "s".to_string(),
+                "Vec" => format!("a{}", arg()?),
+                "Option" => format!("m{}", arg()?),
+                "Box" => arg()?,
+                "HashMap" | "BTreeMap" => {
+                    let kv = args(2)?;
+                    // Dictionary keys must be basic types, which are all a
+                    // single character
+                    if kv[0].len() != 1 {
+                        return None;
+                    }
+                    format!("a{{{}{}}}", kv[0], kv[1])
+                }
+                _ => return None,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(ty: &str) -> Option<String> {
+        infer_typestr(&syn::parse_str(ty).unwrap())
+    }
+
+    #[test]
+    fn test_infer_typestr() {
+        assert_eq!(infer("u32").as_deref(), Some("u"));
+        assert_eq!(infer("&'a str").as_deref(), Some("s"));
+        assert_eq!(infer("Vec<String>").as_deref(), Some("as"));
+        assert_eq!(infer("Option<Box<[u8]>>").as_deref(), Some("may"));
+        assert_eq!(infer("(i64, std::string::String)").as_deref(), Some("(xs)"));
+        assert_eq!(infer("[u16; 4]").as_deref(), Some("aq"));
+        assert_eq!(infer("HashMap<String, u32>").as_deref(), Some("a{su}"));
+        assert_eq!(
+            infer("std::collections::BTreeMap<u8, Vec<(i32, bool)>>").as_deref(),
+            Some("a{ya(ib)}")
+        );
+        assert_eq!(infer("HashMap<String>"), None);
+        assert_eq!(infer("HashMap<Vec<u8>, u32>"), None);
+        assert_eq!(infer("Vec<MyStruct>"), None);
+    }
+
+    #[test]
+    fn test_check_typestr() {
+        assert!(check_typestr("(sa{sv}t)", 3).is_ok());
+        assert!(check_typestr("{sv}", 2).is_ok());
+        assert!(check_typestr("(sa{sv}t)", 2).is_err());
+        assert!(check_typestr("as", 1).is_err());
+        assert!(check_typestr("(s", 1).is_err());
+    }
+}
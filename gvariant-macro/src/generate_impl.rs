@@ -1,62 +1,183 @@
 use std::error::Error;
 use std::io::Write;
 
-use crate::{marker_type, type_parser::GVariantType};
-
-pub(crate) fn generate_types(spec: &GVariantType) -> Result<String, Box<dyn Error>> {
-    Ok(match spec {
-        GVariantType::Tuple(children) => {
-            let mut out = "".to_string();
-            for child in children {
-                out += generate_types(child)?.as_ref();
-            }
-            out += generate_tuple(&spec, children)?.as_ref();
-            out
-        }
-        GVariantType::DictItem(children) => {
-            let mut out = "".to_string();
-            out += generate_types(&children[0])?.as_ref();
-            out += generate_types(&children[1])?.as_ref();
-            out += generate_tuple(&spec, children.as_ref())?.as_ref();
-            out
-        }
-        GVariantType::A(x) => generate_types(x)?,
-        GVariantType::M(x) => generate_types(x)?,
+use crate::{is_generated, marker_type, type_parser::GVariantType};
+
+// Generates the types for the structures with more than 16 fields used by each
+// of `specs`, for putting in a single module.  Other structures use the
+// generic types from `gvariant::structure`.  The types are named after their
+// type strings, so each one is only generated once however many times it
+// appears.
+//
+// `vis` is the visibility of the generated types.  `gv!` generates them in a
+// private module so `pub(crate)` is enough there.
+pub(crate) fn generate_types<'a>(
+    specs: impl IntoIterator<Item = &'a GVariantType>,
+    vis: &str,
+) -> Result<String, Box<dyn Error>> {
+    let mut structures = vec![];
+    for spec in specs {
+        find_structures(spec, &mut structures);
+    }
+    let mut out = "".to_string();
+    for spec in structures {
+        out += &generate_structure(spec, vis)?;
+    }
+    Ok(out)
+}
+
+// Generates the type for the structure `spec` whatever its size, along with
+// the types for any structures with more than 16 fields within it.  This is
+// for `#[derive(GVariant)]`, which adds accessors to the type.
+pub(crate) fn generate_structure_and_types(
+    spec: &GVariantType,
+    vis: &str,
+) -> Result<String, Box<dyn Error>> {
+    Ok(generate_types(children(spec), vis)? + &generate_structure(spec, vis)?)
+}
+
+fn children(spec: &GVariantType) -> &[GVariantType] {
+    match spec {
+        GVariantType::Tuple(children) => children.as_slice(),
+        GVariantType::DictItem(children) => children.as_ref(),
+        _ => &[],
+    }
+}
+
+fn generate_structure(spec: &GVariantType, vis: &str) -> Result<String, Box<dyn Error>> {
+    match spec {
+        GVariantType::Tuple(children) => generate_tuple(spec, children, vis),
+        GVariantType::DictItem(children) => generate_tuple(spec, children.as_ref(), vis),
+        _ => unreachable!("Only called for structures"),
+    }
+}
+
+// Appends the structures with more than 16 fields in `spec` to `out`,
+// children first, skipping any that are already there.
+fn find_structures<'a>(spec: &'a GVariantType, out: &mut Vec<&'a GVariantType>) {
+    let children = match spec {
+        GVariantType::Tuple(children) => children.as_slice(),
+        GVariantType::DictItem(children) => children.as_ref(),
+        GVariantType::A(x) | GVariantType::M(x) => return find_structures(x, out),
 
         // Everything else is a builtin
-        _ => "".to_owned(),
-    })
+        _ => return,
+    };
+    for child in children {
+        find_structures(child, out);
+    }
+    if is_generated(spec) && !out.contains(&spec) {
+        out.push(spec);
+    }
 }
 
 fn generate_tuple(
     spec: &GVariantType,
     children: &[GVariantType],
+    vis: &str,
 ) -> Result<String, Box<dyn Error>> {
-    let size = size_of(&spec);
+    let size = size_of(spec);
     let mut out = vec![];
     if size.is_some() {
-        write_packed_struct(spec, children, &mut out)
+        write_packed_struct(spec, children, vis, &mut out)
     } else {
-        write_non_fixed_size_structure(spec, children, &mut out)
+        write_non_fixed_size_structure(spec, children, vis, &mut out)
     }?;
+    // The standard library only implements traits for tuples of up to 12
+    // elements so these are implemented field by field rather than with
+    // `to_tuple()`, to support structures of any size.  The output matches the
+    // tuple implementations.
     write!(
         out,
-        "impl std::fmt::Debug for Structure{} {{
-        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {{
-            std::fmt::Debug::fmt(&self.to_tuple(), f)
+        "impl core::fmt::Debug for Structure{escaped} {{
+        fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {{
+            let t = __gvariant::Structure::to_tuple(self);
+            f.debug_tuple(\"\"){fields}.finish()
         }}
     }}",
-        escape(spec.to_string())
+        escaped = escape(spec.to_string()),
+        fields = (0..children.len())
+            .map(|n| format!(".field(&t.{})", n))
+            .collect::<String>(),
     )?;
+    // The fields for StructureBuilder, as a list like
+    // (Field<T0>, (Field<T1>, ()))
+    let mut fields = "()".to_owned();
+    for child in children.iter().rev() {
+        fields = format!(
+            "(__gvariant::builder::Field<{}>, {})",
+            marker_type(child),
+            fields
+        );
+    }
+    write!(
+        out,
+        "__gvariant::_alloc_only! {{
+    impl __gvariant::builder::Container for Structure{escaped} {{
+        type Builder<P: __gvariant::builder::Sink> =
+            __gvariant::builder::StructureBuilder<Self, P, {fields}>;
+        fn builder<P: __gvariant::builder::Sink>(parent: P) -> Self::Builder<P> {{
+            __gvariant::builder::StructureBuilder::new(parent)
+        }}
+    }}
+    }}",
+        escaped = escape(spec.to_string()),
+        fields = fields,
+    )?;
+    if let GVariantType::DictItem(children) = spec {
+        // Iterating over a `HashMap` or `BTreeMap` gives `(&K, &V)` tuples,
+        // so we support serialising those for dictionaries
+        write!(
+            out,
+            "impl<K: __gvariant::SerializeTo<{key}> + Copy, V: __gvariant::SerializeTo<{value}> + Copy>
+        __gvariant::SerializeTo<Structure{escaped}> for (K, V) {{
+        fn serialize(self, f: &mut impl __gvariant::write::Write) -> ::core::result::Result<usize, __gvariant::write::Error> {{
+            __gvariant::SerializeTo::<Structure{escaped}>::serialize(&self, f)
+        }}
+    }}
+    impl __gvariant::DictEntry for Structure{escaped} {{
+        type Key = {key};
+        type Value = {value};
+        fn key(&self) -> &{key} {{
+            self.field_0()
+        }}
+        fn value(&self) -> &{value} {{
+            self.field_1()
+        }}
+    }}",
+            escaped = escape(spec.to_string()),
+            key = marker_type(&children[0]),
+            value = marker_type(&children[1]),
+        )?;
+    }
+    if !contains_double(spec) {
+        // f64 is neither Eq nor Hash, so these are only available for
+        // structures without any doubles in them
+        write!(
+            out,
+            "impl Eq for Structure{escaped} {{}}
+    impl core::hash::Hash for Structure{escaped} {{
+        fn hash<H: core::hash::Hasher>(&self, state: &mut H) {{
+            let t = __gvariant::Structure::to_tuple(self);
+            {fields}
+        }}
+    }}",
+            escaped = escape(spec.to_string()),
+            fields = (0..children.len())
+                .map(|n| format!("core::hash::Hash::hash(t.{}, state);", n))
+                .collect::<String>(),
+        )?;
+    }
     Ok(String::from_utf8(out).unwrap())
 }
 
 fn write_non_fixed_size_structure(
     spec: &GVariantType,
     children: &[GVariantType],
+    vis: &str,
     code: &mut impl Write,
 ) -> Result<(), Box<dyn Error>> {
-    let alignment = align_of(&spec);
+    let alignment = align_of(spec);
     let n_frames: usize = children.iter().filter(|x| size_of(x).is_none()).count();
     // After all of the items have been added, a framing offset is appended, in
     // reverse order, for each non-fixed-sized item that is not the last item in
@@ -71,7 +192,7 @@ fn write_non_fixed_size_structure(
         n_frames
     };
     let escaped = escape(spec.to_string());
-    let types: Vec<String> = children.iter().map(|x| marker_type(x)).collect();
+    let types: Vec<String> = children.iter().map(marker_type).collect();
     let mut tuple = vec![b'('];
     for child in children {
         write!(tuple, "                &'a {},", marker_type(child))?;
@@ -83,7 +204,7 @@ fn write_non_fixed_size_structure(
         code,
         "
     #[repr(transparent)]
-    pub(crate) struct Structure{spec} {{
+    {vis} struct Structure{spec} {{
         data: AlignedSlice<aligned_bytes::A{alignment}>,
     }}
     impl Structure{spec} {{
@@ -93,6 +214,7 @@ fn write_non_fixed_size_structure(
             unsafe {{&mut *(slice as *mut AlignedSlice<aligned_bytes::A{alignment}> as *mut Structure{spec})}}
         }}
     }}
+    __gvariant::_alloc_only! {{
     impl ToOwned for Structure{spec} {{
         type Owned = Box<Self>;
         fn to_owned(&self) -> Self::Owned {{
@@ -100,33 +222,45 @@ fn write_non_fixed_size_structure(
             unsafe {{ Box::from_raw(Self::from_aligned_slice_mut(Box::leak(cp)) as *mut Self) }}
         }}
     }}
-    impl ::gvariant::Cast for Structure{spec} {{
+    }}
+    impl __gvariant::Cast for Structure{spec} {{
+        const FIXED_SIZE: Option<usize> = None;
         fn default_ref() -> &'static Self {{
             let d = empty_aligned();
             // This is safe because Structure{spec} is repr(transparent) around
             // this same type:
             unsafe {{&*(d as *const AlignedSlice<aligned_bytes::A{alignment}> as *const Structure{spec})}}
         }}
-        fn try_from_aligned_slice(slice:&AlignedSlice<Self::AlignOf>) -> Result<&Self, ::gvariant::casting::WrongSize> {{
+        fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {{
+            &self.data
+        }}
+        fn is_normal_form(&self) -> bool {{
+            __gvariant::is_normal_form_for_typestr(b\"{typestr}\", &self.data)
+        }}
+        fn try_from_aligned_slice(slice:&AlignedSlice<Self::AlignOf>) -> Result<&Self, __gvariant::casting::WrongSize> {{
             // This is safe because Structure{spec} is repr(transparent) around
             // this same type:
             Ok(unsafe {{&*(slice as *const AlignedSlice<aligned_bytes::A{alignment}> as *const Structure{spec})}})
         }}
-        fn try_from_aligned_slice_mut(slice:&mut AlignedSlice<Self::AlignOf>) -> Result<&mut Self, ::gvariant::casting::WrongSize> {{
+        fn try_from_aligned_slice_mut(slice:&mut AlignedSlice<Self::AlignOf>) -> Result<&mut Self, __gvariant::casting::WrongSize> {{
             // This is safe because Structure{spec} is repr(transparent) around
             // this same type:
             Ok(Self::from_aligned_slice_mut(slice))
         }}
     }}
-    unsafe impl ::gvariant::casting::AllBitPatternsValid for Structure{spec} {{}}
-    unsafe impl ::gvariant::casting::AlignOf for Structure{spec} {{
+    unsafe impl __gvariant::casting::AllBitPatternsValid for Structure{spec} {{}}
+    unsafe impl __gvariant::casting::AlignOf for Structure{spec} {{
         type AlignOf = aligned_bytes::A{alignment};
     }}
     impl<'a> Structure<'a> for Structure{spec} {{
+        const N_FIELDS: usize = {n_fields};
         type RefTuple = {tuple};
         fn to_tuple(&'a self) -> {tuple} {{ (",
+        vis = vis,
         spec = escaped,
+        typestr = spec,
         alignment = alignment,
+        n_fields = children.len(),
         tuple = tuple,
     )?;
 
@@ -149,13 +283,16 @@ fn write_non_fixed_size_structure(
     let mut base = 8;
     let mut offset = 0;
     let mut nth_frame_offset = 0;
+    let mut field_fns = vec![];
 
     for ((n, child), (i, a, b, c)) in children.iter().enumerate().zip(generate_table(children)) {
         let last_child = n == children.len() - 1;
-        writeln!(
-            code,
+        writeln!(code, "self.field_{}(),", n)?;
+        field_fns.push(format!(
             "
-            // {ty}
+        /// Field {n} ({ty}).  Unlike `to_tuple()` this only finds the offset of
+        /// this one field.
+        pub fn field_{n}(&self) -> &{marker_type} {{
             get_child_elem::<{marker_type}, aligned_bytes::A{b}>(
                 self.data.as_aligned(),
                 {i},
@@ -163,8 +300,10 @@ fn write_non_fixed_size_structure(
                 {c},
                 {child_size:?},
                 {last_child},
-                {n_frame_offsets}),",
-            ty = child.to_string(),
+                {n_frame_offsets})
+        }}",
+            n = n,
+            ty = child,
             marker_type = marker_type(child),
             i = i,
             a = a,
@@ -173,14 +312,44 @@ fn write_non_fixed_size_structure(
             child_size = size_of(child),
             last_child = last_child,
             n_frame_offsets = n_frame_offsets
-        )?;
+        ));
+        if let Some(size) = size_of(child) {
+            field_fns.push(format!(
+                "
+        /// Mutable access to field {n} ({ty}), or `None` if the data is too
+        /// short to contain it
+        pub fn field_{n}_mut(&mut self) -> Option<&mut {marker_type}> {{
+            __gvariant::get_child_elem_mut::<{marker_type}, aligned_bytes::A{b}>(
+                __gvariant::aligned_bytes::AsAlignedMut::as_aligned_mut(&mut self.data),
+                {i},
+                {a},
+                {c},
+                Some({size}),
+                {last_child},
+                {n_frame_offsets})
+        }}",
+                n = n,
+                ty = child,
+                marker_type = marker_type(child),
+                i = i,
+                a = a,
+                b = b,
+                c = c,
+                size = size,
+                last_child = last_child,
+                n_frame_offsets = n_frame_offsets
+            ));
+        }
         if align_of(child) <= base {
             // Statically known number of padding bytes
             let old_offset = offset;
             offset = align(offset, align_of(child));
             let n_padding = offset - old_offset;
             if n_padding > 0 {
-                serialize_cmds.push(format!("f.write_all(b\"{}\")?;", "\\0".repeat(n_padding)));
+                serialize_cmds.push(format!(
+                    "__gvariant::write::Write::write_all(f, b\"{}\")?;",
+                    "\\0".repeat(n_padding)
+                ));
                 serialize_cmds.push(format!("off += {};", n_padding));
             }
         } else {
@@ -209,9 +378,9 @@ fn write_non_fixed_size_structure(
             }
         }
         serialize_types.push(format!(
-            "T{}: ::gvariant::SerializeTo<{}> + Copy",
+            "T{}: __gvariant::SerializeTo<{}> + Copy",
             n,
-            marker_type(&child)
+            marker_type(child)
         ));
         serialize_types2.push(format!("T{},", n));
     }
@@ -220,6 +389,9 @@ fn write_non_fixed_size_structure(
         "            )
         }}
     }}
+    impl Structure{escaped} {{
+        {field_fns}
+    }}
     impl<'a> From<&'a Structure{escaped}> for ({tuple}) {{
         fn from(value : &'a Structure{escaped}) -> Self {{
             value.to_tuple()
@@ -227,11 +399,12 @@ fn write_non_fixed_size_structure(
     }}
     impl PartialEq for Structure{escaped} {{
         fn eq(&self, other: &Self) -> bool {{
-            self.to_tuple() == other.to_tuple()
+            let (a, b) = (self.to_tuple(), other.to_tuple());
+            true {eq}
         }}
     }}
-    impl<{serialize_types}> ::gvariant::SerializeTo<Structure{escaped}> for &({serialize_types2}) {{
-        fn serialize(self, f: &mut impl std::io::Write) -> std::io::Result<usize> {{
+    impl<{serialize_types}> __gvariant::SerializeTo<Structure{escaped}> for &({serialize_types2}) {{
+        fn serialize(self, f: &mut impl __gvariant::write::Write) -> ::core::result::Result<usize, __gvariant::write::Error> {{
             let mut off: usize = 0;
             let mut framing_offsets : [usize; {n_frame_offsets}] = [0; {n_frame_offsets}];
             {serialize_cmds}
@@ -239,6 +412,11 @@ fn write_non_fixed_size_structure(
             Ok(off)
         }}
     }}
+    impl<'a> __gvariant::SerializeTo<Structure{escaped}> for &'a Structure{escaped} {{
+        fn serialize(self, f: &mut impl __gvariant::write::Write) -> ::core::result::Result<usize, __gvariant::write::Error> {{
+            __gvariant::SerializeTo::<Structure{escaped}>::serialize(&self.to_tuple(), f)
+        }}
+    }}
     ",
         escaped = escaped,
         tuple = types
@@ -250,6 +428,10 @@ fn write_non_fixed_size_structure(
         serialize_types = serialize_types.join(", "),
         serialize_types2 = serialize_types2.join(" "),
         serialize_cmds = serialize_cmds.join("\n"),
+        field_fns = field_fns.join("\n"),
+        eq = (0..types.len())
+            .map(|n| format!("&& a.{n} == b.{n}", n = n))
+            .collect::<String>(),
     )?;
 
     Ok(())
@@ -274,7 +456,7 @@ fn align_of(t: &GVariantType) -> usize {
         GVariantType::B | GVariantType::Y => 1,
         // Each integer type has alignment equal to its fixed size.
         GVariantType::N | GVariantType::Q => 2,
-        GVariantType::I | GVariantType::U => 4,
+        GVariantType::I | GVariantType::U | GVariantType::H => 4,
         GVariantType::X | GVariantType::T => 8,
         GVariantType::D => 8,
         // Including object paths and signature strings, strings are not
@@ -305,6 +487,7 @@ pub(crate) fn size_of(t: &GVariantType) -> Option<usize> {
         GVariantType::Q => Some(2),
         GVariantType::I => Some(4),
         GVariantType::U => Some(4),
+        GVariantType::H => Some(4),
         GVariantType::X => Some(8),
         GVariantType::T => Some(8),
         GVariantType::D => Some(8),
@@ -343,7 +526,7 @@ pub(crate) fn size_of(t: &GVariantType) -> Option<usize> {
                 let mut pos = a;
                 pos = align(pos, align_of(&x[1]));
                 pos += b;
-                pos = align(pos, align_of(&t));
+                pos = align(pos, align_of(t));
                 Some(pos)
             }
             _ => None,
@@ -351,17 +534,27 @@ pub(crate) fn size_of(t: &GVariantType) -> Option<usize> {
     }
 }
 
+fn contains_double(t: &GVariantType) -> bool {
+    match t {
+        GVariantType::D => true,
+        GVariantType::A(t) | GVariantType::M(t) => contains_double(t),
+        GVariantType::Tuple(subtypes) => subtypes.iter().any(contains_double),
+        GVariantType::DictItem(x) => x.iter().any(contains_double),
+        _ => false,
+    }
+}
+
 // This is a streight port of the Python code from the GVariant paper section
 // 3.2.2 Computing the Table
 //
 // The offset of an item n is then:
 //
 //     let (i, a, b, c) = table[n];
-//     let off = (frame_offset[i] + a + b - 1) & !(b - 1) | c
+//     let off = ((frame_offset[i] + a + b - 1) & !(b - 1)) + c
 //
 // which is:
 //
-//     align<B>(frame_offset[i] + a) | c
+//     align<B>(frame_offset[i] + a) + c
 //
 // Postconditions:
 //
@@ -398,19 +591,22 @@ fn generate_table(children: &[GVariantType]) -> Vec<(isize, usize, u8, usize)> {
 fn write_packed_struct(
     gv: &GVariantType,
     children: &[GVariantType],
+    vis: &str,
     out: &mut impl std::io::Write,
 ) -> Result<(), Box<dyn Error>> {
-    writeln!(out, "#[derive(Default,Copy,Clone)]")?;
+    writeln!(out, "#[derive(Copy,Clone)]")?;
     writeln!(out, "#[repr(C)]")?;
     let escaped = escape(gv.to_string());
 
     // This is only called for fixed-size structures
-    writeln!(out, "pub(crate) struct Structure{} {{", escaped)?;
+    writeln!(out, "{} struct Structure{} {{", vis, escaped)?;
 
     let mut field_arglist = vec![];
     let mut get_fields = vec![];
+    let mut field_fns = vec![];
     let mut set_fields = "".to_string();
     let mut eq = vec!["true".to_owned()];
+    let mut byteswap_fields = vec![];
     let mut defaults = vec![];
     let mut types = vec![];
     let mut tuple = vec![];
@@ -422,10 +618,10 @@ fn write_packed_struct(
     let mut padding_count = 0;
     for (n, (child, &(_, a, b, c))) in children
         .iter()
-        .zip(generate_table(&children).iter())
+        .zip(generate_table(children).iter())
         .enumerate()
     {
-        let start = align(a, b as usize) | c;
+        let start = align(a, b as usize) + c;
         let end = start + size_of(child).unwrap();
         let padding = start - last_end;
         if padding > 0 {
@@ -434,25 +630,42 @@ fn write_packed_struct(
                 "_padding_{} : [0u8;{}],\n",
                 padding_count, padding
             ));
-            serialize_cmds.push(format!("f.write_all(b\"{}\")?;", "\\0".repeat(padding)));
+            serialize_cmds.push(format!(
+                "__gvariant::write::Write::write_all(f, b\"{}\")?;",
+                "\\0".repeat(padding)
+            ));
             padding_count += 1;
         }
-        let rust_type: String = marker_type(&child);
+        let rust_type: String = marker_type(child);
         writeln!(out, "    // {} bytes {}..{}", child, start, end)?;
         writeln!(out, "    pub field_{} : {},", n, rust_type)?;
         field_arglist.push(format!("field_{} : {}", n, rust_type));
         get_fields.push(format!("&self.field_{},", n));
+        field_fns.push(format!(
+            "
+            /// Field {n} ({ty})
+            pub fn field_{n}(&self) -> &{rust_type} {{
+                &self.field_{n}
+            }}",
+            n = n,
+            ty = child,
+            rust_type = rust_type,
+        ));
         tuple.push(format!("&'a {},", rust_type));
         serialize_types.push(format!(
-            "T{}: ::gvariant::SerializeTo<{}> + Copy",
+            "T{}: __gvariant::SerializeTo<{}> + Copy",
             n, rust_type
         ));
         serialize_types2.push(format!("T{},", n));
         serialize_cmds.push(format!("self.{}.serialize(f)?;", n));
         types.push(rust_type);
         set_fields.push_str(format!("field_{} : field_{},\n", n, n).as_str());
-        defaults.push("0".to_string());
+        defaults.push(zero(child));
         eq.push(format!("self.field_{n} == other.field_{n}", n = n));
+        byteswap_fields.push(format!(
+            "__gvariant::endian::Endian::byteswap(self.field_{})",
+            n
+        ));
         last_end = end;
     }
     let padding = size_of(gv).unwrap() - last_end;
@@ -462,36 +675,54 @@ fn write_packed_struct(
             "_padding_{} : [0u8;{}],\n",
             padding_count, padding
         ));
-        serialize_cmds.push(format!("f.write_all(b\"{}\")?;", "\\0".repeat(padding)));
+        serialize_cmds.push(format!(
+            "__gvariant::write::Write::write_all(f, b\"{}\")?;",
+            "\\0".repeat(padding)
+        ));
     }
     writeln!(
         out,
         "}}
-        unsafe impl ::gvariant::casting::AllBitPatternsValid for Structure{escaped} {{}}
-        unsafe impl ::gvariant::casting::AlignOf for Structure{escaped} {{
-            type AlignOf = ::gvariant::aligned_bytes::A{align};
+        unsafe impl __gvariant::casting::AllBitPatternsValid for Structure{escaped} {{}}
+        unsafe impl __gvariant::casting::AlignOf for Structure{escaped} {{
+            type AlignOf = __gvariant::aligned_bytes::A{align};
         }}
         impl Structure{escaped} {{
+            #[allow(clippy::too_many_arguments)]
             pub const fn new({field_arglist}) -> Structure{escaped} {{
                 Structure{escaped} {{ {set_fields} }}
             }}
+            {field_fns}
         }}
-        impl<'a> ::gvariant::Structure<'a> for Structure{escaped} {{
+        impl<'a> __gvariant::Structure<'a> for Structure{escaped} {{
+            const N_FIELDS: usize = {n_fields};
             type RefTuple = ({tuple});
             fn to_tuple(&'a self) -> ({tuple}) {{
                 ({get_fields})
             }}
         }}
-        impl ::gvariant::Cast for Structure{escaped} {{
+        impl Default for Structure{escaped} {{
+            fn default() -> Self {{
+                *<Self as __gvariant::Cast>::default_ref()
+            }}
+        }}
+        impl __gvariant::Cast for Structure{escaped} {{
+            const FIXED_SIZE: Option<usize> = Some(core::mem::size_of::<Self>());
             fn default_ref() -> &'static Self {{
                 static s : Structure{escaped} = Structure{escaped}::new({defaults});
                 &s
             }}
-            fn try_from_aligned_slice(slice:&::gvariant::aligned_bytes::AlignedSlice<Self::AlignOf>) -> Result<&Self, ::gvariant::casting::WrongSize> {{
-                ::gvariant::casting::try_cast_slice_to::<Self>(slice)
+            fn as_aligned_slice(&self) -> &__gvariant::aligned_bytes::AlignedSlice<Self::AlignOf> {{
+                __gvariant::casting::cast_to_slice(self)
             }}
-            fn try_from_aligned_slice_mut(slice:&mut ::gvariant::aligned_bytes::AlignedSlice<Self::AlignOf>) -> Result<&mut Self, ::gvariant::casting::WrongSize> {{
-                ::gvariant::casting::try_cast_slice_to_mut::<Self>(slice)
+            fn is_normal_form(&self) -> bool {{
+                __gvariant::is_normal_form_for_typestr(b\"{gv}\", self.as_aligned_slice())
+            }}
+            fn try_from_aligned_slice(slice:&__gvariant::aligned_bytes::AlignedSlice<Self::AlignOf>) -> Result<&Self, __gvariant::casting::WrongSize> {{
+                __gvariant::casting::try_cast_slice_to::<Self>(slice)
+            }}
+            fn try_from_aligned_slice_mut(slice:&mut __gvariant::aligned_bytes::AlignedSlice<Self::AlignOf>) -> Result<&mut Self, __gvariant::casting::WrongSize> {{
+                __gvariant::casting::try_cast_slice_to_mut::<Self>(slice)
             }}
         }}
         impl PartialEq for Structure{escaped} {{
@@ -499,21 +730,33 @@ fn write_packed_struct(
                 {eq}
             }}
         }}
+        impl __gvariant::endian::Endian for Structure{escaped} {{
+            fn byteswap(self) -> Self {{
+                Structure{escaped}::new({byteswap_fields})
+            }}
+        }}
         impl<'a> From<&'a Structure{escaped}> for ({tuple}) {{
             fn from(value : &'a Structure{escaped}) -> Self {{
                 value.to_tuple()
             }}
         }}
-        impl<{serialize_types}> ::gvariant::SerializeTo<Structure{escaped}> for &({serialize_types2}) {{
-            fn serialize(self, f: &mut impl std::io::Write) -> std::io::Result<usize> {{
+        impl<{serialize_types}> __gvariant::SerializeTo<Structure{escaped}> for &({serialize_types2}) {{
+            fn serialize(self, f: &mut impl __gvariant::write::Write) -> ::core::result::Result<usize, __gvariant::write::Error> {{
                 {serialize_cmds}
                 Ok(core::mem::size_of::<Structure{escaped}>())
             }}
         }}
+        impl<'a> __gvariant::SerializeTo<Structure{escaped}> for &'a Structure{escaped} {{
+            fn serialize(self, f: &mut impl __gvariant::write::Write) -> ::core::result::Result<usize, __gvariant::write::Error> {{
+                __gvariant::SerializeTo::<Structure{escaped}>::serialize(&self.to_tuple(), f)
+            }}
+        }}
         ",
         escaped = escaped,
         align = align_of(gv),
         get_fields = get_fields.join(" "),
+        field_fns = field_fns.join("\n"),
+        n_fields = children.len(),
         field_arglist = field_arglist.join(", "),
         serialize_types = serialize_types.join(", "),
         serialize_types2 = serialize_types2.join(" "),
@@ -521,12 +764,53 @@ fn write_packed_struct(
         set_fields = set_fields,
         defaults = defaults.join(", "),
         eq = eq.join(" && "),
+        byteswap_fields = byteswap_fields.join(", "),
         tuple = tuple.join(" ")
     )?;
 
     Ok(())
 }
 
+// A const expression for the all zeros value of a fixed size type, for
+// `Cast::default_ref()`
+fn zero(spec: &GVariantType) -> String {
+    match spec {
+        // The field of `Bool` is private, but it's repr(transparent) around
+        // a u8
+        GVariantType::B => "unsafe { core::mem::transmute::<u8, Bool>(0) }".to_owned(),
+        GVariantType::D => "0.0".to_owned(),
+        GVariantType::H => "Handle(0)".to_owned(),
+        GVariantType::Tuple(children) => zero_structure(spec, children),
+        GVariantType::DictItem(children) => zero_structure(spec, children.as_ref()),
+        _ => "0".to_owned(),
+    }
+}
+
+fn zero_structure(spec: &GVariantType, children: &[GVariantType]) -> String {
+    let fields: Vec<String> = children.iter().map(zero).collect();
+    format!("{}::new({})", marker_type(spec), fields.join(", "))
+}
+
+// The padding after each field of a fixed size structure, which are the const
+// parameters of its `gvariant::structure` type.  The padding after the last
+// field makes the size a multiple of the alignment of the structure.
+pub(crate) fn padding(t: &GVariantType, children: &[GVariantType]) -> Vec<usize> {
+    let mut padding = vec![];
+    let mut end = 0;
+    for child in children {
+        let start = align(end, align_of(child));
+        if let Some(last) = padding.last_mut() {
+            *last = start - end;
+        }
+        end = start + size_of(child).expect("Only called for fixed size structures");
+        padding.push(0);
+    }
+    if let Some(last) = padding.last_mut() {
+        *last = align(end, align_of(t)) - end;
+    }
+    padding
+}
+
 fn align(off: usize, alignment: usize) -> usize {
     (off + alignment - 1) & !(alignment - 1)
 }
@@ -554,6 +838,43 @@ mod tests {
         assert_eq!(size_of(&one(b"(ti)").unwrap()), Some(16));
     }
 
+    #[test]
+    fn test_contains_double() {
+        assert!(!contains_double(&one(b"(sv)").unwrap()));
+        assert!(contains_double(&one(b"(sd)").unwrap()));
+        assert!(contains_double(&one(b"a{smad}").unwrap()));
+    }
+
+    #[test]
+    fn test_find_structures() {
+        let wide = "(iiiiiiiiiiiiiiiii)";
+        let wider = format!("(s{}{})", wide, "y".repeat(16));
+        let specs = [
+            one(format!("({}a{})", wide, wide).as_bytes()).unwrap(),
+            one(format!("a{{s{}}}", wider).as_bytes()).unwrap(),
+            one(b"((ii)a(ii))").unwrap(),
+        ];
+        let mut structures = vec![];
+        for spec in &specs {
+            find_structures(spec, &mut structures);
+        }
+        let structures: Vec<String> = structures.iter().map(|x| x.to_string()).collect();
+        assert_eq!(structures, [wide, &wider]);
+    }
+
+    #[test]
+    fn test_padding() {
+        let padding = |t: &[u8]| {
+            let t = one(t).unwrap();
+            padding(&t, children(&t))
+        };
+        assert_eq!(padding(b"(yi)"), [3, 0]);
+        assert_eq!(padding(b"(iy)"), [0, 3]);
+        assert_eq!(padding(b"(ty(yy)n)"), [0, 0, 1, 2]);
+        assert_eq!(padding(b"{yt}"), [7, 0]);
+        assert!(padding(b"()").is_empty());
+    }
+
     #[test]
     fn test_align() {
         assert_eq!(align(0, 1), 0);
@@ -1,61 +1,231 @@
 //! This is an implementation detail of the [gvariant](../gvariant/index.html) crate
 
 extern crate proc_macro;
-use proc_macro::TokenStream;
-use std::error::Error;
+use proc_macro::{Group, TokenStream, TokenTree};
 
+mod derive;
 mod generate_impl;
+mod type_alias;
 mod type_parser;
-use generate_impl::{escape, size_of};
-use syn::{parse_macro_input, LitStr};
+use generate_impl::{escape, padding, size_of};
+use syn::{parse_macro_input, DeriveInput, LitStr};
 
 use type_parser::{one, GVariantType};
 
 #[proc_macro]
 pub fn gv_type(input: TokenStream) -> TokenStream {
-    let typestr = parse_macro_input!(input as LitStr).value();
-    type_for_typestr(typestr.as_ref()).unwrap().parse().unwrap()
+    let lit = parse_macro_input!(input as LitStr);
+    match parse_typestr(&lit) {
+        Ok(spec) => marker_type(&spec).parse().unwrap(),
+        // `gv!` always calls `define_gv!` with the same type string, which
+        // reports the error.  Any valid type here avoids follow-on errors
+        // about `Marker::Type`.
+        Err(_) => "u8".parse().unwrap(),
+    }
+}
+
+#[proc_macro]
+pub fn gv_qualified_type(input: TokenStream) -> TokenStream {
+    let (krate, input) = split_crate(input);
+    let lit = parse_macro_input!(input as LitStr);
+    let spec = match parse_typestr(&lit) {
+        Ok(spec) => spec,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    match rust_type(&spec, "__gvariant::", None) {
+        Some(ty) => with_crate(&ty, &krate),
+        None => syn::Error::new(
+            lit.span(),
+            format!(
+                "gv_type!({:?}) needs a name to define its structures with more than {} \
+                 fields.  Declare one with gv_type! {{ type MyType = {:?}; }}",
+                lit.value(),
+                MAX_GENERIC_FIELDS,
+                lit.value()
+            ),
+        )
+        .to_compile_error()
+        .into(),
+    }
+}
+
+#[proc_macro]
+pub fn define_gv_type(input: TokenStream) -> TokenStream {
+    let (krate, input) = split_crate(input);
+    let decls = parse_macro_input!(input as type_alias::TypeDecls);
+    match type_alias::define_types(&decls) {
+        Ok(code) => with_crate(&code, &krate),
+        Err(e) => e.to_compile_error().into(),
+    }
 }
 
 #[proc_macro]
 pub fn define_gv(input: TokenStream) -> TokenStream {
-    let typestr = parse_macro_input!(input as LitStr).value();
-    generate_impl::generate_types(&one(typestr.as_ref()).unwrap())
-        .unwrap()
-        .parse()
-        .unwrap()
+    let (krate, input) = split_crate(input);
+    let lit = parse_macro_input!(input as LitStr);
+    match parse_typestr(&lit) {
+        Ok(spec) => with_crate(
+            &generate_impl::generate_types(Some(&spec), "pub(crate)").unwrap(),
+            &krate,
+        ),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Derive GVariant serialisation and a zero-copy view for a struct with named
+/// fields or an enum
+///
+/// See the documentation of the gvariant crate for details.
+#[proc_macro_derive(GVariant, attributes(gvariant))]
+pub fn derive_gvariant(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match derive::derive_gvariant(&input) {
+        Ok((code, krate)) => with_crate(&code, &krate),
+        Err(e) => e.to_compile_error().into(),
+    }
 }
 
-fn type_for_typestr(gv_typestr: &[u8]) -> Result<String, Box<dyn Error>> {
-    let spec = type_parser::one(gv_typestr)?;
-    Ok(marker_type(&spec))
+/// The name that the code we generate uses for the gvariant crate.  It's
+/// replaced by the path we've been given by [`with_crate`], so the code works
+/// wherever the crate is and whatever it's called.
+const CRATE: &str = "__gvariant";
+
+/// Splits off the path to the gvariant crate that our `macro_rules!` wrappers
+/// pass as `$crate,` before the real input
+fn split_crate(input: TokenStream) -> (TokenStream, TokenStream) {
+    let mut tokens = input.into_iter();
+    let krate = tokens
+        .by_ref()
+        .take_while(|t| !matches!(t, TokenTree::Punct(p) if p.as_char() == ','))
+        .collect();
+    (krate, tokens.collect())
+}
+
+/// Parses the generated `code`, replacing [`CRATE`] with `krate`
+pub(crate) fn with_crate(code: &str, krate: &TokenStream) -> TokenStream {
+    fn replace(tokens: TokenStream, krate: &TokenStream) -> TokenStream {
+        tokens
+            .into_iter()
+            .flat_map(|t| match t {
+                TokenTree::Ident(i) if i.to_string() == CRATE => krate.clone(),
+                TokenTree::Group(g) => {
+                    let mut new = Group::new(g.delimiter(), replace(g.stream(), krate));
+                    new.set_span(g.span());
+                    TokenTree::Group(new).into()
+                }
+                t => t.into(),
+            })
+            .collect()
+    }
+    replace(code.parse().unwrap(), krate)
 }
 
+/// Parse the type string in `lit`, reporting errors against the offending
+/// character
+pub(crate) fn parse_typestr(lit: &LitStr) -> Result<GVariantType, syn::Error> {
+    let typestr = lit.value();
+    one(typestr.as_bytes()).map_err(|e| {
+        // We can only point into the literal if each character of the type
+        // string is a character of the source, that is without escapes.  Even
+        // then stable rust doesn't support subspans yet, so we draw our own
+        // arrow in the message too.
+        let token = lit.token();
+        let span = if token.to_string() == format!("{:?}", typestr) {
+            token.subspan(e.position + 1..e.position + 2)
+        } else {
+            None
+        };
+        syn::Error::new(
+            span.unwrap_or_else(|| lit.span()),
+            format!(
+                "invalid type string: {}\n{}\n{}^",
+                e.message,
+                typestr,
+                " ".repeat(e.position)
+            ),
+        )
+    })
+}
+
+/// The Rust type used for values of type `t` in the code that we generate
 pub(crate) fn marker_type(t: &GVariantType) -> String {
-    match t {
-        GVariantType::B => "Bool".to_string(),
+    rust_type(t, "", Some("")).unwrap()
+}
+
+/// The most fields that a structure can have to use the generic types from
+/// `gvariant::structure`.  We generate types for wider structures.
+const MAX_GENERIC_FIELDS: usize = 16;
+
+/// Is `t` a structure that we generate a type for, rather than using one of the
+/// generic types from `gvariant::structure`?
+pub(crate) fn is_generated(t: &GVariantType) -> bool {
+    matches!(t, GVariantType::Tuple(children) if children.len() > MAX_GENERIC_FIELDS)
+}
+
+/// The Rust type used for values of type `t`
+///
+/// `gvariant` is the path prefix for the types from the gvariant crate and
+/// `structures` for the generated structure types.  Returns `None` for types
+/// containing generated structures if we haven't generated any.
+pub(crate) fn rust_type(
+    t: &GVariantType,
+    gvariant: &str,
+    structures: Option<&str>,
+) -> Option<String> {
+    let ty = |t: &GVariantType| rust_type(t, gvariant, structures);
+    // The type parameters of a generic structure type: the type of each field,
+    // then the padding after each field if it's fixed size
+    let params = |children: &[GVariantType]| -> Option<String> {
+        let mut params = children.iter().map(ty).collect::<Option<Vec<_>>>()?;
+        if size_of(t).is_some() {
+            params.extend(padding(t, children).iter().map(|p| p.to_string()));
+        }
+        Some(params.join(", "))
+    };
+    let fixed = if size_of(t).is_some() {
+        "Fixed"
+    } else {
+        "NonFixed"
+    };
+    Some(match t {
+        GVariantType::B => format!("{}Bool", gvariant),
         GVariantType::Y => "u8".to_string(),
         GVariantType::N => "i16".to_string(),
         GVariantType::Q => "u16".to_string(),
         GVariantType::I => "i32".to_string(),
         GVariantType::U => "u32".to_string(),
+        GVariantType::H => format!("{}Handle", gvariant),
         GVariantType::X => "i64".to_string(),
         GVariantType::T => "u64".to_string(),
         GVariantType::D => "f64".to_string(),
-        GVariantType::S => "Str".to_string(),
-        GVariantType::O => "Str".to_string(),
-        GVariantType::G => "Str".to_string(),
-        GVariantType::V => "Variant".to_string(),
+        GVariantType::S => format!("{}Str", gvariant),
+        GVariantType::O => format!("{}ObjectPath", gvariant),
+        GVariantType::G => format!("{}Signature", gvariant),
+        GVariantType::V => format!("{}Variant", gvariant),
         GVariantType::A(t) => match size_of(t) {
-            None => format!("NonFixedWidthArray::<{}>", marker_type(t)),
-            Some(_) => format!("[{}]", marker_type(t)),
+            None => format!("{}NonFixedWidthArray::<{}>", gvariant, ty(t)?),
+            Some(_) => format!("[{}]", ty(t)?),
         },
         GVariantType::M(t) => match size_of(t) {
-            None => format!("MaybeNonFixedSize::<{}>", marker_type(t)),
-            Some(_) => format!("MaybeFixedSize::<{}>", marker_type(t)),
+            None => format!("{}MaybeNonFixedSize::<{}>", gvariant, ty(t)?),
+            Some(_) => format!("{}MaybeFixedSize::<{}>", gvariant, ty(t)?),
         },
-        GVariantType::Tuple(_) | GVariantType::DictItem(_) => {
-            format!("Structure{name}", name = escape(t.to_string()))
+        _ if is_generated(t) => format!("{}Structure{}", structures?, escape(t.to_string())),
+        GVariantType::Tuple(children) if children.is_empty() => {
+            format!("{}structure::Fixed0", gvariant)
         }
-    }
+        GVariantType::Tuple(children) => format!(
+            "{}structure::{}{}::<{}>",
+            gvariant,
+            fixed,
+            children.len(),
+            params(children)?
+        ),
+        GVariantType::DictItem(children) => format!(
+            "{}structure::{}Entry::<{}>",
+            gvariant,
+            fixed,
+            params(children.as_ref())?
+        ),
+    })
 }
@@ -0,0 +1,143 @@
+// Implementation of the item form of `gv_type!`
+//
+// All the declarations in a block share one private module, named after the
+// first of them, holding a `Marker_{name}` for each and the types for any
+// structures with more than 16 fields in their type strings.  Other structures
+// are the generic types from `gvariant::structure`.  As the generated types
+// are only generated once per module `(i...)` is the same Rust type wherever it
+// appears in the block's declarations, however many fields it has.  Each `Foo`
+// is then declared twice, like a unit struct: as a type alias for the Rust type
+// and as a constant holding its marker.
+
+use syn::parse::{Parse, ParseStream};
+use syn::{Attribute, Ident, LitStr, Token, Visibility};
+
+use crate::derive::visibility;
+use crate::generate_impl::generate_types;
+use crate::{marker_type, parse_typestr, rust_type};
+
+// `$(#[$attr])* $vis type $name = $typestr;`
+struct TypeDecl {
+    attrs: Vec<Attribute>,
+    vis: Visibility,
+    name: Ident,
+    typestr: LitStr,
+}
+
+impl Parse for TypeDecl {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        input.parse::<Token![type]>()?;
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        let typestr = input.parse()?;
+        input.parse::<Token![;]>()?;
+        Ok(TypeDecl {
+            attrs,
+            vis,
+            name,
+            typestr,
+        })
+    }
+}
+
+pub(crate) struct TypeDecls(Vec<TypeDecl>);
+
+impl Parse for TypeDecls {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut decls = vec![];
+        while !input.is_empty() {
+            decls.push(input.parse()?);
+        }
+        Ok(TypeDecls(decls))
+    }
+}
+
+fn attribute(attr: &Attribute) -> String {
+    let path: Vec<String> = attr
+        .path
+        .segments
+        .iter()
+        .map(|s| s.ident.to_string())
+        .collect();
+    format!("#[{}{}]", path.join("::"), attr.tokens)
+}
+
+pub(crate) fn define_types(decls: &TypeDecls) -> syn::Result<String> {
+    let first = match decls.0.first() {
+        Some(decl) => &decl.name,
+        None => return Ok(String::new()),
+    };
+    let module = format!("_gvariant_{}", first);
+    let specs = decls
+        .0
+        .iter()
+        .map(|decl| parse_typestr(&decl.typestr))
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    let mut decls_out = String::new();
+    let mut markers = String::new();
+    for (decl, spec) in decls.0.iter().zip(&specs) {
+        // `#[cfg]` must apply to everything we generate for the declaration,
+        // everything else, such as the docs, is for the type.
+        let (cfgs, attrs): (Vec<&Attribute>, Vec<&Attribute>) =
+            decl.attrs.iter().partition(|a| a.path.is_ident("cfg"));
+        let cfgs: String = cfgs.into_iter().map(attribute).collect();
+        let attrs: String = attrs.into_iter().map(attribute).collect();
+        markers += &format!(
+            "
+    {cfgs}
+    #[derive(Copy, Clone)]
+    pub struct Marker_{name}();
+    {cfgs}
+    impl __gvariant::Marker for Marker_{name} {{
+        type Type = {marker_type};
+        const TYPESTR: &'static [u8] = b\"{typestr}\";
+    }}
+",
+            cfgs = cfgs,
+            name = decl.name,
+            marker_type = marker_type(spec),
+            typestr = spec,
+        );
+        decls_out += &format!(
+            "
+{cfgs}
+{attrs}
+{vis} type {name} = {ty};
+
+{cfgs}
+/// The [`Marker`](gvariant::Marker) for the GVariant type **{typestr}**
+#[allow(non_upper_case_globals)]
+{vis} const {name}: {module}::Marker_{name} = {module}::Marker_{name}();
+",
+            cfgs = cfgs,
+            attrs = attrs,
+            module = module,
+            typestr = spec,
+            vis = visibility(&decl.vis),
+            name = decl.name,
+            ty = rust_type(spec, "__gvariant::", Some(&format!("{}::", module))).unwrap(),
+        );
+    }
+    Ok(format!(
+        "
+#[allow(non_snake_case, non_camel_case_types, dead_code, unused_imports, clippy::unused_unit)]
+mod {module} {{
+    use __gvariant::aligned_bytes::{{
+        align_offset, empty_aligned, AlignedOffset, AlignedSlice, AsAligned, A1, A2, A4, A8,
+    }};
+    use __gvariant::casting::{{AlignOf, AllBitPatternsValid}};
+    use __gvariant::*;
+
+    {types}
+    {markers}
+}}
+{decls}",
+        module = module,
+        types = generate_types(&specs, "pub").unwrap(),
+        markers = markers,
+        decls = decls_out,
+    ))
+}
@@ -1,13 +1,18 @@
 use std::error::Error;
 use std::fmt;
 
-#[derive(Debug)]
-struct ParseError {
-    message: &'static str,
+/// An invalid type string
+///
+/// `position` is the index of the byte in the type string that the error
+/// refers to, for pointing at it in compile errors.
+#[derive(Debug, PartialEq)]
+pub(crate) struct ParseError {
+    pub(crate) message: String,
+    pub(crate) position: usize,
 }
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Parse Error")
+        write!(f, "{} (at position {})", self.message, self.position)
     }
 }
 impl Error for ParseError {}
@@ -20,6 +25,7 @@ pub(crate) enum GVariantType {
     Q,
     I,
     U,
+    H,
     X,
     T,
     D,
@@ -33,6 +39,20 @@ pub(crate) enum GVariantType {
     DictItem(Box<[GVariantType; 2]>),
 }
 
+impl GVariantType {
+    /// The basic types are the ones that can be used as dict entry keys
+    pub(crate) fn is_basic(&self) -> bool {
+        !matches!(
+            self,
+            GVariantType::V
+                | GVariantType::A(_)
+                | GVariantType::M(_)
+                | GVariantType::Tuple(_)
+                | GVariantType::DictItem(_)
+        )
+    }
+}
+
 impl fmt::Display for GVariantType {
     fn fmt(&self, out: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
@@ -42,6 +62,7 @@ impl fmt::Display for GVariantType {
             GVariantType::Q => write!(out, "q")?,
             GVariantType::I => write!(out, "i")?,
             GVariantType::U => write!(out, "u")?,
+            GVariantType::H => write!(out, "h")?,
             GVariantType::X => write!(out, "x")?,
             GVariantType::T => write!(out, "t")?,
             GVariantType::D => write!(out, "d")?,
@@ -66,98 +87,151 @@ impl fmt::Display for GVariantType {
     }
 }
 
-pub(crate) fn one(spec: &[u8]) -> Result<GVariantType, Box<dyn Error>> {
-    let mut it = spec.iter().copied();
-    let out = parse_one_type_spec(
-        it.next().ok_or(ParseError {
-            message: "Type string is empty",
-        })?,
-        &mut it,
-    )?;
-    if it.next().is_some() {
-        return Err(ParseError {
-            message: "More than one type in type string",
-        }
-        .into());
+/// Maximum nesting depth of containers, the same as GLib's
+/// `G_VARIANT_MAX_RECURSION_DEPTH`
+const MAX_DEPTH: usize = 128;
+const TOO_DEEP: &str = "type nesting is too deep";
+
+pub(crate) fn one(spec: &[u8]) -> Result<GVariantType, ParseError> {
+    let mut p = Parser { spec, pos: 0 };
+    if spec.is_empty() {
+        return Err(p.error(0, "type string is empty".into()));
+    }
+    let out = p.parse_one_type_spec(MAX_DEPTH)?;
+    if p.pos < spec.len() {
+        return Err(p.error(
+            p.pos,
+            "expected end of type string: a type string must contain exactly one type.  \
+             Use a tuple for more than one"
+                .into(),
+        ));
     }
     Ok(out)
 }
 
-fn parse_one_type_spec(
-    c: u8,
-    it: &mut impl Iterator<Item = u8>,
-) -> Result<GVariantType, Box<dyn Error>> {
-    Ok(match c {
-        b'b' => GVariantType::B,
-        b'y' => GVariantType::Y,
-        b'n' => GVariantType::N,
-        b'q' => GVariantType::Q,
-        b'i' => GVariantType::I,
-        b'u' => GVariantType::U,
-        b'x' => GVariantType::X,
-        b't' => GVariantType::T,
-        b'd' => GVariantType::D,
-
-        b's' => GVariantType::S,
-        b'o' => GVariantType::O,
-        b'g' => GVariantType::G,
-        b'v' => GVariantType::V,
-
-        b'a' => GVariantType::A(Box::new(parse_one_type_spec(
-            it.next().ok_or(ParseError {
-                message: "Array item type required",
-            })?,
-            it,
-        )?)),
-        b'm' => GVariantType::M(Box::new(parse_one_type_spec(
-            it.next().ok_or(ParseError {
-                message: "Maybe item type required",
-            })?,
-            it,
-        )?)),
-
-        b'(' => {
-            let mut subtype = vec![];
-            loop {
-                let c = it.next().ok_or(ParseError {
-                    message: "EOF when inside tuple definition",
-                })?;
-                if c == b')' {
-                    break;
+struct Parser<'a> {
+    spec: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, position: usize, message: String) -> ParseError {
+        ParseError { message, position }
+    }
+
+    fn next(&mut self, eof_message: &str) -> Result<u8, ParseError> {
+        let c = self
+            .spec
+            .get(self.pos)
+            .copied()
+            .ok_or_else(|| self.error(self.pos, eof_message.into()))?;
+        self.pos += 1;
+        Ok(c)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.spec.get(self.pos).copied()
+    }
+
+    // `depth_limit` is the number of containers we may still descend into
+    fn parse_one_type_spec(&mut self, depth_limit: usize) -> Result<GVariantType, ParseError> {
+        let start = self.pos;
+        let c = self.next("expected a type")?;
+        // Like GLib, the unit type `()` is permitted even at the depth limit
+        if matches!(c, b'a' | b'm' | b'{') && depth_limit == 0 {
+            return Err(self.error(start, TOO_DEEP.into()));
+        }
+        Ok(match c {
+            b'b' => GVariantType::B,
+            b'y' => GVariantType::Y,
+            b'n' => GVariantType::N,
+            b'q' => GVariantType::Q,
+            b'i' => GVariantType::I,
+            b'u' => GVariantType::U,
+            b'h' => GVariantType::H,
+            b'x' => GVariantType::X,
+            b't' => GVariantType::T,
+            b'd' => GVariantType::D,
+
+            b's' => GVariantType::S,
+            b'o' => GVariantType::O,
+            b'g' => GVariantType::G,
+            b'v' => GVariantType::V,
+
+            b'a' => {
+                if self.peek().is_none() {
+                    return Err(self.error(start, "array requires an element type".into()));
                 }
-                subtype.push(parse_one_type_spec(c, it)?);
+                GVariantType::A(Box::new(self.parse_one_type_spec(depth_limit - 1)?))
             }
-            GVariantType::Tuple(subtype)
-        }
-        b'{' => {
-            let a = parse_one_type_spec(
-                it.next().ok_or(ParseError {
-                    message: "EOF: Dict entry requires two types",
-                })?,
-                it,
-            )?;
-            let b = parse_one_type_spec(
-                it.next().ok_or(ParseError {
-                    message: "EOF: Dict entry requires two types",
-                })?,
-                it,
-            )?;
-            if it.next().ok_or(ParseError {
-                message: "EOF when expecting }",
-            })? != b'}'
-            {
-                return Err(Box::new(ParseError {
-                    message: "Expected }, got something else",
-                }));
+            b'm' => {
+                if self.peek().is_none() {
+                    return Err(self.error(start, "maybe requires an element type".into()));
+                }
+                GVariantType::M(Box::new(self.parse_one_type_spec(depth_limit - 1)?))
             }
-            GVariantType::DictItem(Box::new([a, b]))
-        }
-        _ => {
-            return Err(Box::new(ParseError {
-                message: "Unexpected charactor",
-            }))
-        }
-    })
+
+            b'(' => {
+                let mut subtype = vec![];
+                loop {
+                    match self.peek() {
+                        None => return Err(self.error(start, "unterminated tuple".into())),
+                        Some(b')') => {
+                            self.pos += 1;
+                            break;
+                        }
+                        Some(_) if depth_limit == 0 => {
+                            return Err(self.error(start, TOO_DEEP.into()))
+                        }
+                        Some(_) => subtype.push(self.parse_one_type_spec(depth_limit - 1)?),
+                    }
+                }
+                GVariantType::Tuple(subtype)
+            }
+            b'{' => {
+                let mut children = vec![];
+                loop {
+                    match self.peek() {
+                        None => return Err(self.error(start, "unterminated dict entry".into())),
+                        Some(b'}') if children.len() == 2 => {
+                            self.pos += 1;
+                            break;
+                        }
+                        Some(_) if children.len() == 2 => {
+                            return Err(self.error(
+                                self.pos,
+                                "expected '}': a dict entry must contain exactly two types".into(),
+                            ))
+                        }
+                        Some(b'}') => {
+                            return Err(self.error(
+                                self.pos,
+                                "a dict entry must contain exactly two types, a key and a value"
+                                    .into(),
+                            ))
+                        }
+                        Some(_) => {
+                            let child_start = self.pos;
+                            let child = self.parse_one_type_spec(depth_limit - 1)?;
+                            if children.is_empty() && !child.is_basic() {
+                                return Err(self.error(
+                                    child_start,
+                                    "dict entry key must be a basic type".into(),
+                                ));
+                            }
+                            children.push(child)
+                        }
+                    }
+                }
+                let b = children.pop().unwrap();
+                let a = children.pop().unwrap();
+                GVariantType::DictItem(Box::new([a, b]))
+            }
+            b')' => return Err(self.error(start, "unexpected ')' without matching '('".into())),
+            b'}' => return Err(self.error(start, "unexpected '}' without matching '{'".into())),
+            c => return Err(self.error(start, format!("{:?} is not a valid type", char::from(c)))),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -165,7 +239,7 @@ mod tests {
     use super::*;
     #[test]
     fn parsing_complex_type() {
-        let c = one(b"(bynqiuxtdsogvaamb(abi)m(yy)a{si})").unwrap();
+        let c = one(b"(bynqiuhxtdsogvaamb(abi)m(yy)a{si})").unwrap();
 
         use super::GVariantType::*;
         assert_eq!(
@@ -177,6 +251,7 @@ mod tests {
                 Q,
                 I,
                 U,
+                H,
                 X,
                 T,
                 D,
@@ -192,4 +267,43 @@ mod tests {
         );
         assert_eq!(one(b"i").unwrap(), I);
     }
+
+    #[test]
+    fn test_errors() {
+        let err = |spec: &[u8]| {
+            let e = one(spec).unwrap_err();
+            (e.position, e.message)
+        };
+        assert_eq!(err(b"").0, 0);
+        assert_eq!(err(b"a{s"), (1, "unterminated dict entry".into()));
+        assert_eq!(err(b"(ii"), (0, "unterminated tuple".into()));
+        assert_eq!(err(b"a(i(s)"), (1, "unterminated tuple".into()));
+        assert_eq!(err(b"ma").0, 1);
+        assert_eq!(err(b"m").0, 0);
+        assert_eq!(err(b"{sis}").0, 3);
+        assert_eq!(err(b"{s}").0, 2);
+        assert_eq!(err(b"(iz)"), (2, "'z' is not a valid type".into()));
+        assert_eq!(err(b"i)").0, 1);
+        assert_eq!(err(b"(i))").0, 3);
+        assert_eq!(err(b"ii").0, 1);
+        assert_eq!(
+            err(b"a{(ii)s}"),
+            (2, "dict entry key must be a basic type".into())
+        );
+        assert_eq!(err(b"{vs}").0, 1);
+        assert_eq!(err(b"{ahs}").0, 1);
+        assert_eq!(one(b"a{hs}").unwrap().to_string(), "a{hs}");
+
+        let deep = "a".repeat(MAX_DEPTH) + "i";
+        assert!(one(deep.as_bytes()).is_ok());
+        let too_deep = "a".repeat(MAX_DEPTH + 1) + "i";
+        assert_eq!(
+            err(too_deep.as_bytes()),
+            (MAX_DEPTH, "type nesting is too deep".into())
+        );
+        let too_deep = "(".repeat(MAX_DEPTH + 1) + "i" + &")".repeat(MAX_DEPTH + 1);
+        assert_eq!(err(too_deep.as_bytes()).0, MAX_DEPTH);
+        let unit = "a".repeat(MAX_DEPTH) + "()";
+        assert!(one(unit.as_bytes()).is_ok());
+    }
 }
@@ -43,8 +43,9 @@
 //! # Ok(()) }
 //! ```
 //!
-//! I've not yet implemented it, but it may become necessary to create an
-//! equivalent to `Vec<u8>` but for aligned memory.  We'll see how we get on.
+//! [`AlignedVec`] is the aligned equivalent of `Vec<u8>`, for when you don't
+//! know how big the data is going to be in advance, for example when
+//! serialising.
 //!
 //! #### Efficiency of statically known alignment
 //!
@@ -85,14 +86,11 @@ use alloc::{
     borrow::{Cow, ToOwned},
     boxed::Box,
 };
+use core::fmt::Debug;
 use core::ops::{
     Deref, DerefMut, Index, IndexMut, Range, RangeFrom, RangeFull, RangeInclusive, RangeTo,
     RangeToInclusive,
 };
-use core::{
-    cmp::{max, min},
-    fmt::Debug,
-};
 #[cfg(feature = "std")]
 use std::io::IoSliceMut;
 
@@ -102,14 +100,28 @@ use std::io::IoSliceMut;
 /// rust stable channel yet.  This is the type-level equivalent of
 /// `enum Alignment {A1 = 1, A2 = 2, A4 = 4, A8 = 8}`.
 ///
+/// # Safety
+///
 /// This is unsafe because it must only be implemented for zero-sized types.  Do
 /// not implement this trait.  The implementations in this module should be the
 /// only implementations.
 pub unsafe trait Alignment: Debug {
     const ALIGNMENT: usize;
+
+    /// The larger of this alignment and `B`, for the alignment of structures
+    /// from the alignments of their fields
+    type Max<B: Alignment>: Alignment;
+    /// The larger of this alignment and [`A2`]
+    #[doc(hidden)]
+    type Max2: Alignment;
+    /// The larger of this alignment and [`A4`]
+    #[doc(hidden)]
+    type Max4: Alignment;
 }
 /// This is a promise that the type is aligned as described by A.
 ///
+/// # Safety
+///
 /// It is unsafe because safe code must be able to assume the given alignment
 /// for e.g. pointer casts.
 ///
@@ -132,6 +144,9 @@ pub unsafe trait AlignedTo<A: Alignment>: Alignment {}
 pub struct A1;
 unsafe impl Alignment for A1 {
     const ALIGNMENT: usize = 1;
+    type Max<B: Alignment> = B;
+    type Max2 = A2;
+    type Max4 = A4;
 }
 unsafe impl AlignedTo<A1> for A1 {}
 
@@ -141,6 +156,9 @@ unsafe impl AlignedTo<A1> for A1 {}
 pub struct A2;
 unsafe impl Alignment for A2 {
     const ALIGNMENT: usize = 2;
+    type Max<B: Alignment> = B::Max2;
+    type Max2 = A2;
+    type Max4 = A4;
 }
 unsafe impl AlignedTo<A1> for A2 {}
 unsafe impl AlignedTo<A2> for A2 {}
@@ -151,6 +169,9 @@ unsafe impl AlignedTo<A2> for A2 {}
 pub struct A4;
 unsafe impl Alignment for A4 {
     const ALIGNMENT: usize = 4;
+    type Max<B: Alignment> = B::Max4;
+    type Max2 = A4;
+    type Max4 = A4;
 }
 unsafe impl AlignedTo<A1> for A4 {}
 unsafe impl AlignedTo<A2> for A4 {}
@@ -162,6 +183,9 @@ unsafe impl AlignedTo<A4> for A4 {}
 pub struct A8;
 unsafe impl Alignment for A8 {
     const ALIGNMENT: usize = 8;
+    type Max<B: Alignment> = A8;
+    type Max2 = A8;
+    type Max4 = A8;
 }
 unsafe impl AlignedTo<A1> for A8 {}
 unsafe impl AlignedTo<A2> for A8 {}
@@ -174,7 +198,6 @@ unsafe impl AlignedTo<A8> for A8 {}
 /// alignment as an argument to a function.  For example:
 ///
 ///     # use gvariant::aligned_bytes::{A2, AsAligned};
-
 ///     fn foo(data : &impl AsAligned<A2>) {
 ///         let data = data.as_aligned();
 ///     # }
@@ -258,6 +281,14 @@ pub fn copy_to_align<'a, A: Alignment>(data: &'a [u8]) -> Cow<'a, AlignedSlice<A
 /// Data is initialised to all 0.
 #[cfg(feature = "alloc")]
 pub fn alloc_aligned<A: Alignment>(size: usize) -> Box<AlignedSlice<A>> {
+    if size == 0 {
+        // Allocating 0 bytes is undefined behaviour.  Boxes of size 0 don't
+        // allocate, they just need a pointer that's suitably aligned:
+        unsafe {
+            let bs = core::slice::from_raw_parts_mut(dangling::<A>().as_ptr(), 0);
+            return Box::from_raw(to_alignedslice_unchecked_mut(bs));
+        }
+    }
     let layout = alloc::alloc::Layout::from_size_align(size, A::ALIGNMENT).unwrap();
     unsafe {
         // This is safe because:
@@ -287,41 +318,268 @@ pub fn read_to_slice<A: Alignment, R: std::io::Read>(
     mut r: R,
     size_hint: Option<usize>,
 ) -> std::io::Result<Box<AlignedSlice<A>>> {
-    let mut bytes_read = 0;
     let mut eof_byte = [0u8];
-    let mut buf = alloc_aligned(size_hint.unwrap_or(4000));
+    let mut buf = AlignedVec::<A>::with_capacity(size_hint.unwrap_or(4000));
 
     loop {
+        let bytes_read = buf.len();
+        buf.resize(buf.capacity(), 0);
         let mut ios = [
-            IoSliceMut::new(&mut buf.as_mut()[bytes_read..]),
+            IoSliceMut::new(&mut buf[bytes_read..]),
             IoSliceMut::new(&mut eof_byte),
         ];
         let this_read = r.read_vectored(&mut ios)?;
-        bytes_read += this_read;
         if this_read == 0 {
             // EOF
-            return Ok(realloc(buf, bytes_read));
+            buf.truncate(bytes_read);
+            return Ok(buf.into_boxed_slice());
         }
-        if bytes_read > buf.len() {
-            // Ran out of space
-            buf = realloc(buf, max(bytes_read * 2, 4000));
-            buf[bytes_read - 1] = eof_byte[0];
+        if bytes_read + this_read > buf.len() {
+            // Ran out of space.  AlignedVec takes care of growing the buffer
+            buf.push(eof_byte[0]);
+        } else {
+            buf.truncate(bytes_read + this_read);
         }
     }
 }
 
-fn realloc<A: Alignment>(s: Box<AlignedSlice<A>>, new_size: usize) -> Box<AlignedSlice<A>> {
-    if s.len() == new_size {
-        s
-    } else {
-        // TODO: Optimise to use alloc::realloc
-        let mut new_buf = alloc_aligned(new_size);
-        let up_to = min(new_size, s.len());
-        new_buf.as_mut()[..up_to].copy_from_slice(s[..up_to].as_ref());
-        new_buf
+/// A growable [`AlignedSlice`], the aligned equivalent of `Vec<u8>`
+///
+/// Dereferences to an [`AlignedSlice<A>`][AlignedSlice] so the data can be
+/// cast at any point, and can be turned into a `Box<AlignedSlice<A>>` with
+/// [`AlignedVec::into_boxed_slice`] without copying.  It implements
+/// [`crate::write::Write`] (and `std::io::Write` with the `std` feature) so
+/// it makes a good destination for serialisation:
+///
+/// ```rust
+/// # use gvariant::aligned_bytes::{AlignedVec, A8};
+/// # use gvariant::{gv, Marker};
+/// let mut buf = AlignedVec::<A8>::new();
+/// gv!("at").write([1u64, 2, 3], &mut buf)?;
+/// assert_eq!(gv!("at").cast(&buf), &[1, 2, 3]);
+/// # Ok::<(), gvariant::write::Error>(())
+/// ```
+#[cfg(feature = "alloc")]
+pub struct AlignedVec<A: Alignment> {
+    ptr: core::ptr::NonNull<u8>,
+    len: usize,
+    cap: usize,
+    alignment: core::marker::PhantomData<A>,
+}
+
+// AlignedVec owns its data just like Vec<u8> does
+#[cfg(feature = "alloc")]
+unsafe impl<A: Alignment> Send for AlignedVec<A> {}
+#[cfg(feature = "alloc")]
+unsafe impl<A: Alignment> Sync for AlignedVec<A> {}
+
+#[cfg(feature = "alloc")]
+impl<A: Alignment> AlignedVec<A> {
+    /// Create an empty `AlignedVec`.  This doesn't allocate.
+    pub fn new() -> Self {
+        AlignedVec {
+            ptr: dangling::<A>(),
+            len: 0,
+            cap: 0,
+            alignment: core::marker::PhantomData,
+        }
+    }
+    /// Create an empty `AlignedVec` with space for at least `capacity` bytes
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut v = Self::new();
+        v.set_capacity(capacity);
+        v
+    }
+    /// The number of bytes that can be held without reallocating
+    pub fn capacity(&self) -> usize {
+        self.cap
+    }
+    /// Make space for at least `additional` more bytes
+    ///
+    /// Like `Vec::reserve` this may reserve more space than requested to
+    /// avoid frequent reallocations.
+    pub fn reserve(&mut self, additional: usize) {
+        let needed = self.len.checked_add(additional).expect("capacity overflow");
+        if needed > self.cap {
+            self.set_capacity(needed.max(self.cap * 2).max(64));
+        }
+    }
+    /// Append `data` to the end
+    pub fn extend_from_slice(&mut self, data: &[u8]) {
+        self.reserve(data.len());
+        unsafe {
+            // This is safe because we've just reserved space for data, and
+            // data can't overlap with our own allocation as we hold a &mut
+            core::ptr::copy_nonoverlapping(
+                data.as_ptr(),
+                self.ptr.as_ptr().add(self.len),
+                data.len(),
+            );
+        }
+        self.len += data.len();
+    }
+    /// Append a single byte to the end
+    pub fn push(&mut self, value: u8) {
+        self.extend_from_slice(&[value])
+    }
+    /// Resize in place, filling any new space with `value`
+    pub fn resize(&mut self, new_len: usize, value: u8) {
+        if new_len > self.len {
+            self.reserve(new_len - self.len);
+            unsafe {
+                core::ptr::write_bytes(self.ptr.as_ptr().add(self.len), value, new_len - self.len);
+            }
+        }
+        self.len = new_len;
+    }
+    /// Shorten to `len` bytes.  Has no effect if we're already shorter.
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+    /// Remove all the data, keeping the allocation
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+    /// Convert into a boxed slice
+    ///
+    /// Any spare capacity is released with `realloc`, so usually the data
+    /// isn't copied.
+    pub fn into_boxed_slice(mut self) -> Box<AlignedSlice<A>> {
+        // A Box must be deallocated with the same layout it was allocated
+        // with, so we get rid of any spare capacity first:
+        self.set_capacity(self.len);
+        let this = core::mem::ManuallyDrop::new(self);
+        unsafe {
+            // This is safe because we own the allocation, which is exactly
+            // this.len bytes long (or is dangling if this.len == 0), all of
+            // which have been initialised and aligned to A::ALIGNMENT
+            let bs = core::slice::from_raw_parts_mut(this.ptr.as_ptr(), this.len);
+            Box::from_raw(to_alignedslice_unchecked_mut(bs))
+        }
+    }
+    fn set_capacity(&mut self, cap: usize) {
+        debug_assert!(cap >= self.len);
+        if cap == self.cap {
+            return;
+        }
+        let ptr = unsafe {
+            // This is safe because we only ever allocate with the layout
+            // returned by self::layout, and never allocate 0 bytes
+            if cap == 0 {
+                alloc::alloc::dealloc(self.ptr.as_ptr(), Self::layout(self.cap));
+                dangling::<A>().as_ptr()
+            } else if self.cap == 0 {
+                alloc::alloc::alloc(Self::layout(cap))
+            } else {
+                alloc::alloc::realloc(self.ptr.as_ptr(), Self::layout(self.cap), cap)
+            }
+        };
+        self.ptr = core::ptr::NonNull::new(ptr)
+            .unwrap_or_else(|| alloc::alloc::handle_alloc_error(Self::layout(cap)));
+        self.cap = cap;
+    }
+    fn layout(cap: usize) -> alloc::alloc::Layout {
+        alloc::alloc::Layout::from_size_align(cap, A::ALIGNMENT).expect("capacity overflow")
     }
 }
 
+#[cfg(feature = "alloc")]
+impl<A: Alignment> Drop for AlignedVec<A> {
+    fn drop(&mut self) {
+        if self.cap != 0 {
+            unsafe { alloc::alloc::dealloc(self.ptr.as_ptr(), Self::layout(self.cap)) }
+        }
+    }
+}
+#[cfg(feature = "alloc")]
+impl<A: Alignment> Deref for AlignedVec<A> {
+    type Target = AlignedSlice<A>;
+    fn deref(&self) -> &Self::Target {
+        // The first self.len bytes are initialised and self.ptr is aligned
+        unsafe {
+            to_alignedslice_unchecked(core::slice::from_raw_parts(self.ptr.as_ptr(), self.len))
+        }
+    }
+}
+#[cfg(feature = "alloc")]
+impl<A: Alignment> DerefMut for AlignedVec<A> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        unsafe {
+            to_alignedslice_unchecked_mut(core::slice::from_raw_parts_mut(
+                self.ptr.as_ptr(),
+                self.len,
+            ))
+        }
+    }
+}
+#[cfg(feature = "alloc")]
+impl<A: Alignment> Default for AlignedVec<A> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+#[cfg(feature = "alloc")]
+impl<A: Alignment> Clone for AlignedVec<A> {
+    fn clone(&self) -> Self {
+        AlignedVec::from(&**self)
+    }
+}
+#[cfg(feature = "alloc")]
+impl<A: Alignment> Debug for AlignedVec<A> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+#[cfg(feature = "alloc")]
+impl<A: Alignment> PartialEq for AlignedVec<A> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+#[cfg(feature = "alloc")]
+impl<A: Alignment> Eq for AlignedVec<A> {}
+#[cfg(feature = "alloc")]
+impl<A: Alignment> From<&AlignedSlice<A>> for AlignedVec<A> {
+    fn from(data: &AlignedSlice<A>) -> Self {
+        let mut v = Self::with_capacity(data.len());
+        v.extend_from_slice(data);
+        v
+    }
+}
+#[cfg(feature = "alloc")]
+impl<A: Alignment> From<AlignedVec<A>> for Box<AlignedSlice<A>> {
+    fn from(v: AlignedVec<A>) -> Self {
+        v.into_boxed_slice()
+    }
+}
+#[cfg(feature = "alloc")]
+impl<A: Alignment> crate::write::Write for AlignedVec<A> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), crate::write::Error> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+}
+#[cfg(feature = "std")]
+impl<A: Alignment> std::io::Write for AlignedVec<A> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn write_all(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+// A non-null pointer with the given alignment, for empty allocations
+#[cfg(feature = "alloc")]
+fn dangling<A: Alignment>() -> core::ptr::NonNull<u8> {
+    core::ptr::NonNull::new(A::ALIGNMENT as *mut u8).unwrap()
+}
+
 impl<A: Alignment> AlignedSlice<A> {
     pub fn split_at(&self, mid: usize) -> (&AlignedSlice<A>, &[u8]) {
         let (before, after) = self.data.split_at(mid);
@@ -480,7 +738,7 @@ impl<A: Alignment> Index<Range<usize>> for AlignedSlice<A> {
     fn index(&self, index: Range<usize>) -> &Self::Output {
         // Truncating the slice on the left can affect the alignment, so we
         // return an unaligned slice here
-        &self.data[index].as_aligned()
+        self.data[index].as_aligned()
     }
 }
 impl<A: Alignment> Index<RangeInclusive<usize>> for AlignedSlice<A> {
@@ -488,7 +746,7 @@ impl<A: Alignment> Index<RangeInclusive<usize>> for AlignedSlice<A> {
     fn index(&self, index: RangeInclusive<usize>) -> &Self::Output {
         // Truncating the slice on the left can affect the alignment, so we
         // return an unaligned slice here
-        &self.data[index].as_aligned()
+        self.data[index].as_aligned()
     }
 }
 impl<A: Alignment> Index<RangeFrom<usize>> for AlignedSlice<A> {
@@ -496,13 +754,13 @@ impl<A: Alignment> Index<RangeFrom<usize>> for AlignedSlice<A> {
     fn index(&self, index: RangeFrom<usize>) -> &Self::Output {
         // Truncating the slice on the left can affect the alignment, so we
         // return an unaligned slice here
-        &self.data[index].as_aligned()
+        self.data[index].as_aligned()
     }
 }
 impl<A: Alignment> Index<RangeFull> for AlignedSlice<A> {
     type Output = AlignedSlice<A>;
     fn index(&self, _: RangeFull) -> &Self::Output {
-        &self
+        self
     }
 }
 
@@ -608,16 +866,14 @@ impl<A: Alignment> IndexMut<RangeFrom<AlignedOffset<A>>> for AlignedSlice<A> {
     }
 }
 
-unsafe fn to_alignedslice_unchecked<'a, A: Alignment>(value: &'a [u8]) -> &'a AlignedSlice<A> {
+pub(crate) unsafe fn to_alignedslice_unchecked<A: Alignment>(value: &[u8]) -> &AlignedSlice<A> {
     debug_assert!(is_aligned_to::<A>(value));
     #[allow(unused_unsafe)]
     unsafe {
         &*(value as *const [u8] as *const AlignedSlice<A>)
     }
 }
-unsafe fn to_alignedslice_unchecked_mut<'a, A: Alignment>(
-    value: &'a mut [u8],
-) -> &'a mut AlignedSlice<A> {
+unsafe fn to_alignedslice_unchecked_mut<A: Alignment>(value: &mut [u8]) -> &mut AlignedSlice<A> {
     debug_assert!(is_aligned_to::<A>(value));
     #[allow(unused_unsafe)]
     unsafe {
@@ -668,7 +924,7 @@ impl<A: Alignment> AsMut<[u8]> for AlignedSlice<A> {
     }
 }
 
-fn align_bytes<'a, A: Alignment>(value: &'a [u8]) -> &'a AlignedSlice<A> {
+fn align_bytes<A: Alignment>(value: &[u8]) -> &AlignedSlice<A> {
     let p = value as *const [u8] as *const u8 as usize;
     let offset = p.wrapping_neg() & (A::ALIGNMENT - 1);
     value[offset..].try_as_aligned().unwrap()
@@ -682,16 +938,69 @@ pub fn empty_aligned<A: Alignment>() -> &'static AlignedSlice<A> {
     &align_bytes(b"        ")[..0]
 }
 
+/// The size of the largest value that [`zeroed`] can return without
+/// allocating.
+pub(crate) const MAX_ZEROED: usize = 4096;
+
+/// Get a static reference to `len` zero bytes with the given alignment.
+///
+/// Fixed size structures use this for their default values.  Up to
+/// [`MAX_ZEROED`] bytes come from a static buffer.  Without the `alloc` feature
+/// this panics for anything larger.
+pub(crate) fn zeroed<A: Alignment>(len: usize) -> &'static AlignedSlice<A> {
+    static ZEROES: [u8; MAX_ZEROED + 7] = [0; MAX_ZEROED + 7];
+    if len <= MAX_ZEROED {
+        return &align_bytes(&ZEROES)[..len];
+    }
+    #[cfg(feature = "alloc")]
+    return &align_bytes(large_zeroed(len))[..len];
+    #[cfg(not(feature = "alloc"))]
+    panic!(
+        "Can't get more than {} zero bytes without allocating",
+        MAX_ZEROED
+    );
+}
+
+/// At least `len` zero bytes, aligned to 8.
+///
+/// The memory is leaked, as there's no way of knowing when the references we
+/// return are dropped.  To bound this we keep the largest buffer that we've
+/// allocated, so this only allocates when it's called with a larger `len` than
+/// before.  That happens at most once for each size of fixed size structure in
+/// the program.
+#[cfg(feature = "alloc")]
+fn large_zeroed(len: usize) -> &'static AlignedSlice<A8> {
+    use core::sync::atomic::{AtomicPtr, Ordering};
+    static LARGEST: AtomicPtr<&'static AlignedSlice<A8>> = AtomicPtr::new(core::ptr::null_mut());
+
+    let largest = LARGEST.load(Ordering::Acquire);
+    // This is safe because LARGEST only ever holds pointers from the
+    // `Box::into_raw` below, which are never freed once they've been stored.
+    if let Some(zeroes) = unsafe { largest.as_ref() } {
+        if zeroes.len() >= len {
+            return zeroes;
+        }
+    }
+    let zeroes: &'static AlignedSlice<A8> = Box::leak(alloc_aligned(len));
+    let new = Box::into_raw(Box::new(zeroes));
+    if LARGEST
+        .compare_exchange(largest, new, Ordering::AcqRel, Ordering::Acquire)
+        .is_err()
+    {
+        // Another thread got there first.  Our buffer is still fine to use,
+        // it's just not kept for next time.
+        drop(unsafe { Box::from_raw(new) });
+    }
+    zeroes
+}
+
 #[cfg(test)]
 mod test {
-    use super::{read_to_slice, AlignedSlice, A8};
+    use super::{read_to_slice, AlignedSlice, AlignedVec, A2, A8};
 
     #[test]
     fn test_read_to_slice() {
-        let mut d: Vec<u8> = vec![0; 16384];
-        for x in 0..16384 {
-            d[x] = (x % 256) as u8;
-        }
+        let d: Vec<u8> = (0..16384).map(|x| (x % 256) as u8).collect();
 
         for size_hint in &[
             Some(11),
@@ -715,4 +1024,41 @@ mod test {
             assert_eq!(&**s, d.as_slice());
         }
     }
+
+    #[test]
+    fn test_aligned_vec() {
+        let mut v = AlignedVec::<A8>::new();
+        assert_eq!(v.capacity(), 0);
+        assert_eq!(**v, *b"");
+        for x in 0..1000u32 {
+            v.extend_from_slice(&x.to_le_bytes());
+            assert_eq!(v.as_ptr() as usize % 8, 0);
+        }
+        assert_eq!(v.len(), 4000);
+        assert!(v.capacity() >= 4000);
+        assert_eq!(*v[3996..], 999u32.to_le_bytes());
+
+        v.truncate(5);
+        v.push(7);
+        v.resize(8, 9);
+        assert_eq!(**v, [0, 0, 0, 0, 1, 7, 9, 9]);
+        assert_eq!(v.clone(), v);
+
+        let b: Box<AlignedSlice<A8>> = v.into_boxed_slice();
+        assert_eq!(**b, [0, 0, 0, 0, 1, 7, 9, 9]);
+        assert_eq!(b.as_ptr() as usize % 8, 0);
+
+        let mut v = AlignedVec::<A2>::with_capacity(10);
+        v.clear();
+        let b: Box<AlignedSlice<A2>> = v.into();
+        assert_eq!(**b, *b"");
+    }
+
+    #[test]
+    fn test_aligned_vec_io_write() {
+        use std::io::Write;
+        let mut v = AlignedVec::<A8>::default();
+        write!(v, "hello {}", 5).unwrap();
+        assert_eq!(**v, *b"hello 5");
+    }
 }
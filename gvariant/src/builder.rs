@@ -0,0 +1,536 @@
+//! Build up container values one child at a time
+//!
+//! [`SerializeTo`] needs the whole value up front.  That's awkward if you're
+//! generating a large array as you go - for example listing the files in a
+//! directory tree as **a(say)** - or if you want to write a container inside
+//! a container without building either in memory first.  The builders in this
+//! module are the equivalent of GLib's `GVariantBuilder`, but statically
+//! typed.  You open a builder for a container type with [`Marker::builder`],
+//! add its children one at a time, and finish it.  The builder takes care of
+//! padding and framing offsets for you:
+//!
+//! ```rust
+//! # use gvariant::{aligned_bytes::copy_to_align, gv, Marker, Structure};
+//! let mut out = vec![];
+//! let mut files = gv!("a(say)").builder(&mut out);
+//! files.push(&("hello.txt", b"Hello"))?;
+//!
+//! // Children that are containers themselves can be built up too:
+//! let mut contents = files.open()?.field("big.bin")?.open()?;
+//! for x in 0..1000u32 {
+//!     contents.push((x % 256) as u8)?;
+//! }
+//! contents.finish()?.finish()?;
+//! files.finish()?;
+//!
+//! let data = copy_to_align(&out);
+//! let files = gv!("a(say)").cast(data.as_ref());
+//! assert_eq!(files.len(), 2);
+//! let (name, contents) = files[1].to_tuple();
+//! assert_eq!((name.to_str(), contents.len()), ("big.bin", 1000));
+//! # Ok::<(), gvariant::write::Error>(())
+//! ```
+//!
+//! The builder you get depends on the type of the container:
+//!
+//! * Arrays **aX** use an [`ArrayBuilder`].  Children are added with
+//!   [`ArrayBuilder::push`] and [`ArrayBuilder::open`].
+//! * Structures **(XYZ)** and dictionary entries **{KV}** use a
+//!   [`StructureBuilder`].  Each field must be provided in order, so the type
+//!   of the builder changes as each field is added.  It can only be finished
+//!   once all the fields have been added.
+//! * Maybes **mX** use a [`MaybeBuilder`].
+//! * Variants **v** use a [`VariantBuilder`].  The type of the contents is
+//!   chosen with a [`Marker`] when adding them.
+//!
+//! Builders write to a [`write::Write`] as they go, so wrap a
+//! `std::io::Write` in [`write::IoWrite`] to write straight to a file.
+//! Builders for containers within containers write through the builder of
+//! the enclosing container.  `finish` returns the parent so you can carry on
+//! adding to it.
+//!
+//! Builders must be finished.  A builder that is dropped without being
+//! finished leaves its output incomplete.
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use crate::{
+    aligned_bytes::Alignment, casting::AlignOf, framing_offset_size, write, write_offset,
+    write_padding, Cast, Marker, MaybeFixedSize, MaybeNonFixedSize, NonFixedWidthArray,
+    SerializeTo, Variant,
+};
+
+/// Where a builder writes its output
+///
+/// This is implemented for every [`write::Write`], and for the builders
+/// themselves so that containers can be nested.  You shouldn't need to
+/// implement it yourself.
+pub trait Sink {
+    /// Append `buf` to the output
+    fn append(&mut self, buf: &[u8]) -> Result<(), write::Error>;
+    /// Called by a builder for a child container when it's finished
+    fn end_child(&mut self) {}
+}
+
+impl<W: write::Write> Sink for W {
+    fn append(&mut self, buf: &[u8]) -> Result<(), write::Error> {
+        self.write_all(buf)
+    }
+}
+
+/// Container types that can be built up one child at a time
+///
+/// This is implemented for arrays, structures, dictionary entries, maybes and
+/// variants.
+pub trait Container: Cast {
+    /// The builder for this type, writing to `P`
+    type Builder<P: Sink>;
+    /// Start building a value of this type by writing to `parent`
+    fn builder<P: Sink>(parent: P) -> Self::Builder<P>;
+}
+
+/// Array types **aX**, implemented for `[T]` and [`NonFixedWidthArray<T>`]
+pub trait Array: Cast {
+    /// The type of the array elements
+    type Element: Cast + ?Sized;
+    /// Arrays of fixed size elements don't have framing offsets
+    const FIXED_SIZE_ELEMENTS: bool;
+}
+impl<T: Cast + Copy> Array for [T] {
+    type Element = T;
+    const FIXED_SIZE_ELEMENTS: bool = true;
+}
+impl<T: Cast + ?Sized> Array for NonFixedWidthArray<T> {
+    type Element = T;
+    const FIXED_SIZE_ELEMENTS: bool = false;
+}
+
+/// Maybe types **mX**, implemented for [`MaybeFixedSize<T>`] and
+/// [`MaybeNonFixedSize<T>`]
+pub trait Maybe: Cast {
+    /// The type of the contents
+    type Element: Cast + ?Sized;
+    /// Maybes of non-fixed size types have a `\0` appended to the contents
+    const FIXED_SIZE_ELEMENT: bool;
+}
+impl<T: Cast> Maybe for MaybeFixedSize<T> {
+    type Element = T;
+    const FIXED_SIZE_ELEMENT: bool = true;
+}
+impl<T: Cast + ?Sized> Maybe for MaybeNonFixedSize<T> {
+    type Element = T;
+    const FIXED_SIZE_ELEMENT: bool = false;
+}
+
+impl<T: Cast + Copy> Container for [T] {
+    type Builder<P: Sink> = ArrayBuilder<Self, P>;
+    fn builder<P: Sink>(parent: P) -> Self::Builder<P> {
+        ArrayBuilder::new(parent)
+    }
+}
+impl<T: Cast + ?Sized> Container for NonFixedWidthArray<T> {
+    type Builder<P: Sink> = ArrayBuilder<Self, P>;
+    fn builder<P: Sink>(parent: P) -> Self::Builder<P> {
+        ArrayBuilder::new(parent)
+    }
+}
+impl<T: Cast> Container for MaybeFixedSize<T> {
+    type Builder<P: Sink> = MaybeBuilder<Self, P>;
+    fn builder<P: Sink>(parent: P) -> Self::Builder<P> {
+        MaybeBuilder::new(parent)
+    }
+}
+impl<T: Cast + ?Sized> Container for MaybeNonFixedSize<T> {
+    type Builder<P: Sink> = MaybeBuilder<Self, P>;
+    fn builder<P: Sink>(parent: P) -> Self::Builder<P> {
+        MaybeBuilder::new(parent)
+    }
+}
+impl Container for Variant {
+    type Builder<P: Sink> = VariantBuilder<P>;
+    fn builder<P: Sink>(parent: P) -> Self::Builder<P> {
+        VariantBuilder {
+            out: Out::new(parent),
+            typestr: None,
+        }
+    }
+}
+
+// Keeps track of how much has been written since the start of the container,
+// for padding and framing offsets
+#[derive(Debug)]
+struct Out<P> {
+    parent: P,
+    pos: usize,
+}
+impl<P: Sink> Out<P> {
+    fn new(parent: P) -> Self {
+        Out { parent, pos: 0 }
+    }
+    fn pad<A: Alignment>(&mut self) -> Result<(), write::Error> {
+        write_padding::<A, _>(self.pos, self)?;
+        Ok(())
+    }
+    fn finish(mut self) -> P {
+        self.parent.end_child();
+        self.parent
+    }
+}
+impl<P: Sink> write::Write for Out<P> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), write::Error> {
+        self.parent.append(buf)?;
+        self.pos += buf.len();
+        Ok(())
+    }
+}
+
+/// Builds an array **aX** one element at a time
+///
+/// Created with [`Marker::builder`] or by opening an array inside another
+/// container.  See the [module documentation](self).
+#[must_use = "the array is incomplete until finish is called"]
+#[derive(Debug)]
+pub struct ArrayBuilder<A: Array + ?Sized, P> {
+    out: Out<P>,
+    offsets: Vec<usize>,
+    array: PhantomData<A>,
+}
+
+impl<A: Array + ?Sized, P: Sink> ArrayBuilder<A, P> {
+    fn new(parent: P) -> Self {
+        ArrayBuilder {
+            out: Out::new(parent),
+            offsets: Vec::new(),
+            array: PhantomData,
+        }
+    }
+    /// Append `value` to the array
+    pub fn push(&mut self, value: impl SerializeTo<A::Element>) -> Result<(), write::Error> {
+        self.out.pad::<<A::Element as AlignOf>::AlignOf>()?;
+        value.serialize(&mut self.out)?;
+        self.end_element();
+        Ok(())
+    }
+    /// Start building the next element, for arrays of containers
+    ///
+    /// The element builder writes through this one, so it must be finished
+    /// before this builder can be used again.
+    pub fn open(&mut self) -> Result<<A::Element as Container>::Builder<&mut Self>, write::Error>
+    where
+        A::Element: Container,
+    {
+        self.out.pad::<<A::Element as AlignOf>::AlignOf>()?;
+        Ok(A::Element::builder(self))
+    }
+    /// The number of bytes written so far
+    pub fn position(&self) -> usize {
+        self.out.pos
+    }
+    /// Write the framing offsets, if any, returning the parent
+    pub fn finish(mut self) -> Result<P, write::Error> {
+        if !A::FIXED_SIZE_ELEMENTS {
+            let size = framing_offset_size(self.out.pos, self.offsets.len());
+            for offset in core::mem::take(&mut self.offsets) {
+                write_offset(offset, size, &mut self.out)?;
+            }
+        }
+        Ok(self.out.finish())
+    }
+    fn end_element(&mut self) {
+        if !A::FIXED_SIZE_ELEMENTS {
+            self.offsets.push(self.out.pos);
+        }
+    }
+}
+
+impl<A: Array + ?Sized, P: Sink> Sink for &mut ArrayBuilder<A, P> {
+    fn append(&mut self, buf: &[u8]) -> Result<(), write::Error> {
+        write::Write::write_all(&mut self.out, buf)
+    }
+    fn end_child(&mut self) {
+        self.end_element()
+    }
+}
+
+/// A field of a structure with type `T`, for [`StructureBuilder`]
+#[derive(Debug)]
+pub struct Field<T: ?Sized>(PhantomData<T>);
+
+/// The fields of a structure that have yet to be added to a
+/// [`StructureBuilder`]
+///
+/// This is a list like `(Field<A>, (Field<B>, ()))` for a structure **(as)**
+/// where `A` and `B` are the types of the fields.  It's implemented by the
+/// code generated by [`gv!`][crate::gv].
+pub trait FieldList {
+    /// True if there are no fields left
+    const IS_EMPTY: bool;
+}
+impl FieldList for () {
+    const IS_EMPTY: bool = true;
+}
+impl<T: ?Sized, Rest: FieldList> FieldList for (Field<T>, Rest) {
+    const IS_EMPTY: bool = false;
+}
+
+/// Builds a structure **(XYZ)** or dictionary entry **{KV}** one field at a
+/// time
+///
+/// `F` is the list of fields that still need to be added.  Each field is
+/// added with either [`field`][StructureBuilder::field] or
+/// [`open`][StructureBuilder::open], which return a builder for the rest of
+/// the fields.  Once all the fields have been added the builder can be
+/// finished:
+///
+///     # use gvariant::{gv, Marker};
+///     let mut out = vec![];
+///     gv!("(sai)")
+///         .builder(&mut out)
+///         .field("numbers")?
+///         .field([1, 2, 3])?
+///         .finish()?;
+///     assert_eq!(out, gv!("(sai)").serialize_to_vec(&("numbers", [1, 2, 3])));
+///     # Ok::<(), gvariant::write::Error>(())
+#[must_use = "the structure is incomplete until finish is called"]
+#[derive(Debug)]
+pub struct StructureBuilder<S: ?Sized, P, F> {
+    out: Out<P>,
+    offsets: Vec<usize>,
+    // The size of the fields so far is fixed, so we need padding at the end
+    fixed_size: bool,
+    // The field being built by a child builder needs a framing offset
+    pending_offset: bool,
+    structure: PhantomData<S>,
+    fields: PhantomData<F>,
+}
+
+impl<S: Cast + ?Sized, P: Sink, F: FieldList> StructureBuilder<S, P, F> {
+    /// Start building a structure by writing to `parent`
+    ///
+    /// Not really pub, just for use by the code generated by our macro.
+    #[doc(hidden)]
+    pub fn new(parent: P) -> Self {
+        StructureBuilder {
+            out: Out::new(parent),
+            offsets: Vec::new(),
+            fixed_size: true,
+            pending_offset: false,
+            structure: PhantomData,
+            fields: PhantomData,
+        }
+    }
+    /// The number of bytes written so far
+    pub fn position(&self) -> usize {
+        self.out.pos
+    }
+    fn next<Rest>(self) -> StructureBuilder<S, P, Rest> {
+        StructureBuilder {
+            out: self.out,
+            offsets: self.offsets,
+            fixed_size: self.fixed_size,
+            pending_offset: self.pending_offset,
+            structure: PhantomData,
+            fields: PhantomData,
+        }
+    }
+}
+
+impl<S, P, T, Rest> StructureBuilder<S, P, (Field<T>, Rest)>
+where
+    S: Cast + ?Sized,
+    P: Sink,
+    T: Cast + ?Sized,
+    Rest: FieldList,
+{
+    /// Add the next field
+    pub fn field(
+        mut self,
+        value: impl SerializeTo<T>,
+    ) -> Result<StructureBuilder<S, P, Rest>, write::Error> {
+        self.out.pad::<T::AlignOf>()?;
+        value.serialize(&mut self.out)?;
+        self.start_field();
+        let mut next = self.next();
+        next.end_child();
+        Ok(next)
+    }
+    /// Start building the next field, for fields that are containers
+    ///
+    /// The returned builder's `finish` gives back the builder for the rest of
+    /// the fields.
+    pub fn open(
+        mut self,
+    ) -> Result<<T as Container>::Builder<StructureBuilder<S, P, Rest>>, write::Error>
+    where
+        T: Container,
+    {
+        self.out.pad::<T::AlignOf>()?;
+        self.start_field();
+        Ok(T::builder(self.next()))
+    }
+    fn start_field(&mut self) {
+        // After all of the items have been added, a framing offset is appended,
+        // in reverse order, for each non-fixed-sized item that is not the last
+        // item in the structure.
+        let fixed = T::FIXED_SIZE.is_some();
+        self.fixed_size &= fixed;
+        self.pending_offset = !fixed && !Rest::IS_EMPTY;
+    }
+}
+
+impl<S: Cast + ?Sized, P: Sink> StructureBuilder<S, P, ()> {
+    /// Write the padding or framing offsets, returning the parent
+    pub fn finish(mut self) -> Result<P, write::Error> {
+        if self.fixed_size {
+            if self.out.pos == 0 {
+                // The unit structure () is 1 byte long
+                write::Write::write_all(&mut self.out, b"\0")?;
+            } else {
+                // Fixed size structures are padded to their alignment
+                self.out.pad::<S::AlignOf>()?;
+            }
+        } else {
+            let size = framing_offset_size(self.out.pos, self.offsets.len());
+            for offset in core::mem::take(&mut self.offsets).into_iter().rev() {
+                write_offset(offset, size, &mut self.out)?;
+            }
+        }
+        Ok(self.out.finish())
+    }
+}
+
+impl<S: Cast + ?Sized, P: Sink, F: FieldList> Sink for StructureBuilder<S, P, F> {
+    fn append(&mut self, buf: &[u8]) -> Result<(), write::Error> {
+        write::Write::write_all(&mut self.out, buf)
+    }
+    fn end_child(&mut self) {
+        if self.pending_offset {
+            self.offsets.push(self.out.pos);
+            self.pending_offset = false;
+        }
+    }
+}
+
+/// Builds a maybe **mX**
+///
+/// Finish it with [`some`][MaybeBuilder::some] or [`none`][MaybeBuilder::none],
+/// or [`open`][MaybeBuilder::open] a builder for the contents, finish that,
+/// then [`finish`][MaybeBuilder::finish] this.
+#[must_use = "the maybe is incomplete until finish is called"]
+#[derive(Debug)]
+pub struct MaybeBuilder<M: Maybe + ?Sized, P> {
+    out: Out<P>,
+    is_some: bool,
+    maybe: PhantomData<M>,
+}
+
+impl<M: Maybe + ?Sized, P: Sink> MaybeBuilder<M, P> {
+    fn new(parent: P) -> Self {
+        MaybeBuilder {
+            out: Out::new(parent),
+            is_some: false,
+            maybe: PhantomData,
+        }
+    }
+    /// Write `value` as the contents, returning the parent
+    pub fn some(mut self, value: impl SerializeTo<M::Element>) -> Result<P, write::Error> {
+        value.serialize(&mut self.out)?;
+        self.is_some = true;
+        self.finish()
+    }
+    /// Write nothing, returning the parent
+    pub fn none(self) -> Result<P, write::Error> {
+        self.finish()
+    }
+    /// Start building the contents, for maybes of containers
+    pub fn open(mut self) -> Result<<M::Element as Container>::Builder<Self>, write::Error>
+    where
+        M::Element: Container,
+    {
+        self.is_some = true;
+        Ok(M::Element::builder(self))
+    }
+    /// Finish after building the contents with [`open`][MaybeBuilder::open],
+    /// returning the parent
+    ///
+    /// If nothing has been opened the maybe is **Nothing**.
+    pub fn finish(mut self) -> Result<P, write::Error> {
+        if self.is_some && !M::FIXED_SIZE_ELEMENT {
+            write::Write::write_all(&mut self.out, b"\0")?;
+        }
+        Ok(self.out.finish())
+    }
+}
+
+impl<M: Maybe + ?Sized, P: Sink> Sink for MaybeBuilder<M, P> {
+    fn append(&mut self, buf: &[u8]) -> Result<(), write::Error> {
+        write::Write::write_all(&mut self.out, buf)
+    }
+}
+
+/// Builds a variant **v**
+///
+/// The type of the contents is given by the [`Marker`] passed to
+/// [`value`][VariantBuilder::value] or [`open`][VariantBuilder::open]:
+///
+///     # use gvariant::{gv, Marker, VariantWrap};
+///     let mut out = vec![];
+///     let mut v = gv!("v").builder(&mut out).open(gv!("as"))?;
+///     v.push("hello")?;
+///     v.push("world")?;
+///     v.finish()?.finish()?;
+///     assert_eq!(
+///         out,
+///         gv!("v").serialize_to_vec(VariantWrap(gv!("as"), ["hello", "world"]))
+///     );
+///     # Ok::<(), gvariant::write::Error>(())
+#[must_use = "the variant is incomplete until finish is called"]
+#[derive(Debug)]
+pub struct VariantBuilder<P> {
+    out: Out<P>,
+    typestr: Option<&'static [u8]>,
+}
+
+impl<P: Sink> VariantBuilder<P> {
+    /// Write `value` as the contents, returning the parent
+    pub fn value<M: Marker>(
+        mut self,
+        _: M,
+        value: impl SerializeTo<M::Type>,
+    ) -> Result<P, write::Error> {
+        value.serialize(&mut self.out)?;
+        self.typestr = Some(M::TYPESTR);
+        self.finish()
+    }
+    /// Start building contents of the type given by the marker `M`, for
+    /// variants containing containers
+    pub fn open<M: Marker>(
+        mut self,
+        _: M,
+    ) -> Result<<M::Type as Container>::Builder<Self>, write::Error>
+    where
+        M::Type: Container,
+    {
+        self.typestr = Some(M::TYPESTR);
+        Ok(M::Type::builder(self))
+    }
+    /// Finish after building the contents with
+    /// [`open`][VariantBuilder::open], returning the parent
+    ///
+    /// Fails with [`write::Error::InvalidInput`] if nothing has been opened.
+    pub fn finish(mut self) -> Result<P, write::Error> {
+        let typestr = self
+            .typestr
+            .ok_or(write::Error::InvalidInput("Variant has no contents"))?;
+        write::Write::write_all(&mut self.out, b"\0")?;
+        write::Write::write_all(&mut self.out, typestr)?;
+        Ok(self.out.finish())
+    }
+}
+
+impl<P: Sink> Sink for VariantBuilder<P> {
+    fn append(&mut self, buf: &[u8]) -> Result<(), write::Error> {
+        write::Write::write_all(&mut self.out, buf)
+    }
+}
@@ -18,6 +18,8 @@
 //!   match. Similarly when casting between slices we only perform the cast if
 //!   the input slice size is a multiple of the element size
 
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
 use core::{convert::TryInto, fmt::Display};
 
 #[cfg(feature = "std")]
@@ -25,8 +27,11 @@ use std::error::Error;
 
 use crate::aligned_bytes;
 use crate::aligned_bytes::{is_aligned, AlignedSlice};
+#[cfg(feature = "alloc")]
 use ref_cast::RefCast;
 
+/// # Safety
+///
 /// If a type implements this trait it's a promise that all representations of
 /// underlying memory are valid for this type.  That means any struct must be
 /// `repr(C)` or ``repr(transparent)` and be made up of members that are also
@@ -35,6 +40,8 @@ pub unsafe trait AllBitPatternsValid {}
 
 /// Get the alignment of a type as a [`aligned_bytes::Alignment`]
 ///
+/// # Safety
+///
 /// This trait is unsafe because we will be relying on the information from the
 /// trait to do casting safely.  The alignment needs to be correct, or at least
 /// conservative.
@@ -48,6 +55,20 @@ unsafe impl<T: AlignOf> AlignOf for [T] {
     type AlignOf = T::AlignOf;
 }
 
+/// [`ToOwned`][alloc::borrow::ToOwned] with the `alloc` feature, and no
+/// requirement at all without it
+///
+/// [`Cast`][crate::Cast] types can be copied with `to_owned()`, but only when
+/// we're able to allocate.
+#[cfg(feature = "alloc")]
+pub trait ToOwnedIfAlloc: alloc::borrow::ToOwned {}
+#[cfg(feature = "alloc")]
+impl<T: alloc::borrow::ToOwned + ?Sized> ToOwnedIfAlloc for T {}
+#[cfg(not(feature = "alloc"))]
+pub trait ToOwnedIfAlloc {}
+#[cfg(not(feature = "alloc"))]
+impl<T: ?Sized> ToOwnedIfAlloc for T {}
+
 /// Error returned by [`try_cast_slice_to`] and [`try_cast_slice_to_mut`] if the
 /// size of the slice doesn't match the size of the desination type.
 #[derive(Debug)]
@@ -89,6 +110,47 @@ pub(crate) fn cast_slice<'a, A: aligned_bytes::Alignment, T: AllBitPatternsValid
     }
 }
 
+/// Safely cast an `&mut AlignedSlice` to `&mut [T]`
+///
+/// The mutable equivalent of [`cast_slice`].
+pub(crate) fn cast_slice_mut<'a, A: aligned_bytes::Alignment, T: AllBitPatternsValid>(
+    a: &'a mut AlignedSlice<A>,
+) -> Result<&'a mut [T], WrongSize> {
+    if A::ALIGNMENT < core::mem::align_of::<T>() {
+        panic!()
+    }
+    debug_assert!(is_aligned(a, core::mem::align_of::<T>()));
+    if a.len() % core::mem::size_of::<T>() == 0 {
+        // As for cast_slice.  We have the only reference to the data, and any
+        // value written through the `&mut [T]` is valid as bytes:
+        Ok(unsafe {
+            core::slice::from_raw_parts_mut::<'a, T>(
+                a.as_mut_ptr() as *mut T,
+                a.len() / core::mem::size_of::<T>(),
+            )
+        })
+    } else {
+        Err(WrongSize {})
+    }
+}
+
+/// Cast `&[T]` back to the `&AlignedSlice` that it was cast from
+///
+/// This is the inverse of [`cast_slice`].
+pub(crate) fn slice_to_aligned<T: AlignOf + AllBitPatternsValid>(
+    s: &[T],
+) -> &AlignedSlice<T::AlignOf> {
+    // AllBitPatternsValid types have no padding, so all the bytes are
+    // initialised, and `T::AlignOf` is the alignment of `T`:
+    unsafe {
+        aligned_bytes::to_alignedslice_unchecked(core::slice::from_raw_parts(
+            s.as_ptr() as *const u8,
+            core::mem::size_of_val(s),
+        ))
+    }
+}
+
+#[cfg(feature = "alloc")]
 pub(crate) fn ref_cast_box<T: RefCast + ?Sized>(a: Box<T::From>) -> Box<T> {
     // We lean on RefCast to make this safe
     unsafe { Box::from_raw(T::ref_cast_mut(Box::leak(a)) as *mut T) }
@@ -98,9 +160,9 @@ pub(crate) fn ref_cast_box<T: RefCast + ?Sized>(a: Box<T::From>) -> Box<T> {
 ///
 /// If the length of the input slice isn't exactly the size of `T` this function
 /// will return [`Err(WrongSize)`][WrongSize].
-pub fn try_cast_slice_to<'a, T: AlignOf + AllBitPatternsValid>(
-    s: &'a AlignedSlice<T::AlignOf>,
-) -> Result<&'a T, WrongSize> {
+pub fn try_cast_slice_to<T: AlignOf + AllBitPatternsValid>(
+    s: &AlignedSlice<T::AlignOf>,
+) -> Result<&T, WrongSize> {
     if core::mem::size_of::<T>() == s.len() {
         debug_assert!(is_aligned(s, core::mem::align_of::<T>()));
         Ok(unsafe { &*(s.as_ptr() as *const T) })
@@ -109,13 +171,21 @@ pub fn try_cast_slice_to<'a, T: AlignOf + AllBitPatternsValid>(
     }
 }
 
+/// Safely cast `&T` to the `&AlignedSlice` of its underlying bytes
+///
+/// This is the inverse of [`try_cast_slice_to`].  It's used by the code
+/// generated by the [`gv!`][crate::gv] macro.
+pub fn cast_to_slice<T: AlignOf + AllBitPatternsValid>(value: &T) -> &AlignedSlice<T::AlignOf> {
+    slice_to_aligned(core::slice::from_ref(value))
+}
+
 /// Safely cast a `&mut AlignedSlice` to `&mut T` where `T: Sized`
 ///
 /// If the length of the input slice isn't exactly the size of `T` this function
 /// will return [`Err(WrongSize)`][WrongSize].
-pub fn try_cast_slice_to_mut<'a, T: AlignOf + AllBitPatternsValid>(
-    s: &'a mut AlignedSlice<T::AlignOf>,
-) -> Result<&'a mut T, WrongSize> {
+pub fn try_cast_slice_to_mut<T: AlignOf + AllBitPatternsValid>(
+    s: &mut AlignedSlice<T::AlignOf>,
+) -> Result<&mut T, WrongSize> {
     if core::mem::size_of::<T>() == s.len() {
         debug_assert!(is_aligned(s, core::mem::align_of::<T>()));
         Ok(unsafe { &mut *(s.as_mut_ptr() as *mut T) })
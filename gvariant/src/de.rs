@@ -0,0 +1,376 @@
+//! Deserialisation with serde
+//!
+//! This module lets you deserialise GVariant data into any type that
+//! implements serde's [`Deserialize`].  It requires the `serde` feature.
+//!
+//! ```rust
+//! # use gvariant::{aligned_bytes::copy_to_align, de, VariantTypeStr};
+//! #[derive(serde::Deserialize, Debug, PartialEq)]
+//! struct Person<'a> {
+//!     name: &'a str,
+//!     age: u32,
+//! }
+//! let data = copy_to_align(b"William\0\x22\x00\x00\x00\x08");
+//! let ty = VariantTypeStr::new("(su)").unwrap();
+//!
+//! let william: Person = de::from_slice(ty, &data).unwrap();
+//! assert_eq!(william, Person { name: "William", age: 34 });
+//! ```
+//!
+//! Like the rest of this crate the data is read without copying.  Strings and
+//! byte arrays **ay** can be borrowed from the data as `&str` and `&[u8]`.
+//!
+//! The data is interpreted as a [`DynValue`], so it follows the same rules as
+//! [`Marker::cast`] for data that isn't in normal form: children that can't be
+//! read take their default value, rather than causing errors.
+//!
+//! ### Types
+//!
+//! Values are deserialised according to the GVariant type of the data, so
+//! integers can be read into any rust integer type that can hold their value.
+//! Tuples and structures can be read as rust structs, tuples or sequences.
+//! Dictionaries **a{**KV**}** can be read as maps, or as structs where the keys
+//! are the names of the fields - the inverse of writing a struct as **a{sv}**
+//! with the [`ser`] module.
+//!
+//! The contents of variants **v** are deserialised according to the type
+//! embedded in the data, so an **a{sv}** can be read as a map whose values are
+//! enums marked `#[serde(untagged)]`, for example.
+//!
+//! Enums can be read from data of type **(sv)** or **(uv)**, tagged with the
+//! name or index of the variant, or for unit variants just the name **s** or
+//! index **u**.  This matches the [`ser`] module.
+//!
+//! [`Marker::cast`]: crate::Marker::cast
+//! [`ser`]: crate::ser
+
+use core::fmt::Display;
+
+use serde::de::{
+    self, value::BorrowedStrDeserializer, value::U32Deserializer, Deserialize, DeserializeSeed,
+    IntoDeserializer, Visitor,
+};
+
+use crate::aligned_bytes::{AlignedSlice, A8};
+use crate::dyn_value::{DynIter, DynValue};
+use crate::variant_type::VariantTypeStr;
+use crate::{Marker, Variant};
+
+/// Error returned when deserialising with serde
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The data can't be deserialised as the requested type
+    Type(String),
+    /// An error reported by the [`Deserialize`] implementation
+    Custom(String),
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Type(msg) | Error::Custom(msg) => f.write_str(msg),
+        }
+    }
+}
+impl std::error::Error for Error {}
+impl de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+
+/// Deserialise `data`, which has the GVariant type `ty`
+///
+/// `ty` must be a definite type.
+pub fn from_slice<'de, T: Deserialize<'de>>(
+    ty: &'de VariantTypeStr,
+    data: &'de AlignedSlice<A8>,
+) -> Result<T, Error> {
+    T::deserialize(Deserializer::new(ty, data)?)
+}
+
+/// Deserialise the contents of a [`Variant`] according to its embedded type
+pub fn from_variant<'de, T: Deserialize<'de>>(v: &'de Variant) -> Result<T, Error> {
+    T::deserialize(Deserializer::from_dyn_value(DynValue::from_variant(v)))
+}
+
+/// A serde [`Deserializer`][de::Deserializer] for GVariant data
+///
+/// Most of the time you'll want [`from_slice`] instead.  Use this if you want
+/// to use the type string from a [`Marker`]:
+///
+/// ```rust
+/// # use gvariant::{aligned_bytes::copy_to_align, de::Deserializer, gv};
+/// # use serde::Deserialize;
+/// let data = copy_to_align(b"\x01\x00\x00\x00\x02\x00\x00\x00");
+/// let d = Deserializer::for_marker(gv!("ai"), &data);
+/// assert_eq!(Vec::<u8>::deserialize(d).unwrap(), [1, 2]);
+/// ```
+#[derive(Clone, Copy)]
+pub struct Deserializer<'de> {
+    value: DynValue<'de>,
+}
+
+impl<'de> Deserializer<'de> {
+    /// Deserialise `data`, which has the GVariant type `ty`
+    ///
+    /// Fails if `ty` isn't a definite type.
+    pub fn new(ty: &'de VariantTypeStr, data: &'de AlignedSlice<A8>) -> Result<Self, Error> {
+        match DynValue::new(ty, data) {
+            Ok(value) => Ok(Deserializer { value }),
+            Err(e) => Err(Error::Type(e.to_string())),
+        }
+    }
+
+    /// Deserialise `data`, which has the type of the [`Marker`] `m`
+    pub fn for_marker<M: Marker>(_m: M, data: &'de AlignedSlice<A8>) -> Self {
+        // gv! has already validated the type string
+        let ty = VariantTypeStr::new(M::TYPESTR).unwrap();
+        Deserializer::new(ty, data).unwrap()
+    }
+
+    /// Deserialise a value that has already been interpreted
+    pub fn from_dyn_value(value: DynValue<'de>) -> Self {
+        Deserializer { value }
+    }
+
+    // The contents of variants are treated as if they were the value itself
+    fn unwrapped(self) -> DynValue<'de> {
+        let mut value = self.value;
+        while let DynValue::Variant(v) = value {
+            value = v.value();
+        }
+        value
+    }
+
+    fn mismatch(value: &DynValue<'de>, what: &str) -> Error {
+        Error::Type(format!(
+            "Can't deserialise {} from data of type {}",
+            what,
+            value.type_str()
+        ))
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.unwrapped() {
+            DynValue::Bool(x) => visitor.visit_bool(x),
+            DynValue::Byte(x) => visitor.visit_u8(x),
+            DynValue::Int16(x) => visitor.visit_i16(x),
+            DynValue::Uint16(x) => visitor.visit_u16(x),
+            DynValue::Int32(x) => visitor.visit_i32(x),
+            DynValue::Uint32(x) => visitor.visit_u32(x),
+            DynValue::Handle(x) => visitor.visit_i32(x),
+            DynValue::Int64(x) => visitor.visit_i64(x),
+            DynValue::Uint64(x) => visitor.visit_u64(x),
+            DynValue::Double(x) => visitor.visit_f64(x),
+            DynValue::Str(x) => visitor.visit_borrowed_str(x.to_str()),
+            DynValue::ObjectPath(x) => visitor.visit_borrowed_str(x.to_str()),
+            DynValue::Signature(x) => visitor.visit_borrowed_str(x.to_str()),
+            DynValue::Variant(_) => unreachable!("unwrapped() removes variants"),
+            DynValue::Array(x) if x.element_type().is_dict_entry() => {
+                visitor.visit_map(MapAccess {
+                    entries: x.iter(),
+                    value: None,
+                })
+            }
+            DynValue::Array(x) => visitor.visit_seq(SeqAccess(x.iter())),
+            DynValue::Maybe(x) => match x.to_option() {
+                Some(value) => visitor.visit_some(Deserializer { value }),
+                None => visitor.visit_none(),
+            },
+            DynValue::Tuple(x) | DynValue::DictEntry(x) => visitor.visit_seq(SeqAccess(x.iter())),
+        }
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.unwrapped() {
+            DynValue::Array(x) if x.element_type() == "y" => {
+                visitor.visit_borrowed_bytes(x.as_bytes())
+            }
+            DynValue::Str(x) => visitor.visit_borrowed_bytes(x.to_str().as_bytes()),
+            value => Deserializer { value }.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.unwrapped() {
+            DynValue::Maybe(x) => match x.to_option() {
+                Some(value) => visitor.visit_some(Deserializer { value }),
+                None => visitor.visit_none(),
+            },
+            // A value that isn't a maybe is always there
+            value => visitor.visit_some(Deserializer { value }),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.unwrapped() {
+            DynValue::Tuple(x) if x.is_empty() => visitor.visit_unit(),
+            value => Err(Self::mismatch(&value, "()")),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let value = self.unwrapped();
+        let (tag, payload) = match &value {
+            DynValue::Tuple(x) if x.type_str() == "(sv)" || x.type_str() == "(uv)" => {
+                (x.get(0).unwrap(), x.get(1).unwrap())
+            }
+            DynValue::Str(_) | DynValue::Uint32(_) => (value, DynValue::Tuple(unit())),
+            _ => return Err(Self::mismatch(&value, "an enum")),
+        };
+        visitor.visit_enum(EnumAccess {
+            tag,
+            payload: Deserializer { value: payload },
+        })
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        seq tuple tuple_struct map struct identifier
+    }
+}
+
+fn unit() -> crate::dyn_value::DynStructure<'static> {
+    match DynValue::new(
+        VariantTypeStr::new("()").unwrap(),
+        crate::aligned_bytes::empty_aligned(),
+    ) {
+        Ok(DynValue::Tuple(x)) => x,
+        _ => unreachable!(),
+    }
+}
+
+struct SeqAccess<'de>(DynIter<'de>);
+
+impl<'de> de::SeqAccess<'de> for SeqAccess<'de> {
+    type Error = Error;
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.0.next() {
+            Some(value) => seed.deserialize(Deserializer { value }).map(Some),
+            None => Ok(None),
+        }
+    }
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct MapAccess<'de> {
+    entries: DynIter<'de>,
+    // The value of the entry whose key we've just returned
+    value: Option<DynValue<'de>>,
+}
+
+impl<'de> de::MapAccess<'de> for MapAccess<'de> {
+    type Error = Error;
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some(DynValue::DictEntry(entry)) => {
+                self.value = entry.value();
+                seed.deserialize(Deserializer {
+                    value: entry.key().unwrap(),
+                })
+                .map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let value = self
+            .value
+            .take()
+            .expect("next_value_seed called before next_key_seed");
+        seed.deserialize(Deserializer { value })
+    }
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+struct EnumAccess<'de> {
+    // The name or index of the variant
+    tag: DynValue<'de>,
+    payload: Deserializer<'de>,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumAccess<'de> {
+    type Error = Error;
+    type Variant = Deserializer<'de>;
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Deserializer<'de>), Error> {
+        let variant = match self.tag {
+            DynValue::Str(x) => seed.deserialize(BorrowedStrDeserializer::new(x.to_str()))?,
+            DynValue::Uint32(x) => {
+                let d: U32Deserializer<Error> = x.into_deserializer();
+                seed.deserialize(d)?
+            }
+            _ => unreachable!("deserialize_enum only accepts s and u tags"),
+        };
+        Ok((variant, self.payload))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Deserializer<'de> {
+    type Error = Error;
+    fn unit_variant(self) -> Result<(), Error> {
+        de::Deserialize::deserialize(self)
+    }
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_any(self, visitor)
+    }
+}
@@ -0,0 +1,783 @@
+//! Run-time interpretation of GVariant data
+//!
+//! The rest of this crate requires the type of the data to be known at compile
+//! time.  [`DynValue`] is for the cases where it isn't, such as when inspecting
+//! the contents of a [`Variant`] **v** of unknown type.  Like the rest of this
+//! crate it doesn't copy or allocate, it's just a view onto the underlying
+//! bytes.
+//!
+//! ```rust
+//! # use gvariant::{aligned_bytes::copy_to_align, DynValue, VariantTypeStr};
+//! let data = copy_to_align(b"\x22\x00\x00\x00William\0");
+//! let ty = VariantTypeStr::new("(is)").unwrap();
+//! let value = DynValue::new(ty, &data).unwrap();
+//! if let DynValue::Tuple(t) = value {
+//!     assert_eq!(t.len(), 2);
+//!     assert!(matches!(t.get(0), Some(DynValue::Int32(34))));
+//!     assert!(matches!(t.get(1), Some(DynValue::Str(s)) if s == "William"));
+//! }
+//! ```
+//!
+//! Children are read with the same rules as the statically typed API, so a
+//! `DynValue` will always agree with the result of [`Marker::cast`] for the
+//! same data, including data that is not in normal form.
+//!
+//! ### Recursion depth
+//!
+//! Values of type **v** can be nested arbitrarily deeply, so untrusted data can
+//! describe values that are much more deeply nested than any type string.  To
+//! protect code that processes [`DynValue`]s recursively, values are given a
+//! limit on the depth of containers that may be nested within them.  By
+//! default this is [`MAX_DEPTH`], the same limit that GLib uses.  Variants
+//! whose contents would exceed the limit are treated as containing the unit
+//! value `()`, as GLib does.  Use [`DynValue::with_max_depth`] to pick a
+//! different limit.
+//!
+//! [`Marker::cast`]: crate::Marker::cast
+
+use core::{
+    convert::{TryFrom, TryInto},
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+};
+use ref_cast::RefCast;
+
+use crate::aligned_bytes::{AlignedSlice, TryAsAligned, A8};
+pub use crate::variant_type::MAX_DEPTH;
+use crate::variant_type::{align, VariantTypeStr};
+use crate::{
+    calc_offsets, read_last_frame_offset, read_uint, Cast, ObjectPath, Signature, Str, Variant,
+};
+
+/// A GVariant value whose type is only known at run-time
+///
+/// Numbers and booleans are stored by value, everything else refers to the
+/// underlying data.  See the [module documentation][crate::dyn_value] for more
+/// information.
+///
+/// Values are equal if they have the same type and the same normal form, so
+/// differences in padding or in the size of framing offsets are ignored.
+///
+/// All NaNs compare equal to each other, whatever their payload, so that this
+/// can implement [`Eq`] and [`Hash`] and be used as a map key.  This differs
+/// from GLib's `g_variant_equal`.  That compares the serialised bytes of values
+/// that are trusted to be in normal form, so NaNs with different payloads are
+/// unequal there, and compares other values by their printed text.
+#[derive(Debug, Clone, Copy)]
+pub enum DynValue<'a> {
+    /// **b**
+    Bool(bool),
+    /// **y**
+    Byte(u8),
+    /// **n**
+    Int16(i16),
+    /// **q**
+    Uint16(u16),
+    /// **i**
+    Int32(i32),
+    /// **u**
+    Uint32(u32),
+    /// **h**, an index into an out-of-band list of file descriptors
+    Handle(i32),
+    /// **x**
+    Int64(i64),
+    /// **t**
+    Uint64(u64),
+    /// **d**
+    Double(f64),
+    /// **s**
+    Str(&'a Str),
+    /// **o**
+    ObjectPath(&'a ObjectPath),
+    /// **g**
+    Signature(&'a Signature),
+    /// **v**
+    Variant(DynVariant<'a>),
+    /// **a**X for any type X, including dictionaries **a{**KV**}**
+    Array(DynArray<'a>),
+    /// **m**X for any type X
+    Maybe(DynMaybe<'a>),
+    /// Tuples like **(**XYZ**)**, including the unit type **()**
+    Tuple(DynStructure<'a>),
+    /// **{**KV**}**
+    DictEntry(DynStructure<'a>),
+}
+
+/// Error returned when creating a [`DynValue`] with a type that no value can
+/// have
+///
+/// Values can only be created with definite types.  Types like **a?** or **r**
+/// describe sets of types, rather than a single type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndefiniteType {}
+#[cfg(feature = "std")]
+impl std::error::Error for IndefiniteType {}
+impl Display for IndefiniteType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Values cannot have indefinite types")
+    }
+}
+
+impl<'a> DynValue<'a> {
+    /// Interpret `data` as a value of type `ty`
+    ///
+    /// Fails if `ty` is not definite.  The returned value will have a maximum
+    /// nesting depth of [`MAX_DEPTH`].
+    pub fn new(
+        ty: &'a VariantTypeStr,
+        data: &'a AlignedSlice<A8>,
+    ) -> Result<DynValue<'a>, IndefiniteType> {
+        Self::with_max_depth(ty, data, MAX_DEPTH)
+    }
+
+    /// Like [`DynValue::new`], but with a custom limit on the nesting depth of
+    /// containers.
+    ///
+    /// Variants nested more deeply than this will appear to contain `()`.
+    pub fn with_max_depth(
+        ty: &'a VariantTypeStr,
+        data: &'a AlignedSlice<A8>,
+        max_depth: usize,
+    ) -> Result<DynValue<'a>, IndefiniteType> {
+        if ty.is_definite() {
+            Ok(Self::read(ty, data, max_depth))
+        } else {
+            Err(IndefiniteType {})
+        }
+    }
+
+    /// Interpret the contents of a [`Variant`]
+    ///
+    /// This is infallible.  If the type string embedded in the variant is
+    /// invalid the value is taken to be `()`, the same as GLib does.
+    pub fn from_variant(v: &'a Variant) -> DynValue<'a> {
+        DynVariant {
+            variant: v,
+            remaining_depth: MAX_DEPTH,
+        }
+        .value()
+    }
+
+    // `data` must be aligned to `ty.align_of()`.  This is always the case for
+    // the children of aligned data because children are always at offsets
+    // aligned to their own alignment.
+    pub(crate) fn read(
+        ty: &'a VariantTypeStr,
+        data: &'a [u8],
+        remaining_depth: usize,
+    ) -> DynValue<'a> {
+        // Fixed size values with the wrong size take their default value.
+        // The empty slice gives the default for every type:
+        let data = match ty.size_of() {
+            Some(size) if size != data.len() => b"",
+            _ => data,
+        };
+        let container = |ty| DynContainer {
+            ty,
+            data,
+            remaining_depth: remaining_depth.saturating_sub(1),
+        };
+        match ty.as_bytes()[0] {
+            b'b' => DynValue::Bool(data.iter().any(|x| *x != 0)),
+            b'y' => DynValue::Byte(data.first().copied().unwrap_or_default()),
+            b'n' => DynValue::Int16(i16::from_ne_bytes(fixed(data))),
+            b'q' => DynValue::Uint16(u16::from_ne_bytes(fixed(data))),
+            b'i' => DynValue::Int32(i32::from_ne_bytes(fixed(data))),
+            b'u' => DynValue::Uint32(u32::from_ne_bytes(fixed(data))),
+            b'h' => DynValue::Handle(i32::from_ne_bytes(fixed(data))),
+            b'x' => DynValue::Int64(i64::from_ne_bytes(fixed(data))),
+            b't' => DynValue::Uint64(u64::from_ne_bytes(fixed(data))),
+            b'd' => DynValue::Double(f64::from_ne_bytes(fixed(data))),
+            b's' => DynValue::Str(Str::ref_cast(data)),
+            b'o' => DynValue::ObjectPath(ObjectPath::ref_cast(data)),
+            b'g' => DynValue::Signature(Signature::ref_cast(data)),
+            b'v' => DynValue::Variant(DynVariant {
+                variant: match data.try_as_aligned() {
+                    Ok(x) => Variant::ref_cast(x),
+                    Err(_) => Variant::default_ref(),
+                },
+                remaining_depth,
+            }),
+            b'a' => DynValue::Array(DynArray(container(ty))),
+            b'm' => DynValue::Maybe(DynMaybe(container(ty))),
+            b'(' => DynValue::Tuple(DynStructure(container(ty))),
+            b'{' => DynValue::DictEntry(DynStructure(container(ty))),
+            _ => unreachable!("DynValue must have a definite type"),
+        }
+    }
+
+    /// The type of this value
+    pub fn type_str(&self) -> &'a VariantTypeStr {
+        let basic: &'static [u8] = match self {
+            DynValue::Bool(_) => b"b",
+            DynValue::Byte(_) => b"y",
+            DynValue::Int16(_) => b"n",
+            DynValue::Uint16(_) => b"q",
+            DynValue::Int32(_) => b"i",
+            DynValue::Uint32(_) => b"u",
+            DynValue::Handle(_) => b"h",
+            DynValue::Int64(_) => b"x",
+            DynValue::Uint64(_) => b"t",
+            DynValue::Double(_) => b"d",
+            DynValue::Str(_) => b"s",
+            DynValue::ObjectPath(_) => b"o",
+            DynValue::Signature(_) => b"g",
+            DynValue::Variant(_) => b"v",
+            DynValue::Array(x) => return x.type_str(),
+            DynValue::Maybe(x) => return x.type_str(),
+            DynValue::Tuple(x) | DynValue::DictEntry(x) => return x.type_str(),
+        };
+        VariantTypeStr::from_bytes_unchecked(basic)
+    }
+}
+
+fn fixed<T: Default + for<'b> TryFrom<&'b [u8]>>(data: &[u8]) -> T {
+    data.try_into().unwrap_or_default()
+}
+
+// All NaNs are equal to each other, so they must also hash the same
+fn double_bits(x: f64) -> u64 {
+    if x.is_nan() {
+        f64::NAN.to_bits()
+    } else {
+        x.to_bits()
+    }
+}
+
+impl PartialEq for DynValue<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        use DynValue::*;
+        match (self, other) {
+            (Bool(a), Bool(b)) => a == b,
+            (Byte(a), Byte(b)) => a == b,
+            (Int16(a), Int16(b)) => a == b,
+            (Uint16(a), Uint16(b)) => a == b,
+            (Int32(a), Int32(b)) => a == b,
+            (Uint32(a), Uint32(b)) => a == b,
+            (Handle(a), Handle(b)) => a == b,
+            (Int64(a), Int64(b)) => a == b,
+            (Uint64(a), Uint64(b)) => a == b,
+            (Double(a), Double(b)) => double_bits(*a) == double_bits(*b),
+            (Str(a), Str(b)) => a.to_str() == b.to_str(),
+            (ObjectPath(a), ObjectPath(b)) => a == b,
+            (Signature(a), Signature(b)) => a == b,
+            (Variant(a), Variant(b)) => a.value() == b.value(),
+            (Array(a), Array(b)) => {
+                a.type_str() == b.type_str() && a.len() == b.len() && a.iter().eq(b.iter())
+            }
+            (Maybe(a), Maybe(b)) => a.type_str() == b.type_str() && a.to_option() == b.to_option(),
+            (Tuple(a), Tuple(b)) | (DictEntry(a), DictEntry(b)) => {
+                a.type_str() == b.type_str() && a.iter().eq(b.iter())
+            }
+            _ => false,
+        }
+    }
+}
+impl Eq for DynValue<'_> {}
+impl Hash for DynValue<'_> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        use DynValue::*;
+        self.type_str().as_bytes().hash(state);
+        match self {
+            Bool(x) => x.hash(state),
+            Byte(x) => x.hash(state),
+            Int16(x) => x.hash(state),
+            Uint16(x) => x.hash(state),
+            Int32(x) => x.hash(state),
+            Uint32(x) => x.hash(state),
+            Handle(x) => x.hash(state),
+            Int64(x) => x.hash(state),
+            Uint64(x) => x.hash(state),
+            Double(x) => double_bits(*x).hash(state),
+            Str(x) => x.to_str().hash(state),
+            ObjectPath(x) => x.hash(state),
+            Signature(x) => x.hash(state),
+            Variant(x) => x.value().hash(state),
+            Array(x) => {
+                x.len().hash(state);
+                x.iter().for_each(|x| x.hash(state));
+            }
+            Maybe(x) => x.to_option().hash(state),
+            Tuple(x) | DictEntry(x) => x.iter().for_each(|x| x.hash(state)),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct DynContainer<'a> {
+    ty: &'a VariantTypeStr,
+    data: &'a [u8],
+    // The depth remaining for our children
+    remaining_depth: usize,
+}
+impl<'a> DynContainer<'a> {
+    fn child(&self, ty: &'a VariantTypeStr, data: &'a [u8]) -> DynValue<'a> {
+        DynValue::read(ty, data, self.remaining_depth)
+    }
+}
+
+/// The value of a [`Variant`] **v** interpreted at run-time
+///
+/// Created by [`DynValue::new`] for values of type **v**.  Use
+/// [`value`][DynVariant::value] to get at the contained value.
+#[derive(Clone, Copy)]
+pub struct DynVariant<'a> {
+    variant: &'a Variant,
+    remaining_depth: usize,
+}
+impl<'a> DynVariant<'a> {
+    /// The underlying [`Variant`]
+    pub fn as_variant(&self) -> &'a Variant {
+        self.variant
+    }
+
+    /// The type of the contained value
+    ///
+    /// This will be `()` if the type string embedded in the data is invalid or
+    /// the value would exceed the maximum nesting depth.
+    pub fn type_str(&self) -> &'a VariantTypeStr {
+        self.split().0
+    }
+
+    /// The contained value
+    pub fn value(&self) -> DynValue<'a> {
+        let (ty, data) = self.split();
+        DynValue::read(ty, data, self.remaining_depth.saturating_sub(1))
+    }
+
+    fn split(&self) -> (&'a VariantTypeStr, &'a [u8]) {
+        let (ty, data) = self.variant.split();
+        match VariantTypeStr::new(ty) {
+            Ok(ty) if ty.is_definite() && ty.depth() < self.remaining_depth => (ty, data),
+            _ => (VariantTypeStr::from_bytes_unchecked(b"()"), b""),
+        }
+    }
+}
+impl Debug for DynVariant<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Variant").field(&self.value()).finish()
+    }
+}
+
+/// An array **a**X interpreted at run-time
+///
+/// Created by [`DynValue::new`] for array types.  The elements can be accessed
+/// with [`get`][DynArray::get] or [`iter`][DynArray::iter].
+#[derive(Clone, Copy)]
+pub struct DynArray<'a>(DynContainer<'a>);
+impl<'a> DynArray<'a> {
+    /// The type of the array
+    pub fn type_str(&self) -> &'a VariantTypeStr {
+        self.0.ty
+    }
+
+    /// The underlying serialised data
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0.data
+    }
+
+    /// The type of the array elements
+    pub fn element_type(&self) -> &'a VariantTypeStr {
+        self.0.ty.element().unwrap()
+    }
+
+    /// Returns the number of elements in the array.
+    pub fn len(&self) -> usize {
+        let data = self.0.data;
+        match self.element_type().size_of() {
+            // Fixed width arrays that aren't an exact multiple of the
+            // element size are taken to be empty
+            Some(size) if data.len() % size == 0 => data.len() / size,
+            Some(_) => 0,
+            None => {
+                let (osz, lfo) = read_last_frame_offset(data);
+                (data.len() - lfo) / osz as usize
+            }
+        }
+    }
+
+    /// Returns `true` if the array has a length of 0.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the element at `index`, or [`None`] if it's out of bounds
+    pub fn get(&self, index: usize) -> Option<DynValue<'a>> {
+        if index >= self.len() {
+            return None;
+        }
+        let ty = self.element_type();
+        let data = self.0.data;
+        if let Some(size) = ty.size_of() {
+            return Some(self.0.child(ty, &data[index * size..(index + 1) * size]));
+        }
+        // This follows the implementation of `Index` for `NonFixedWidthArray`
+        let (osz, lfo) = read_last_frame_offset(data);
+        let frame_offsets = &data[lfo..];
+        let end = read_uint(frame_offsets, osz, index);
+        let start = align(
+            match index {
+                0 => 0,
+                x => read_uint(frame_offsets, osz, x - 1),
+            },
+            ty.align_of(),
+        );
+        Some(self.0.child(
+            ty,
+            if start < data.len() && end <= lfo && start <= end {
+                &data[start..end]
+            } else {
+                // Start or End Boundary of a Child Falls Outside the Container
+                b""
+            },
+        ))
+    }
+
+    /// Returns an iterator over the array elements.
+    pub fn iter(&self) -> DynIter<'a> {
+        DynIter {
+            get: DynIterSource::Array(*self),
+            next: 0,
+            len: self.len(),
+        }
+    }
+}
+impl<'a> IntoIterator for DynArray<'a> {
+    type Item = DynValue<'a>;
+    type IntoIter = DynIter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+impl Debug for DynArray<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// A maybe **m**X interpreted at run-time
+///
+/// Created by [`DynValue::new`] for maybe types.  You probably want to call
+/// [`to_option`][DynMaybe::to_option].
+#[derive(Clone, Copy)]
+pub struct DynMaybe<'a>(DynContainer<'a>);
+impl<'a> DynMaybe<'a> {
+    /// The type of the maybe
+    pub fn type_str(&self) -> &'a VariantTypeStr {
+        self.0.ty
+    }
+
+    /// The underlying serialised data
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0.data
+    }
+
+    /// The type of the contained value
+    pub fn element_type(&self) -> &'a VariantTypeStr {
+        self.0.ty.element().unwrap()
+    }
+
+    /// Convert to a rust native [`Option`] type.
+    ///
+    /// This follows the same rules as [`MaybeFixedSize`][crate::MaybeFixedSize]
+    /// and [`MaybeNonFixedSize`][crate::MaybeNonFixedSize].
+    pub fn to_option(&self) -> Option<DynValue<'a>> {
+        let ty = self.element_type();
+        let data = self.0.data;
+        match ty.size_of() {
+            Some(size) if size == data.len() => Some(self.0.child(ty, data)),
+            Some(_) => None,
+            None if data.is_empty() => None,
+            None => Some(self.0.child(ty, &data[..data.len() - 1])),
+        }
+    }
+}
+impl Debug for DynMaybe<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.to_option().fmt(f)
+    }
+}
+
+/// A tuple **(**...**)** or dict entry **{**KV**}** interpreted at run-time
+///
+/// Created by [`DynValue::new`] for tuple and dict entry types.  The fields can
+/// be accessed with [`get`][DynStructure::get] or
+/// [`iter`][DynStructure::iter].
+#[derive(Clone, Copy)]
+pub struct DynStructure<'a>(DynContainer<'a>);
+impl<'a> DynStructure<'a> {
+    /// The type of the tuple or dict entry
+    pub fn type_str(&self) -> &'a VariantTypeStr {
+        self.0.ty
+    }
+
+    /// The underlying serialised data
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0.data
+    }
+
+    /// The number of fields.  This is always 2 for dict entries.
+    pub fn len(&self) -> usize {
+        self.0.ty.n_items()
+    }
+
+    /// Returns `true` for the unit type `()`
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns field `index`, or [`None`] if it's out of bounds
+    pub fn get(&self, index: usize) -> Option<DynValue<'a>> {
+        let ty = self.0.ty;
+        let data = self.0.data;
+        // After all of the items have been added, a framing offset is
+        // appended, in reverse order, for each non-fixed-sized item that is
+        // not the last item in the structure.
+        let mut n_frame_offsets = ty.items().filter(|x| !x.is_fixed_size()).count();
+        if ty.items().last().map(|x| x.is_fixed_size()) == Some(false) {
+            n_frame_offsets -= 1;
+        }
+        let (child, (i, a, b, c)) = ty.table().nth(index)?;
+        let last_child = index == self.len() - 1;
+        Some(self.0.child(
+            child,
+            match calc_offsets(
+                data,
+                i,
+                a,
+                b,
+                c,
+                child.size_of(),
+                last_child,
+                n_frame_offsets,
+            ) {
+                Some((start, end)) => &data[start..end],
+                None => b"",
+            },
+        ))
+    }
+
+    /// The key of a dict entry.  This is the same as `get(0)`.
+    pub fn key(&self) -> Option<DynValue<'a>> {
+        self.get(0)
+    }
+
+    /// The value of a dict entry.  This is the same as `get(1)`.
+    pub fn value(&self) -> Option<DynValue<'a>> {
+        self.get(1)
+    }
+
+    /// Returns an iterator over the fields.
+    pub fn iter(&self) -> DynIter<'a> {
+        DynIter {
+            get: DynIterSource::Structure(*self),
+            next: 0,
+            len: self.len(),
+        }
+    }
+}
+impl<'a> IntoIterator for DynStructure<'a> {
+    type Item = DynValue<'a>;
+    type IntoIter = DynIter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+impl Debug for DynStructure<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut t = f.debug_tuple("");
+        for x in self.iter() {
+            t.field(&x);
+        }
+        t.finish()
+    }
+}
+
+#[derive(Clone)]
+enum DynIterSource<'a> {
+    Array(DynArray<'a>),
+    Structure(DynStructure<'a>),
+}
+
+/// An iterator over the children of a [`DynArray`] or [`DynStructure`]
+#[derive(Clone)]
+pub struct DynIter<'a> {
+    get: DynIterSource<'a>,
+    next: usize,
+    len: usize,
+}
+impl<'a> Iterator for DynIter<'a> {
+    type Item = DynValue<'a>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.len {
+            return None;
+        }
+        let out = match &self.get {
+            DynIterSource::Array(x) => x.get(self.next),
+            DynIterSource::Structure(x) => x.get(self.next),
+        };
+        self.next += 1;
+        out
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let l = self.len - self.next;
+        (l, Some(l))
+    }
+}
+impl ExactSizeIterator for DynIter<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aligned_bytes::copy_to_align;
+
+    fn t(s: &str) -> &VariantTypeStr {
+        VariantTypeStr::new(s).unwrap()
+    }
+
+    #[test]
+    fn test_basic_types() {
+        let data = copy_to_align(b"\x01\x02\x03\x04\x05\x06\x07\x08");
+        let v = |ty| DynValue::new(t(ty), &data).unwrap();
+        assert!(matches!(v("i"), DynValue::Int32(0)));
+        assert!(matches!(v("h"), DynValue::Handle(0)));
+        assert!(matches!(v("x"), DynValue::Int64(0x0807060504030201)));
+        assert!(matches!(v("t"), DynValue::Uint64(0x0807060504030201)));
+        assert!(matches!(v("s"), DynValue::Str(s) if s == ""));
+        assert!(matches!(v("(ii)"), DynValue::Tuple(_)));
+        assert_eq!(v("a{sv}").type_str(), "a{sv}");
+        assert_eq!(v("d").type_str(), "d");
+
+        let data = copy_to_align(b"hello\0");
+        let v = |ty| DynValue::new(t(ty), &data).unwrap();
+        assert!(matches!(v("s"), DynValue::Str(s) if s == "hello"));
+        // Invalid object paths and signatures take the default value
+        assert!(matches!(v("o"), DynValue::ObjectPath(s) if s == "/"));
+        assert!(matches!(v("g"), DynValue::Signature(s) if s == ""));
+
+        let data = copy_to_align(b"/a/b\0");
+        assert!(
+            matches!(DynValue::new(t("o"), &data).unwrap(), DynValue::ObjectPath(s) if s == "/a/b")
+        );
+        let data = copy_to_align(b"a{sv}\0");
+        assert!(
+            matches!(DynValue::new(t("g"), &data).unwrap(), DynValue::Signature(s) if s == "a{sv}")
+        );
+
+        assert!(DynValue::new(t("a?"), &data).is_err());
+    }
+
+    #[test]
+    fn test_containers() {
+        // From the GVariant spec section 2.8 "Examples"
+        let data = copy_to_align(b"i\0can\0has\0strings?\0\x02\x06\x0a\x13");
+        let v = DynValue::new(t("as"), &data).unwrap();
+        let a = match v {
+            DynValue::Array(a) => a,
+            _ => panic!(),
+        };
+        assert_eq!(a.len(), 4);
+        let strs: Vec<&str> = a
+            .iter()
+            .map(|x| match x {
+                DynValue::Str(s) => s.to_str(),
+                _ => panic!(),
+            })
+            .collect();
+        assert_eq!(strs, ["i", "can", "has", "strings?"]);
+        assert!(a.get(4).is_none());
+
+        let data = copy_to_align(b"foo\0\xff\xff\xff\xff\x04");
+        let v = DynValue::new(t("(si)"), &data).unwrap();
+        assert_eq!(format!("{:?}", v), "Tuple((Str(\"foo\"), Int32(-1)))");
+
+        let data = copy_to_align(b"hello world\0\0");
+        let v = DynValue::new(t("ms"), &data).unwrap();
+        assert_eq!(format!("{:?}", v), "Maybe(Some(Str(\"hello world\")))");
+        let v = DynValue::new(t("mi"), &data).unwrap();
+        assert_eq!(format!("{:?}", v), "Maybe(None)");
+
+        let data = copy_to_align(b"a sp\0\0\x00\x00\x00\x00\0v\0(t)\x05");
+        let v = DynValue::new(t("{sv}"), &data).unwrap();
+        assert_eq!(
+            format!("{:?}", v),
+            "DictEntry((Str(\"a sp\"), Variant(Variant(Tuple((Uint64(0),))))))"
+        );
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        // A variant containing a variant containing ... an int
+        let mut bytes = b"\x05\x00\x00\x00\0i".to_vec();
+        for _ in 0..10 {
+            bytes.extend_from_slice(b"\0v");
+        }
+        let data = copy_to_align(&bytes);
+        let depth = |max_depth| {
+            let mut v = DynValue::with_max_depth(t("v"), &data, max_depth).unwrap();
+            let mut n = 0;
+            while let DynValue::Variant(x) = v {
+                v = x.value();
+                n += 1;
+            }
+            (n, v.type_str().as_str())
+        };
+        assert_eq!(depth(MAX_DEPTH), (11, "i"));
+        assert_eq!(depth(12), (11, "i"));
+        assert_eq!(depth(11), (11, "()"));
+        assert_eq!(depth(5), (5, "()"));
+
+        // Invalid types in variants are taken to be ()
+        let data = copy_to_align::<A8>(b"\x05\0ii");
+        let v = DynValue::from_variant(Variant::from_aligned_slice(&data));
+        assert!(matches!(v, DynValue::Tuple(x) if x.is_empty()));
+    }
+    #[test]
+    fn test_equality() {
+        use std::collections::hash_map::DefaultHasher;
+        fn hash(v: &DynValue) -> u64 {
+            let mut h = DefaultHasher::new();
+            v.hash(&mut h);
+            h.finish()
+        }
+        let eq = |ty_a: &str, a: &[u8], ty_b: &str, b: &[u8]| {
+            let a = copy_to_align(a);
+            let b = copy_to_align(b);
+            let a = DynValue::new(t(ty_a), &a).unwrap();
+            let b = DynValue::new(t(ty_b), &b).unwrap();
+            if a == b {
+                assert_eq!(hash(&a), hash(&b));
+            }
+            a == b
+        };
+        let (normal, padded) = (b"\x01\0\0\0\x05\0\0\0", b"\x01\xff\xff\xff\x05\0\0\0");
+        assert!(eq("(yi)", normal, "(yi)", padded));
+        assert!(!eq("(yi)", normal, "(yu)", normal));
+        assert!(!eq("i", b"\x05\0\0\0", "u", b"\x05\0\0\0"));
+
+        // Padding within a variant is ignored too
+        let normal = b"\x01\0\0\0\x05\0\0\0\0(yi)";
+        let padded = b"\x01\xff\xff\xff\x05\0\0\0\0(yi)";
+        assert!(eq("v", normal, "v", padded));
+        assert!(!eq("v", normal, "v", b"\x01\0\0\0\x06\0\0\0\0(yi)"));
+
+        // Invalid values are equal to the default value for their type
+        assert!(eq("s", b"\xff\0", "s", b"\0"));
+        assert!(eq("b", b"\x01\x01", "b", b"\0"));
+        assert!(!eq("b", b"\x01\x01", "b", b"\x01"));
+        assert!(eq("ai", b"\x01\0\0", "ai", b""));
+
+        // Any non-zero byte is true, like g_variant_get_boolean
+        assert!(eq("b", b"\x02", "b", b"\x01"));
+
+        // Unlike g_variant_equal all NaNs are equal, whatever their payload
+        let nan = f64::NAN.to_ne_bytes();
+        let other_nan = (-f64::NAN).to_ne_bytes();
+        assert!(eq("d", &nan, "d", &other_nan));
+        assert!(!eq("d", &nan, "d", &1.0f64.to_ne_bytes()));
+
+        assert!(eq("ms", b"a\0\0", "ms", b"a\0\0"));
+        assert!(!eq("ms", b"a\0\0", "ms", b""));
+        assert!(!eq("as", b"", "ao", b""));
+    }
+}
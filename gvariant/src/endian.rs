@@ -0,0 +1,117 @@
+//! Data with a declared byte order
+//!
+//! GVariant data is normally in the byte order of the machine that reads it,
+//! and that's how this crate interprets the data it casts.  Some formats
+//! declare a byte order instead.  For example ostree stores some of the
+//! numbers in its commit objects big-endian, whatever the byte order of the
+//! machine.
+//!
+//! There are two ways of dealing with this.  Fixed size values can be read in
+//! place with the [`Endian`] trait, without copying the rest of the data:
+//!
+//! ```rust
+//! # use gvariant::{aligned_bytes::copy_to_align, endian::{ByteOrder, Endian}, gv, Marker};
+//! let data = copy_to_align(b"\x00\x00\x00\x05\x00\x00\x01\x00");
+//! let numbers = gv!("au").cast(data.as_ref());
+//! let numbers: Vec<u32> = numbers.iter().map(|x| x.to_native(ByteOrder::Big)).collect();
+//! assert_eq!(numbers, [5, 256]);
+//! ```
+//!
+//! Alternatively, values of any type can be converted to the other byte order
+//! in a new buffer with [`Marker::byteswap`], like GLib's
+//! `g_variant_byteswap`.
+//!
+//! [`Marker::byteswap`]: crate::Marker::byteswap
+
+use crate::{Bool, Handle};
+
+/// The byte order of serialised data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ByteOrder {
+    /// Least significant byte first, as on x86 and most ARM systems
+    Little,
+    /// Most significant byte first, also known as network byte order
+    Big,
+}
+
+impl ByteOrder {
+    /// The byte order of this machine
+    #[cfg(target_endian = "little")]
+    pub const NATIVE: ByteOrder = ByteOrder::Little;
+    /// The byte order of this machine
+    #[cfg(target_endian = "big")]
+    pub const NATIVE: ByteOrder = ByteOrder::Big;
+}
+
+/// Fixed size values that can be read with a declared byte order
+///
+/// This is implemented for the numeric types, [`Bool`], [`Handle`] and for the
+/// fixed size [`Structure`][crate::Structure] types, which swap each of their
+/// fields.
+pub trait Endian: Copy {
+    /// Reverse the byte order of each number within this value
+    fn byteswap(self) -> Self;
+
+    /// Interpret this value as having been stored with byte order `order`
+    ///
+    ///     # use gvariant::endian::{ByteOrder, Endian};
+    ///     assert_eq!(0x0102u16.to_native(ByteOrder::NATIVE), 0x0102);
+    ///     assert_eq!(0x0102u16.to_native(ByteOrder::Big), u16::from_be(0x0102));
+    fn to_native(self, order: ByteOrder) -> Self {
+        if order == ByteOrder::NATIVE {
+            self
+        } else {
+            self.byteswap()
+        }
+    }
+}
+
+macro_rules! impl_endian_for {
+    ($($t:ty),*) => {
+        $(
+            impl Endian for $t {
+                fn byteswap(self) -> Self {
+                    self.swap_bytes()
+                }
+            }
+        )*
+    };
+}
+impl_endian_for!(u8, i16, u16, i32, u32, i64, u64);
+
+impl Endian for f64 {
+    fn byteswap(self) -> Self {
+        f64::from_bits(self.to_bits().swap_bytes())
+    }
+}
+
+impl Endian for Bool {
+    fn byteswap(self) -> Self {
+        self
+    }
+}
+
+impl Endian for Handle {
+    fn byteswap(self) -> Self {
+        Handle(self.0.swap_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_native() {
+        assert_eq!(0x0102_0304u32.byteswap(), 0x0403_0201);
+        assert_eq!((-2i16).to_native(ByteOrder::NATIVE), -2);
+        assert_eq!(0x0102i16.to_native(ByteOrder::Big), i16::from_be(0x0102));
+        assert_eq!(0x0102i16.to_native(ByteOrder::Little), i16::from_le(0x0102));
+        assert_eq!(5u8.byteswap(), 5);
+        let x = 1.5f64;
+        let mut swapped = x.to_ne_bytes();
+        swapped.reverse();
+        assert_eq!(x.byteswap().to_ne_bytes(), swapped);
+        assert_eq!(x.byteswap().byteswap(), x);
+    }
+}
@@ -29,6 +29,17 @@
 //! through use of code generation via macros.  See the gvariant-macro
 //! subdirectory.
 //!
+//! For the cases where a type is only known at run-time, such as the contents
+//! of a [`Variant`], see [`VariantTypeStr`] and [`DynValue`] in the
+//! [`variant_type`] and [`dyn_value`] modules.
+//!
+//! Values can be converted to and from GLib's text format (as used by
+//! `g_variant_print` and `g_variant_parse`) with the [`print`] and [`parse`]
+//! modules.
+//!
+//! Data with a declared byte order, rather than the byte order of the machine
+//! reading it, can be handled with the [`endian`] module.
+//!
 //! The library is intended to be sound and safe to run on untrusted input,
 //! although the implementation does include use of `unsafe`.  See ["Use of
 //! `unsafe`"](#use-of-unsafe) below. Help with validating the unsafe portions
@@ -70,9 +81,11 @@
 //! * [`Marker::deserialize`]
 //! * [`aligned_bytes::read_to_slice`]
 //! * Some CPU dependent string handling optimisations in the memchr crate
-//! * Serialisation: although this requirement could be relaxed in the future
+//! * Serialising to a `std::io::Write` with [`Marker::serialize`]
 //!
-//! Disable this feature for no-std support.
+//! Disable this feature for no-std support.  Serialisation is still available
+//! with [`Marker::write`], which writes to a [`write::Write`] such as a
+//! `&mut [u8]`.  See the [`write`] module.
 //!
 //! ### `alloc` - enabled by default
 //!
@@ -80,12 +93,26 @@
 //!
 //! * Allocating [`AlignedSlice`]s with [`ToOwned`],
 //!   [`copy_to_align`][aligned_bytes::copy_to_align] and
-//!   [`alloc_aligned`][aligned_bytes::alloc_aligned].
+//!   [`alloc_aligned`][aligned_bytes::alloc_aligned], and the growable
+//!   [`AlignedVec`][aligned_bytes::AlignedVec].
+//! * Building containers one child at a time with the [`builder`] module
 //! * The convenience API `Marker::from_bytes` - use `Marker::cast` instead
 //! * Correctly displaying non-utf-8 formatted strings
 //! * Copying unsized GVariant objects with `to_owned()`
+//! * [`Marker::serialize_to_vec`] and serialising the contents of a
+//!   [`Variant`] of unknown type
 //! * The std feature
 //!
+//! Without this feature arrays of non-fixed size types such as **as** can
+//! still be serialised, but only from iterators that implement [`Clone`], as
+//! the elements are visited several times.
+//!
+//! ### `serde` - disabled by default
+//!
+//! Serialisation of types that implement serde's `Serialize`, see the [`ser`]
+//! module, and deserialisation of types that implement `Deserialize`, see the
+//! [`de`] module.  Requires the std feature.
+//!
 //! ## Deviations from the Specification and reference implementation
 //!
 //! This implementation is intended to conform to the [GVariant specification]
@@ -105,29 +132,6 @@
 //! In this implementation the maximum size of an object is [`usize`] (typically
 //! 64-bits).  This should not be a problem in practice on 64-bit machines.
 //!
-//! ### Equality of Variant **v** type for non-normal form data
-//!
-//! See note under [`Variant`].
-//!
-//! ### Validation of non-normal form object path "o" and signature "g" types
-//!
-//! The spec says:
-//!
-//! > ### 2.7.3 Handling Non-Normal Serialised Data
-//! >
-//! > #### Invalid Object Path
-//! >
-//! > If the serialised form of an object path is not a valid object path
-//! > followed by a zero byte then the default value is used.
-//! >
-//! > #### Invalid Signature
-//! >
-//! > If the serialised form of a signature string is not a valid DBus signature
-//! > followed by a zero byte then the default value is used.
-//!
-//! We don't currently do any validation of the object path or signature types,
-//! treating them as normal strings.
-//!
 //! ### Data that overlaps framing offsets (non-normal form)
 //!
 //! This applies to arrays of non-fixed size type in non-normal form and to
@@ -167,8 +171,8 @@
 //! For other types this library defines such types (such as
 //! [`gvariant::Str`][Str] for **s** or
 //! [`gvariant::NonFixedWidthArray<[i32]>`][NonFixedWidthArray] for **aai**).
-//! For structure types this library provides a macro [`gv!`] to generate the
-//! code for struct types.
+//! For structure types this library provides generic types in the
+//! [`structure`] module.
 //!
 //! If we have a type with the same representation as the underlying bytes we
 //! can just cast the data to the appropriate type and then read it.  The macro
@@ -190,6 +194,168 @@
 //!
 //! For casting data to be valid and safe the byte buffer must be aligned...
 //!
+//! ### Naming types
+//!
+//! Use [`gv_type!`] to write the type that [`gv!`] casts to in struct fields
+//! or function signatures, or to declare a name for it:
+//!
+//!     # use gvariant::{gv_type, Marker, Structure};
+//!     gv_type! {
+//!         pub type Entry = "(sia{sv})";
+//!     }
+//!
+//!     fn name(entry: &Entry) -> &str {
+//!         entry.to_tuple().0.to_str()
+//!     }
+//!
+//!     fn size(entry: &gv_type!("(sia{sv})")) -> i32 {
+//!         *entry.field_1()
+//!     }
+//!
+//! Structures are the generic types in the [`structure`] module, so a type
+//! string always gives the same Rust type.  Here `Entry` and
+//! `gv_type!("(sia{sv})")` are the same type, and so is the type that
+//! `gv!("(sia{sv})")` casts to anywhere in your crate.  The exception is
+//! structures with more than 16 fields, see [`gv_type!`].
+//!
+//! ### Structures with named fields
+//!
+//! The structures that [`gv!`] casts to only have a `to_tuple()` method and
+//! numbered accessors `field_0()`, `field_1()`, etc., so you have to remember
+//! which field is which.  Alternatively you can
+//! `#[derive(GVariant)]` for a struct with named fields.  For a struct `Foo`
+//! this generates:
+//!
+//! * `FooView` - the zero-copy type that data is cast to, with accessor methods
+//!   named after the fields of `Foo`.
+//! * `Foo::marker()` - a [`Marker`] for casting data to `FooView`.
+//! * An implementation of [`SerializeTo<FooView>`][SerializeTo] for `&Foo`.
+//!
+//! ```rust
+//! # use gvariant::{aligned_bytes::copy_to_align, GVariant, Marker};
+//! #[derive(GVariant)]
+//! struct Person {
+//!     name: String,
+//!     age: u32,
+//! }
+//!
+//! let william = Person { name: "William".into(), age: 34 };
+//! let data = Person::marker().serialize_to_vec(&william);
+//! assert_eq!(data, b"William\0\x22\x00\x00\x00\x08");
+//!
+//! let data = copy_to_align(&data);
+//! let view: &PersonView = Person::marker().cast(data.as_ref());
+//! assert_eq!(view.name(), "William");
+//! assert_eq!(*view.age(), 34);
+//! ```
+//!
+//! The GVariant type is worked out from the types of the fields where that's
+//! possible, that is for the numeric types, `bool`, `String`/`&str` and
+//! `Vec`s, `Option`s, slices, tuples and `HashMap`s/`BTreeMap`s of those.
+//! Otherwise give the type of the field with `#[gvariant(type = "...")]` on
+//! the field, or of the whole struct on the struct.  The fields are matched to
+//! the type string in order:
+//!
+//! ```rust
+//! # use gvariant::{aligned_bytes::copy_to_align, Dict, GVariant, Marker};
+//! #[derive(GVariant)]
+//! #[gvariant(type = "(oa{ss})")]
+//! struct Object<'a> {
+//!     path: &'a str,
+//!     properties: Vec<(&'a str, &'a str)>,
+//! }
+//!
+//! let obj = Object { path: "/org/example", properties: vec![("colour", "red")] };
+//! let bytes = Object::marker().serialize_to_vec(&obj);
+//! let data = copy_to_align(&bytes);
+//! let view = Object::marker().cast(data.as_ref());
+//! assert_eq!(view.path(), "/org/example");
+//! assert_eq!(view.properties().lookup("colour").unwrap(), "red");
+//! ```
+//!
+//! The generated code refers to this crate as `::gvariant`.  If it's known by
+//! another name, such as when it's renamed in `Cargo.toml` or re-exported by
+//! another crate, give the path with `#[gvariant(crate = "...")]` on the struct
+//! or enum.  [`gv!`] and [`gv_type!`] find the crate themselves.
+//!
+//! ### Enums
+//!
+//! `#[derive(GVariant)]` can also be used on enums whose variants are unit
+//! variants or have a single unnamed field.  The enum is serialised as a
+//! **v** holding the payload of the variant, so each variant must have a
+//! different type.  Unit variants have type **()**.  The type of a payload is
+//! worked out from its rust type in the same way as for structures, or can be
+//! given with `#[gvariant(type = "...")]` on the variant or its field.
+//!
+//! As the serialised data could hold any of the variants a separate enum
+//! `FooView<'a>` is generated, with each variant holding a reference to the
+//! zero-copy payload.  It's created with `TryFrom`, which returns an
+//! [`UnknownVariant`] error if the data doesn't match any variant:
+//!
+//! ```rust
+//! # use gvariant::{aligned_bytes::copy_to_align, GVariant, Marker, VariantWrap};
+//! use std::convert::TryFrom;
+//!
+//! #[derive(GVariant)]
+//! enum Value {
+//!     None,
+//!     Int(i32),
+//!     Text(String),
+//! }
+//!
+//! let data = Value::marker().serialize_to_vec(&Value::Int(5));
+//! assert_eq!(data, gvariant::gv!("v").serialize_to_vec(VariantWrap(gvariant::gv!("i"), &5)));
+//!
+//! let data = copy_to_align(&data);
+//! let v = Value::marker().cast(data.as_ref());
+//! assert_eq!(ValueView::try_from(v), Ok(ValueView::Int(&5)));
+//! ```
+//!
+//! Alternatively the enum can be serialised as a structure **(sv)** holding
+//! the name of the variant or **(uv)** holding its index, along with the
+//! payload.  Use `#[gvariant(name = "...")]` on a variant to change the name
+//! used:
+//!
+//! ```rust
+//! # use gvariant::{aligned_bytes::copy_to_align, GVariant, Marker};
+//! # use std::convert::TryFrom;
+//! #[derive(GVariant)]
+//! #[gvariant(type = "(sv)")]
+//! enum Command<'a> {
+//!     #[gvariant(name = "quit")]
+//!     Quit,
+//!     #[gvariant(name = "open")]
+//!     Open(&'a str),
+//! }
+//!
+//! let data = Command::marker().serialize_to_vec(&Command::Open("/tmp"));
+//! let data = copy_to_align(&data);
+//! let cmd = Command::marker().cast(data.as_ref());
+//! assert_eq!(Command::marker().print(cmd, false).to_string(), "('open', <'/tmp'>)");
+//! match CommandView::try_from(cmd) {
+//!     Ok(CommandView::Open(path)) => assert_eq!(path, "/tmp"),
+//!     _ => panic!(),
+//! }
+//! ```
+//!
+//! ### Modifying data in place
+//!
+//! Fixed size data can be modified without reserialising it, for example to
+//! update a counter in a memory-mapped file.  [`Marker::try_cast_mut`] gives
+//! mutable access to fixed size types, arrays of them and [`MaybeFixedSize`].
+//! The types of non-fixed size structures have a `field_N_mut()` method for
+//! each fixed size field `N`, which returns `None` if the data is too short to
+//! contain the field:
+//!
+//! ```rust
+//! # use gvariant::{gv, Marker, Structure};
+//! let mut data = gv!("(sta{sv})").parse("('counter', 5, {'x': <1>})").unwrap();
+//! let value = gv!("(sta{sv})").try_cast_mut(&mut data)?;
+//! *value.field_1_mut().unwrap() += 1;
+//! assert_eq!(*value.to_tuple().1, 6);
+//! # Ok::<(), gvariant::casting::WrongSize>(())
+//! ```
+//!
 //! ### Use of `unsafe`
 //!
 //! I've tried to concentrate almost all of the unsafe in [`aligned_bytes`] and
@@ -209,7 +375,8 @@
 //!   This makes the build process simpler - there are no external tools, and it
 //!   makes it easier to get started - there is no new schema format to learn.
 //!   The cost is that the user is responsible for remember which field means
-//!   what and what endianness should be used to interpret the data.
+//!   what and what endianness should be used to interpret the data, although
+//!   [`GVariant`] can be derived to give the fields of structures names.
 //!
 //!   It might make sense in the future to extend GSC to generate rust code as
 //!   well - in which case the generated code may depend on this library.
@@ -231,7 +398,7 @@
 #[cfg(feature = "alloc")]
 extern crate alloc;
 #[cfg(feature = "alloc")]
-use alloc::{borrow::ToOwned, string::String};
+use alloc::{borrow::ToOwned, boxed::Box, string::String, vec::Vec};
 
 use core::{
     convert::TryInto,
@@ -240,23 +407,46 @@ use core::{
     marker::PhantomData,
 };
 
-#[cfg(feature = "std")]
-use std::io::Write;
-
-use memchr;
 use ref_cast::RefCast;
 
 pub mod aligned_bytes;
+#[cfg(feature = "alloc")]
+pub mod builder;
 use offset::align_offset;
 
 pub mod casting;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod dyn_value;
+pub mod endian;
+mod normal_form;
 mod offset;
-
-use aligned_bytes::{empty_aligned, AlignedSlice, AsAligned, A8};
+#[cfg(feature = "std")]
+pub mod parse;
+pub mod print;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod structure;
+mod unichar;
+pub mod variant_type;
+pub mod write;
+
+use aligned_bytes::{empty_aligned, AlignedSlice, Alignment, AsAligned, TryAsAligned, A8};
 use casting::{AlignOf, AllBitPatternsValid};
+pub use dyn_value::DynValue;
+#[cfg(feature = "alloc")]
+pub use variant_type::VariantType;
+pub use variant_type::VariantTypeStr;
+use write::Write;
 
+/// See ["Structures with named fields"](crate#structures-with-named-fields)
+/// and ["Enums"](crate#enums)
+pub use gvariant_macro::GVariant;
 #[doc(hidden)]
-pub use gvariant_macro::{define_gv as _define_gv, gv_type as _gv_type};
+pub use gvariant_macro::{
+    define_gv as _define_gv, define_gv_type as _define_gv_type,
+    gv_qualified_type as _gv_qualified_type, gv_type as _gv_type,
+};
 
 /// This is the return type of the `gv!` macro.
 ///
@@ -296,13 +486,46 @@ pub trait Marker: Copy {
     }
 
     /// Cast `data` to the appropriate rust type `Self::Type` for the type
-    /// string `Self::TYPESTR`.
+    /// string `Self::TYPESTR`, for modifying fixed size data in place.
+    ///
+    /// Unlike [`cast`][Marker::cast] this fails if `data` is the wrong size
+    /// for a fixed size type rather than returning the default value.
+    ///
+    ///     # use gvariant::{aligned_bytes::alloc_aligned, gv, Marker};
+    ///     let mut data = alloc_aligned(12);
+    ///     let counters = gv!("au").try_cast_mut(&mut data)?;
+    ///     counters[1] += 5;
+    ///     assert_eq!(gv!("au").cast(&data), &[0, 5, 0]);
+    ///     # Ok::<(), gvariant::casting::WrongSize>(())
+    ///
+    /// Non-fixed size structures have methods for mutable access to their
+    /// fixed size fields.  See ["Modifying data in place"](crate#modifying-data-in-place).
+    ///
+    /// This takes `&self` like [`cast`][Marker::cast] so that it can be called
+    /// on the marker returned by [`gv!`].  In gvariant 0.4 it was an associated
+    /// function, so calls like `M::try_cast_mut(data)` must now be written
+    /// `M::try_cast_mut(&marker, data)` or `marker.try_cast_mut(data)`.
     fn try_cast_mut<'a>(
+        &self,
         data: &'a mut AlignedSlice<<Self::Type as AlignOf>::AlignOf>,
     ) -> Result<&'a mut Self::Type, casting::WrongSize> {
         Self::Type::try_from_aligned_slice_mut(data)
     }
 
+    /// Is `data` in normal form for the type string `Self::TYPESTR`?
+    ///
+    /// This gives the same results as GLib's `g_variant_is_normal_form`.
+    /// Unlike [`Cast::is_normal_form`] this will also catch data that is the
+    /// wrong size for a fixed size type:
+    ///
+    ///     # use gvariant::{aligned_bytes::copy_to_align, gv, Cast, Marker};
+    ///     let data = copy_to_align(b"\x05\0\0");
+    ///     assert!(!gv!("u").is_normal_form(data.as_ref()));
+    ///     assert!(gv!("u").cast(data.as_ref()).is_normal_form());
+    fn is_normal_form(&self, data: &AlignedSlice<<Self::Type as AlignOf>::AlignOf>) -> bool {
+        is_normal_form_for_typestr(Self::TYPESTR, data)
+    }
+
     /// Read the data from r returning an owned deserialised GVariant object
     ///
     /// Example
@@ -341,6 +564,7 @@ pub trait Marker: Copy {
     ///     let v = gv!("s").from_bytes(b"An example string\0");
     ///     assert_eq!(&*v, "An example string");
     #[cfg(feature = "alloc")]
+    #[allow(clippy::wrong_self_convention)]
     fn from_bytes(&self, data: impl AsRef<[u8]>) -> <Self::Type as ToOwned>::Owned {
         let cow = aligned_bytes::copy_to_align(data.as_ref());
         self.cast(cow.as_ref()).to_owned()
@@ -365,19 +589,193 @@ pub trait Marker: Copy {
     fn serialize(
         &self,
         data: impl SerializeTo<Self::Type>,
-        out: &mut impl Write,
+        out: &mut impl std::io::Write,
     ) -> std::io::Result<usize> {
+        Ok(data.serialize(&mut write::IoWrite(out))?)
+    }
+
+    /// Serialize the data as a GVariant to a [`write::Write`]
+    ///
+    /// Like [`Marker::serialize`], but this doesn't require `std`, so it can
+    /// be used to write into a buffer in `no_std` code.  See the [`write`]
+    /// module for more information.
+    ///
+    ///     # use gvariant::{gv, Marker};
+    ///     let mut buf = [0u8; 8];
+    ///     let len = gv!("(qs)").write(&(3u16, "hi"), &mut buf.as_mut())?;
+    ///     assert_eq!(&buf[..len], b"\x03\0hi\0");
+    ///     # Ok::<(), gvariant::write::Error>(())
+    fn write(
+        &self,
+        data: impl SerializeTo<Self::Type>,
+        out: &mut impl Write,
+    ) -> Result<usize, write::Error> {
         data.serialize(out)
     }
 
+    /// Start building a container of this type one child at a time, writing
+    /// to `out`
+    ///
+    ///     # use gvariant::{gv, Marker};
+    ///     let mut out = vec![];
+    ///     let mut names = gv!("as").builder(&mut out);
+    ///     for name in ["foo", "bar"] {
+    ///         names.push(name)?;
+    ///     }
+    ///     names.finish()?;
+    ///     assert_eq!(out, b"foo\0bar\0\x04\x08");
+    ///     # Ok::<(), gvariant::write::Error>(())
+    ///
+    /// See the [`builder`] module for more information.
+    #[cfg(feature = "alloc")]
+    fn builder<W: Write>(&self, out: W) -> <Self::Type as builder::Container>::Builder<W>
+    where
+        Self::Type: builder::Container,
+    {
+        <Self::Type as builder::Container>::builder(out)
+    }
+
+    /// Print `value` in the GVariant text format, like GLib's
+    /// `g_variant_print`
+    ///
+    ///     # use gvariant::{aligned_bytes::copy_to_align, gv, Marker};
+    ///     # let data = copy_to_align(b"\x05\0\0\0");
+    ///     let value = gv!("au").cast(data.as_ref());
+    ///     assert_eq!(gv!("au").print(value, true).to_string(), "[uint32 5]");
+    ///
+    /// See the [`print`] module for more information.
+    fn print<'a>(&self, value: &'a Self::Type, type_annotate: bool) -> print::Print<'a> {
+        // gv! has already validated our type string
+        let ty = VariantTypeStr::new(Self::TYPESTR).unwrap();
+        DynValue::read(ty, value.as_aligned_slice(), dyn_value::MAX_DEPTH).print(type_annotate)
+    }
+
+    /// Parse `text` in the GVariant text format as this type, like GLib's
+    /// `g_variant_parse`
+    ///
+    /// The result is serialised in normal form, ready to be [`cast`]:
+    ///
+    ///     # use gvariant::{aligned_bytes::AsAligned, gv, Marker};
+    ///     let data = gv!("ai").parse("[1, 2, 3]").unwrap();
+    ///     assert_eq!(gv!("ai").cast(data.as_aligned()), &[1, 2, 3]);
+    ///
+    /// See the [`parse`] module for more information.
+    ///
+    /// [`cast`]: Marker::cast
+    #[cfg(feature = "std")]
+    fn parse(&self, text: &str) -> Result<Box<AlignedSlice<A8>>, parse::ParseError> {
+        // gv! has already validated our type string
+        let ty = VariantTypeStr::new(Self::TYPESTR).unwrap();
+        parse::parse(Some(ty), text).map(|(_, data)| data)
+    }
+
+    /// Serialise `value` in normal form, like GLib's
+    /// `g_variant_get_normal_form`
+    ///
+    /// `value` may have been cast from untrusted or non-normal data.  The
+    /// result is a fresh buffer that can be [`cast`] as usual:
+    ///
+    ///     # use gvariant::{aligned_bytes::{copy_to_align, AsAligned}, gv, Marker};
+    ///     // A non-zero padding byte and a boolean other than 0 or 1
+    ///     let data = copy_to_align(b"\x02\x05\x07\0\x01\0\0\0\0(byi)");
+    ///     let value = gv!("v").cast(data.as_ref());
+    ///     let normal = gv!("v").normalize(value);
+    ///     assert_eq!(&**normal, b"\x01\x05\0\0\x01\0\0\0\0(byi)");
+    ///     assert!(gv!("v").is_normal_form(normal.as_aligned()));
+    ///
+    /// [`cast`]: Marker::cast
+    #[cfg(feature = "std")]
+    fn normalize(&self, value: &Self::Type) -> Box<AlignedSlice<A8>>
+    where
+        for<'a> &'a Self::Type: SerializeTo<Self::Type>,
+    {
+        self.serialize_to_aligned(value)
+            .expect("Reserialising a value we've cast is infallible")
+    }
+
+    /// Serialise `value` in normal form with the byte order of all numbers
+    /// reversed, like GLib's `g_variant_byteswap`
+    ///
+    /// This converts data between little-endian and big-endian.  Framing
+    /// offsets are always little-endian, so they are unaffected:
+    ///
+    ///     # use gvariant::{aligned_bytes::copy_to_align, gv, Marker};
+    ///     let data = copy_to_align(b"\x01\x02\x03\x04hi\0\x09\x07");
+    ///     let m = gv!("(usy)");
+    ///     let swapped = m.byteswap(m.cast(data.as_ref()));
+    ///     assert_eq!(&**swapped, b"\x04\x03\x02\x01hi\0\x09\x07");
+    ///
+    /// See the [`endian`] module for reading data with a declared byte order
+    /// without copying.
+    #[cfg(feature = "std")]
+    fn byteswap(&self, value: &Self::Type) -> Box<AlignedSlice<A8>> {
+        // gv! has already validated our type string
+        let ty = VariantTypeStr::new(Self::TYPESTR).unwrap();
+        let value = DynValue::read(ty, value.as_aligned_slice(), dyn_value::MAX_DEPTH);
+        // Find the size first so we only allocate once
+        let size = normal_form::normalize(value, true, &mut write::Discard)
+            .expect("Discard is infallible");
+        let mut out = aligned_bytes::AlignedVec::with_capacity(size);
+        normal_form::normalize(value, true, &mut out)
+            .expect("Writing to an AlignedVec is infallible");
+        out.into_boxed_slice()
+    }
+
+    /// Serialize the data as a GVariant into `out`, starting at the beginning
+    ///
+    /// Returns the number of bytes written.  `out` is aligned for this type, so
+    /// the data can be cast without being copied.  Fails with
+    /// [`write::Error::BufferFull`] if `out` is too small.  Use
+    /// [`SerializeTo::serialized_size`] to find out how big it needs to be.
+    ///
+    ///     # use gvariant::{aligned_bytes::{alloc_aligned, A8}, gv, Marker};
+    ///     let mut buf = alloc_aligned::<A8>(64);
+    ///     let len = gv!("ai").serialize_into(&[1, 2, 3], &mut buf)?;
+    ///     assert_eq!(gv!("ai").cast(buf[..len].as_ref()), &[1, 2, 3]);
+    ///     # Ok::<(), gvariant::write::Error>(())
+    fn serialize_into<A>(
+        &self,
+        data: impl SerializeTo<Self::Type>,
+        out: &mut AlignedSlice<A>,
+    ) -> Result<usize, write::Error>
+    where
+        A: aligned_bytes::AlignedTo<<Self::Type as AlignOf>::AlignOf>,
+    {
+        let mut cursor = write::Cursor::new(out);
+        data.serialize(&mut cursor)?;
+        Ok(cursor.position())
+    }
+
+    /// Serialize the data as a GVariant into a newly allocated
+    /// [`AlignedSlice`] of exactly the right size
+    ///
+    /// Unlike [`serialize_to_vec`][Marker::serialize_to_vec] the result is
+    /// ready to [`cast`][Marker::cast] without copying it again.  The data is
+    /// serialised twice: once to find its size, and then into the buffer.
+    ///
+    ///     # use gvariant::{aligned_bytes::AsAligned, gv, Marker, Structure};
+    ///     let data = gv!("(sb)").serialize_to_aligned(&("hello", &true))?;
+    ///     assert_eq!(data.len(), 8);
+    ///     assert_eq!(gv!("(sb)").cast(data.as_aligned()).to_tuple().0, "hello");
+    ///     # Ok::<(), gvariant::write::Error>(())
+    #[cfg(feature = "alloc")]
+    fn serialize_to_aligned(
+        &self,
+        data: impl SerializeTo<Self::Type> + Copy,
+    ) -> Result<Box<AlignedSlice<A8>>, write::Error> {
+        let mut out = aligned_bytes::AlignedVec::with_capacity(data.serialized_size()?);
+        data.serialize(&mut out)?;
+        Ok(out.into_boxed_slice())
+    }
+
     /// Convenience method for in-memory serialization
     ///
     /// Used by our tests.  You probably want to use the more flexible
     /// `serialize` instead which can be used to write to files/sockets.
-    #[cfg(feature = "std")]
+    #[cfg(feature = "alloc")]
     fn serialize_to_vec(&self, data: impl SerializeTo<Self::Type>) -> Vec<u8> {
-        let mut out = vec![];
-        self.serialize(data, &mut out)
+        let mut out = Vec::new();
+        self.write(data, &mut out)
             .expect("Serialization to Vec should be infallible");
         out
     }
@@ -395,7 +793,32 @@ pub trait Marker: Copy {
 /// `SerializeTo<>` is implemented for appropriate built-in types, and you may
 /// wish to implement it for your own types as well.
 pub trait SerializeTo<T: Cast + ?Sized> {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize>;
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error>;
+
+    /// The number of bytes that [`serialize`][SerializeTo::serialize] would
+    /// write
+    ///
+    /// This serialises the data without storing it, so it takes as long as
+    /// serialising does.  `SerializeTo` is usually implemented for references,
+    /// which are `Copy`, so you can find the size of a value and then
+    /// serialise it into a buffer of exactly the right size:
+    ///
+    ///     # use gvariant::{aligned_bytes::{alloc_aligned, A8}, gv, Marker, SerializeTo, Str};
+    ///     let value = "hello";
+    ///     let size = SerializeTo::<Str>::serialized_size(value)?;
+    ///     assert_eq!(size, 6);
+    ///     let mut buf = alloc_aligned::<A8>(size);
+    ///     gv!("s").serialize_into(value, &mut buf)?;
+    ///     assert_eq!(&**buf, b"hello\0");
+    ///     # Ok::<(), gvariant::write::Error>(())
+    ///
+    /// See also [`Marker::serialize_to_aligned`].
+    fn serialized_size(self) -> Result<usize, write::Error>
+    where
+        Self: Sized,
+    {
+        self.serialize(&mut write::Discard)
+    }
 }
 
 /// Maps from GVariant typestrs to compatible Rust types returning a `Marker`.
@@ -434,23 +857,45 @@ pub trait SerializeTo<T: Cast + ?Sized> {
 /// | **q**         | [`u16`]                                                                                     | Yes                               |
 /// | **i**         | [`i32`]                                                                                     | Yes                               |
 /// | **u**         | [`u32`]                                                                                     | Yes                               |
+/// | **h**         | [`Handle`]                                                                                  | Yes                               |
 /// | **x**         | [`i64`]                                                                                     | Yes                               |
 /// | **t**         | [`u64`]                                                                                     | Yes                               |
 /// | **d**         | [`f64`]                                                                                     | Yes                               |
 /// | **s**         | [`Str`]                                                                                     | No                                |
-/// | **o**         | [`Str`]                                                                                     | No                                |
-/// | **g**         | [`Str`]                                                                                     | No                                |
+/// | **o**         | [`ObjectPath`]                                                                              | No                                |
+/// | **g**         | [`Signature`]                                                                               | No                                |
 /// | **v**         | [`Variant`]                                                                                 | No                                |
 /// | **m**s        | [`MaybeNonFixedSize<Str>`][MaybeNonFixedSize] - and similarly for all non-[`Sized`] types   | No                                |
 /// | **m**i        | [`MaybeFixedSize<i32>`][MaybeFixedSize] - and similarly for all [`Sized`] types             | No                                |
 /// | **a**s        | [`NonFixedWidthArray<Str>`][NonFixedWidthArray] - and similarly for all non-[`Sized`] types | No                                |
 /// | **a**i        | `[i32]` and similarly for all [`Sized`] types                                               | No                                |
-/// | **(sv)**      | Custom struct generated by this macro. Implements `.to_tuple()` method                      | Yes if all children are [`Sized`] |
-/// | **{si}**      | Custom struct generated by this Macro. Implements `.to_tuple()` method                      | Yes if all children are [`Sized`] |
+/// | **(sv)**      | [`NonFixed2<Str, Variant>`][structure::NonFixed2] - see the [`structure`] module            | Yes if all children are [`Sized`] |
+/// | **{si}**      | [`NonFixedEntry<Str, i32>`][structure::NonFixedEntry] - see the [`structure`] module        | Yes if all children are [`Sized`] |
+///
+/// An invalid type string is a compile error, with the message showing which
+/// character is wrong.  For example each of these fails to compile:
+///
+/// ```compile_fail
+/// # use gvariant::gv;
+/// gv!("a{s"); // unterminated dict entry
+/// ```
+///
+/// ```compile_fail
+/// # use gvariant::gv;
+/// gv!("{vs}"); // dict entry key must be a basic type
+/// ```
+///
+/// ```compile_fail
+/// # use gvariant::gv;
+/// gv!("(ii"); // unterminated tuple
+/// ```
+///
+/// On stable rust the error highlights the whole literal rather than just the
+/// problem character, because `Span::subspan` always returns `None` there.
 #[macro_export]
 macro_rules! gv {
     ($typestr:literal) => {{
-        #[allow(unused_imports)]
+        #[allow(unused_imports, clippy::unused_unit)]
         mod _m {
             use $crate::aligned_bytes::{
                 align_offset, empty_aligned, AlignedOffset, AlignedSlice, AsAligned, A1, A2, A4, A8,
@@ -458,7 +903,7 @@ macro_rules! gv {
             use $crate::casting::{AlignOf, AllBitPatternsValid};
             use $crate::*;
 
-            _define_gv!($typestr);
+            _define_gv!($crate, $typestr);
             #[derive(Copy, Clone)]
             pub(crate) struct Marker();
             impl $crate::Marker for Marker {
@@ -466,33 +911,133 @@ macro_rules! gv {
                 #[allow(clippy::string_lit_as_bytes)]
                 const TYPESTR: &'static [u8] = $typestr.as_bytes();
             }
-        };
-        // TODO: I'd much rather that this macro returns a type, rather than
-        // a value.  That way getting a gvariant looks like:
-        //
-        //     let a : <gv!("as")> = v.get()?
-        //     let b = <gv!("(yii)")>::cast(bytes);
-        //
-        // rather than:
-        //
-        //     let a = v.get(gv!("as"))?
-        //     let b = gv!("(yii)").cast(bytes);
-        //
-        // The former makes it much clearer what's happening at compile time
-        // and what's happening at run time.
-        //
-        // As it is, when I try to make this return a type I get the error
-        // message
+        }
+        // This returns a value rather than a type because a macro in type
+        // position can't define the structure types that it refers to.  See
+        // gv_type! for naming the types.
         _m::Marker()
     }};
 }
 
+/// Name the Rust type for a GVariant type string
+///
+/// In type position this is the type that [`gv!`] would cast to:
+///
+///     # use gvariant::{aligned_bytes::AsAligned, gv, gv_type, Marker};
+///     fn count(strings: &gv_type!("as")) -> usize {
+///         strings.len()
+///     }
+///
+///     fn names(related: &gv_type!("a(say)")) -> Vec<&str> {
+///         related.iter().map(|x| x.field_0().to_str()).collect()
+///     }
+///
+///     let data = gv!("a(say)").serialize_to_vec(&[("a", b"x")]);
+///     assert_eq!(names(gv!("a(say)").cast(data.as_aligned())), ["a"]);
+///
+/// Structure types are defined once in the [`structure`] module, so the same
+/// type string always names the same Rust type, whichever crate or macro
+/// invocation it appears in.
+///
+/// To give a type a name declare it instead, with the same syntax as a type
+/// alias:
+///
+///     # use gvariant::{aligned_bytes::AsAligned, gv_type, Marker, Structure};
+///     gv_type! {
+///         /// Names and data, as in an ostree commit's related objects
+///         pub type Related = "a(say)";
+///         type Point = "(ii)";
+///     }
+///
+///     struct Cache {
+///         related: Box<Related>,
+///     }
+///
+///     fn names(related: &Related) -> Vec<&str> {
+///         related.iter().map(|x| x.to_tuple().0.to_str()).collect()
+///     }
+///
+///     static ORIGIN: Point = Point::new(0, 0);
+///
+///     let data = Related.serialize_to_vec(&[("a", b"x")]);
+///     let cache = Cache { related: Related.cast(data.as_aligned()).to_owned() };
+///     assert_eq!(names(&cache.related), ["a"]);
+///     assert_eq!(ORIGIN.to_tuple(), (&0, &0));
+///
+/// Like a unit struct, this declares `Related` both as a type and as a
+/// constant.  The type is the same as `<gv!("a(say)") as Marker>::Type` and
+/// the constant is its [`Marker`], so `Related.cast(data)` gives a
+/// `&Related`.
+///
+/// Structures with more than 16 fields don't have types in the [`structure`]
+/// module.  Instead the macros generate a type for each of them where they're
+/// used, so they can only be named by declaring a type like this.  The
+/// declarations in one `gv_type!` share these generated types, but types from
+/// different invocations of `gv!` or `gv_type!` are different:
+///
+///     # use gvariant::gv_type;
+///     gv_type! {
+///         type Wide = "(yyyyyyyyyyyyyyyyy)";
+///         type Wides = "a(yyyyyyyyyyyyyyyyy)";
+///     }
+///
+///     fn first(wides: &Wides) -> &Wide {
+///         &wides[0]
+///     }
+///
+/// ```compile_fail
+/// # use gvariant::gv_type;
+/// fn first(wides: &gv_type!("a(yyyyyyyyyyyyyyyyy)")) {}
+/// ```
+#[macro_export]
+macro_rules! gv_type {
+    ($typestr:literal) => {
+        $crate::_gv_qualified_type!($crate, $typestr)
+    };
+    ($($decl:tt)*) => {
+        $crate::_define_gv_type! { $crate, $($decl)* }
+    };
+}
+
+// Not really pub, just for use by the code generated by our macro.
+#[doc(hidden)]
+pub fn is_normal_form_for_typestr(typestr: &[u8], data: &[u8]) -> bool {
+    // gv! has already validated the type string
+    normal_form::is_normal_form(VariantTypeStr::new(typestr).unwrap(), data)
+}
+
+// Not really pub, just for use by the code generated by our macro.  The
+// generated code may be in a no_std crate, so items that need to allocate are
+// only emitted if we have the alloc feature.
+#[doc(hidden)]
+#[cfg(feature = "alloc")]
+#[macro_export]
+macro_rules! _alloc_only {
+    ($($item:item)*) => {
+        const _: () = {
+            use $crate::_alloc::{Box, ToOwned};
+            $($item)*
+        };
+    };
+}
+#[doc(hidden)]
+#[cfg(not(feature = "alloc"))]
+#[macro_export]
+macro_rules! _alloc_only {
+    ($($item:item)*) => {};
+}
+#[doc(hidden)]
+#[cfg(feature = "alloc")]
+pub mod _alloc {
+    pub use alloc::{borrow::ToOwned, boxed::Box};
+}
+
 // Not really pub, just for use by the code generated by our macro.
 #[doc(hidden)]
 pub fn write_padding<A: aligned_bytes::Alignment, W: Write>(
     offset: usize,
     f: &mut W,
-) -> std::io::Result<usize> {
+) -> Result<usize, write::Error> {
     let len = align_offset::<A>(offset).to_usize() - offset;
     f.write_all(&b"\0\0\0\0\0\0\0"[..len])?;
     Ok(len)
@@ -504,11 +1049,20 @@ pub fn write_padding<A: aligned_bytes::Alignment, W: Write>(
 /// This allows casting appropriately aligned [`AlignedSlice`]s to rust types.
 ///
 /// Don't implement this class for your own types.  It's already implemented for
-/// all appropriate types.  It's automatically implemented for [`Structure`]
-/// types generated by the [`gv!`] macro.
+/// all appropriate types, including the [`Structure`] types.
 pub trait Cast:
-    casting::AlignOf + casting::AllBitPatternsValid + 'static + PartialEq + Debug + ToOwned
+    casting::AlignOf
+    + casting::AllBitPatternsValid
+    + 'static
+    + PartialEq
+    + Debug
+    + casting::ToOwnedIfAlloc
 {
+    /// The size of every value of this type if it's a fixed size type, like
+    /// **i** or **(yy)**, or `None` for types like **s** whose size depends on
+    /// the value
+    const FIXED_SIZE: Option<usize>;
+
     /// Cast `slice` to type `Self`.
     ///
     /// This always succeeds.  If the slice is the wrong size a defualt value is
@@ -527,6 +1081,24 @@ pub trait Cast:
     /// always dealing with references so [`std::default::Default`] isn't
     /// appropriate.
     fn default_ref() -> &'static Self;
+
+    /// Get the serialised data that this value was cast from.
+    ///
+    /// This is the inverse of [`Cast::from_aligned_slice`].
+    fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf>;
+
+    /// Is this value's serialised data in normal form?
+    ///
+    /// Every GVariant value has exactly one serialisation in normal form, but
+    /// data received from elsewhere may not be in normal form.  For example it
+    /// might have non-zero padding bytes, oversized framing offsets, or a
+    /// [`Bool`] with a value other than 0 or 1.  This gives the same results
+    /// as GLib's `g_variant_is_normal_form`.
+    ///
+    /// Fixed size values that were cast from data of the wrong size will have
+    /// been replaced with their default value, which is in normal form.  Use
+    /// [`Marker::is_normal_form`] to check the data itself.
+    fn is_normal_form(&self) -> bool;
     fn try_from_aligned_slice(
         slice: &AlignedSlice<Self::AlignOf>,
     ) -> Result<&Self, casting::WrongSize>;
@@ -538,9 +1110,17 @@ pub trait Cast:
 macro_rules! impl_cast_for {
     ($t:ty, $default:expr) => {
         impl Cast for $t {
+            const FIXED_SIZE: Option<usize> = Some(core::mem::size_of::<$t>());
             fn default_ref() -> &'static Self {
                 &$default
             }
+            fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+                casting::cast_to_slice(self)
+            }
+            fn is_normal_form(&self) -> bool {
+                // All bit patterns are normal
+                true
+            }
             fn try_from_aligned_slice(
                 slice: &AlignedSlice<Self::AlignOf>,
             ) -> Result<&Self, casting::WrongSize> {
@@ -553,13 +1133,13 @@ macro_rules! impl_cast_for {
             }
         }
         impl SerializeTo<$t> for $t {
-            fn serialize(self, f: &mut impl std::io::Write) -> std::io::Result<usize> {
+            fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
                 f.write_all(self.to_ne_bytes().as_ref())?;
-                Ok(std::mem::size_of::<$t>())
+                Ok(core::mem::size_of::<$t>())
             }
         }
         impl SerializeTo<$t> for &$t {
-            fn serialize(self, f: &mut impl std::io::Write) -> std::io::Result<usize> {
+            fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
                 (*self).serialize(f)
             }
         }
@@ -575,7 +1155,7 @@ impl_cast_for!(u64, 0);
 impl_cast_for!(i64, 0);
 impl_cast_for!(f64, 0.);
 
-/// Type with same representation as GVariant "s", "o" and "g" types
+/// Type with same representation as GVariant "s" type
 ///
 /// This is the type returned by:
 ///
@@ -644,10 +1224,7 @@ impl Str {
         if memchr::memchr(b'\0', b).is_some() {
             ""
         } else {
-            match core::str::from_utf8(&self.as_bytes_non_conformant()) {
-                Ok(x) => x,
-                Err(_) => "",
-            }
+            core::str::from_utf8(self.as_bytes_non_conformant()).unwrap_or_default()
         }
     }
 }
@@ -657,9 +1234,16 @@ unsafe impl AlignOf for Str {
 }
 
 impl Cast for Str {
+    const FIXED_SIZE: Option<usize> = None;
     fn default_ref() -> &'static Self {
         unsafe { &*(b"" as *const [u8] as *const Str) }
     }
+    fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+        self.data.as_aligned()
+    }
+    fn is_normal_form(&self) -> bool {
+        normal_form::is_string(&self.data)
+    }
     fn try_from_aligned_slice(
         slice: &AlignedSlice<Self::AlignOf>,
     ) -> Result<&Self, casting::WrongSize> {
@@ -672,7 +1256,7 @@ impl Cast for Str {
     }
 }
 impl SerializeTo<Str> for &Str {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
         let b = self.to_str().as_bytes();
         f.write_all(b)?;
         f.write_all(b"\0")?;
@@ -680,16 +1264,13 @@ impl SerializeTo<Str> for &Str {
     }
 }
 impl SerializeTo<Str> for &str {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
         let b = self.as_bytes();
         if memchr::memchr(b'\0', b).is_some() {
             // GVariant can't represent strings with embedded NULs.  We don't
             // want to silently encode something that won't round-trip, so fail
             // here:
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "Strings may not contain NULs",
-            ));
+            return Err(write::Error::InvalidInput("Strings may not contain NULs"));
         }
         f.write_all(self.as_bytes())?;
         f.write_all(b"\0")?;
@@ -697,18 +1278,20 @@ impl SerializeTo<Str> for &str {
     }
 }
 impl<T: SerializeTo<Str> + Copy> SerializeTo<Str> for &T {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
         (*self).serialize(f)
     }
 }
+#[cfg(feature = "alloc")]
 impl SerializeTo<Str> for &Box<Str> {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
-        self.to_str().serialize(f)
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+        SerializeTo::<Str>::serialize(self.to_str(), f)
     }
 }
+#[cfg(feature = "alloc")]
 impl SerializeTo<Str> for &String {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
-        self.as_str().serialize(f)
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+        SerializeTo::<Str>::serialize(self.as_str(), f)
     }
 }
 impl PartialEq for Str {
@@ -717,6 +1300,11 @@ impl PartialEq for Str {
             || self.to_str() == other.to_str()
     }
 }
+impl Hash for Str {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.to_str().hash(state)
+    }
+}
 impl PartialEq<Str> for str {
     fn eq(&self, other: &Str) -> bool {
         self == other.to_str()
@@ -727,6 +1315,11 @@ impl PartialEq<str> for Str {
         self.to_str() == other
     }
 }
+impl PartialOrd<str> for Str {
+    fn partial_cmp(&self, other: &str) -> Option<core::cmp::Ordering> {
+        self.to_str().partial_cmp(other)
+    }
+}
 impl Display for Str {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         core::fmt::Display::fmt(self.to_str(), f)
@@ -749,6 +1342,237 @@ impl From<&Str> for String {
     }
 }
 
+/// Type with same representation as GVariant "o" type
+///
+/// This is the type returned by:
+///
+///     # use gvariant::{aligned_bytes::copy_to_align, gv, Marker};
+///     let data = copy_to_align(b"/org/example\0");
+///     let path = gv!("o").cast(data.as_ref());
+///     assert_eq!(path.to_str(), "/org/example");
+///
+/// Following the spec, if the data isn't a valid D-Bus object path then the
+/// value is taken to be the default object path `/`:
+///
+///     # use gvariant::{aligned_bytes::copy_to_align, gv, Marker};
+///     let data = copy_to_align(b"not a path\0");
+///     assert_eq!(gv!("o").cast(data.as_ref()).to_str(), "/");
+#[derive(RefCast, Eq)]
+#[repr(transparent)]
+pub struct ObjectPath {
+    data: [u8],
+}
+
+/// Type with same representation as GVariant "g" type
+///
+/// This is the type returned by:
+///
+///     # use gvariant::{aligned_bytes::copy_to_align, gv, Marker};
+///     let data = copy_to_align(b"a{sv}\0");
+///     let sig = gv!("g").cast(data.as_ref());
+///     assert_eq!(sig.to_str(), "a{sv}");
+///
+/// Following the spec, if the data isn't a valid D-Bus signature then the value
+/// is taken to be the empty signature.
+#[derive(RefCast, Eq)]
+#[repr(transparent)]
+pub struct Signature {
+    data: [u8],
+}
+
+/// Error returned by [`ObjectPath::from_bytes_with_nul`] and
+/// [`ObjectPath::new`] for invalid object paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidObjectPath;
+
+/// Error returned by [`Signature::from_bytes_with_nul`] and [`Signature::new`]
+/// for invalid signatures
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidSignature;
+
+// ObjectPath and Signature are strings with extra validation.  Everything
+// but the validation and the default value is the same for the two:
+macro_rules! impl_validated_str {
+    ($t:ident, $err:ident, $default:expr, $is_valid:path, $what:expr) => {
+        impl $t {
+            /// Create from serialised data, that is the string followed by a
+            /// single NUL byte.  Returns an error if the data isn't valid.
+            ///
+            /// This doesn't allocate or copy.
+            pub fn from_bytes_with_nul(data: &[u8]) -> Result<&Self, $err> {
+                match data.split_last() {
+                    Some((b'\0', s)) if $is_valid(s) => Ok(Self::ref_cast(data)),
+                    _ => Err($err),
+                }
+            }
+            /// Create from a `&str`, returning an error if it isn't valid
+            #[cfg(feature = "alloc")]
+            pub fn new(s: &str) -> Result<Box<Self>, $err> {
+                if !$is_valid(s.as_bytes()) {
+                    return Err($err);
+                }
+                let mut data = Vec::with_capacity(s.len() + 1);
+                data.extend_from_slice(s.as_bytes());
+                data.push(b'\0');
+                Ok(casting::ref_cast_box(data.into_boxed_slice()))
+            }
+            /// Convert `&Self` to `&str`
+            ///
+            /// This validates the data, giving the default value if it is
+            /// invalid, so it executes in linear time with the length of the
+            /// data.  It doesn't allocate.
+            pub fn to_str(&self) -> &str {
+                self.as_gv_str().to_str()
+            }
+            /// Convert to the equivalent [`Str`], with the same validation as
+            /// [`Self::to_str`]
+            pub fn as_gv_str(&self) -> &Str {
+                // Valid data is always ASCII without embedded NULs, so is also
+                // a valid Str
+                match self.data.split_last() {
+                    Some((b'\0', s)) if $is_valid(s) => Str::ref_cast(&self.data),
+                    _ => Str::ref_cast(concat!($default, "\0").as_bytes()),
+                }
+            }
+        }
+        impl Display for $err {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.write_str(concat!("Invalid ", $what))
+            }
+        }
+        #[cfg(feature = "std")]
+        impl std::error::Error for $err {}
+        #[cfg(feature = "alloc")]
+        impl ToOwned for $t {
+            type Owned = Box<Self>;
+            fn to_owned(&self) -> Self::Owned {
+                casting::ref_cast_box(self.data.to_owned().into_boxed_slice())
+            }
+        }
+        unsafe impl AllBitPatternsValid for $t {}
+        unsafe impl AlignOf for $t {
+            type AlignOf = aligned_bytes::A1;
+        }
+        impl Cast for $t {
+            const FIXED_SIZE: Option<usize> = None;
+            fn default_ref() -> &'static Self {
+                Self::ref_cast(concat!($default, "\0").as_bytes())
+            }
+            fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+                self.data.as_aligned()
+            }
+            fn is_normal_form(&self) -> bool {
+                normal_form::is_string(&self.data) && $is_valid(&self.data[..self.data.len() - 1])
+            }
+            fn try_from_aligned_slice(
+                slice: &AlignedSlice<Self::AlignOf>,
+            ) -> Result<&Self, casting::WrongSize> {
+                Ok(Self::ref_cast(slice.as_ref()))
+            }
+            fn try_from_aligned_slice_mut(
+                slice: &mut AlignedSlice<Self::AlignOf>,
+            ) -> Result<&mut Self, casting::WrongSize> {
+                Ok(Self::ref_cast_mut(slice.as_mut()))
+            }
+        }
+        impl SerializeTo<$t> for &$t {
+            fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+                SerializeTo::<Str>::serialize(self.to_str(), f)
+            }
+        }
+        impl SerializeTo<$t> for &str {
+            fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+                if !$is_valid(self.as_bytes()) {
+                    return Err(write::Error::InvalidInput(concat!("Invalid ", $what)));
+                }
+                SerializeTo::<Str>::serialize(self, f)
+            }
+        }
+        impl<T: SerializeTo<$t> + Copy> SerializeTo<$t> for &T {
+            fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+                (*self).serialize(f)
+            }
+        }
+        #[cfg(feature = "alloc")]
+        impl SerializeTo<$t> for &Box<$t> {
+            fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+                (**self).serialize(f)
+            }
+        }
+        #[cfg(feature = "alloc")]
+        impl SerializeTo<$t> for &String {
+            fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+                SerializeTo::<$t>::serialize(self.as_str(), f)
+            }
+        }
+        impl PartialEq for $t {
+            fn eq(&self, other: &Self) -> bool {
+                self.data == other.data || self.to_str() == other.to_str()
+            }
+        }
+        impl Hash for $t {
+            fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+                self.to_str().hash(state)
+            }
+        }
+        impl PartialEq<$t> for str {
+            fn eq(&self, other: &$t) -> bool {
+                self == other.to_str()
+            }
+        }
+        impl PartialEq<str> for $t {
+            fn eq(&self, other: &str) -> bool {
+                self.to_str() == other
+            }
+        }
+        impl PartialOrd<str> for $t {
+            fn partial_cmp(&self, other: &str) -> Option<core::cmp::Ordering> {
+                self.to_str().partial_cmp(other)
+            }
+        }
+        impl Display for $t {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                core::fmt::Display::fmt(self.to_str(), f)
+            }
+        }
+        impl Debug for $t {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                core::fmt::Debug::fmt(self.to_str(), f)
+            }
+        }
+        impl<'a> From<&'a $t> for &'a str {
+            fn from(x: &'a $t) -> Self {
+                x.to_str()
+            }
+        }
+        impl<'a> From<&'a $t> for &'a Str {
+            fn from(x: &'a $t) -> Self {
+                x.as_gv_str()
+            }
+        }
+        #[cfg(feature = "alloc")]
+        impl From<&$t> for String {
+            fn from(x: &$t) -> Self {
+                x.to_str().into()
+            }
+        }
+    };
+}
+impl_validated_str!(
+    ObjectPath,
+    InvalidObjectPath,
+    "/",
+    normal_form::is_object_path,
+    "object path"
+);
+impl_validated_str!(
+    Signature,
+    InvalidSignature,
+    "",
+    normal_form::is_signature,
+    "signature"
+);
+
 // TODO: Replace this with core::str::lossy::Utf8Lossy if it's ever stabilised.
 struct DisplayUtf8Lossy<'a>(&'a [u8]);
 impl core::fmt::Display for DisplayUtf8Lossy<'_> {
@@ -785,7 +1609,7 @@ impl core::fmt::Debug for DisplayUtf8Lossy<'_> {
 ///
 /// The Variant type can contain any GVariant value.
 ///
-/// ### Non-spec conformant implementation of Equality with non-normal form data
+/// ### Equality
 ///
 /// While every value has a single canoncial byte representation ("normal form")
 /// there other representations that have the same value.  For example: values
@@ -793,19 +1617,14 @@ impl core::fmt::Debug for DisplayUtf8Lossy<'_> {
 /// normal form these bytes are 0, but they are irrelevant for the actual value.
 /// Ignoring the value of the padding bytes is correct according to the spec.
 ///
-/// This is handled correctly when comparing the values of two **(yi)**
-/// instances in rust code.  What isn't correct is the handling of `Variant`
-/// **v** types that contain non-normal data with respect to checking for
-/// equality.  Correct checking of equality would require deserialising the data
-/// according to the typestr contained within the variant, and then doing the
-/// comparison.  We don't do run-time interpretation of typestrs in this crate,
-/// prefering to do it at compile time.  Instead we just compare the underlying
-/// data.  This gives correct results for data in normal form, but there will be
-/// some false-negatives for non-normal form data.
-///
-/// Therefore [`Variant`] implements [`PartialEq`], but not [`Eq`] because the
-/// comparison is not "reflexive".
-
+/// The type of the contents of a `Variant` is only known at run-time, so
+/// variants are compared by interpreting their contents with [`DynValue`], so
+/// data that isn't in normal form is equal to its normal form.  GLib's
+/// `g_variant_equal` only compares the serialised bytes of values that are
+/// trusted to be in normal form, and compares other values by their printed
+/// text, which gives the same results except that here all NaNs are equal to
+/// each other.
+/// [`Variant`] implements [`Eq`] and [`Hash`] so it can be used as a map key.
 #[derive(RefCast)]
 #[repr(transparent)]
 pub struct Variant(AlignedSlice<A8>);
@@ -814,9 +1633,16 @@ unsafe impl AlignOf for Variant {
 }
 unsafe impl AllBitPatternsValid for Variant {}
 impl Cast for Variant {
+    const FIXED_SIZE: Option<usize> = None;
     fn default_ref() -> &'static Self {
         Self::ref_cast(empty_aligned())
     }
+    fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+        &self.0
+    }
+    fn is_normal_form(&self) -> bool {
+        normal_form::variant(&self.0, 0)
+    }
     fn try_from_aligned_slice(
         slice: &AlignedSlice<Self::AlignOf>,
     ) -> Result<&Self, casting::WrongSize> {
@@ -828,15 +1654,34 @@ impl Cast for Variant {
         Ok(Self::ref_cast_mut(slice))
     }
 }
+/// The contents are written in normal form.  Without the `alloc` feature they
+/// are copied as they are instead, so the result is only in normal form if the
+/// original was.
+#[cfg(feature = "alloc")]
+impl SerializeTo<Variant> for &Variant {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+        // The contents could be of any type, so we normalise them at run-time
+        normal_form::normalize(
+            DynValue::read(
+                VariantTypeStr::from_bytes_unchecked(b"v"),
+                &self.0,
+                dyn_value::MAX_DEPTH,
+            ),
+            false,
+            f,
+        )
+    }
+}
+/// The contents are copied as they are, as normalising them at run-time needs
+/// the `alloc` feature
+#[cfg(not(feature = "alloc"))]
 impl SerializeTo<Variant> for &Variant {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
-        // Our ability to normalise variants is limited because we only deal
-        // with typestrs at compile time and this could be anything.
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
         let (typestr, data) = self.split();
         f.write_all(data)?;
         f.write_all(b"\0")?;
         f.write_all(typestr)?;
-        Ok(data.len() + typestr.len() + 1)
+        Ok(data.len() + 1 + typestr.len())
     }
 }
 impl Debug for Variant {
@@ -878,6 +1723,19 @@ impl Variant {
             None
         }
     }
+    /// Print the variant in the GVariant text format, like GLib's
+    /// `g_variant_print`
+    ///
+    /// The output is surrounded by `<>`, and the contained value is always
+    /// type annotated.  See the [`print`] module for more information.
+    pub fn print(&self, type_annotate: bool) -> print::Print<'_> {
+        DynValue::read(
+            VariantTypeStr::from_bytes_unchecked(b"v"),
+            &self.0,
+            dyn_value::MAX_DEPTH,
+        )
+        .print(type_annotate)
+    }
     /// Destructures the variant into (typestr, data).
     ///
     /// Note: typestr is not guaranteed to be a valid GVariant type.
@@ -909,10 +1767,17 @@ impl Variant {
 }
 
 impl PartialEq for Variant {
-    /// Caveat: The current implementation has false negatives for data not in
-    /// "normal form".  This may change in the future.
     fn eq(&self, other: &Self) -> bool {
-        self.split() == other.split()
+        // Identical data always has the same value, so avoid interpreting it
+        // in the common case
+        self.0.as_ref() as &[u8] == other.0.as_ref() as &[u8]
+            || DynValue::from_variant(self) == DynValue::from_variant(other)
+    }
+}
+impl Eq for Variant {}
+impl Hash for Variant {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        DynValue::from_variant(self).hash(state)
     }
 }
 
@@ -942,9 +1807,9 @@ impl PartialEq for Variant {
 ///     let serialized_vvi = gv!("v").serialize_to_vec(
 ///         VariantWrap(gv!("v"), VariantWrap(gv!("i"), x)));
 ///
-/// Typically you'd represent rust enums as GVariant variants.  The best way to
-/// serialize enums as variants is to implement `SerializeTo` for the enum.
-/// Example:
+/// Typically you'd represent rust enums as GVariant variants.  The easiest way
+/// to do that is with `#[derive(GVariant)]`, see ["Enums"](crate#enums).
+/// Alternatively you can implement `SerializeTo` for the enum yourself:
 ///
 ///     # use gvariant::{gv, Marker, SerializeTo, VariantWrap};
 ///     enum MyEnum {
@@ -952,7 +1817,10 @@ impl PartialEq for Variant {
 ///         String(String),
 ///     }
 ///     impl SerializeTo<gvariant::Variant> for &MyEnum {
-///         fn serialize(self, f: &mut impl std::io::Write) -> std::io::Result<usize> {
+///         fn serialize(
+///             self,
+///             f: &mut impl gvariant::write::Write,
+///         ) -> Result<usize, gvariant::write::Error> {
 ///             match self {
 ///                 MyEnum::Bool(x) => VariantWrap(gv!("b"), x).serialize(f),
 ///                 MyEnum::String(x) => VariantWrap(gv!("s"), x).serialize(f),
@@ -964,14 +1832,42 @@ impl PartialEq for Variant {
 #[derive(Debug, Copy, Clone)]
 pub struct VariantWrap<M: Marker, T: SerializeTo<M::Type>>(pub M, pub T);
 impl<M: Marker, T: SerializeTo<M::Type>> SerializeTo<Variant> for VariantWrap<M, T> {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
-        let len = self.0.serialize(self.1, f)?;
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+        let len = self.0.write(self.1, f)?;
         f.write_all(b"\0")?;
         f.write_all(M::TYPESTR)?;
         Ok(len + 1 + M::TYPESTR.len())
     }
 }
 
+/// Error returned when converting data to the view type of an enum with
+/// `#[derive(GVariant)]` if it doesn't match any of the enum's variants
+///
+/// See ["Enums"](crate#enums).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnknownVariant<'a> {
+    /// The payload has this type, which doesn't match the variant's type (or
+    /// any variant's type for enums serialised as **v**)
+    Type(&'a [u8]),
+    /// The tag of an enum serialised as **(sv)** doesn't match any variant
+    Name(&'a str),
+    /// The tag of an enum serialised as **(uv)** doesn't match any variant
+    Index(u32),
+}
+impl Display for UnknownVariant<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            UnknownVariant::Type(ty) => {
+                write!(f, "No enum variant with type {}", DisplayUtf8Lossy(ty))
+            }
+            UnknownVariant::Name(name) => write!(f, "No enum variant named {:?}", name),
+            UnknownVariant::Index(n) => write!(f, "No enum variant with index {}", n),
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for UnknownVariant<'_> {}
+
 // #### 2.5.3.1 Fixed Width Arrays
 //
 // In this case, the serialised form of each array element is packed
@@ -986,19 +1882,26 @@ impl<M: Marker, T: SerializeTo<M::Type>> SerializeTo<Variant> for VariantWrap<M,
 //
 // We implement this a normal rust slice.
 
-impl<'a, T: Cast + 'static + Copy> Cast for [T] {
+impl<T: Cast + 'static + Copy> Cast for [T] {
+    const FIXED_SIZE: Option<usize> = None;
     fn default_ref() -> &'static Self {
         &[]
     }
+    fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+        casting::slice_to_aligned(self)
+    }
+    fn is_normal_form(&self) -> bool {
+        self.iter().all(T::is_normal_form)
+    }
     fn try_from_aligned_slice(
         slice: &AlignedSlice<Self::AlignOf>,
     ) -> Result<&Self, casting::WrongSize> {
         casting::cast_slice::<Self::AlignOf, T>(slice)
     }
     fn try_from_aligned_slice_mut(
-        _: &mut AlignedSlice<Self::AlignOf>,
+        slice: &mut AlignedSlice<Self::AlignOf>,
     ) -> Result<&mut Self, casting::WrongSize> {
-        todo!()
+        casting::cast_slice_mut::<Self::AlignOf, T>(slice)
     }
 }
 
@@ -1006,7 +1909,7 @@ impl<GvT: Cast + Copy, It: IntoIterator> SerializeTo<[GvT]> for It
 where
     It::Item: SerializeTo<GvT>,
 {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
         let mut bytes_written = 0;
         for x in self.into_iter() {
             bytes_written += x.serialize(f)?;
@@ -1139,9 +2042,21 @@ unsafe impl<T: Cast + ?Sized> AlignOf for NonFixedWidthArray<T> {
 }
 unsafe impl<T: Cast + ?Sized> AllBitPatternsValid for NonFixedWidthArray<T> {}
 impl<T: Cast + ?Sized> Cast for NonFixedWidthArray<T> {
+    const FIXED_SIZE: Option<usize> = None;
     fn default_ref() -> &'static Self {
         Self::ref_cast(empty_aligned())
     }
+    fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+        &self.data
+    }
+    fn is_normal_form(&self) -> bool {
+        normal_form::variable_sized_array(&self.data, <T::AlignOf as Alignment>::ALIGNMENT, |x| {
+            match x.try_as_aligned() {
+                Ok(x) => T::from_aligned_slice(x).is_normal_form(),
+                Err(_) => false,
+            }
+        })
+    }
     fn try_from_aligned_slice(
         slice: &AlignedSlice<Self::AlignOf>,
     ) -> Result<&Self, casting::WrongSize> {
@@ -1172,7 +2087,7 @@ impl<T: Cast + ?Sized> NonFixedWidthArray<T> {
         self.len() == 0
     }
     /// Returns an iterator over the array.
-    pub fn iter(&self) -> NonFixedWidthArrayIterator<T> {
+    pub fn iter(&self) -> NonFixedWidthArrayIterator<'_, T> {
         self.into_iter()
     }
     /// Returns the first element of the array, or [`None`] if it is empty.
@@ -1207,6 +2122,14 @@ impl<T: Cast + PartialEq + ?Sized> PartialEq for NonFixedWidthArray<T> {
     }
 }
 impl<T: Cast + PartialEq + Eq + ?Sized> Eq for NonFixedWidthArray<T> {}
+impl<T: Cast + Hash + ?Sized> Hash for NonFixedWidthArray<T> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        state.write_usize(self.len());
+        for x in self {
+            x.hash(state);
+        }
+    }
+}
 
 /// A iterator over the items of a [`NonFixedWidthArray`]
 ///
@@ -1311,53 +2234,97 @@ impl<Item: Cast + 'static + ?Sized> core::ops::Index<usize> for NonFixedWidthArr
     }
 }
 
+// The framing offsets are written after all the elements, so we need to store
+// them as we go
+#[cfg(feature = "alloc")]
 impl<GvT: Cast + ?Sized, It: IntoIterator> SerializeTo<NonFixedWidthArray<GvT>> for It
 where
     It::Item: SerializeTo<GvT>,
 {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
         let mut bytes_written = 0;
-        let mut offsets = vec![];
+        let mut offsets = Vec::new();
         for x in self.into_iter() {
+            // There's no padding after the last element, only between them
+            bytes_written += write_padding::<GvT::AlignOf, _>(bytes_written, f)?;
             bytes_written += x.serialize(f)?;
             offsets.push(bytes_written);
-            let padding = align_offset::<GvT::AlignOf>(bytes_written).to_usize() - bytes_written;
-            f.write_all(&b"\0\0\0\0\0\0\0"[..padding])?;
-            bytes_written += padding;
         }
         write_offsets(bytes_written, offsets.as_ref(), f)
     }
 }
 
+// Without alloc we have nowhere to store the framing offsets, so instead we
+// iterate over the elements three times: to find the size of the offsets, to
+// write the elements and to write the offsets.
+#[cfg(not(feature = "alloc"))]
+impl<GvT: Cast + ?Sized, It: IntoIterator + Clone> SerializeTo<NonFixedWidthArray<GvT>> for It
+where
+    It::Item: SerializeTo<GvT>,
+{
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+        fn write_elements<GvT: Cast + ?Sized, It: IntoIterator>(
+            it: It,
+            f: &mut impl Write,
+            mut on_end: impl FnMut(usize) -> Result<(), write::Error>,
+        ) -> Result<usize, write::Error>
+        where
+            It::Item: SerializeTo<GvT>,
+        {
+            let mut bytes_written = 0;
+            for x in it {
+                bytes_written += write_padding::<GvT::AlignOf, _>(bytes_written, f)?;
+                bytes_written += x.serialize(f)?;
+                on_end(bytes_written)?;
+            }
+            Ok(bytes_written)
+        }
+        let mut n = 0;
+        let len = write_elements(self.clone(), &mut write::Discard, |_| {
+            n += 1;
+            Ok(())
+        })?;
+        let size = framing_offset_size(len, n);
+        write_elements(self.clone(), f, |_| Ok(()))?;
+        write_elements(self, &mut write::Discard, |end| write_offset(end, size, f))?;
+        Ok(len + n * size)
+    }
+}
+
 // Not really pub, just for use by the code generated by our macro.
 #[doc(hidden)]
 pub fn write_offsets(
-    mut bytes_written: usize,
+    bytes_written: usize,
     offsets: &[usize],
     f: &mut impl Write,
-) -> std::io::Result<usize> {
-    if bytes_written + offsets.len() <= 0xff {
-        bytes_written += offsets.len();
-        for offset in offsets {
-            f.write_all((*offset as u8).to_le_bytes().as_ref())?;
-        }
-    } else if bytes_written + offsets.len() * 2 <= 0xffff {
-        bytes_written += offsets.len() * 2;
-        for offset in offsets {
-            f.write_all((*offset as u16).to_le_bytes().as_ref())?;
-        }
-    } else if bytes_written + offsets.len() * 4 <= 0xffff_ffff {
-        bytes_written += offsets.len() * 4;
-        for offset in offsets {
-            f.write_all((*offset as u32).to_le_bytes().as_ref())?;
-        }
+) -> Result<usize, write::Error> {
+    let size = framing_offset_size(bytes_written, offsets.len());
+    for offset in offsets {
+        write_offset(*offset, size, f)?;
+    }
+    Ok(bytes_written + offsets.len() * size)
+}
+
+// The size of each of `n` framing offsets appended to `bytes_written` bytes of
+// data
+pub(crate) fn framing_offset_size(bytes_written: usize, n: usize) -> usize {
+    if bytes_written + n <= 0xff {
+        1
+    } else if bytes_written + n * 2 <= 0xffff {
+        2
+    } else if bytes_written + n * 4 <= 0xffff_ffff {
+        4
     } else {
-        bytes_written += offsets.len() * 8;
-        for offset in offsets {
-            f.write_all((*offset as u64).to_le_bytes().as_ref())?;
-        }
+        8
     }
-    Ok(bytes_written)
+}
+
+pub(crate) fn write_offset(
+    offset: usize,
+    size: usize,
+    f: &mut impl Write,
+) -> Result<(), write::Error> {
+    f.write_all(&(offset as u64).to_le_bytes()[..size])
 }
 
 // 2.5.2 Maybes
@@ -1388,7 +2355,6 @@ pub fn write_offsets(
 /// is non-fixed, but it implements the same interface as this type
 ///
 /// You probably just want to call `.to_option()` on this type.
-
 #[repr(transparent)]
 #[derive(RefCast)]
 pub struct MaybeFixedSize<T: Cast> {
@@ -1434,6 +2400,11 @@ impl<T: Cast> MaybeFixedSize<T> {
         // value is taken to be `Nothing`.
         T::try_from_aligned_slice(&self.data).ok()
     }
+    /// Like [`to_option`][MaybeFixedSize::to_option], but for modifying the
+    /// contents in place
+    pub fn to_option_mut(&mut self) -> Option<&mut T> {
+        T::try_from_aligned_slice_mut(&mut self.data).ok()
+    }
 }
 
 impl<'a, T: Cast> From<&'a MaybeFixedSize<T>> for Option<&'a T> {
@@ -1459,12 +2430,12 @@ impl<T: Cast + PartialEq> PartialEq<&MaybeFixedSize<T>> for Option<&T> {
     }
 }
 impl<T: Cast + PartialOrd> PartialOrd for MaybeFixedSize<T> {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
         self.to_option().partial_cmp(&other.to_option())
     }
 }
 impl<T: Cast + Hash> Hash for MaybeFixedSize<T> {
-    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
         self.to_option().hash(state)
     }
 }
@@ -1475,9 +2446,19 @@ unsafe impl<T: Cast> AlignOf for MaybeFixedSize<T> {
 unsafe impl<T: Cast> AllBitPatternsValid for MaybeFixedSize<T> {}
 
 impl<T: Cast> Cast for MaybeFixedSize<T> {
+    const FIXED_SIZE: Option<usize> = None;
     fn default_ref() -> &'static Self {
         Self::ref_cast(empty_aligned())
     }
+    fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+        &self.data
+    }
+    fn is_normal_form(&self) -> bool {
+        // A `Just` must contain exactly one `T`.  `T` is fixed size so
+        // `try_from_aligned_slice` checks this for us.
+        self.data.is_empty()
+            || T::try_from_aligned_slice(&self.data).map_or(false, T::is_normal_form)
+    }
     fn try_from_aligned_slice(
         slice: &AlignedSlice<Self::AlignOf>,
     ) -> Result<&Self, casting::WrongSize> {
@@ -1498,11 +2479,11 @@ impl<'a, T: Cast> IntoIterator for &'a MaybeFixedSize<T> {
     }
 }
 
-impl<'a, GvT: Cast + SerializeTo<GvT>> SerializeTo<MaybeFixedSize<GvT>> for &'a MaybeFixedSize<GvT>
+impl<'a, GvT: Cast> SerializeTo<MaybeFixedSize<GvT>> for &'a MaybeFixedSize<GvT>
 where
     &'a GvT: SerializeTo<GvT>,
 {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
         SerializeTo::<MaybeFixedSize<GvT>>::serialize(&self.to_option(), f)
     }
 }
@@ -1511,7 +2492,7 @@ impl<GvT: Cast, T: SerializeTo<GvT> + Copy> SerializeTo<MaybeFixedSize<GvT>> for
 where
     T: SerializeTo<GvT>,
 {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
         if let Some(x) = self {
             x.serialize(f)
         } else {
@@ -1538,7 +2519,6 @@ where
 /// Maybes are encoded differently depending on if their element type is
 /// fixed-sized or not.  [`MaybeFixedSize`] is used when the contained size is
 /// fixed, but it implements the same interface as this type.
-
 #[derive(RefCast)]
 #[repr(transparent)]
 pub struct MaybeNonFixedSize<T: Cast + ?Sized> {
@@ -1586,9 +2566,19 @@ unsafe impl<T: Cast + ?Sized> AlignOf for MaybeNonFixedSize<T> {
 unsafe impl<T: Cast + ?Sized> AllBitPatternsValid for MaybeNonFixedSize<T> {}
 
 impl<T: Cast + ?Sized> Cast for MaybeNonFixedSize<T> {
+    const FIXED_SIZE: Option<usize> = None;
     fn default_ref() -> &'static Self {
         Self::ref_cast(empty_aligned())
     }
+    fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+        &self.data
+    }
+    fn is_normal_form(&self) -> bool {
+        normal_form::maybe(&self.data, false, |x| match x.try_as_aligned() {
+            Ok(x) => T::from_aligned_slice(x).is_normal_form(),
+            Err(_) => false,
+        })
+    }
     fn try_from_aligned_slice(
         slice: &AlignedSlice<Self::AlignOf>,
     ) -> Result<&Self, casting::WrongSize> {
@@ -1621,6 +2611,11 @@ impl<T: Cast + PartialEq + ?Sized> PartialEq for MaybeNonFixedSize<T> {
     }
 }
 impl<T: Cast + Eq + ?Sized> Eq for MaybeNonFixedSize<T> {}
+impl<T: Cast + Hash + ?Sized> Hash for MaybeNonFixedSize<T> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.to_option().hash(state)
+    }
+}
 impl<T: Cast + PartialEq> PartialEq<Option<&T>> for MaybeNonFixedSize<T> {
     fn eq(&self, other: &Option<&T>) -> bool {
         self.to_option() == *other
@@ -1636,7 +2631,7 @@ impl<GvT: Cast + ?Sized, T: IntoIterator> SerializeTo<MaybeNonFixedSize<GvT>> fo
 where
     T::Item: SerializeTo<GvT>,
 {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
         if let Some(x) = self.into_iter().next() {
             let len = x.serialize(f)?;
             f.write_all(b"\0")?;
@@ -1667,9 +2662,16 @@ impl Bool {
     }
 }
 impl Cast for Bool {
+    const FIXED_SIZE: Option<usize> = Some(1);
     fn default_ref() -> &'static Self {
         &Bool(0u8)
     }
+    fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+        casting::cast_to_slice(self)
+    }
+    fn is_normal_form(&self) -> bool {
+        self.0 < 2
+    }
     fn try_from_aligned_slice(
         slice: &AlignedSlice<Self::AlignOf>,
     ) -> Result<&Self, casting::WrongSize> {
@@ -1700,77 +2702,286 @@ impl PartialEq for Bool {
         self.to_bool() == other.to_bool()
     }
 }
+impl Hash for Bool {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.to_bool().hash(state)
+    }
+}
 impl PartialEq<bool> for Bool {
     fn eq(&self, other: &bool) -> bool {
         self.to_bool() == *other
     }
 }
+impl PartialOrd<bool> for Bool {
+    fn partial_cmp(&self, other: &bool) -> Option<core::cmp::Ordering> {
+        self.to_bool().partial_cmp(other)
+    }
+}
 impl PartialEq<Bool> for bool {
     fn eq(&self, other: &Bool) -> bool {
         other == self
     }
 }
 impl SerializeTo<Bool> for &Bool {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
         self.to_bool().serialize(f)
     }
 }
 
 impl SerializeTo<Bool> for &bool {
-    fn serialize(self, f: &mut impl Write) -> std::io::Result<usize> {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
         f.write_all(if *self { b"\x01" } else { b"\x00" })?;
         Ok(1)
     }
 }
 
-/// A trait that all generated structure types implement.
+/// Type with same representation as GVariant "h" type
+///
+/// This is the type returned by:
+///
+///     # use gvariant::{aligned_bytes::copy_to_align, gv, Handle, Marker};
+///     # let bytes = 3i32.to_ne_bytes();
+///     # let data = copy_to_align(&bytes);
+///     let h = gv!("h").cast(data.as_ref());
+///     # assert_eq!(*h, Handle(3));
+///
+/// A handle is a 32-bit signed integer, but it isn't a number in its own
+/// right.  By convention it's an index into an array of file descriptors that
+/// is sent alongside the message, as in D-Bus's `UNIX_FD` type.  This crate
+/// doesn't deal with the file descriptors themselves, so the index is all you
+/// get.  It has its own type to stop it being confused with an **i**.
+#[derive(RefCast, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+#[repr(transparent)]
+pub struct Handle(pub i32);
+impl Cast for Handle {
+    const FIXED_SIZE: Option<usize> = Some(4);
+    fn default_ref() -> &'static Self {
+        &Handle(0)
+    }
+    fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+        casting::cast_to_slice(self)
+    }
+    fn is_normal_form(&self) -> bool {
+        // All bit patterns are normal
+        true
+    }
+    fn try_from_aligned_slice(
+        slice: &AlignedSlice<Self::AlignOf>,
+    ) -> Result<&Self, casting::WrongSize> {
+        casting::try_cast_slice_to::<Self>(slice)
+    }
+    fn try_from_aligned_slice_mut(
+        slice: &mut AlignedSlice<Self::AlignOf>,
+    ) -> Result<&mut Self, casting::WrongSize> {
+        casting::try_cast_slice_to_mut::<Self>(slice)
+    }
+}
+unsafe impl AllBitPatternsValid for Handle {}
+unsafe impl AlignOf for Handle {
+    type AlignOf = aligned_bytes::A4;
+}
+impl From<Handle> for i32 {
+    fn from(h: Handle) -> Self {
+        h.0
+    }
+}
+impl From<i32> for Handle {
+    fn from(x: i32) -> Self {
+        Handle(x)
+    }
+}
+impl SerializeTo<Handle> for Handle {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+        self.0.serialize(f)
+    }
+}
+impl SerializeTo<Handle> for &Handle {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+        (*self).serialize(f)
+    }
+}
+
+/// A trait that all structure types implement.
 ///
-/// This exists mostly to document the interface of the generated types.  Don't
-/// implement this for your own types.
+/// This exists mostly to document the interface of the types in the
+/// [`structure`] module.  Don't implement this for your own types.
 ///
-/// All structures also implement `Into<Self::RefTuple>`.
+/// All structures also implement `Into<Self::RefTuple>`, `Debug` and
+/// `PartialEq`, and `Eq` and `Hash` unless they contain a **d**.  These don't
+/// depend on the standard library's tuple implementations, so they are
+/// available for structures with any number of fields.
+///
+/// Each field N also has an accessor `field_N()`, returning the same reference
+/// as `to_tuple().N`.  Finding a field of a structure that isn't fixed size
+/// means reading framing offsets, so if you only need some fields of a wide
+/// structure this is cheaper than `to_tuple()`, which finds all of them:
+///
+///     # use gvariant::{aligned_bytes::copy_to_align, gv, Marker, Structure};
+///     let data = gv!("(sssi)").serialize_to_vec(&("a", "b", "c", 4));
+///     let data = copy_to_align(&data);
+///     let s = gv!("(sssi)").cast(data.as_ref());
+///     assert_eq!(s.field_2(), "c");
+///     assert_eq!(*s.field_3(), 4);
 pub trait Structure<'a>: Cast + Debug + casting::AlignOf + casting::AllBitPatternsValid {
+    /// The number of fields in the structure
+    ///
+    /// For **(is)** this is 2.
+    const N_FIELDS: usize;
+
     /// This a tuple of refs, one for each structure element
     ///
     /// For **(is)** this will be `(&'a i32, &'a Str)`.
     type RefTuple;
 
     /// Convert this struct to a rust tuple
+    ///
+    /// This finds every field.  Use the `field_N()` accessors to find just the
+    /// ones you need.
     fn to_tuple(&'a self) -> Self::RefTuple;
 }
 
+/// A trait that the dictionary entry types **{KV}** implement.
+///
+/// Like [`Structure`] don't implement this for your own types.
+pub trait DictEntry {
+    /// The type of the key **K**.  This is always a basic type.
+    type Key: Cast + ?Sized;
+    /// The type of the value **V**
+    type Value: Cast + ?Sized;
+
+    /// The key, the first field of the entry
+    fn key(&self) -> &Self::Key;
+    /// The value, the second field of the entry
+    fn value(&self) -> &Self::Value;
+}
+
+/// Key lookup for dictionaries, that is arrays of dictionary entries **a{KV}**
+///
+/// This is implemented for both `[T]` and [`NonFixedWidthArray<T>`] where `T`
+/// is a dictionary entry type:
+///
+///     # use gvariant::{aligned_bytes::copy_to_align, gv, Dict, Marker};
+///     let data = copy_to_align(b"a\0\0\0\0\0\0\0\x05\0\0\0\0i\x02\x0f");
+///     let dict = gv!("a{sv}").cast(data.as_ref());
+///     let value = dict.lookup("a").unwrap();
+///     assert_eq!(*value.get(gv!("i")).unwrap(), 5);
+///     assert!(dict.lookup("b").is_none());
+///
+/// Keys can be compared with anything the key type implements [`PartialEq`]
+/// for, so `&str` for **s**, **o** and **g** and the rust integer types for the
+/// integer types.
+pub trait Dict {
+    /// The type of the dictionary entries **{KV}**
+    type Entry: DictEntry + ?Sized;
+
+    /// The number of entries in the dictionary
+    fn n_entries(&self) -> usize;
+
+    /// The `n`th entry in the dictionary
+    ///
+    /// Panics if `n >= self.n_entries()`
+    fn entry(&self, n: usize) -> &Self::Entry;
+
+    /// Find the value for `key`
+    ///
+    /// This searches the dictionary from the start, so takes time proportional
+    /// to its length.  If there are duplicate keys the first one wins.
+    fn lookup<Q: ?Sized>(&self, key: &Q) -> Option<&<Self::Entry as DictEntry>::Value>
+    where
+        <Self::Entry as DictEntry>::Key: PartialEq<Q>,
+    {
+        (0..self.n_entries())
+            .map(|n| self.entry(n))
+            .find(|e| *e.key() == *key)
+            .map(DictEntry::value)
+    }
+
+    /// Find the value for `key` in a dictionary sorted by key
+    ///
+    /// This uses a binary search so takes time proportional to the log of the
+    /// length of the dictionary.  Dictionaries aren't sorted in general, but
+    /// some formats such as ostree's commit metadata do sort them so they can be
+    /// looked up efficiently.  Strings are sorted bytewise, like `strcmp`.
+    ///
+    /// If the dictionary isn't sorted the result is unspecified, but this won't
+    /// panic.
+    ///
+    ///     # use gvariant::{aligned_bytes::copy_to_align, gv, Dict, Marker};
+    ///     let data = copy_to_align(b"\x01\x00\x00\x00\x0a\x00\x00\x00\x05\x00\x00\x00\x14\x00\x00\x00");
+    ///     let dict = gv!("a{uu}").cast(data.as_ref());
+    ///     assert_eq!(dict.lookup_sorted(&5), Some(&20));
+    ///     assert_eq!(dict.lookup_sorted(&2), None);
+    fn lookup_sorted<Q: ?Sized>(&self, key: &Q) -> Option<&<Self::Entry as DictEntry>::Value>
+    where
+        <Self::Entry as DictEntry>::Key: PartialOrd<Q>,
+    {
+        let (mut lo, mut hi) = (0, self.n_entries());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let entry = self.entry(mid);
+            match entry.key().partial_cmp(key)? {
+                core::cmp::Ordering::Less => lo = mid + 1,
+                core::cmp::Ordering::Greater => hi = mid,
+                core::cmp::Ordering::Equal => return Some(entry.value()),
+            }
+        }
+        None
+    }
+}
+
+impl<T: Cast + DictEntry> Dict for [T] {
+    type Entry = T;
+    fn n_entries(&self) -> usize {
+        self.len()
+    }
+    fn entry(&self, n: usize) -> &T {
+        &self[n]
+    }
+}
+
+impl<T: Cast + DictEntry + ?Sized> Dict for NonFixedWidthArray<T> {
+    type Entry = T;
+    fn n_entries(&self) -> usize {
+        self.len()
+    }
+    fn entry(&self, n: usize) -> &T {
+        &self[n]
+    }
+}
+
 #[inline]
 fn nth_last_frame_offset(data: &[u8], osz: crate::OffsetSize, n: usize) -> Option<usize> {
     if n == 0 {
         Some(0)
-    } else if let Some(off) = usize::checked_sub(data.len(), n * osz as usize) {
-        Some(read_uint(&data[off..], osz, 0))
     } else {
-        None
+        usize::checked_sub(data.len(), n * osz as usize).map(|off| read_uint(&data[off..], osz, 0))
     }
 }
 
+/// The start and end of a child of a non-fixed size structure
+///
+/// `i`, `a`, `b` and `c` are as described for [`get_child_elem`].  This is
+/// shared by the [`structure`] types, the structures generated by [`gv!`] and
+/// [`DynValue`], which only knows `b` at run-time.
 #[inline]
-fn calc_offsets<ChildAlign: aligned_bytes::Alignment, B: aligned_bytes::Alignment>(
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn calc_offsets(
     data: &[u8],
     i: isize,
     a: usize,
+    b: usize,
     c: usize,
     size: Option<usize>,
     last_child: bool,
     n_frame_offsets: usize,
-) -> Option<(aligned_bytes::AlignedOffset<ChildAlign>, usize)>
-where
-    aligned_bytes::AlignedOffset<B>: Into<aligned_bytes::AlignedOffset<ChildAlign>>,
-{
+) -> Option<(usize, usize)> {
     let osz = offset_size(data.len());
     let fo = nth_last_frame_offset(data, osz, (i + 1) as usize)?;
     let data_end = usize::checked_sub(data.len(), osz as usize * n_frame_offsets)?;
 
-    let start: aligned_bytes::AlignedOffset<ChildAlign> = align_offset::<B>(fo + a).into()
-        + aligned_bytes::AlignedOffset::<ChildAlign>::try_new(c).unwrap();
+    let start = variant_type::align(fo + a, b) + c;
     let end = if let Some(size) = size {
-        start.to_usize() + size
+        start + size
     } else if last_child {
         data_end
     } else {
@@ -1783,6 +2994,34 @@ where
     }
 }
 
+#[inline]
+fn calc_aligned_offsets<ChildAlign: aligned_bytes::Alignment, B: aligned_bytes::Alignment>(
+    data: &[u8],
+    i: isize,
+    a: usize,
+    c: usize,
+    size: Option<usize>,
+    last_child: bool,
+    n_frame_offsets: usize,
+) -> Option<(aligned_bytes::AlignedOffset<ChildAlign>, usize)>
+where
+    aligned_bytes::AlignedOffset<B>: Into<aligned_bytes::AlignedOffset<ChildAlign>>,
+{
+    let (start, end) = calc_offsets(
+        data,
+        i,
+        a,
+        B::ALIGNMENT,
+        c,
+        size,
+        last_child,
+        n_frame_offsets,
+    )?;
+    // `start` is a multiple of `B` plus `c`, both of which are aligned for the
+    // child, so this doesn't change it.
+    Some((align_offset::<ChildAlign>(start), end))
+}
+
 /// Used for getting children of structures
 ///
 /// `i`, `a`, `B` and `c` are described in the GVariant spec section "3.2.2
@@ -1804,7 +3043,7 @@ pub fn get_child_elem<T: Cast + ?Sized, B: aligned_bytes::Alignment>(
 where
     aligned_bytes::AlignedOffset<B>: Into<aligned_bytes::AlignedOffset<T::AlignOf>>,
 {
-    match calc_offsets::<<T as AlignOf>::AlignOf, B>(
+    match calc_aligned_offsets::<<T as AlignOf>::AlignOf, B>(
         data,
         i,
         a,
@@ -1813,11 +3052,42 @@ where
         last_child,
         n_frame_offsets,
     ) {
-        Some((start, end)) => &T::from_aligned_slice(&data[..end][start..]),
-        None => &T::default_ref(),
+        Some((start, end)) => T::from_aligned_slice(&data[..end][start..]),
+        None => T::default_ref(),
     }
 }
 
+/// Mutable access to a fixed size child of a non-fixed size structure
+///
+/// Returns `None` if the child doesn't fit in `data`, in which case it reads
+/// as its default value.  Not really pub, just for use by the code generated
+/// by our macro.
+#[doc(hidden)]
+#[inline]
+pub fn get_child_elem_mut<T: Cast + ?Sized, B: aligned_bytes::Alignment>(
+    data: &mut AlignedSlice<<T as AlignOf>::AlignOf>,
+    i: isize,
+    a: usize,
+    c: usize,
+    child_size: Option<usize>,
+    last_child: bool,
+    n_frame_offsets: usize,
+) -> Option<&mut T>
+where
+    aligned_bytes::AlignedOffset<B>: Into<aligned_bytes::AlignedOffset<T::AlignOf>>,
+{
+    let (start, end) = calc_aligned_offsets::<<T as AlignOf>::AlignOf, B>(
+        data,
+        i,
+        a,
+        c,
+        child_size,
+        last_child,
+        n_frame_offsets,
+    )?;
+    T::try_from_aligned_slice_mut(&mut data[..end][start..]).ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1829,46 +3099,31 @@ mod tests {
         let aligned_slice: &AlignedSlice<A8> = data.as_ref();
 
         // If the size doesn't match exactly it should default to 0:
-        assert_eq!(
-            *i32::from_aligned_slice(&aligned_slice[..0].as_aligned()),
-            0
-        );
-        assert_eq!(
-            *i32::from_aligned_slice(&aligned_slice[..3].as_aligned()),
-            0
-        );
-        assert_eq!(
-            *i32::from_aligned_slice(&aligned_slice[..5].as_aligned()),
-            0
-        );
-        assert_eq!(
-            *i32::from_aligned_slice(&aligned_slice[..8].as_aligned()),
-            0
-        );
+        assert_eq!(*i32::from_aligned_slice(aligned_slice[..0].as_aligned()), 0);
+        assert_eq!(*i32::from_aligned_slice(aligned_slice[..3].as_aligned()), 0);
+        assert_eq!(*i32::from_aligned_slice(aligned_slice[..5].as_aligned()), 0);
+        assert_eq!(*i32::from_aligned_slice(aligned_slice[..8].as_aligned()), 0);
 
         // Common case (Little endian):
+        assert!(Bool::from_aligned_slice(aligned_slice[..1].as_aligned()).to_bool());
         assert_eq!(
-            Bool::from_aligned_slice(&aligned_slice[..1].as_aligned()).to_bool(),
-            true
-        );
-        assert_eq!(
-            *u8::from_aligned_slice(&aligned_slice[..1].as_aligned()),
+            *u8::from_aligned_slice(aligned_slice[..1].as_aligned()),
             0x01
         );
         assert_eq!(
-            *i16::from_aligned_slice(&aligned_slice[..2].as_aligned()),
+            *i16::from_aligned_slice(aligned_slice[..2].as_aligned()),
             0x0201
         );
         assert_eq!(
-            *u16::from_aligned_slice(&aligned_slice[..2].as_aligned()),
+            *u16::from_aligned_slice(aligned_slice[..2].as_aligned()),
             0x0201
         );
         assert_eq!(
-            *i32::from_aligned_slice(&aligned_slice[..4].as_aligned()),
+            *i32::from_aligned_slice(aligned_slice[..4].as_aligned()),
             0x04030201
         );
         assert_eq!(
-            *u32::from_aligned_slice(&aligned_slice[..4].as_aligned()),
+            *u32::from_aligned_slice(aligned_slice[..4].as_aligned()),
             0x04030201
         );
         assert_eq!(
@@ -1984,7 +3239,7 @@ mod tests {
     fn test_non_fixed_width_array_panic() {
         // Non-normal regression test found by fuzzing:
         let nfwa = NonFixedWidthArray::<[u8]>::from_aligned_slice(b"\x08".as_aligned());
-        &nfwa[0];
+        let _ = &nfwa[0];
     }
 
     #[test]
@@ -2033,7 +3288,7 @@ mod tests {
         assert_eq!(aob, &[0x04u8, 0x05, 0x06, 0x07]);
         assert_eq!(
             gv!("ay")
-                .serialize_to_vec(&[0x04u8, 0x05, 0x06, 0x07])
+                .serialize_to_vec([0x04u8, 0x05, 0x06, 0x07])
                 .as_slice(),
             &[0x04u8, 0x05, 0x06, 0x07]
         );
@@ -2043,7 +3298,7 @@ mod tests {
         // With type 'ai':
         assert_eq!(gv!("ai").from_bytes(b"\x04\0\0\0\x02\x01\0\0"), [4, 258]);
         assert_eq!(
-            gv!("ai").serialize_to_vec(&[4, 258]).as_slice(),
+            gv!("ai").serialize_to_vec([4, 258]).as_slice(),
             b"\x04\0\0\0\x02\x01\0\0"
         );
 
@@ -2067,6 +3322,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_object_path_and_signature() {
+        let o = |x: &'static [u8]| ObjectPath::from_aligned_slice(x.as_aligned()).to_str();
+        assert_eq!(o(b"/a/b\0"), "/a/b");
+        assert_eq!(o(b"/\0"), "/");
+        assert_eq!(o(b"a/b\0"), "/");
+        assert_eq!(o(b"/a/b"), "/");
+        assert_eq!(o(b""), "/");
+        assert_eq!(ObjectPath::default_ref().to_str(), "/");
+        assert!(!ObjectPath::from_aligned_slice(b"/a/\0".as_aligned()).is_normal_form());
+
+        let g = |x: &'static [u8]| Signature::from_aligned_slice(x.as_aligned()).to_str();
+        assert_eq!(g(b"a{sv}\0"), "a{sv}");
+        assert_eq!(g(b"ms\0"), "");
+        assert_eq!(g(b""), "");
+
+        assert_eq!(ObjectPath::from_bytes_with_nul(b"/a\0").unwrap(), "/a");
+        assert_eq!(
+            ObjectPath::from_bytes_with_nul(b"/a"),
+            Err(InvalidObjectPath)
+        );
+        assert_eq!(
+            ObjectPath::from_bytes_with_nul(b"/a\0/\0"),
+            Err(InvalidObjectPath)
+        );
+        assert_eq!(Signature::from_bytes_with_nul(b"i\0").unwrap(), "i");
+        assert_eq!(
+            Signature::from_bytes_with_nul(b"(si\0"),
+            Err(InvalidSignature)
+        );
+
+        let p = ObjectPath::new("/org/example").unwrap();
+        assert_eq!(&*p, "/org/example");
+        assert!(ObjectPath::new("/org/").is_err());
+        assert!(Signature::new("a(").is_err());
+        let s: &Str = p.as_ref().into();
+        assert_eq!(s, "/org/example");
+        assert_eq!(ObjectPath::default_ref().as_gv_str(), "/");
+
+        // Serialisation validates the input
+        assert_eq!(gv!("o").serialize_to_vec("/a"), b"/a\0");
+        assert_eq!(gv!("o").serialize_to_vec(&*p), b"/org/example\0");
+        assert_eq!(gv!("g").serialize_to_vec("as"), b"as\0");
+        let mut out = vec![];
+        let err = gv!("o").serialize("a", &mut out).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(gv!("g").serialize("a", &mut out).is_err());
+        assert!(out.is_empty());
+
+        // Invalid data is reserialised as the default value
+        let invalid = ObjectPath::from_aligned_slice(b"a\0".as_aligned());
+        assert_eq!(gv!("o").serialize_to_vec(invalid), b"/\0");
+    }
+
     #[test]
     fn test_variant() {
         let data = copy_to_align(b"\x04\x00\x00n");
@@ -0,0 +1,545 @@
+// Checking whether serialised data is in normal form
+//
+// Every GVariant value has exactly one serialisation in normal form.  Other
+// serialisations of the same value are possible, for example with non-zero
+// padding or framing offsets that are wider than they need to be.  These rules
+// are a port of GLib's `g_variant_serialised_is_normal` so that we agree with
+// `g_variant_is_normal_form` on all inputs.
+//
+// The container checks take a closure for checking the children so they can be
+// shared between the run-time checks here and the statically typed
+// `Cast::is_normal_form` implementations in lib.rs.
+//
+// This is also where we produce normal form data at run-time, for the parser
+// and for normalising the contents of variants.
+
+#[cfg(feature = "alloc")]
+use alloc::vec;
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "alloc")]
+use crate::variant_type::align;
+use crate::variant_type::{VariantTypeStr, MAX_DEPTH};
+#[cfg(feature = "alloc")]
+use crate::write::{self, Write};
+use crate::{offset_size, read_uint};
+#[cfg(feature = "alloc")]
+use crate::{write_offsets, DynValue};
+
+/// Is `data` the normal form serialisation of a value of type `ty`?
+///
+/// `ty` must be definite.
+pub(crate) fn is_normal_form(ty: &VariantTypeStr, data: &[u8]) -> bool {
+    check(ty, data, 0)
+}
+
+fn check(ty: &VariantTypeStr, data: &[u8], depth: usize) -> bool {
+    if depth >= MAX_DEPTH {
+        return false;
+    }
+    if let Some(size) = ty.size_of() {
+        if size != data.len() {
+            return false;
+        }
+    }
+    match ty.as_bytes()[0] {
+        b'a' => {
+            let element = ty.element().unwrap();
+            let child = |x: &[u8]| check(element, x, depth + 1);
+            match element.size_of() {
+                Some(size) => data.len() % size == 0 && data.chunks(size).all(child),
+                None => variable_sized_array(data, element.align_of(), child),
+            }
+        }
+        b'm' => {
+            let element = ty.element().unwrap();
+            maybe(data, element.is_fixed_size(), |x| {
+                check(element, x, depth + 1)
+            })
+        }
+        b'(' | b'{' => tuple(ty, data, depth),
+        b'v' => variant(data, depth),
+        b'b' => data[0] < 2,
+        b's' => is_string(data),
+        b'o' => is_string(data) && is_object_path(&data[..data.len() - 1]),
+        b'g' => is_string(data) && is_signature(&data[..data.len() - 1]),
+        // All the other types are numbers, for which all bit patterns are valid
+        // (including the various NaN representations of doubles).  We've
+        // already checked the size above.
+        _ => true,
+    }
+}
+
+/// Strings must be valid UTF-8 with a single nul terminator
+pub(crate) fn is_string(data: &[u8]) -> bool {
+    match data.split_last() {
+        Some((0, s)) => !s.contains(&0) && core::str::from_utf8(s).is_ok(),
+        _ => false,
+    }
+}
+
+// Padding must be zero and must not extend beyond `end`
+fn skip_padding(data: &[u8], offset: &mut usize, alignment: usize, end: usize) -> bool {
+    while *offset % alignment != 0 {
+        if *offset >= end || data[*offset] != 0 {
+            return false;
+        }
+        *offset += 1;
+    }
+    true
+}
+
+/// Checks an array of non-fixed size elements aligned to `alignment`.  `child`
+/// is called with the data of each element in turn.  Element data is always a
+/// subslice of `data`, starting at an offset that is a multiple of
+/// `alignment`.
+pub(crate) fn variable_sized_array(
+    data: &[u8],
+    alignment: usize,
+    mut child: impl FnMut(&[u8]) -> bool,
+) -> bool {
+    if data.is_empty() {
+        return true;
+    }
+    let osz = offset_size(data.len());
+    let last_end = read_uint(&data[data.len() - osz as usize..], osz, 0);
+    if last_end > data.len() {
+        return false;
+    }
+    let offsets = &data[last_end..];
+    if offsets.is_empty() || offsets.len() % osz as usize != 0 {
+        return false;
+    }
+    let mut offset = 0;
+    for n in 0..offsets.len() / osz as usize {
+        let end = read_uint(offsets, osz, n);
+        if end < offset || end > last_end {
+            return false;
+        }
+        if !skip_padding(data, &mut offset, alignment, end) || !child(&data[offset..end]) {
+            return false;
+        }
+        offset = end;
+    }
+    true
+}
+
+/// `child` is called with the data of the contained value, if there is one
+pub(crate) fn maybe(data: &[u8], fixed_size: bool, child: impl FnOnce(&[u8]) -> bool) -> bool {
+    match data {
+        [] => true,
+        // The child's size will be checked by `child`
+        _ if fixed_size => child(data),
+        [x @ .., 0] => child(x),
+        _ => false,
+    }
+}
+
+fn tuple(ty: &VariantTypeStr, data: &[u8], depth: usize) -> bool {
+    let items = ty
+        .items()
+        .map(|item| (item.align_of(), item.size_of(), item));
+    structure(data, items, |item, x| check(item, x, depth + 1))
+}
+
+/// Checks a structure or dictionary entry.  `items` gives the alignment and
+/// fixed size (if any) of each field, along with a value that `child` is
+/// called with to check the data of that field.  Field data is always a
+/// subslice of `data` starting at an offset that is a multiple of the field's
+/// alignment.
+pub(crate) fn structure<T>(
+    data: &[u8],
+    items: impl Iterator<Item = (usize, Option<usize>, T)> + Clone,
+    mut child: impl FnMut(T, &[u8]) -> bool,
+) -> bool {
+    let osz = offset_size(data.len());
+    let n_items = items.clone().count();
+    let mut fixed_size = true;
+    let mut alignment = 1;
+    let mut offset_ptr = data.len();
+    let mut offset = 0;
+    for (n, (item_alignment, item_size, item)) in items.enumerate() {
+        fixed_size &= item_size.is_some();
+        alignment = alignment.max(item_alignment);
+        if !skip_padding(data, &mut offset, item_alignment, data.len()) {
+            return false;
+        }
+        let end = match item_size {
+            Some(size) => offset + size,
+            None if n == n_items - 1 => offset_ptr,
+            None => {
+                if offset_ptr < offset + osz as usize {
+                    return false;
+                }
+                offset_ptr -= osz as usize;
+                read_uint(&data[offset_ptr..], osz, 0)
+            }
+        };
+        if end < offset || end > offset_ptr || !child(item, &data[offset..end]) {
+            return false;
+        }
+        offset = end;
+    }
+    if fixed_size {
+        // Fixed size structures are padded out to a multiple of their
+        // alignment.  The unit type `()` is a single zero byte.
+        if n_items == 0 {
+            return data == b"\0";
+        }
+        if !skip_padding(data, &mut offset, alignment, data.len()) {
+            return false;
+        }
+    }
+    offset_ptr == offset
+}
+
+/// Checks the data of a **v**.  `depth` is the nesting depth of the variant
+/// itself.
+pub(crate) fn variant(data: &[u8], depth: usize) -> bool {
+    let mid = match memchr::memrchr(b'\0', data) {
+        Some(mid) => mid,
+        None => return false,
+    };
+    match VariantTypeStr::new(&data[mid + 1..]) {
+        Ok(ty) if ty.is_definite() && depth + ty.depth() < MAX_DEPTH => {
+            check(ty, &data[..mid], depth + 1)
+        }
+        _ => false,
+    }
+}
+
+// From the D-Bus specification: The path must begin with an ASCII '/'
+// character, and must consist of elements separated by slash characters.  Each
+// element must only contain the ASCII characters "[A-Z][a-z][0-9]_".  No
+// element may be the empty string.  A trailing '/' character is not allowed
+// unless the path is the root path.
+pub(crate) fn is_object_path(s: &[u8]) -> bool {
+    match s {
+        b"/" => true,
+        [b'/', rest @ ..] => rest
+            .split(|c| *c == b'/')
+            .all(|x| !x.is_empty() && x.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'_')),
+        _ => false,
+    }
+}
+
+// A signature is a sequence of zero or more complete definite D-Bus types
+pub(crate) fn is_signature(mut s: &[u8]) -> bool {
+    if !s.iter().all(|c| b"ybnqiuxthdvasog(){}".contains(c)) {
+        return false;
+    }
+    while !s.is_empty() {
+        match VariantTypeStr::split_first(s) {
+            Ok((_, rest)) => s = rest,
+            Err(_) => return false,
+        }
+    }
+    true
+}
+
+// The parser builds values from the bottom up, so it serialises containers
+// from their already serialised children
+#[cfg(feature = "std")]
+pub(crate) fn serialize_array(element: &VariantTypeStr, children: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut out = vec![];
+    if element.is_fixed_size() {
+        for child in children {
+            out.extend_from_slice(&child);
+        }
+        return out;
+    }
+    let mut offsets = vec![];
+    for child in children {
+        out.resize(align(out.len(), element.align_of()), b'\0');
+        out.extend_from_slice(&child);
+        offsets.push(out.len());
+    }
+    write_offsets(out.len(), &offsets, &mut out).expect("Writing to a Vec is infallible");
+    out
+}
+
+#[cfg(feature = "std")]
+pub(crate) fn serialize_tuple(ty: &VariantTypeStr, children: Vec<Vec<u8>>) -> Vec<u8> {
+    let mut out = vec![];
+    let mut offsets = vec![];
+    let n_children = children.len();
+    for (n, (item, child)) in ty.items().zip(children).enumerate() {
+        out.resize(align(out.len(), item.align_of()), b'\0');
+        out.extend_from_slice(&child);
+        if !item.is_fixed_size() && n + 1 != n_children {
+            offsets.push(out.len());
+        }
+    }
+    if let Some(size) = ty.size_of() {
+        out.resize(size, b'\0');
+    }
+    offsets.reverse();
+    write_offsets(out.len(), &offsets, &mut out).expect("Writing to a Vec is infallible");
+    out
+}
+
+/// Serialise `value` in normal form into `f`, returning the number of bytes
+/// written
+///
+/// This is the equivalent of GLib's `g_variant_get_normal_form`, or
+/// `g_variant_byteswap` if `byteswap` is true.  Strings, object paths and
+/// signatures that aren't valid are replaced by the default value for their
+/// type.
+#[cfg(feature = "alloc")]
+pub(crate) fn normalize(
+    value: DynValue<'_>,
+    byteswap: bool,
+    f: &mut impl Write,
+) -> Result<usize, write::Error> {
+    let mut out = Counted { f, len: 0 };
+    write_normal(value, byteswap, &mut out)?;
+    Ok(out.len)
+}
+
+// Padding is relative to the start of the container, so as we write children
+// straight into the output we need to know how much we've written
+#[cfg(feature = "alloc")]
+struct Counted<W> {
+    f: W,
+    len: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl<W: Write> Counted<W> {
+    // Write zeros up to `len` bytes from the start
+    fn pad_to(&mut self, len: usize) -> Result<(), write::Error> {
+        while self.len < len {
+            let n = (len - self.len).min(8);
+            self.write_all(&[0; 8][..n])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<W: Write> Write for Counted<W> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), write::Error> {
+        self.f.write_all(buf)?;
+        self.len += buf.len();
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+fn write_normal<W: Write>(
+    value: DynValue<'_>,
+    byteswap: bool,
+    out: &mut Counted<W>,
+) -> Result<(), write::Error> {
+    macro_rules! number {
+        ($x:expr) => {{
+            let mut bytes = $x.to_ne_bytes();
+            if byteswap {
+                bytes.reverse();
+            }
+            out.write_all(&bytes)
+        }};
+    }
+    let mut string = |s: &[u8]| {
+        out.write_all(s)?;
+        out.write_all(b"\0")
+    };
+    match value {
+        DynValue::Bool(x) => out.write_all(&[x as u8]),
+        DynValue::Byte(x) => out.write_all(&[x]),
+        DynValue::Int16(x) => number!(x),
+        DynValue::Uint16(x) => number!(x),
+        DynValue::Int32(x) => number!(x),
+        DynValue::Uint32(x) => number!(x),
+        DynValue::Handle(x) => number!(x),
+        DynValue::Int64(x) => number!(x),
+        DynValue::Uint64(x) => number!(x),
+        DynValue::Double(x) => number!(x),
+        DynValue::Str(s) => string(s.to_str().as_bytes()),
+        DynValue::ObjectPath(s) => string(s.to_str().as_bytes()),
+        DynValue::Signature(s) => string(s.to_str().as_bytes()),
+        DynValue::Variant(v) => {
+            write_normal(v.value(), byteswap, out)?;
+            out.write_all(b"\0")?;
+            out.write_all(v.type_str().as_bytes())
+        }
+        DynValue::Array(a) => {
+            let element = a.element_type();
+            let start = out.len;
+            let mut offsets = vec![];
+            for child in a.iter() {
+                out.pad_to(start + align(out.len - start, element.align_of()))?;
+                write_normal(child, byteswap, out)?;
+                if !element.is_fixed_size() {
+                    offsets.push(out.len - start);
+                }
+            }
+            write_offsets(out.len - start, &offsets, out)?;
+            Ok(())
+        }
+        DynValue::Maybe(m) => match m.to_option() {
+            None => Ok(()),
+            Some(x) => {
+                write_normal(x, byteswap, out)?;
+                if x.type_str().is_fixed_size() {
+                    Ok(())
+                } else {
+                    out.write_all(b"\0")
+                }
+            }
+        },
+        DynValue::Tuple(s) | DynValue::DictEntry(s) => {
+            let ty = s.type_str();
+            let start = out.len;
+            let mut offsets = vec![];
+            let children = s.iter();
+            let n_children = children.len();
+            for (n, (item, child)) in ty.items().zip(children).enumerate() {
+                out.pad_to(start + align(out.len - start, item.align_of()))?;
+                write_normal(child, byteswap, out)?;
+                if !item.is_fixed_size() && n + 1 != n_children {
+                    offsets.push(out.len - start);
+                }
+            }
+            if let Some(size) = ty.size_of() {
+                out.pad_to(start + size)?;
+            }
+            offsets.reverse();
+            write_offsets(out.len - start, &offsets, out)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normal(ty: &str, data: &[u8]) -> bool {
+        is_normal_form(VariantTypeStr::new(ty).unwrap(), data)
+    }
+
+    #[test]
+    fn test_basic_types() {
+        assert!(normal("u", b"\x05\0\0\0"));
+        assert!(!normal("u", b"\x05\0\0"));
+        assert!(!normal("u", b"\x05\0\0\0\0"));
+        assert!(normal("b", b"\x01"));
+        assert!(!normal("b", b"\x02"));
+        assert!(!normal("b", b""));
+        assert!(normal("s", b"hello\0"));
+        assert!(normal("s", b"\0"));
+        assert!(!normal("s", b""));
+        assert!(!normal("s", b"hello"));
+        assert!(!normal("s", b"hel\0lo\0"));
+        assert!(!normal("s", b"\xff\0"));
+        assert!(normal("o", b"/a/b\0"));
+        assert!(!normal("o", b"a/b\0"));
+        assert!(normal("g", b"a{sv}\0"));
+        assert!(!normal("g", b"a{\0"));
+    }
+
+    #[test]
+    fn test_arrays() {
+        assert!(normal("ai", b""));
+        assert!(normal("ai", b"\x01\0\0\0\x02\0\0\0"));
+        assert!(!normal("ai", b"\x01\0\0\0\x02\0"));
+        assert!(normal("ab", b"\x01\x00"));
+        assert!(!normal("ab", b"\x01\x02"));
+
+        assert!(normal("as", b"a\0bc\0\x02\x05"));
+        // Empty array with a framing offset
+        assert!(!normal("as", b"\x00"));
+        // Trailing data between the last element and the offsets
+        assert!(!normal("as", b"a\0bc\0\0\x02\x05"));
+        // Offsets out of order
+        assert!(!normal("as", b"a\0bc\0\x05\x02"));
+        // Wider offsets than necessary
+        assert!(!normal("as", b"a\0bc\0\x02\0\x05\0"));
+
+        // a(si): padding between and within elements must be zero
+        let data = b"a\0\0\0\x01\0\0\0\x02\0\0\0bc\0\0\xfe\xff\xff\xff\x03\x09\x15";
+        assert!(normal("a(si)", data));
+        let mut bad = *data;
+        bad[10] = 1;
+        assert!(!normal("a(si)", &bad));
+        let mut bad = *data;
+        bad[15] = 1;
+        assert!(!normal("a(si)", &bad));
+    }
+
+    #[test]
+    fn test_maybes() {
+        assert!(normal("mi", b""));
+        assert!(normal("mi", b"\x05\0\0\0"));
+        assert!(!normal("mi", b"\x05\0\0"));
+        assert!(normal("ms", b""));
+        assert!(normal("ms", b"a\0\0"));
+        assert!(!normal("ms", b"a\0\x01"));
+        // Just the empty string is "\0\0"
+        assert!(!normal("ms", b"\0"));
+        assert!(normal("mas", b"\0"));
+        assert!(normal("mmb", b"\x01\0"));
+    }
+
+    #[test]
+    fn test_tuples() {
+        assert!(normal("()", b"\0"));
+        assert!(!normal("()", b"\x01"));
+        assert!(!normal("()", b""));
+        assert!(normal("(yi)", b"\x01\0\0\0\x02\0\0\0"));
+        assert!(!normal("(yi)", b"\x01\0\x03\0\x02\0\0\0"));
+        // Trailing padding of fixed size structures
+        assert!(normal("(iy)", b"\x02\0\0\0\x01\0\0\0"));
+        assert!(!normal("(iy)", b"\x02\0\0\0\x01\0\x01\0"));
+        assert!(normal("(ss)", b"a\0bc\0\x02"));
+        assert!(!normal("(ss)", b"a\0bc\0\x03"));
+        // Trailing data after the last item
+        assert!(!normal("(si)", b"a\0\0\0\x01\0\0\0\0"));
+        assert!(normal("{si}", b"a\0\0\0\x01\0\0\0\x02"));
+    }
+
+    #[test]
+    fn test_variants() {
+        assert!(normal("v", b"\x05\0\0\0\0u"));
+        assert!(normal("v", b"\0\0()"));
+        assert!(!normal("v", b"\0()"));
+        assert!(normal("v", b"\0as"));
+        assert!(!normal("v", b""));
+        assert!(!normal("v", b"\x05\0\0\0"));
+        // Wrong size child
+        assert!(!normal("v", b"\x05\0\0\0\0y"));
+        // Invalid and indefinite types
+        assert!(!normal("v", b"\x05\0\0\0\0z"));
+        assert!(!normal("v", b"\0a*"));
+        // Non-normal child
+        assert!(!normal("v", b"\x02\0b"));
+        assert!(normal("av", b"\x01\0b\0\0\0\0\0\x05\0\0\0\0u\x03\x0e"));
+
+        // Nesting is limited to MAX_DEPTH
+        let nested = |n| {
+            let mut v = b"\x01\0b".to_vec();
+            for _ in 0..n {
+                v.extend_from_slice(b"\0v");
+            }
+            v
+        };
+        assert!(normal("v", &nested(MAX_DEPTH - 2)));
+        assert!(!normal("v", &nested(MAX_DEPTH - 1)));
+    }
+
+    #[test]
+    fn test_object_paths_and_signatures() {
+        assert!(is_object_path(b"/"));
+        assert!(is_object_path(b"/a/B_9"));
+        assert!(!is_object_path(b""));
+        assert!(!is_object_path(b"/a/"));
+        assert!(!is_object_path(b"//a"));
+        assert!(!is_object_path(b"/a-b"));
+        assert!(is_signature(b""));
+        assert!(is_signature(b"a{sv}i(ss)"));
+        assert!(!is_signature(b"ms"));
+        assert!(!is_signature(b"a"));
+    }
+}
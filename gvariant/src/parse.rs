@@ -0,0 +1,1345 @@
+//! The GVariant text format: parsing
+//!
+//! This is the other half of the [`print`][crate::print] module.  It's
+//! equivalent to GLib's `g_variant_parse`, accepting the same syntax and
+//! producing the same values.  The result is serialised GVariant data in normal
+//! form, which can be cast to the appropriate type in the usual way:
+//!
+//! ```rust
+//! # use gvariant::{aligned_bytes::AsAligned, gv, Marker, Structure};
+//! let data = gv!("a(is)").parse("[(1, 'a'), (2, 'b')]").unwrap();
+//! let array = gv!("a(is)").cast(data.as_aligned());
+//! let (n, s) = array[1].to_tuple();
+//! assert_eq!((*n, s.to_str()), (2, "b"));
+//! ```
+//!
+//! If the type isn't known in advance it can be inferred from the text with
+//! [`parse`]:
+//!
+//! ```rust
+//! # use gvariant::parse::parse;
+//! let (ty, data) = parse(None, "{'k': <@ai [1]>}").unwrap();
+//! assert_eq!(ty, "a{sv}");
+//! ```
+//!
+//! ### Type inference
+//!
+//! The rules are the same as GLib's.  Numbers are `i` unless written with a
+//! decimal point or exponent, in which case they're `d`.  Strings are `s`.  A
+//! container's type is inferred from its contents, so `[1, 2.5]` is an `ad`.
+//! Values can be given an explicit type with a keyword, like `uint32 5`, or
+//! with an `@` prefix followed by a type string, like `@as []`.  A value can be
+//! used where a maybe is expected without writing `just`.
+//!
+//! ### Errors
+//!
+//! [`ParseError`] includes the location within the text of the problem.  Its
+//! [`Display`] output has the same format as GLib's error messages, for example
+//! `1-3:can not parse as value of type 's'`.
+
+use core::{convert::TryFrom, fmt::Display, ops::Range};
+
+use crate::aligned_bytes::{alloc_aligned, AlignedSlice, A8};
+use crate::normal_form::{is_object_path, is_signature, serialize_array, serialize_tuple};
+use crate::variant_type::{VariantType, VariantTypeStr, MAX_DEPTH};
+
+/// The category of a [`ParseError`]
+///
+/// These are the same as GLib's `GVariantParseError`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// A non-basic type was given where a basic type was expected
+    BasicTypeExpected,
+    /// Cannot infer the type of the value
+    CannotInferType,
+    /// An indefinite type was given where a definite type was expected
+    DefiniteTypeExpected,
+    /// Extra data after parsing finished
+    InputNotAtEnd,
+    /// Invalid character in number or unicode escape
+    InvalidCharacter,
+    /// Not a valid object path
+    InvalidObjectPath,
+    /// Not a valid type signature
+    InvalidSignature,
+    /// Not a valid GVariant type string
+    InvalidTypeString,
+    /// Could not find a common type for array entries
+    NoCommonType,
+    /// The numerical value is out of range of the given type
+    NumberOutOfRange,
+    /// The numerical value is out of range for any type
+    NumberTooBig,
+    /// Cannot parse as the given type
+    TypeError,
+    /// An unexpected token was encountered
+    UnexpectedToken,
+    /// An unknown keyword was encountered
+    UnknownKeyword,
+    /// Unterminated string constant
+    UnterminatedStringConstant,
+    /// No value given
+    ValueExpected,
+    /// Variant was too deeply nested
+    Recursion,
+}
+
+/// Error returned when parsing text in the GVariant text format fails
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    kind: ParseErrorKind,
+    range: Range<usize>,
+    other_range: Option<Range<usize>>,
+    message: String,
+}
+impl ParseError {
+    /// The category of error
+    pub fn kind(&self) -> ParseErrorKind {
+        self.kind
+    }
+    /// The byte range within the text where the problem was found
+    ///
+    /// This may be empty if the problem is at a point between tokens.
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+    /// A second range involved in the problem, if any
+    ///
+    /// This is used for [`ParseErrorKind::NoCommonType`] to point at the
+    /// conflicting value.
+    pub fn other_range(&self) -> Option<Range<usize>> {
+        self.other_range.clone()
+    }
+    /// A human readable description of the problem, without the location
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for ParseError {}
+impl Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.range.is_empty() {
+            write!(f, "{}", self.range.start)?;
+        } else {
+            write!(f, "{}-{}", self.range.start, self.range.end)?;
+        }
+        if let Some(other) = &self.other_range {
+            write!(f, ",{}-{}", other.start, other.end)?;
+        }
+        write!(f, ":{}", self.message)
+    }
+}
+
+fn error(kind: ParseErrorKind, range: Range<usize>, message: impl Into<String>) -> ParseError {
+    ParseError {
+        kind,
+        range,
+        other_range: None,
+        message: message.into(),
+    }
+}
+
+/// Parse `text` in the GVariant text format
+///
+/// If `ty` is `None` the type is inferred from the text.  Otherwise the text
+/// is parsed as a value of type `ty`, which must be definite.  Returns the type
+/// and the serialised data in normal form.
+///
+///     # use gvariant::{parse::parse, VariantTypeStr};
+///     let (ty, data) = parse(None, "(1, 'a')").unwrap();
+///     assert_eq!(ty, "(is)");
+///     let (_, data) = parse(Some(VariantTypeStr::new("ay").unwrap()), "[1, 2]").unwrap();
+///     assert_eq!(&**data, b"\x01\x02");
+pub fn parse(
+    ty: Option<&VariantTypeStr>,
+    text: &str,
+) -> Result<(VariantType, Box<AlignedSlice<A8>>), ParseError> {
+    if let Some(ty) = ty {
+        if !ty.is_definite() {
+            return Err(error(
+                ParseErrorKind::DefiniteTypeExpected,
+                0..0,
+                format!("type '{}' is not definite", ty),
+            ));
+        }
+    }
+    let mut stream = TokenStream {
+        text: text.as_bytes(),
+        this: None,
+        stream: 0,
+    };
+    let ast = parse_value(&mut stream, MAX_DEPTH)?;
+    let (ty, data) = match ty {
+        None => ast.resolve()?,
+        Some(ty) => (ty.to_owned(), ast.get_value(ty)?),
+    };
+
+    let rest = &stream.text[stream.stream..];
+    let end = stream.stream + rest.iter().take_while(|c| c.is_ascii_whitespace()).count();
+    if end != text.len() && stream.text[end] != b'\0' {
+        return Err(error(
+            ParseErrorKind::InputNotAtEnd,
+            end..end,
+            "expected end of input",
+        ));
+    }
+
+    let mut out = alloc_aligned(data.len());
+    out.copy_from_slice(&data);
+    Ok((ty, out))
+}
+
+// This is a port of GLib's `TokenStream`.  `this` is the start of the current
+// token, if we've looked at it yet, and `stream` is the position just after it.
+struct TokenStream<'a> {
+    text: &'a [u8],
+    this: Option<usize>,
+    stream: usize,
+}
+
+impl<'a> TokenStream<'a> {
+    // Finds the next token.  Returns the token, which is empty at the end of
+    // the input
+    fn prepare(&mut self) -> &'a [u8] {
+        if let Some(this) = self.this {
+            return &self.text[this..self.stream];
+        }
+        let text = self.text;
+        let at = |n: usize| text.get(n).copied().unwrap_or(b'\0');
+        let mut start = self.stream;
+        while start < text.len() && text[start].is_ascii_whitespace() {
+            start += 1;
+        }
+        let mut end = start;
+        match at(start) {
+            b'\0' => {}
+            b'-' | b'+' | b'.' | b'0'..=b'9' => {
+                while end < text.len()
+                    && (text[end].is_ascii_alphanumeric() || b"-+.".contains(&text[end]))
+                {
+                    end += 1;
+                }
+            }
+            b'b' if at(start + 1) == b'\'' || at(start + 1) == b'"' => {
+                end = scan_string(text, start + 1);
+            }
+            b'a'..=b'z' => {
+                while end < text.len() && text[end].is_ascii_alphanumeric() {
+                    end += 1;
+                }
+            }
+            b'\'' | b'"' => {
+                end = scan_string(text, start);
+            }
+            b'@' | b'%' => {
+                // stop at the first space, comma, colon or unmatched bracket.
+                // deals nicely with cases like (%i, %i) or {%i: %i}.
+                let mut brackets = 0;
+                end += 1;
+                while !b"\0,:>]".contains(&at(end)) && !at(end).is_ascii_whitespace() {
+                    if at(end) == b'(' || at(end) == b'{' {
+                        brackets += 1;
+                    } else if at(end) == b')' || at(end) == b'}' {
+                        if brackets == 0 {
+                            break;
+                        }
+                        brackets -= 1;
+                    }
+                    end += 1;
+                }
+            }
+            _ => end += 1,
+        }
+        self.this = Some(start);
+        self.stream = end;
+        &text[start..end]
+    }
+
+    fn peek(&mut self, c: u8) -> bool {
+        self.prepare().first() == Some(&c)
+    }
+
+    fn peek2(&mut self, a: u8, b: u8) -> bool {
+        self.prepare().starts_with(&[a, b])
+    }
+
+    fn peek_string(&mut self, s: &str) -> bool {
+        self.prepare() == s.as_bytes()
+    }
+
+    fn is_keyword(&mut self) -> bool {
+        let t = self.prepare();
+        t.len() >= 2 && t[0].is_ascii_alphabetic() && t[1].is_ascii_alphabetic()
+    }
+
+    fn is_numeric(&mut self) -> bool {
+        matches!(self.prepare().first(), Some(c) if c.is_ascii_digit() || b"-+.".contains(c))
+    }
+
+    fn get(&mut self) -> &'a [u8] {
+        let token = self.prepare();
+        self.this = None;
+        token
+    }
+
+    fn consume(&mut self, s: &str) -> bool {
+        if self.peek_string(s) {
+            self.this = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn require(&mut self, s: &str, purpose: &str) -> Result<(), ParseError> {
+        if self.consume(s) {
+            Ok(())
+        } else {
+            Err(self.error(
+                false,
+                ParseErrorKind::UnexpectedToken,
+                format!("expected '{}'{}", s, purpose),
+            ))
+        }
+    }
+
+    // The start of the next token
+    fn start_ref(&mut self) -> usize {
+        self.prepare();
+        self.this.unwrap()
+    }
+
+    fn error(
+        &mut self,
+        this_token: bool,
+        kind: ParseErrorKind,
+        message: impl Into<String>,
+    ) -> ParseError {
+        let start = self.start_ref();
+        let end = if this_token { self.stream } else { start };
+        error(kind, start..end, message)
+    }
+}
+
+// Returns the end of the string token starting with the quote at `start`
+fn scan_string(text: &[u8], start: usize) -> usize {
+    let quote = text[start];
+    let mut end = start + 1;
+    while end < text.len() {
+        if text[end] == quote || text[end] == b'\0' {
+            break;
+        }
+        if text[end] == b'\\' {
+            end += 1;
+            if end == text.len() || text[end] == b'\0' {
+                break;
+            }
+        }
+        end += 1;
+    }
+    if end < text.len() && text[end] != b'\0' {
+        end += 1;
+    }
+    end
+}
+
+struct Ast<'a> {
+    range: Range<usize>,
+    node: Node<'a>,
+}
+
+enum Node<'a> {
+    Array(Vec<Ast<'a>>),
+    Tuple(Vec<Ast<'a>>),
+    Variant(Box<Ast<'a>>),
+    Dict(Vec<Ast<'a>>, Vec<Ast<'a>>),
+    DictEntry(Box<Ast<'a>>, Box<Ast<'a>>),
+    // Strings are only guaranteed to be valid UTF-8 if the escapes were, so we
+    // store the bytes
+    String(Vec<u8>),
+    ByteString(Vec<u8>),
+    Number(&'a [u8]),
+    Boolean(bool),
+    Maybe(Option<Box<Ast<'a>>>),
+    TypeDecl(VariantType, Box<Ast<'a>>),
+}
+
+fn parse_value<'a>(stream: &mut TokenStream<'a>, max_depth: usize) -> Result<Ast<'a>, ParseError> {
+    if max_depth == 0 {
+        return Err(stream.error(
+            false,
+            ParseErrorKind::Recursion,
+            "variant nested too deeply",
+        ));
+    }
+    let start = stream.start_ref();
+    let node = if stream.peek(b'[') {
+        parse_array(stream, max_depth)?
+    } else if stream.peek(b'(') {
+        parse_tuple(stream, max_depth)?
+    } else if stream.peek(b'<') {
+        stream.get();
+        let value = parse_value(stream, max_depth - 1)?;
+        stream.require(">", " to follow variant value")?;
+        Node::Variant(Box::new(value))
+    } else if stream.peek(b'{') {
+        parse_dictionary(stream, max_depth)?
+    } else if stream.consume("true") {
+        Node::Boolean(true)
+    } else if stream.consume("false") {
+        Node::Boolean(false)
+    } else if stream.is_numeric() || stream.peek_string("inf") || stream.peek_string("nan") {
+        Node::Number(stream.get())
+    } else if stream.peek(b'n') || stream.peek(b'j') {
+        if stream.consume("just") {
+            Node::Maybe(Some(Box::new(parse_value(stream, max_depth - 1)?)))
+        } else if stream.consume("nothing") {
+            Node::Maybe(None)
+        } else {
+            return Err(stream.error(true, ParseErrorKind::UnknownKeyword, "unknown keyword"));
+        }
+    } else if stream.peek(b'@') || stream.is_keyword() {
+        parse_typedecl(stream, max_depth)?
+    } else if stream.peek(b'\'') || stream.peek(b'"') {
+        let start = stream.start_ref();
+        let token = stream.get();
+        Node::String(unescape(token, 1, start..stream.stream)?)
+    } else if stream.peek2(b'b', b'\'') || stream.peek2(b'b', b'"') {
+        let start = stream.start_ref();
+        let token = stream.get();
+        Node::ByteString(unescape(token, 2, start..stream.stream)?)
+    } else {
+        return Err(stream.error(false, ParseErrorKind::ValueExpected, "expected value"));
+    };
+    Ok(Ast {
+        range: start..stream.stream,
+        node,
+    })
+}
+
+fn parse_array<'a>(stream: &mut TokenStream<'a>, max_depth: usize) -> Result<Node<'a>, ParseError> {
+    stream.get();
+    let mut children = vec![];
+    while !stream.consume("]") {
+        if !children.is_empty() {
+            stream.require(",", " or ']' to follow array element")?;
+        }
+        children.push(parse_value(stream, max_depth - 1)?);
+    }
+    Ok(Node::Array(children))
+}
+
+fn parse_tuple<'a>(stream: &mut TokenStream<'a>, max_depth: usize) -> Result<Node<'a>, ParseError> {
+    stream.get();
+    let mut children = vec![];
+    while !stream.consume(")") {
+        if children.len() > 1 {
+            stream.require(",", " or ')' to follow tuple element")?;
+        }
+        children.push(parse_value(stream, max_depth - 1)?);
+        // The first time, we absolutely require a comma, so that `(1)` isn't
+        // mistaken for a tuple.
+        if children.len() == 1 {
+            stream.require(",", " after first tuple element")?;
+        }
+    }
+    Ok(Node::Tuple(children))
+}
+
+fn parse_dictionary<'a>(
+    stream: &mut TokenStream<'a>,
+    max_depth: usize,
+) -> Result<Node<'a>, ParseError> {
+    stream.get();
+    if stream.consume("}") {
+        return Ok(Node::Dict(vec![], vec![]));
+    }
+    let key = parse_value(stream, max_depth - 1)?;
+    let only_one = stream.consume(",");
+    if !only_one {
+        stream.require(":", " or ',' to follow dictionary entry key")?;
+    }
+    let value = parse_value(stream, max_depth - 1)?;
+    if only_one {
+        stream.require("}", " at end of dictionary entry")?;
+        return Ok(Node::DictEntry(Box::new(key), Box::new(value)));
+    }
+    let mut keys = vec![key];
+    let mut values = vec![value];
+    while !stream.consume("}") {
+        stream.require(",", " or '}' to follow dictionary entry")?;
+        keys.push(parse_value(stream, max_depth - 1)?);
+        stream.require(":", " to follow dictionary entry key")?;
+        values.push(parse_value(stream, max_depth - 1)?);
+    }
+    Ok(Node::Dict(keys, values))
+}
+
+fn parse_typedecl<'a>(
+    stream: &mut TokenStream<'a>,
+    max_depth: usize,
+) -> Result<Node<'a>, ParseError> {
+    let start = stream.start_ref();
+    let token = stream.get();
+    let range = start..stream.stream;
+    let ty = if let Some(ty) = token.strip_prefix(b"@") {
+        let ty = VariantType::new(ty).map_err(|_| {
+            error(
+                ParseErrorKind::InvalidTypeString,
+                range.clone(),
+                "invalid type declaration",
+            )
+        })?;
+        if !ty.is_definite() {
+            return Err(error(
+                ParseErrorKind::DefiniteTypeExpected,
+                range,
+                "type declarations must be definite",
+            ));
+        }
+        ty
+    } else {
+        let ty: &[u8] = match token {
+            b"boolean" => b"b",
+            b"byte" => b"y",
+            b"int16" => b"n",
+            b"uint16" => b"q",
+            b"int32" => b"i",
+            b"uint32" => b"u",
+            b"handle" => b"h",
+            b"int64" => b"x",
+            b"uint64" => b"t",
+            b"double" => b"d",
+            b"string" => b"s",
+            b"objectpath" => b"o",
+            b"signature" => b"g",
+            _ => {
+                return Err(error(
+                    ParseErrorKind::UnknownKeyword,
+                    range,
+                    "unknown keyword",
+                ))
+            }
+        };
+        VariantTypeStr::from_bytes_unchecked(ty).to_owned()
+    };
+    let value = parse_value(stream, max_depth - 1)?;
+    Ok(Node::TypeDecl(ty, Box::new(value)))
+}
+
+// Unescapes a string or bytestring token.  The string contents start at
+// `start`.  `range` is the location of the token for error reporting.
+fn unescape(token: &[u8], start: usize, range: Range<usize>) -> Result<Vec<u8>, ParseError> {
+    let bytestring = start == 2;
+    let at = |n: usize| token.get(n).copied().unwrap_or(b'\0');
+    let quote = token[start - 1];
+    let unterminated = || {
+        error(
+            ParseErrorKind::UnterminatedStringConstant,
+            range.clone(),
+            "unterminated string constant",
+        )
+    };
+    let mut out = vec![];
+    let mut i = start;
+    while at(i) != quote {
+        match at(i) {
+            b'\0' => return Err(unterminated()),
+            b'\\' => {
+                i += 1;
+                match at(i) {
+                    b'\0' => return Err(unterminated()),
+                    b'u' | b'U' if !bytestring => {
+                        let length = if at(i) == b'u' { 4 } else { 8 };
+                        i += 1;
+                        let digits = token[i..]
+                            .iter()
+                            .take(length)
+                            .take_while(|c| c.is_ascii_hexdigit())
+                            .count();
+                        let c = core::str::from_utf8(&token[i..i + digits])
+                            .ok()
+                            .and_then(|s| u32::from_str_radix(s, 16).ok())
+                            .filter(|&c| c != 0 && digits == length)
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| {
+                                let start = range.start + i;
+                                error(
+                                    ParseErrorKind::InvalidCharacter,
+                                    start..start + digits,
+                                    format!("invalid {}-character unicode escape", length),
+                                )
+                            })?;
+                        out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                        i += length;
+                    }
+                    b'0'..=b'7' if bytestring => {
+                        // up to 3 characters
+                        let mut val = at(i) - b'0';
+                        i += 1;
+                        for _ in 0..2 {
+                            if let b'0'..=b'7' = at(i) {
+                                val = (val << 3) | (at(i) - b'0');
+                                i += 1;
+                            }
+                        }
+                        out.push(val);
+                    }
+                    b'\n' => i += 1,
+                    c => {
+                        out.push(match c {
+                            b'a' => b'\x07',
+                            b'b' => b'\x08',
+                            b'f' => b'\x0c',
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'v' => b'\x0b',
+                            c => c,
+                        });
+                        i += 1;
+                    }
+                }
+            }
+            c => {
+                out.push(c);
+                i += 1;
+            }
+        }
+    }
+    Ok(out)
+}
+
+impl<'a> Ast<'a> {
+    fn error(&self, kind: ParseErrorKind, message: impl Into<String>) -> ParseError {
+        error(kind, self.range.clone(), message)
+    }
+
+    fn type_error(&self, ty: &VariantTypeStr) -> ParseError {
+        self.error(
+            ParseErrorKind::TypeError,
+            format!("can not parse as value of type '{}'", ty),
+        )
+    }
+
+    // Patterns are type strings with some extra characters:
+    //
+    // * `M` - this value can be a maybe, without using `just`
+    // * `N` - some type of number
+    // * `S` - some type of string (s, o or g)
+    // * `*` - we don't know yet
+    fn get_pattern(&self) -> Result<String, ParseError> {
+        Ok(match &self.node {
+            Node::Array(children) if children.is_empty() => "Ma*".into(),
+            Node::Array(children) => format!("Ma{}", array_pattern(children)?),
+            Node::Tuple(children) => {
+                let mut pattern = String::from("M(");
+                for child in children {
+                    pattern.push_str(&child.get_pattern()?);
+                }
+                pattern.push(')');
+                pattern
+            }
+            Node::Variant(_) => "Mv".into(),
+            Node::Dict(keys, _) if keys.is_empty() => "Ma{**}".into(),
+            Node::Dict(keys, values) => self.dict_pattern("a", keys, &values[0])?,
+            Node::DictEntry(key, value) => {
+                self.dict_pattern("", core::slice::from_ref(key), value)?
+            }
+            Node::String(_) => "MS".into(),
+            Node::ByteString(_) => "May".into(),
+            Node::Number(token) => {
+                let contains = |s: &[u8]| token.windows(s.len()).any(|w| w == s);
+                if contains(b".")
+                    || (!token.starts_with(b"0x") && contains(b"e"))
+                    || contains(b"inf")
+                    || contains(b"nan")
+                {
+                    "Md".into()
+                } else {
+                    "MN".into()
+                }
+            }
+            Node::Boolean(_) => "Mb".into(),
+            Node::Maybe(Some(child)) => format!("m{}", child.get_pattern()?),
+            Node::Maybe(None) => "m*".into(),
+            Node::TypeDecl(ty, _) => ty.to_string(),
+        })
+    }
+
+    fn dict_pattern(&self, a: &str, keys: &[Ast], value: &Ast) -> Result<String, ParseError> {
+        let key_pattern = array_pattern(keys)?;
+        // we can not have maybe keys
+        let key_char = key_pattern.trim_start_matches('M').as_bytes()[0];
+        // the basic types, plus undetermined number type and undetermined
+        // string type.
+        if !b"bynqiuxthdsogNS".contains(&key_char) {
+            return Err(self.error(
+                ParseErrorKind::BasicTypeExpected,
+                "dictionary keys must have basic types",
+            ));
+        }
+        Ok(format!(
+            "M{}{{{}{}}}",
+            a,
+            key_char as char,
+            value.get_pattern()?
+        ))
+    }
+
+    // Infer the type of this value and serialise it
+    fn resolve(&self) -> Result<(VariantType, Vec<u8>), ParseError> {
+        // choose reasonable defaults
+        //
+        //   1) favour non-maybe values where possible
+        //   2) default type for strings is 's'
+        //   3) default type for integers is 'i'
+        let mut ty = String::new();
+        for c in self.get_pattern()?.chars() {
+            match c {
+                '*' => {
+                    return Err(self.error(ParseErrorKind::CannotInferType, "unable to infer type"))
+                }
+                'M' => {}
+                'S' => ty.push('s'),
+                'N' => ty.push('i'),
+                c => ty.push(c),
+            }
+        }
+        let ty = VariantType::new(&ty)
+            .map_err(|_| self.error(ParseErrorKind::Recursion, "variant nested too deeply"))?;
+        let data = self.get_value(&ty)?;
+        Ok((ty, data))
+    }
+
+    // Serialise this value as type `ty`
+    fn get_value(&self, ty: &VariantTypeStr) -> Result<Vec<u8>, ParseError> {
+        match &self.node {
+            Node::Maybe(child) => {
+                let element = ty.element().filter(|_| ty.is_maybe());
+                let element = element.ok_or_else(|| self.type_error(ty))?;
+                Ok(match child {
+                    Some(child) => {
+                        let mut data = child.get_value(element)?;
+                        if !element.is_fixed_size() {
+                            data.push(b'\0');
+                        }
+                        data
+                    }
+                    None => vec![],
+                })
+            }
+            Node::TypeDecl(decl, value) => {
+                if !decl.is_subtype_of(ty) {
+                    return Err(self.type_error(ty));
+                }
+                value.get_value(ty)
+            }
+            _ => {
+                // Values can be used where a maybe is expected without writing
+                // `just`, possibly nested several levels deep.
+                let mut base = ty;
+                let mut depth = 0;
+                while base.is_maybe() {
+                    base = base.element().unwrap();
+                    depth += 1;
+                }
+                let mut data = self.get_base_value(base)?;
+                // Only the innermost maybe can have a fixed size element.
+                if depth > 0 && base.is_fixed_size() {
+                    depth -= 1;
+                }
+                data.resize(data.len() + depth, b'\0');
+                Ok(data)
+            }
+        }
+    }
+
+    fn get_base_value(&self, ty: &VariantTypeStr) -> Result<Vec<u8>, ParseError> {
+        let type_error = || self.type_error(ty);
+        match &self.node {
+            Node::Array(children) => {
+                let element = ty.element().filter(|_| ty.is_array());
+                let element = element.ok_or_else(type_error)?;
+                let children = children
+                    .iter()
+                    .map(|x| x.get_value(element))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(serialize_array(element, children))
+            }
+            Node::Tuple(children) => {
+                if !ty.is_tuple() {
+                    return Err(type_error());
+                }
+                let mut items = ty.items();
+                let mut values = vec![];
+                for child in children {
+                    values.push(child.get_value(items.next().ok_or_else(type_error)?)?);
+                }
+                if items.next().is_some() {
+                    return Err(type_error());
+                }
+                Ok(serialize_tuple(ty, values))
+            }
+            Node::Variant(value) => {
+                if ty != "v" {
+                    return Err(type_error());
+                }
+                let (ty, mut data) = value.resolve()?;
+                data.push(b'\0');
+                data.extend_from_slice(ty.as_bytes());
+                Ok(data)
+            }
+            Node::Dict(keys, values) => {
+                let entry = ty.element().filter(|x| ty.is_array() && x.is_dict_entry());
+                let entry = entry.ok_or_else(type_error)?;
+                let entries = keys
+                    .iter()
+                    .zip(values)
+                    .map(|(k, v)| dict_entry_value(entry, k, v))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(serialize_array(entry, entries))
+            }
+            Node::DictEntry(key, value) => {
+                if !ty.is_dict_entry() {
+                    return Err(type_error());
+                }
+                dict_entry_value(ty, key, value)
+            }
+            Node::String(s) => {
+                let valid = match ty.as_bytes() {
+                    b"s" => true,
+                    b"o" => is_object_path(s),
+                    b"g" => is_signature(s),
+                    _ => return Err(type_error()),
+                };
+                if !valid && ty == "o" {
+                    return Err(
+                        self.error(ParseErrorKind::InvalidObjectPath, "not a valid object path")
+                    );
+                } else if !valid {
+                    return Err(
+                        self.error(ParseErrorKind::InvalidSignature, "not a valid signature")
+                    );
+                }
+                let mut data = s.clone();
+                data.push(b'\0');
+                Ok(data)
+            }
+            Node::ByteString(s) => {
+                if ty != "ay" {
+                    return Err(type_error());
+                }
+                let mut data = s.clone();
+                data.push(b'\0');
+                Ok(data)
+            }
+            Node::Number(token) => self.number_value(token, ty),
+            Node::Boolean(b) => {
+                if ty != "b" {
+                    return Err(type_error());
+                }
+                Ok(vec![*b as u8])
+            }
+            Node::Maybe(_) | Node::TypeDecl(..) => unreachable!(),
+        }
+    }
+
+    fn number_value(&self, token: &[u8], ty: &VariantTypeStr) -> Result<Vec<u8>, ParseError> {
+        let too_big = || self.error(ParseErrorKind::NumberTooBig, "number too big for any type");
+        let out_of_range = || {
+            self.error(
+                ParseErrorKind::NumberOutOfRange,
+                format!("number out of range for type '{}'", ty),
+            )
+        };
+        if ty == "d" {
+            let (x, end) = strtod(token);
+            // `inf` is fine, but `1e999` is too big
+            if x.is_infinite() && token[..end].iter().any(u8::is_ascii_digit) {
+                return Err(too_big());
+            }
+            self.check_number_end(token, end)?;
+            return Ok(x.to_ne_bytes().to_vec());
+        }
+
+        let (negative, digits) = match token.strip_prefix(b"-") {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+        let (abs_val, end) = strtoull(digits).ok_or_else(too_big)?;
+        self.check_number_end(token, end + negative as usize)?;
+        let val = if negative {
+            -(abs_val as i128)
+        } else {
+            abs_val as i128
+        };
+        Ok(match ty.as_bytes() {
+            b"y" => vec![u8::try_from(val).map_err(|_| out_of_range())?],
+            b"n" => i16::try_from(val)
+                .map_err(|_| out_of_range())?
+                .to_ne_bytes()
+                .to_vec(),
+            b"q" => u16::try_from(val)
+                .map_err(|_| out_of_range())?
+                .to_ne_bytes()
+                .to_vec(),
+            b"i" | b"h" => i32::try_from(val)
+                .map_err(|_| out_of_range())?
+                .to_ne_bytes()
+                .to_vec(),
+            b"u" => u32::try_from(val)
+                .map_err(|_| out_of_range())?
+                .to_ne_bytes()
+                .to_vec(),
+            b"x" => i64::try_from(val)
+                .map_err(|_| out_of_range())?
+                .to_ne_bytes()
+                .to_vec(),
+            b"t" => u64::try_from(val)
+                .map_err(|_| out_of_range())?
+                .to_ne_bytes()
+                .to_vec(),
+            _ => return Err(self.type_error(ty)),
+        })
+    }
+
+    fn check_number_end(&self, token: &[u8], end: usize) -> Result<(), ParseError> {
+        if end == token.len() {
+            Ok(())
+        } else {
+            let start = self.range.start + end;
+            Err(error(
+                ParseErrorKind::InvalidCharacter,
+                start..start + 1,
+                "invalid character in number",
+            ))
+        }
+    }
+}
+
+fn dict_entry_value(entry: &VariantTypeStr, key: &Ast, value: &Ast) -> Result<Vec<u8>, ParseError> {
+    let key = key.get_value(entry.key().unwrap())?;
+    let value = value.get_value(entry.value().unwrap())?;
+    Ok(serialize_tuple(entry, vec![key, value]))
+}
+
+// Find the pattern which applies to all children in the array, by l-folding a
+// coalesce operation.
+fn array_pattern(items: &[Ast]) -> Result<String, ParseError> {
+    let mut pattern = items[0].get_pattern()?;
+    for (i, item) in items.iter().enumerate().skip(1) {
+        let tmp = item.get_pattern()?;
+        pattern = match pattern_coalesce(&pattern, &tmp) {
+            Some(merged) => merged,
+            None => {
+                // set coalescence implies pairwise coalescence (i think).  we
+                // should therefore be able to trace the failure to a single
+                // pair of values.
+                let mut err =
+                    item.error(ParseErrorKind::NoCommonType, "unable to find a common type");
+                for other in &items[..i] {
+                    if pattern_coalesce(&tmp, &other.get_pattern()?).is_none() {
+                        err.range = other.range.clone();
+                        err.other_range = Some(item.range.clone());
+                        break;
+                    }
+                }
+                return Err(err);
+            }
+        };
+    }
+    Ok(pattern)
+}
+
+// Find the most specific pattern that matches both `left` and `right`, if any.
+//
+//   (*(iii)) + ((iii)*) = ((iii)(iii))
+pub(crate) fn pattern_coalesce(left: &str, right: &str) -> Option<String> {
+    let mut out = vec![];
+    let mut left = left.as_bytes();
+    let mut right = right.as_bytes();
+    while let (Some(&l), Some(&r)) = (left.first(), right.first()) {
+        if l == r {
+            out.push(l);
+            left = &left[1..];
+            right = &right[1..];
+        } else if !coalesce_step(&mut left, &mut right, &mut out)
+            && !coalesce_step(&mut right, &mut left, &mut out)
+        {
+            break;
+        }
+    }
+    if left.is_empty() && right.is_empty() {
+        Some(String::from_utf8(out).unwrap())
+    } else {
+        None
+    }
+}
+
+// Returns `false` if there's no rule that lets `one` match `other`
+fn coalesce_step(one: &mut &[u8], other: &mut &[u8], out: &mut Vec<u8>) -> bool {
+    let (a, b) = (one[0], other[0]);
+    if a == b'*' && b != b')' {
+        pattern_copy(out, other);
+        *one = &one[1..];
+    } else if a == b'M' && b == b'm' {
+        out.push(b);
+        *other = &other[1..];
+    } else if a == b'M' && b != b'm' && b != b'*' {
+        *one = &one[1..];
+    } else if (a == b'N' && b"ynqiuxthd".contains(&b)) || (a == b'S' && b"sog".contains(&b)) {
+        out.push(b);
+        *one = &one[1..];
+        *other = &other[1..];
+    } else {
+        return false;
+    }
+    true
+}
+
+// Copy one complete type from the start of `pattern` to `out`
+fn pattern_copy(out: &mut Vec<u8>, pattern: &mut &[u8]) {
+    let prefix = pattern.iter().take_while(|c| b"amM".contains(c)).count();
+    let mut brackets = 0;
+    let mut end = prefix;
+    while let Some(&c) = pattern.get(end) {
+        end += 1;
+        if c == b'(' || c == b'{' {
+            brackets += 1;
+        } else if c == b')' || c == b'}' {
+            brackets -= 1;
+        }
+        if brackets == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(&pattern[..end]);
+    *pattern = &pattern[end..];
+}
+
+// Equivalent to C's `strtoull(s, &end, 0)`.  Returns the value and the number
+// of bytes consumed, or `None` if the value doesn't fit.
+fn strtoull(s: &[u8]) -> Option<(u64, usize)> {
+    let (sign, rest) = match s.first() {
+        Some(b'+') => (1, &s[1..]),
+        Some(b'-') => (1, &s[1..]),
+        _ => (0, s),
+    };
+    let (radix, prefix) = match rest {
+        [b'0', b'x' | b'X', c, ..] if c.is_ascii_hexdigit() => (16, 2),
+        [b'0', ..] => (8, 0),
+        _ => (10, 0),
+    };
+    let digits = rest[prefix..]
+        .iter()
+        .take_while(|c| (**c as char).is_digit(radix))
+        .count();
+    if digits == 0 {
+        // No number at all
+        return Some((0, 0));
+    }
+    let mut val: u64 = 0;
+    for &c in &rest[prefix..prefix + digits] {
+        let d = (c as char).to_digit(radix).unwrap() as u64;
+        val = val.checked_mul(radix as u64)?.checked_add(d)?;
+    }
+    if s.first() == Some(&b'-') {
+        val = val.wrapping_neg();
+    }
+    Some((val, sign + prefix + digits))
+}
+
+// Equivalent to C's `strtod(s, &end)` in the C locale.  Returns the value and
+// the number of bytes consumed.
+fn strtod(s: &[u8]) -> (f64, usize) {
+    let sign = match s.first() {
+        Some(b'+') | Some(b'-') => 1,
+        _ => 0,
+    };
+    let negative = s.first() == Some(&b'-');
+    let rest = &s[sign..];
+    let lower: Vec<u8> = rest
+        .iter()
+        .take(8)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    let (x, len) = if lower.starts_with(b"infinity") {
+        (f64::INFINITY, 8)
+    } else if lower.starts_with(b"inf") {
+        (f64::INFINITY, 3)
+    } else if lower.starts_with(b"nan") {
+        (f64::NAN, 3)
+    } else if let [b'0', b'x' | b'X', ..] = rest {
+        match strtod_hex(&rest[2..]) {
+            Some((x, len)) => (x, len + 2),
+            // Just the "0"
+            None => (0., 1),
+        }
+    } else {
+        let int = count_digits(rest);
+        let mut len = int;
+        let mut frac = 0;
+        if rest.get(len) == Some(&b'.') {
+            frac = count_digits(&rest[len + 1..]);
+            len += 1 + frac;
+        }
+        if int + frac == 0 {
+            return (0., 0);
+        }
+        if let Some(b'e' | b'E') = rest.get(len) {
+            let exp_sign = match rest.get(len + 1) {
+                Some(b'+') | Some(b'-') => 1,
+                _ => 0,
+            };
+            let exp = count_digits(&rest[len + 1 + exp_sign..]);
+            if exp > 0 {
+                len += 1 + exp_sign + exp;
+            }
+        }
+        // This is ASCII, and in a format that rust can parse
+        let x = core::str::from_utf8(&rest[..len]).unwrap().parse().unwrap();
+        (x, len)
+    };
+    (if negative { -x } else { x }, sign + len)
+}
+
+fn strtod_hex(s: &[u8]) -> Option<(f64, usize)> {
+    let hex = |s: &[u8]| s.iter().take_while(|c| c.is_ascii_hexdigit()).count();
+    let int = hex(s);
+    let mut len = int;
+    let mut frac = 0;
+    if s.get(len) == Some(&b'.') {
+        frac = hex(&s[len + 1..]);
+        len += 1 + frac;
+    }
+    if int + frac == 0 {
+        return None;
+    }
+    let mut x = 0f64;
+    let mut exp = 0i32;
+    for &c in s[..len].iter().filter(|c| **c != b'.') {
+        x = x * 16. + (c as char).to_digit(16).unwrap() as f64;
+    }
+    exp -= 4 * frac as i32;
+    if let Some(b'p' | b'P') = s.get(len) {
+        let exp_sign = match s.get(len + 1) {
+            Some(b'+') | Some(b'-') => 1,
+            _ => 0,
+        };
+        let digits = count_digits(&s[len + 1 + exp_sign..]);
+        if digits > 0 {
+            let e: i32 = core::str::from_utf8(&s[len + 1 + exp_sign..len + 1 + exp_sign + digits])
+                .unwrap()
+                .parse()
+                .unwrap_or(i32::MAX);
+            exp = exp.saturating_add(if s[len + 1] == b'-' { -e } else { e });
+            len += 1 + exp_sign + digits;
+        }
+    }
+    Some((x * 2f64.powi(exp), len))
+}
+
+fn count_digits(s: &[u8]) -> usize {
+    s.iter().take_while(|c| c.is_ascii_digit()).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aligned_bytes::copy_to_align;
+    use crate::DynValue;
+
+    fn t(s: &str) -> &VariantTypeStr {
+        VariantTypeStr::new(s).unwrap()
+    }
+
+    // Returns the type string and serialised data
+    fn p(text: &str) -> (String, Vec<u8>) {
+        let (ty, data) = parse(None, text).unwrap();
+        (ty.to_string(), data.to_vec())
+    }
+
+    fn pt(ty: &str, text: &str) -> Vec<u8> {
+        parse(Some(t(ty)), text).unwrap().1.to_vec()
+    }
+
+    fn err(ty: Option<&str>, text: &str) -> String {
+        parse(ty.map(t), text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_basic_types() {
+        assert_eq!(p("true"), ("b".into(), vec![1]));
+        assert_eq!(p("5"), ("i".into(), 5i32.to_ne_bytes().to_vec()));
+        assert_eq!(p("-5"), ("i".into(), (-5i32).to_ne_bytes().to_vec()));
+        assert_eq!(p("2.5"), ("d".into(), 2.5f64.to_ne_bytes().to_vec()));
+        assert_eq!(p("1e3"), ("d".into(), 1000f64.to_ne_bytes().to_vec()));
+        assert_eq!(p("inf"), ("d".into(), f64::INFINITY.to_ne_bytes().to_vec()));
+        assert_eq!(p("'hi'"), ("s".into(), b"hi\0".to_vec()));
+        assert_eq!(p("\"it's\""), ("s".into(), b"it's\0".to_vec()));
+        assert_eq!(p("b'hi'"), ("ay".into(), b"hi\0".to_vec()));
+        assert_eq!(p("byte 0x10"), ("y".into(), vec![0x10]));
+        assert_eq!(p("uint16 010"), ("q".into(), 8u16.to_ne_bytes().to_vec()));
+        assert_eq!(p("int64 -1"), ("x".into(), (-1i64).to_ne_bytes().to_vec()));
+        assert_eq!(p("handle 3"), ("h".into(), 3i32.to_ne_bytes().to_vec()));
+        assert_eq!(p("@h 3"), ("h".into(), 3i32.to_ne_bytes().to_vec()));
+        assert_eq!(p("objectpath '/a/b'"), ("o".into(), b"/a/b\0".to_vec()));
+        assert_eq!(p("signature 'a{sv}'"), ("g".into(), b"a{sv}\0".to_vec()));
+        assert_eq!(pt("d", "5"), 5f64.to_ne_bytes().to_vec());
+        assert_eq!(pt("d", "0x10"), 16f64.to_ne_bytes().to_vec());
+        assert_eq!(pt("d", "-0x1.8p1"), (-3f64).to_ne_bytes().to_vec());
+        assert_eq!(
+            pt("t", "18446744073709551615"),
+            u64::MAX.to_ne_bytes().to_vec()
+        );
+        assert_eq!(pt("n", "-32768"), i16::MIN.to_ne_bytes().to_vec());
+    }
+
+    #[test]
+    fn test_escapes() {
+        assert_eq!(p(r"'a\nb\\\'c'").1, b"a\nb\\'c\0");
+        assert_eq!(p(r"'é\U0001F600'").1, "\u{e9}\u{1f600}\0".as_bytes());
+        assert_eq!(p("'a\\\nb'").1, b"ab\0");
+        assert_eq!(p(r"'\q'").1, b"q\0");
+        assert_eq!(p(r"b'\0\101\1234\377'").1, b"\0A\x534\xff\0");
+        assert_eq!(p(r"b'\u'").1, b"u\0");
+    }
+
+    #[test]
+    fn test_containers() {
+        assert_eq!(p("[1, 2]"), ("ai".into(), pt("ai", "[1, 2]")));
+        assert_eq!(p("[1, 2.5]").0, "ad");
+        assert_eq!(p("@as []"), ("as".into(), vec![]));
+        assert_eq!(p("['a', 'bc']"), ("as".into(), b"a\0bc\0\x02\x05".to_vec()));
+        assert_eq!(p("(1, 'a')"), ("(is)".into(), b"\x01\0\0\0a\0".to_vec()));
+        assert_eq!(
+            p("('a', 1)"),
+            ("(si)".into(), b"a\0\0\0\x01\0\0\0\x02".to_vec())
+        );
+        assert_eq!(p("('a',)").0, "(s)");
+        assert_eq!(p("()"), ("()".into(), vec![0]));
+        assert_eq!(p("(byte 1, int64 2)").1.len(), 16);
+        assert_eq!(p("{1, 'a'}"), ("{is}".into(), b"\x01\0\0\0a\0".to_vec()));
+        assert_eq!(
+            p("{'a': 1, 'b': 2}"),
+            (
+                "a{si}".into(),
+                b"a\0\0\0\x01\0\0\0\x02\0\0\0b\0\0\0\x02\0\0\0\x02\x09\x15".to_vec()
+            )
+        );
+        assert_eq!(p("<5>"), ("v".into(), b"\x05\0\0\0\0i".to_vec()));
+        assert_eq!(p("[<5>, <'a'>]").0, "av");
+        assert_eq!(p("[[1], [2, 3]]").0, "aai");
+        assert_eq!(p("[@ay [], [byte 1]]").0, "aay");
+        assert_eq!(p("[(1, @as []), (2, ['a'])]").0, "a(ias)");
+    }
+
+    #[test]
+    fn test_maybes() {
+        assert_eq!(p("just 5"), ("mi".into(), 5i32.to_ne_bytes().to_vec()));
+        assert_eq!(p("just 'a'"), ("ms".into(), b"a\0\0".to_vec()));
+        assert_eq!(p("@ms nothing"), ("ms".into(), vec![]));
+        assert_eq!(p("[just 5, nothing]").0, "ami");
+        assert_eq!(p("[5, nothing]").0, "ami");
+        assert_eq!(p("[nothing, 'a']").0, "ams");
+        assert_eq!(pt("mmi", "just nothing"), vec![0]);
+        assert_eq!(pt("mmi", "5"), b"\x05\0\0\0\0".to_vec());
+        assert_eq!(pt("mms", "'a'"), b"a\0\0\0".to_vec());
+        assert_eq!(pt("ms", "'a'"), b"a\0\0".to_vec());
+        assert_eq!(pt("(mi)", "(nothing,)"), vec![]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(err(None, ""), "0:expected value");
+        assert_eq!(
+            err(None, "[1, 'a']"),
+            "1-2,4-7:unable to find a common type"
+        );
+        assert_eq!(err(None, "[]"), "0-2:unable to infer type");
+        assert_eq!(err(None, "nothing"), "0-7:unable to infer type");
+        assert_eq!(err(None, "(1)"), "2:expected ',' after first tuple element");
+        assert_eq!(
+            err(None, "[1 2]"),
+            "3:expected ',' or ']' to follow array element"
+        );
+        assert_eq!(err(None, "1 2"), "2:expected end of input");
+        assert_eq!(err(None, "'abc"), "0-4:unterminated string constant");
+        assert_eq!(
+            err(None, "@a* []"),
+            "0-3:type declarations must be definite"
+        );
+        assert_eq!(err(None, "@z 5"), "0-2:invalid type declaration");
+        assert_eq!(err(None, "int33 5"), "0-5:unknown keyword");
+        assert_eq!(err(None, "null"), "0-4:unknown keyword");
+        assert_eq!(
+            err(None, "{[1]: 2}"),
+            "0-8:dictionary keys must have basic types"
+        );
+        assert_eq!(
+            err(None, "'\\u00zz'"),
+            "3-5:invalid 4-character unicode escape"
+        );
+        assert_eq!(err(None, "5x"), "1-2:invalid character in number");
+        assert_eq!(
+            err(None, "99999999999999999999"),
+            "0-20:number too big for any type"
+        );
+        assert_eq!(
+            err(None, "3000000000"),
+            "0-10:number out of range for type 'i'"
+        );
+        assert_eq!(err(Some("y"), "-1"), "0-2:number out of range for type 'y'");
+        assert_eq!(
+            err(Some("s"), "5"),
+            "0-1:can not parse as value of type 's'"
+        );
+        assert_eq!(
+            err(Some("(ii)"), "(1,)"),
+            "0-4:can not parse as value of type '(ii)'"
+        );
+        assert_eq!(err(Some("o"), "'a'"), "0-3:not a valid object path");
+        assert_eq!(err(Some("g"), "'z'"), "0-3:not a valid signature");
+        assert_eq!(
+            err(Some("as"), "@ai []"),
+            "0-6:can not parse as value of type 'as'"
+        );
+        assert_eq!(err(Some("a*"), "[]"), "0:type 'a*' is not definite");
+
+        let e = parse(None, &"[".repeat(200)).unwrap_err();
+        assert_eq!(e.kind(), ParseErrorKind::Recursion);
+        assert_eq!(e.range(), 128..128);
+    }
+
+    #[test]
+    fn test_print_round_trip() {
+        for text in &[
+            "('Hello', @as [], 7, 5)",
+            "{'name': <'William'>, 'age': <uint32 34>, 'pets': <@as []>}",
+            "[byte 0x01, 0x02, 0x03]",
+            "b'abc\\n'",
+            "(@mmi just nothing, @ms 'a', @mmy 5)",
+            "[(1, true, 2.5, objectpath '/', signature 'ai')]",
+            "{int16 -5: [@a{sv} {}, {'a': <<5>>}]}",
+            "(\"it's\", '\\u0001\\\\')",
+            "[0.10000000000000001, 1e+17, -inf]",
+        ] {
+            let (ty, data) = parse(None, text).unwrap();
+            let value = DynValue::new(&ty, &data).unwrap();
+            let printed = value.print(true).to_string();
+            let (ty2, data2) = parse(None, &printed).unwrap();
+            assert_eq!(ty, ty2, "{} -> {}", text, printed);
+            assert_eq!(data, data2, "{} -> {}", text, printed);
+            assert_eq!(
+                printed,
+                DynValue::new(&ty2, &data2).unwrap().print(true).to_string()
+            );
+        }
+        // Parsing the printed form of data in normal form gives the same bytes
+        let data = copy_to_align(b"a\0\0\0\x01\0\0\0\x02\0\0\0b\0\0\0\x02\0\0\0\x02\x09\x15");
+        let value = DynValue::new(t("a{si}"), &data).unwrap();
+        assert_eq!(pt("a{si}", &value.print(false).to_string()), &**data);
+    }
+}
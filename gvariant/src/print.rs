@@ -0,0 +1,489 @@
+//! The GVariant text format
+//!
+//! GLib has a text format for GVariant values, as produced by `g_variant_print`
+//! and understood by `g_variant_parse`.  It looks like:
+//!
+//! ```text
+//! {'name': <'William'>, 'age': <uint32 34>, 'pets': <@as []>}
+//! ```
+//!
+//! This module implements the printing half, see [`parse`][crate::parse] for
+//! the other.  The output is identical to that
+//! of `g_variant_print` for data in normal form.  Printing is done with
+//! [`Display`], so there are no allocations unless you ask for them with
+//! `to_string()`:
+//!
+//! ```rust
+//! # use gvariant::{aligned_bytes::copy_to_align, gv, Marker};
+//! let data = copy_to_align(b"\x22\x00\x00\x00William\0");
+//! let m = gv!("(us)");
+//! let value = m.cast(data.as_ref());
+//! assert_eq!(m.print(value, false).to_string(), "(34, 'William')");
+//! assert_eq!(m.print(value, true).to_string(), "(uint32 34, 'William')");
+//! ```
+//!
+//! Values whose type is only known at run-time can be printed with
+//! [`DynValue::print`].
+//!
+//! ### Type annotations
+//!
+//! Like GLib we can optionally include enough type information in the output
+//! that the type of the value can be determined from the text.  This is enabled
+//! with the `type_annotate` argument.  Values within a **v** are always type
+//! annotated.
+
+use core::fmt::{Display, Write};
+
+use crate::dyn_value::{DynArray, DynMaybe, DynValue};
+use crate::unichar::is_print;
+use crate::Str;
+
+/// Prints a value in the GVariant text format
+///
+/// This is returned by [`Marker::print`][crate::Marker::print],
+/// [`Variant::print`][crate::Variant::print] and [`DynValue::print`].  It
+/// implements [`Display`] which does the actual printing.
+#[derive(Debug, Clone, Copy)]
+pub struct Print<'a> {
+    value: DynValue<'a>,
+    type_annotate: bool,
+}
+
+impl Display for Print<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        print(&self.value, self.type_annotate, f)
+    }
+}
+
+impl<'a> DynValue<'a> {
+    /// Print this value in the GVariant text format, like `g_variant_print`
+    ///
+    ///     # use gvariant::{aligned_bytes::copy_to_align, DynValue, VariantTypeStr};
+    ///     let data = copy_to_align(b"hello\0\0\0\x01\0\0\0\x02\0\0\0\x06");
+    ///     let ty = VariantTypeStr::new("(sai)").unwrap();
+    ///     let value = DynValue::new(ty, &data).unwrap();
+    ///     assert_eq!(value.print(false).to_string(), "('hello', [1, 2])");
+    pub fn print(&self, type_annotate: bool) -> Print<'a> {
+        Print {
+            value: *self,
+            type_annotate,
+        }
+    }
+}
+
+fn print(value: &DynValue, type_annotate: bool, f: &mut impl Write) -> core::fmt::Result {
+    match value {
+        DynValue::Maybe(x) => print_maybe(x, type_annotate, f),
+        DynValue::Array(x) => print_array(x, type_annotate, f),
+        DynValue::Tuple(x) => {
+            // for >1 item:  no trailing ","
+            // for 1 item:   trailing ","
+            // for 0 items:  just "()"
+            f.write_char('(')?;
+            for (n, child) in x.iter().enumerate() {
+                if n > 0 {
+                    f.write_str(", ")?;
+                }
+                print(&child, type_annotate, f)?;
+            }
+            if x.len() == 1 {
+                f.write_char(',')?;
+            }
+            f.write_char(')')
+        }
+        DynValue::DictEntry(x) => {
+            f.write_char('{')?;
+            print(&x.key().unwrap(), type_annotate, f)?;
+            f.write_str(", ")?;
+            print(&x.value().unwrap(), type_annotate, f)?;
+            f.write_char('}')
+        }
+        DynValue::Variant(x) => {
+            // Always annotate types in nested variants, because they are (by
+            // nature) of variable type.
+            f.write_char('<')?;
+            print(&x.value(), true, f)?;
+            f.write_char('>')
+        }
+        DynValue::Bool(x) => f.write_str(if *x { "true" } else { "false" }),
+        DynValue::Str(x) => print_str(x.to_str(), f),
+        DynValue::Byte(x) => {
+            if type_annotate {
+                f.write_str("byte ")?;
+            }
+            write!(f, "0x{:02x}", x)
+        }
+        DynValue::Int16(x) => print_number("int16 ", x, type_annotate, f),
+        DynValue::Uint16(x) => print_number("uint16 ", x, type_annotate, f),
+        // Never annotate this type because it is the default for numbers (and
+        // this is a *pretty* printer)
+        DynValue::Int32(x) => print_number("", x, false, f),
+        DynValue::Uint32(x) => print_number("uint32 ", x, type_annotate, f),
+        DynValue::Handle(x) => print_number("handle ", x, type_annotate, f),
+        DynValue::Int64(x) => print_number("int64 ", x, type_annotate, f),
+        DynValue::Uint64(x) => print_number("uint64 ", x, type_annotate, f),
+        DynValue::Double(x) => print_double(*x, f),
+        DynValue::ObjectPath(x) => print_quoted("objectpath ", x.as_gv_str(), type_annotate, f),
+        DynValue::Signature(x) => print_quoted("signature ", x.as_gv_str(), type_annotate, f),
+    }
+}
+
+fn print_number(
+    annotation: &str,
+    x: impl Display,
+    type_annotate: bool,
+    f: &mut impl Write,
+) -> core::fmt::Result {
+    if type_annotate {
+        f.write_str(annotation)?;
+    }
+    write!(f, "{}", x)
+}
+
+fn print_quoted(
+    annotation: &str,
+    x: &Str,
+    type_annotate: bool,
+    f: &mut impl Write,
+) -> core::fmt::Result {
+    if type_annotate {
+        f.write_str(annotation)?;
+    }
+    write!(f, "'{}'", x)
+}
+
+fn print_maybe(x: &DynMaybe, type_annotate: bool, f: &mut impl Write) -> core::fmt::Result {
+    if type_annotate {
+        write!(f, "@{} ", x.type_str())?;
+    }
+    // Nested maybes:
+    //
+    // Consider the types "ms" and "mms" and the values of these types.
+    //
+    //   "ms":  nothing, just 'x'
+    //  "mms":  nothing, just nothing, just just 'x'
+    //
+    // The innermost value is printed without any "just"s if every maybe
+    // contains a value, otherwise we print enough "just"s to determine which
+    // maybe was `Nothing`:
+    //
+    //   "ms":  nothing, 'x'
+    //  "mms":  nothing, just nothing, 'x'
+    let mut value = match x.to_option() {
+        None => return f.write_str("nothing"),
+        Some(v) => v,
+    };
+    let mut justs = 0;
+    loop {
+        match value {
+            DynValue::Maybe(m) => match m.to_option() {
+                Some(v) => {
+                    value = v;
+                    justs += 1;
+                }
+                None => {
+                    for _ in 0..justs + 1 {
+                        f.write_str("just ")?;
+                    }
+                    return f.write_str("nothing");
+                }
+            },
+            _ => return print(&value, false, f),
+        }
+    }
+}
+
+fn print_array(x: &DynArray, mut type_annotate: bool, f: &mut impl Write) -> core::fmt::Result {
+    let ty = x.type_str();
+    if ty == "ay" {
+        // If there is a single nul and it's at the end then it's a byte
+        // string.
+        if let Some((b'\0', s)) = x.as_bytes().split_last() {
+            if !s.contains(&b'\0') {
+                return print_bytestring(s, f);
+            }
+        }
+    }
+    let is_dict = x.element_type().is_dict_entry();
+    if x.is_empty() {
+        if type_annotate {
+            write!(f, "@{} ", ty)?;
+        }
+        return f.write_str(if is_dict { "{}" } else { "[]" });
+    }
+    f.write_char(if is_dict { '{' } else { '[' })?;
+    for (n, child) in x.iter().enumerate() {
+        if n > 0 {
+            f.write_str(", ")?;
+        }
+        match child {
+            DynValue::DictEntry(entry) if is_dict => {
+                print(&entry.key().unwrap(), type_annotate, f)?;
+                f.write_str(": ")?;
+                print(&entry.value().unwrap(), type_annotate, f)?;
+            }
+            _ => print(&child, type_annotate, f)?,
+        }
+        // The first element determines the type of the rest:
+        type_annotate = false;
+    }
+    f.write_char(if is_dict { '}' } else { ']' })
+}
+
+fn print_bytestring(s: &[u8], f: &mut impl Write) -> core::fmt::Result {
+    // Use double quotes only if a ' is in the string.  The escaping follows
+    // `g_strescape`.
+    let quote = if s.contains(&b'\'') { '"' } else { '\'' };
+    f.write_char('b')?;
+    f.write_char(quote)?;
+    for &c in s {
+        match c {
+            b'\x08' => f.write_str("\\b")?,
+            b'\x0c' => f.write_str("\\f")?,
+            b'\n' => f.write_str("\\n")?,
+            b'\r' => f.write_str("\\r")?,
+            b'\t' => f.write_str("\\t")?,
+            b'\x0b' => f.write_str("\\v")?,
+            b'\\' => f.write_str("\\\\")?,
+            b'"' => f.write_str("\\\"")?,
+            c if !(b' '..0o177).contains(&c) => write!(f, "\\{:03o}", c)?,
+            c => f.write_char(c as char)?,
+        }
+    }
+    f.write_char(quote)
+}
+
+fn print_str(s: &str, f: &mut impl Write) -> core::fmt::Result {
+    let quote = if s.contains('\'') { '"' } else { '\'' };
+    f.write_char(quote)?;
+    for c in s.chars() {
+        if c == quote || c == '\\' {
+            f.write_char('\\')?;
+        }
+        if is_print(c) {
+            f.write_char(c)?;
+        } else {
+            match c {
+                '\x07' => f.write_str("\\a")?,
+                '\x08' => f.write_str("\\b")?,
+                '\x0c' => f.write_str("\\f")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                '\x0b' => f.write_str("\\v")?,
+                c if (c as u32) < 0x10000 => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "\\U{:08x}", c as u32)?,
+            }
+        }
+    }
+    f.write_char(quote)
+}
+
+// A fixed size buffer for formatting doubles without allocating
+struct DoubleBuf {
+    buf: [u8; 32],
+    len: usize,
+}
+impl DoubleBuf {
+    fn as_str(&self) -> &str {
+        // We only ever write ASCII into this buffer
+        core::str::from_utf8(&self.buf[..self.len]).unwrap()
+    }
+}
+impl Write for DoubleBuf {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(core::fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+// Equivalent to C's `printf("%.17g", x)`, which is what GLib uses
+fn format_double(x: f64, out: &mut impl Write) -> core::fmt::Result {
+    if x.is_nan() {
+        return out.write_str(if x.is_sign_negative() { "-nan" } else { "nan" });
+    }
+    if x.is_infinite() {
+        return out.write_str(if x < 0.0 { "-inf" } else { "inf" });
+    }
+    // 17 significant digits, correctly rounded, as d.dddddddddddddddde[-]x
+    let mut sci = DoubleBuf {
+        buf: [0; 32],
+        len: 0,
+    };
+    write!(sci, "{:.16e}", x)?;
+    let (mantissa, exp) = sci.as_str().split_at(sci.as_str().find('e').unwrap());
+    let exp: i32 = exp[1..].parse().unwrap();
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(m) => ("-", m),
+        None => ("", mantissa),
+    };
+    out.write_str(sign)?;
+    if !(-4..17).contains(&exp) {
+        let mantissa = mantissa.trim_end_matches('0').trim_end_matches('.');
+        let exp_sign = if exp < 0 { '-' } else { '+' };
+        write!(out, "{}e{}{:02}", mantissa, exp_sign, exp.abs())
+    } else {
+        let digits = mantissa.as_bytes().iter().filter(|c| **c != b'.');
+        let mut frac = DoubleBuf {
+            buf: [0; 32],
+            len: 0,
+        };
+        if exp < 0 {
+            out.write_char('0')?;
+            for _ in 0..-exp - 1 {
+                frac.write_char('0')?;
+            }
+            for &d in digits {
+                frac.write_char(d as char)?;
+            }
+        } else {
+            for (n, &d) in digits.enumerate() {
+                if n as i32 <= exp {
+                    out.write_char(d as char)?;
+                } else {
+                    frac.write_char(d as char)?;
+                }
+            }
+        }
+        let frac = frac.as_str().trim_end_matches('0');
+        if !frac.is_empty() {
+            write!(out, ".{}", frac)?;
+        }
+        Ok(())
+    }
+}
+
+fn print_double(x: f64, f: &mut impl Write) -> core::fmt::Result {
+    let mut buf = DoubleBuf {
+        buf: [0; 32],
+        len: 0,
+    };
+    format_double(x, &mut buf)?;
+    let s = buf.as_str();
+    f.write_str(s)?;
+    // if there is no '.' or 'e' in the float then add one
+    if !s.contains(['.', 'e', 'n', 'N']) {
+        f.write_str(".0")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aligned_bytes::copy_to_align;
+    use crate::VariantTypeStr;
+
+    fn p(ty: &str, data: &[u8], type_annotate: bool) -> String {
+        let data = copy_to_align(data);
+        DynValue::new(VariantTypeStr::new(ty).unwrap(), &data)
+            .unwrap()
+            .print(type_annotate)
+            .to_string()
+    }
+
+    #[test]
+    fn test_doubles() {
+        let d = |x: f64| {
+            let mut s = String::new();
+            print_double(x, &mut s).unwrap();
+            s
+        };
+        assert_eq!(d(0.0), "0.0");
+        assert_eq!(d(-0.0), "-0.0");
+        assert_eq!(d(1.0), "1.0");
+        assert_eq!(d(-2.5), "-2.5");
+        assert_eq!(d(0.1), "0.10000000000000001");
+        assert_eq!(d(1e16), "10000000000000000.0");
+        assert_eq!(d(1e17), "1e+17");
+        assert_eq!(d(1.5e300), "1.5000000000000001e+300");
+        assert_eq!(d(0.0001), "0.0001");
+        assert_eq!(d(0.00001), "1.0000000000000001e-05");
+        assert_eq!(d(123456.789), "123456.789");
+        assert_eq!(d(f64::INFINITY), "inf");
+        assert_eq!(d(f64::NEG_INFINITY), "-inf");
+        assert_eq!(d(f64::NAN), "nan");
+        assert_eq!(d(1.0 / 3.0), "0.33333333333333331");
+        assert_eq!(d(5e-324), "4.9406564584124654e-324");
+    }
+
+    #[test]
+    fn test_strings() {
+        assert_eq!(p("s", b"hello\0", false), "'hello'");
+        assert_eq!(p("s", b"it's\0", false), "\"it's\"");
+        assert_eq!(p("s", b"\"it's\"\0", false), "\"\\\"it's\\\"\"");
+        assert_eq!(p("s", b"a\\b\0", false), "'a\\\\b'");
+        assert_eq!(p("s", b"\x07\n\t\x01\0", false), "'\\a\\n\\t\\u0001'");
+        assert_eq!(
+            p("s", "caf\u{e9} \u{200b}\0".as_bytes(), false),
+            "'caf\u{e9} \\u200b'"
+        );
+        assert_eq!(p("s", "\u{e0001}\0".as_bytes(), false), "'\\U000e0001'");
+        assert_eq!(p("o", b"/a/b\0", true), "objectpath '/a/b'");
+        assert_eq!(p("g", b"a{sv}\0", true), "signature 'a{sv}'");
+        assert_eq!(p("g", b"a{sv}\0", false), "'a{sv}'");
+    }
+
+    #[test]
+    fn test_numbers() {
+        assert_eq!(p("b", b"\x01", true), "true");
+        assert_eq!(p("b", b"\x00", true), "false");
+        assert_eq!(p("y", b"\x0a", false), "0x0a");
+        assert_eq!(p("y", b"\xff", true), "byte 0xff");
+        assert_eq!(p("n", &(-5i16).to_ne_bytes(), true), "int16 -5");
+        assert_eq!(p("q", &5u16.to_ne_bytes(), true), "uint16 5");
+        assert_eq!(p("i", &(-5i32).to_ne_bytes(), true), "-5");
+        assert_eq!(p("u", &5u32.to_ne_bytes(), true), "uint32 5");
+        assert_eq!(p("h", &5i32.to_ne_bytes(), true), "handle 5");
+        assert_eq!(p("h", &5i32.to_ne_bytes(), false), "5");
+        assert_eq!(p("x", &(-5i64).to_ne_bytes(), true), "int64 -5");
+        assert_eq!(p("t", &5u64.to_ne_bytes(), true), "uint64 5");
+        assert_eq!(p("t", &5u64.to_ne_bytes(), false), "5");
+        assert_eq!(p("d", &2f64.to_ne_bytes(), true), "2.0");
+    }
+
+    #[test]
+    fn test_containers() {
+        assert_eq!(p("as", b"", false), "[]");
+        assert_eq!(p("as", b"", true), "@as []");
+        assert_eq!(p("a{sv}", b"", true), "@a{sv} {}");
+        assert_eq!(p("ay", b"", true), "@ay []");
+        assert_eq!(p("ay", b"abc\0", true), "b'abc'");
+        assert_eq!(p("ay", b"it's\0", true), "b\"it's\"");
+        assert_eq!(p("ay", b"\"\n\x01\xff\0", true), "b'\\\"\\n\\001\\377'");
+        assert_eq!(p("ay", b"a\0c\0", true), "[byte 0x61, 0x00, 0x63, 0x00]");
+        assert_eq!(p("ay", b"\0", false), "b''");
+        let aq: Vec<u8> = [1u16, 2].iter().flat_map(|x| x.to_ne_bytes()).collect();
+        assert_eq!(p("aq", &aq, true), "[uint16 1, 2]");
+        assert_eq!(p("(ss)", b"a\0b\0\x02", false), "('a', 'b')");
+        assert_eq!(p("(s)", b"a\0", false), "('a',)");
+        assert_eq!(p("()", b"\0", false), "()");
+        assert_eq!(p("{ys}", b"\x01a\0", true), "{byte 0x01, 'a'}");
+        assert_eq!(
+            p("a{ys}", b"\x01a\0\x02b\0\x03\x06", true),
+            "{byte 0x01: 'a', 0x02: 'b'}"
+        );
+        assert_eq!(p("v", b"\x01\0y", false), "<byte 0x01>");
+        assert_eq!(p("v", b"\0as", false), "<@as []>");
+        assert_eq!(p("v", b"", false), "<()>");
+    }
+
+    #[test]
+    fn test_maybes() {
+        assert_eq!(p("ms", b"", false), "nothing");
+        assert_eq!(p("ms", b"", true), "@ms nothing");
+        assert_eq!(p("ms", b"a\0\0", false), "'a'");
+        assert_eq!(p("ms", b"a\0\0", true), "@ms 'a'");
+        assert_eq!(p("mms", b"\0", false), "just nothing");
+        assert_eq!(p("mms", b"a\0\0\0", false), "'a'");
+        assert_eq!(p("mmms", b"\0\0", true), "@mmms just just nothing");
+        assert_eq!(p("my", b"\x05", true), "@my 0x05");
+        assert_eq!(p("mmy", b"\0", true), "@mmy just nothing");
+        assert_eq!(p("mv", b"\x05\0y\0", true), "@mv <byte 0x05>");
+    }
+}
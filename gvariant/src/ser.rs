@@ -0,0 +1,1103 @@
+//! Serialisation with serde
+//!
+//! This module lets you serialise any type that implements serde's
+//! [`Serialize`] as GVariant data, without having to write [`SerializeTo`]
+//! implementations for it.  It requires the `serde` feature.
+//!
+//! ```rust
+//! # use gvariant::{ser, VariantTypeStr};
+//! #[derive(serde::Serialize)]
+//! struct Person {
+//!     name: String,
+//!     age: u32,
+//! }
+//! let william = Person { name: "William".into(), age: 34 };
+//! let ty = VariantTypeStr::new("(su)").unwrap();
+//!
+//! let mut out = vec![];
+//! ser::to_writer(&william, Some(ty), &mut out).unwrap();
+//! assert_eq!(out, b"William\0\x22\x00\x00\x00\x08");
+//! ```
+//!
+//! The output is the same as you'd get from [`Marker::serialize`] for the same
+//! type.
+//!
+//! ### Types
+//!
+//! The GVariant type to write can be given explicitly, as above, or inferred
+//! from the value with [`infer_type`].  Inference follows the serde data
+//! model:
+//!
+//! | serde                          | GVariant                               |
+//! |--------------------------------|----------------------------------------|
+//! | `bool`                         | **b**                                  |
+//! | `u8`, `u16`, `u32`, `u64`      | **y**, **q**, **u**, **t**             |
+//! | `i8`/`i16`, `i32`, `i64`       | **n**, **i**, **x**                    |
+//! | `f32`, `f64`                   | **d**                                  |
+//! | `char`, `str`                  | **s**                                  |
+//! | bytes                          | **ay**                                 |
+//! | `Option<T>`                    | **m**T                                 |
+//! | `()` and unit structs          | **()**                                 |
+//! | sequences                      | **a**T                                 |
+//! | tuples and structs             | **(**...**)**                          |
+//! | maps                           | **a{**KV**}**                          |
+//! | enums                          | **(sv)**                               |
+//!
+//! The type of an empty sequence, an empty map or `None` can't be inferred, so
+//! values containing them need an explicit type.
+//!
+//! An explicit type can be more flexible: integers can be written as any
+//! GVariant integer type that can hold their value, strings can be written as
+//! object paths **o** or signatures **g** and structs can be written as a
+//! "bag of properties" **a{sv}** (or any other **a{s**...**}**) with the field
+//! names as keys.
+//!
+//! ### Variants and enums
+//!
+//! A value written where the type is **v** is given the type that
+//! [`infer_type`] would give it, so `a{sv}` can be used with a map whose values
+//! are of any type.
+//!
+//! Enums are written as an **(sv)** of the name of the variant along with its
+//! contents by default.  The type can also be given as **(uv)**, with the index
+//! of the variant instead of its name.  Enums written where the type is **v**
+//! contain the contents of the variant directly, like enums that
+//! `#[derive(GVariant)]`.  Unit variants have the contents **()**, but can also
+//! be written as the name **s** or index **u** of the variant by themselves.
+//!
+//! ```rust
+//! # use gvariant::{ser, VariantTypeStr};
+//! #[derive(serde::Serialize)]
+//! enum Value {
+//!     Int(i32),
+//!     Text(String),
+//! }
+//! let v = vec![("a", Value::Int(1)), ("b", Value::Text("x".into()))];
+//! assert_eq!(ser::infer_type(&v).unwrap(), "a(s(sv))");
+//!
+//! let ty = VariantTypeStr::new("a{sv}").unwrap();
+//! let data = ser::to_vec(&v, Some(ty)).unwrap();
+//! # let data = gvariant::aligned_bytes::copy_to_align(&data);
+//! # use gvariant::{gv, Marker};
+//! # let m = gv!("a{sv}");
+//! # assert_eq!(m.print(m.cast(data.as_ref()), false).to_string(), "{'a': <1>, 'b': <'x'>}");
+//! ```
+//!
+//! [`Marker::serialize`]: crate::Marker::serialize
+//! [`SerializeTo`]: crate::SerializeTo
+
+use core::{convert::TryFrom, fmt::Display};
+
+use serde::ser::{self, Serialize};
+
+use crate::aligned_bytes::{A1, A2, A4, A8};
+use crate::normal_form::{is_object_path, is_signature};
+use crate::parse::pattern_coalesce;
+use crate::variant_type::{align, VariantType, VariantTypeStr};
+use crate::write::{self, IoWrite, Write};
+use crate::{write_offsets, write_padding, SerializeTo, Str};
+
+/// Error returned when serialising with serde
+#[derive(Debug)]
+pub enum Error {
+    /// Writing to the output failed
+    Io(std::io::Error),
+    /// The value can't be represented as the GVariant type, or its type can't
+    /// be inferred
+    Type(String),
+    /// An error reported by the value's [`Serialize`] implementation
+    Custom(String),
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Io(e) => Display::fmt(e, f),
+            Error::Type(msg) | Error::Custom(msg) => f.write_str(msg),
+        }
+    }
+}
+impl std::error::Error for Error {}
+impl ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Custom(msg.to_string())
+    }
+}
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+impl From<write::Error> for Error {
+    fn from(e: write::Error) -> Self {
+        Error::Io(e.into())
+    }
+}
+
+/// Serialise `value` as GVariant data of type `ty` to `f`
+///
+/// If `ty` is `None` it's inferred with [`infer_type`].  Returns the number of
+/// bytes written.
+///
+/// `f` should be positioned at an offset that's a multiple of 8 bytes, as the
+/// padding that's written depends on it.
+pub fn to_writer<T: Serialize + ?Sized, W: std::io::Write>(
+    value: &T,
+    ty: Option<&VariantTypeStr>,
+    f: &mut W,
+) -> Result<usize, Error> {
+    let inferred;
+    let ty = match ty {
+        Some(ty) if !ty.is_definite() => {
+            return Err(Error::Type(format!("Type {} isn't definite", ty)))
+        }
+        Some(ty) => ty,
+        None => {
+            inferred = infer_type(value)?;
+            &inferred
+        }
+    };
+    let mut out = Output {
+        f: &mut IoWrite(f),
+        pos: 0,
+    };
+    child(&mut out, ty, value)?;
+    Ok(out.pos)
+}
+
+/// Serialise `value` as GVariant data of type `ty`
+///
+/// See [`to_writer`].
+pub fn to_vec<T: Serialize + ?Sized>(
+    value: &T,
+    ty: Option<&VariantTypeStr>,
+) -> Result<Vec<u8>, Error> {
+    let mut out = vec![];
+    to_writer(value, ty, &mut out)?;
+    Ok(out)
+}
+
+/// Infer the GVariant type of `value`
+///
+/// See the [module documentation][crate::ser] for the rules.
+pub fn infer_type<T: Serialize + ?Sized>(value: &T) -> Result<VariantType, Error> {
+    infer(value, false)
+}
+
+// If `payload` is true we're inferring the type of the contents of a **v**, so
+// enums are given the type of the contents of the variant.
+fn infer<T: Serialize + ?Sized>(value: &T, payload: bool) -> Result<VariantType, Error> {
+    let pattern = value.serialize(Infer { payload })?;
+    if pattern.contains('*') {
+        return Err(Error::Type(format!(
+            "Can't infer the type of empty sequences, empty maps or None (found {}).  \
+             Give the type explicitly",
+            pattern
+        )));
+    }
+    VariantType::new(&pattern).map_err(|e| Error::Type(format!("{}: {}", e, pattern)))
+}
+
+// Keeps track of our position so we know how much padding to write
+struct Output<'a, W> {
+    f: &'a mut W,
+    pos: usize,
+}
+
+impl<W: Write> Output<'_, W> {
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.f.write_all(data)?;
+        self.pos += data.len();
+        Ok(())
+    }
+    fn pad(&mut self, alignment: usize) -> Result<(), Error> {
+        self.pos += match alignment {
+            1 => write_padding::<A1, _>(self.pos, self.f),
+            2 => write_padding::<A2, _>(self.pos, self.f),
+            4 => write_padding::<A4, _>(self.pos, self.f),
+            _ => write_padding::<A8, _>(self.pos, self.f),
+        }?;
+        Ok(())
+    }
+    // Write the framing offsets for the container that started at `start`
+    fn offsets(&mut self, start: usize, offsets: &[usize]) -> Result<(), Error> {
+        self.pos = start + write_offsets(self.pos - start, offsets, self.f)?;
+        Ok(())
+    }
+}
+
+// Serialise `value` as a child of a container with type `ty`
+fn child<W: Write, T: Serialize + ?Sized>(
+    out: &mut Output<'_, W>,
+    ty: &VariantTypeStr,
+    value: &T,
+) -> Result<(), Error> {
+    if ty.is_variant() {
+        variant(out, value, true)
+    } else {
+        value.serialize(Serializer {
+            out,
+            ty,
+            payload: false,
+        })
+    }
+}
+
+fn variant<W: Write, T: Serialize + ?Sized>(
+    out: &mut Output<'_, W>,
+    value: &T,
+    payload: bool,
+) -> Result<(), Error> {
+    let ty = infer(value, payload)?;
+    value.serialize(Serializer {
+        out,
+        ty: &ty,
+        payload,
+    })?;
+    out.write(b"\0")?;
+    out.write(ty.as_bytes())
+}
+
+struct Serializer<'a, 'w, W> {
+    out: &'a mut Output<'w, W>,
+    ty: &'a VariantTypeStr,
+    // We're the contents of a **v**, so enums are written as just the contents
+    // of the variant
+    payload: bool,
+}
+
+impl<'a, 'w, W: Write> Serializer<'a, 'w, W> {
+    fn mismatch(&self, what: &str) -> Error {
+        Error::Type(format!("Can't serialise {} as type {}", what, self.ty))
+    }
+    fn integer<T: Copy + Display>(self, x: T) -> Result<(), Error>
+    where
+        u8: TryFrom<T>,
+        i16: TryFrom<T>,
+        u16: TryFrom<T>,
+        i32: TryFrom<T>,
+        u32: TryFrom<T>,
+        i64: TryFrom<T>,
+        u64: TryFrom<T>,
+    {
+        let bytes = match self.ty.as_bytes() {
+            b"y" => u8::try_from(x).map(|x| x.to_ne_bytes().to_vec()).ok(),
+            b"n" => i16::try_from(x).map(|x| x.to_ne_bytes().to_vec()).ok(),
+            b"q" => u16::try_from(x).map(|x| x.to_ne_bytes().to_vec()).ok(),
+            b"i" | b"h" => i32::try_from(x).map(|x| x.to_ne_bytes().to_vec()).ok(),
+            b"u" => u32::try_from(x).map(|x| x.to_ne_bytes().to_vec()).ok(),
+            b"x" => i64::try_from(x).map(|x| x.to_ne_bytes().to_vec()).ok(),
+            b"t" => u64::try_from(x).map(|x| x.to_ne_bytes().to_vec()).ok(),
+            _ => return Err(self.mismatch("an integer")),
+        };
+        match bytes {
+            Some(bytes) => self.out.write(&bytes),
+            None => Err(Error::Type(format!(
+                "{} is out of range for type {}",
+                x, self.ty
+            ))),
+        }
+    }
+    fn tuple(self, len: usize) -> Result<Compound<'a, 'w, W>, Error> {
+        if self.ty.is_array() {
+            return Ok(self.array());
+        }
+        if !(self.ty.is_tuple() || self.ty.is_dict_entry()) || self.ty.n_items() != len {
+            return Err(self.mismatch(&format!("a tuple of {} items", len)));
+        }
+        Ok(Compound {
+            tuple: Tuple::new(self.out),
+            out: self.out,
+            kind: Kind::Tuple(self.ty.items()),
+        })
+    }
+    fn array(self) -> Compound<'a, 'w, W> {
+        Compound {
+            tuple: Tuple::new(self.out),
+            out: self.out,
+            kind: Kind::Array(self.ty.element().unwrap()),
+        }
+    }
+    // Enums that aren't the contents of a **v** are written as **(sv)** or
+    // **(uv)**.  This writes the name or index, leaving the **v** to the
+    // caller.
+    fn tag(&mut self, name: &'static str, index: u32) -> Result<Tuple, Error> {
+        let mut outer = Tuple::new(self.out);
+        match self.ty.as_bytes() {
+            b"(sv)" => outer.item(self.out, VariantTypeStr::from_bytes_unchecked(b"s"), name)?,
+            b"(uv)" => outer.item(self.out, VariantTypeStr::from_bytes_unchecked(b"u"), &index)?,
+            _ => return Err(self.mismatch("an enum")),
+        }
+        outer.begin_item(self.out, 8)?;
+        Ok(outer)
+    }
+    fn tagged<T: Serialize + ?Sized>(
+        mut self,
+        name: &'static str,
+        index: u32,
+        value: &T,
+    ) -> Result<(), Error> {
+        let mut outer = self.tag(name, index)?;
+        variant(self.out, value, false)?;
+        outer.end_item(self.out, false);
+        outer.end_tuple(self.out)
+    }
+    fn tagged_fields(
+        mut self,
+        name: &'static str,
+        index: u32,
+    ) -> Result<Compound<'a, 'w, W>, Error> {
+        let outer = self.tag(name, index)?;
+        Ok(Compound {
+            tuple: Tuple::new(self.out),
+            out: self.out,
+            kind: Kind::Tagged(outer, String::new()),
+        })
+    }
+}
+
+impl<'a, 'w, W: Write> ser::Serializer for Serializer<'a, 'w, W> {
+    type Ok = ();
+    type Error = Error;
+    type SerializeSeq = Compound<'a, 'w, W>;
+    type SerializeTuple = Compound<'a, 'w, W>;
+    type SerializeTupleStruct = Compound<'a, 'w, W>;
+    type SerializeTupleVariant = Compound<'a, 'w, W>;
+    type SerializeMap = Compound<'a, 'w, W>;
+    type SerializeStruct = Compound<'a, 'w, W>;
+    type SerializeStructVariant = Compound<'a, 'w, W>;
+
+    fn serialize_bool(self, v: bool) -> Result<(), Error> {
+        if self.ty != "b" {
+            return Err(self.mismatch("a bool"));
+        }
+        self.out.write(&[v as u8])
+    }
+    fn serialize_i8(self, v: i8) -> Result<(), Error> {
+        self.integer(v)
+    }
+    fn serialize_i16(self, v: i16) -> Result<(), Error> {
+        self.integer(v)
+    }
+    fn serialize_i32(self, v: i32) -> Result<(), Error> {
+        self.integer(v)
+    }
+    fn serialize_i64(self, v: i64) -> Result<(), Error> {
+        self.integer(v)
+    }
+    fn serialize_u8(self, v: u8) -> Result<(), Error> {
+        self.integer(v)
+    }
+    fn serialize_u16(self, v: u16) -> Result<(), Error> {
+        self.integer(v)
+    }
+    fn serialize_u32(self, v: u32) -> Result<(), Error> {
+        self.integer(v)
+    }
+    fn serialize_u64(self, v: u64) -> Result<(), Error> {
+        self.integer(v)
+    }
+    fn serialize_f32(self, v: f32) -> Result<(), Error> {
+        self.serialize_f64(v.into())
+    }
+    fn serialize_f64(self, v: f64) -> Result<(), Error> {
+        if self.ty != "d" {
+            return Err(self.mismatch("a float"));
+        }
+        self.out.write(&v.to_ne_bytes())
+    }
+    fn serialize_char(self, v: char) -> Result<(), Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+    fn serialize_str(self, v: &str) -> Result<(), Error> {
+        let valid = match self.ty.as_bytes() {
+            b"s" => true,
+            b"o" => is_object_path(v.as_bytes()),
+            b"g" => is_signature(v.as_bytes()),
+            _ => return Err(self.mismatch("a string")),
+        };
+        if !valid {
+            return Err(Error::Type(format!(
+                "{:?} isn't valid for type {}",
+                v, self.ty
+            )));
+        }
+        self.out.pos += SerializeTo::<Str>::serialize(v, self.out.f)?;
+        Ok(())
+    }
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), Error> {
+        if self.ty != "ay" {
+            return Err(self.mismatch("bytes"));
+        }
+        self.out.write(v)
+    }
+    fn serialize_none(self) -> Result<(), Error> {
+        if !self.ty.is_maybe() {
+            return Err(self.mismatch("None"));
+        }
+        Ok(())
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), Error> {
+        let element = match self.ty.element() {
+            Some(element) if self.ty.is_maybe() => element,
+            _ => return Err(self.mismatch("Some")),
+        };
+        child(self.out, element, value)?;
+        if !element.is_fixed_size() {
+            self.out.write(b"\0")?;
+        }
+        Ok(())
+    }
+    fn serialize_unit(self) -> Result<(), Error> {
+        if self.ty != "()" {
+            return Err(self.mismatch("()"));
+        }
+        self.out.write(b"\0")
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), Error> {
+        self.serialize_unit()
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        variant: &'static str,
+    ) -> Result<(), Error> {
+        match self.ty.as_bytes() {
+            _ if self.payload => self.serialize_unit(),
+            b"s" => self.serialize_str(variant),
+            b"u" => self.serialize_u32(index),
+            _ => self.tagged(variant, index, &()),
+        }
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        if self.payload {
+            child(self.out, self.ty, value)
+        } else {
+            self.tagged(variant, index, value)
+        }
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Compound<'a, 'w, W>, Error> {
+        if !self.ty.is_array() {
+            return Err(self.mismatch("a sequence"));
+        }
+        Ok(self.array())
+    }
+    fn serialize_tuple(self, len: usize) -> Result<Compound<'a, 'w, W>, Error> {
+        self.tuple(len)
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a, 'w, W>, Error> {
+        self.tuple(len)
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a, 'w, W>, Error> {
+        if self.payload {
+            self.tuple(len)
+        } else {
+            self.tagged_fields(variant, index)
+        }
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Compound<'a, 'w, W>, Error> {
+        match self.ty.element() {
+            Some(entry) if self.ty.is_array() && entry.is_dict_entry() => Ok(Compound {
+                tuple: Tuple::new(self.out),
+                out: self.out,
+                kind: Kind::Dict(entry, None),
+            }),
+            _ => Err(self.mismatch("a map")),
+        }
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a, 'w, W>, Error> {
+        match self.ty.element() {
+            Some(entry)
+                if self.ty.is_array() && entry.key().map(|k| k.as_bytes()) == Some(b"s") =>
+            {
+                Ok(Compound {
+                    tuple: Tuple::new(self.out),
+                    out: self.out,
+                    kind: Kind::Dict(entry, None),
+                })
+            }
+            _ => self.tuple(len),
+        }
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a, 'w, W>, Error> {
+        if self.payload {
+            self.tuple(len)
+        } else {
+            self.tagged_fields(variant, index)
+        }
+    }
+}
+
+// Writes the items of a tuple or dict entry, or the elements of an array,
+// keeping track of the padding and framing offsets.  This is equivalent to
+// `serialize_tuple` and `serialize_array` in normal_form.rs, but it doesn't
+// need all the children up front.
+struct Tuple {
+    start: usize,
+    offsets: Vec<usize>,
+    alignment: usize,
+    fixed_size: bool,
+    last_fixed_size: bool,
+}
+
+impl Tuple {
+    fn new<W>(out: &Output<'_, W>) -> Tuple {
+        Tuple {
+            start: out.pos,
+            offsets: vec![],
+            alignment: 1,
+            fixed_size: true,
+            last_fixed_size: true,
+        }
+    }
+    fn begin_item<W: Write>(
+        &mut self,
+        out: &mut Output<'_, W>,
+        alignment: usize,
+    ) -> Result<(), Error> {
+        self.alignment = self.alignment.max(alignment);
+        out.pad(alignment)
+    }
+    fn end_item<W>(&mut self, out: &Output<'_, W>, fixed_size: bool) {
+        self.last_fixed_size = fixed_size;
+        if !fixed_size {
+            self.fixed_size = false;
+            self.offsets.push(out.pos - self.start);
+        }
+    }
+    fn item<W: Write, T: Serialize + ?Sized>(
+        &mut self,
+        out: &mut Output<'_, W>,
+        ty: &VariantTypeStr,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.begin_item(out, ty.align_of())?;
+        child(out, ty, value)?;
+        self.end_item(out, ty.is_fixed_size());
+        Ok(())
+    }
+    fn end_tuple<W: Write>(mut self, out: &mut Output<'_, W>) -> Result<(), Error> {
+        // There's no framing offset for the last item, as its end is the start
+        // of the offsets
+        if !self.last_fixed_size {
+            self.offsets.pop();
+        }
+        if self.fixed_size {
+            let len = out.pos - self.start;
+            let size = align(len, self.alignment).max(1);
+            out.write(&[0; 8][..size - len])?;
+        }
+        self.offsets.reverse();
+        out.offsets(self.start, &self.offsets)
+    }
+    fn end_array<W: Write>(
+        self,
+        out: &mut Output<'_, W>,
+        element: &VariantTypeStr,
+    ) -> Result<(), Error> {
+        if element.is_fixed_size() {
+            Ok(())
+        } else {
+            out.offsets(self.start, &self.offsets)
+        }
+    }
+}
+
+// The serde compound serialisers
+struct Compound<'a, 'w, W> {
+    out: &'a mut Output<'w, W>,
+    tuple: Tuple,
+    kind: Kind<'a>,
+}
+
+enum Kind<'a> {
+    // The remaining items of the tuple type
+    Tuple(crate::variant_type::Items<'a>),
+    // The element type
+    Array(&'a VariantTypeStr),
+    // The dict entry type and the entry we're in the middle of writing
+    Dict(&'a VariantTypeStr, Option<Tuple>),
+    // The fields of an enum variant written as **(sv)** or **(uv)**.  `Tuple`
+    // is for the outer tuple and `String` is the type of the contents of the
+    // **v**, which we find out as we go.
+    Tagged(Tuple, String),
+}
+
+impl<W: Write> Compound<'_, '_, W> {
+    fn field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        match &mut self.kind {
+            Kind::Tuple(items) => match items.next() {
+                Some(ty) => self.tuple.item(self.out, ty, value),
+                None => Err(Error::Type("Too many items for tuple type".into())),
+            },
+            Kind::Array(element) => self.tuple.item(self.out, element, value),
+            Kind::Tagged(_, types) => {
+                let ty = infer(value, false)?;
+                types.push_str(ty.as_str());
+                self.tuple.item(self.out, &ty, value)
+            }
+            Kind::Dict(..) => unreachable!(),
+        }
+    }
+    fn end_compound(self) -> Result<(), Error> {
+        match self.kind {
+            Kind::Tuple(mut items) => {
+                if items.next().is_some() {
+                    return Err(Error::Type("Not enough items for tuple type".into()));
+                }
+                self.tuple.end_tuple(self.out)
+            }
+            Kind::Array(element) | Kind::Dict(element, _) => {
+                self.tuple.end_array(self.out, element)
+            }
+            Kind::Tagged(..) => self.end_tagged(),
+        }
+    }
+    fn end_tagged(self) -> Result<(), Error> {
+        if let Kind::Tagged(mut outer, types) = self.kind {
+            self.tuple.end_tuple(self.out)?;
+            self.out.write(b"\0")?;
+            self.out.write(format!("({})", types).as_bytes())?;
+            outer.end_item(self.out, false);
+            outer.end_tuple(self.out)
+        } else {
+            unreachable!()
+        }
+    }
+    fn key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        if let Kind::Dict(entry, current) = &mut self.kind {
+            self.tuple.begin_item(self.out, entry.align_of())?;
+            let mut t = Tuple::new(self.out);
+            t.item(self.out, entry.key().unwrap(), key)?;
+            *current = Some(t);
+        }
+        Ok(())
+    }
+    fn value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        if let Kind::Dict(entry, current) = &mut self.kind {
+            let mut t = current
+                .take()
+                .expect("serialize_value called before serialize_key");
+            t.item(self.out, entry.value().unwrap(), value)?;
+            t.end_tuple(self.out)?;
+            self.tuple.end_item(self.out, entry.is_fixed_size());
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> ser::SerializeSeq for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.field(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.end_compound()
+    }
+}
+impl<W: Write> ser::SerializeTuple for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.field(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.end_compound()
+    }
+}
+impl<W: Write> ser::SerializeTupleStruct for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.field(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.end_compound()
+    }
+}
+impl<W: Write> ser::SerializeTupleVariant for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.field(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.end_compound()
+    }
+}
+impl<W: Write> ser::SerializeMap for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.key(key)
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.value(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.end_compound()
+    }
+}
+impl<W: Write> ser::SerializeStruct for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        if let Kind::Dict(..) = self.kind {
+            self.key(key)?;
+            self.value(value)
+        } else {
+            self.field(value)
+        }
+    }
+    fn end(self) -> Result<(), Error> {
+        self.end_compound()
+    }
+}
+impl<W: Write> ser::SerializeStructVariant for Compound<'_, '_, W> {
+    type Ok = ();
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(value)
+    }
+    fn end(self) -> Result<(), Error> {
+        self.end_compound()
+    }
+}
+
+// Works out the GVariant type of a value.  The type is built up as a pattern
+// like those used by the text format parser, with `*` for types that we can't
+// know yet, like the element type of an empty sequence.
+struct Infer {
+    payload: bool,
+}
+
+impl Infer {
+    fn fields() -> InferCompound {
+        InferCompound {
+            pattern: String::new(),
+            kind: InferKind::Tuple,
+        }
+    }
+}
+
+impl ser::Serializer for Infer {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = InferCompound;
+    type SerializeTuple = InferCompound;
+    type SerializeTupleStruct = InferCompound;
+    type SerializeTupleVariant = InferCompound;
+    type SerializeMap = InferCompound;
+    type SerializeStruct = InferCompound;
+    type SerializeStructVariant = InferCompound;
+
+    fn serialize_bool(self, _: bool) -> Result<String, Error> {
+        Ok("b".into())
+    }
+    fn serialize_i8(self, _: i8) -> Result<String, Error> {
+        Ok("n".into())
+    }
+    fn serialize_i16(self, _: i16) -> Result<String, Error> {
+        Ok("n".into())
+    }
+    fn serialize_i32(self, _: i32) -> Result<String, Error> {
+        Ok("i".into())
+    }
+    fn serialize_i64(self, _: i64) -> Result<String, Error> {
+        Ok("x".into())
+    }
+    fn serialize_u8(self, _: u8) -> Result<String, Error> {
+        Ok("y".into())
+    }
+    fn serialize_u16(self, _: u16) -> Result<String, Error> {
+        Ok("q".into())
+    }
+    fn serialize_u32(self, _: u32) -> Result<String, Error> {
+        Ok("u".into())
+    }
+    fn serialize_u64(self, _: u64) -> Result<String, Error> {
+        Ok("t".into())
+    }
+    fn serialize_f32(self, _: f32) -> Result<String, Error> {
+        Ok("d".into())
+    }
+    fn serialize_f64(self, _: f64) -> Result<String, Error> {
+        Ok("d".into())
+    }
+    fn serialize_char(self, _: char) -> Result<String, Error> {
+        Ok("s".into())
+    }
+    fn serialize_str(self, _: &str) -> Result<String, Error> {
+        Ok("s".into())
+    }
+    fn serialize_bytes(self, _: &[u8]) -> Result<String, Error> {
+        Ok("ay".into())
+    }
+    fn serialize_none(self) -> Result<String, Error> {
+        Ok("m*".into())
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<String, Error> {
+        Ok(format!("m{}", value.serialize(Infer { payload: false })?))
+    }
+    fn serialize_unit(self) -> Result<String, Error> {
+        Ok("()".into())
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Error> {
+        Ok("()".into())
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+    ) -> Result<String, Error> {
+        Ok(if self.payload { "()" } else { "(sv)" }.into())
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        if self.payload {
+            value.serialize(Infer { payload: false })
+        } else {
+            Ok("(sv)".into())
+        }
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<InferCompound, Error> {
+        Ok(InferCompound {
+            pattern: "*".into(),
+            kind: InferKind::Array,
+        })
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<InferCompound, Error> {
+        Ok(Infer::fields())
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<InferCompound, Error> {
+        Ok(Infer::fields())
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<InferCompound, Error> {
+        Ok(if self.payload {
+            Infer::fields()
+        } else {
+            InferCompound {
+                pattern: "(sv)".into(),
+                kind: InferKind::Ignore,
+            }
+        })
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<InferCompound, Error> {
+        Ok(InferCompound {
+            pattern: "{**}".into(),
+            kind: InferKind::Map(None),
+        })
+    }
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<InferCompound, Error> {
+        Ok(Infer::fields())
+    }
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<InferCompound, Error> {
+        self.serialize_tuple_variant(name, index, variant, len)
+    }
+}
+
+struct InferCompound {
+    pattern: String,
+    kind: InferKind,
+}
+
+enum InferKind {
+    Tuple,
+    Array,
+    // The pattern of the key we're in the middle of
+    Map(Option<String>),
+    // Enum variants that aren't the contents of a **v** are always **(sv)**
+    Ignore,
+}
+
+impl InferCompound {
+    fn field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let ty = value.serialize(Infer { payload: false })?;
+        match self.kind {
+            InferKind::Tuple => self.pattern.push_str(&ty),
+            InferKind::Array => self.pattern = coalesce(&self.pattern, &ty)?,
+            InferKind::Map(_) | InferKind::Ignore => {}
+        }
+        Ok(())
+    }
+    fn end(self) -> Result<String, Error> {
+        Ok(match self.kind {
+            InferKind::Tuple => format!("({})", self.pattern),
+            InferKind::Array => format!("a{}", self.pattern),
+            InferKind::Map(_) => format!("a{}", self.pattern),
+            InferKind::Ignore => self.pattern,
+        })
+    }
+}
+
+fn coalesce(a: &str, b: &str) -> Result<String, Error> {
+    pattern_coalesce(a, b).ok_or_else(|| {
+        Error::Type(format!(
+            "Elements of types {} and {} can't be in the same array",
+            a, b
+        ))
+    })
+}
+
+impl ser::SerializeSeq for InferCompound {
+    type Ok = String;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.field(value)
+    }
+    fn end(self) -> Result<String, Error> {
+        InferCompound::end(self)
+    }
+}
+impl ser::SerializeTuple for InferCompound {
+    type Ok = String;
+    type Error = Error;
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.field(value)
+    }
+    fn end(self) -> Result<String, Error> {
+        InferCompound::end(self)
+    }
+}
+impl ser::SerializeTupleStruct for InferCompound {
+    type Ok = String;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.field(value)
+    }
+    fn end(self) -> Result<String, Error> {
+        InferCompound::end(self)
+    }
+}
+impl ser::SerializeTupleVariant for InferCompound {
+    type Ok = String;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.field(value)
+    }
+    fn end(self) -> Result<String, Error> {
+        InferCompound::end(self)
+    }
+}
+impl ser::SerializeMap for InferCompound {
+    type Ok = String;
+    type Error = Error;
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        if let InferKind::Map(current) = &mut self.kind {
+            *current = Some(key.serialize(Infer { payload: false })?);
+        }
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        if let InferKind::Map(current) = &mut self.kind {
+            let key = current
+                .take()
+                .expect("serialize_value called before serialize_key");
+            let entry = format!("{{{}{}}}", key, value.serialize(Infer { payload: false })?);
+            self.pattern = coalesce(&self.pattern, &entry)?;
+        }
+        Ok(())
+    }
+    fn end(self) -> Result<String, Error> {
+        InferCompound::end(self)
+    }
+}
+impl ser::SerializeStruct for InferCompound {
+    type Ok = String;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(value)
+    }
+    fn end(self) -> Result<String, Error> {
+        InferCompound::end(self)
+    }
+}
+impl ser::SerializeStructVariant for InferCompound {
+    type Ok = String;
+    type Error = Error;
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.field(value)
+    }
+    fn end(self) -> Result<String, Error> {
+        InferCompound::end(self)
+    }
+}
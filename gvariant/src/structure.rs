@@ -0,0 +1,757 @@
+//! The Rust types of GVariant structures **(XYZ)** and dictionary entries
+//! **{KV}**
+//!
+//! [`gv!`][crate::gv], [`gv_type!`][crate::gv_type] and
+//! [`#[derive(GVariant)]`][crate::GVariant] all use these types, so a
+//! structure type string is the same Rust type wherever it appears in your
+//! crate, or in any other:
+//!
+//!     # use gvariant::{aligned_bytes::AsAligned, gv, gv_type, Marker};
+//!     fn name(x: &gv_type!("(is)")) -> &str {
+//!         x.field_1().to_str()
+//!     }
+//!
+//!     let data = gv!("(is)").serialize_to_aligned(&(5, "five"))?;
+//!     assert_eq!(name(gv!("(is)").cast(data.as_aligned())), "five");
+//!     # Ok::<(), gvariant::write::Error>(())
+//!
+//! Structures where every field is fixed size, like **(yi)**, are a
+//! [`FixedN`][Fixed2] struct with a public field `field_N` for each field,
+//! which can be constructed in Rust.  They also have type parameters for the
+//! padding after each field, so that they have exactly the same layout as the
+//! GVariant data.  These must match the fields, which is checked at compile
+//! time, so it's easiest to let [`gv_type!`][crate::gv_type] name them:
+//! **(yi)** is `Fixed2<u8, i32, 3, 0>`.
+//!
+//! Other structures, like **(is)**, are a [`NonFixedN`][NonFixed2] wrapping
+//! the serialised data.  Fields are found by reading the framing offsets, so
+//! they have accessor methods `field_N()` instead.  Dictionary entries are
+//! [`FixedEntry`] or [`NonFixedEntry`] in the same way.
+//!
+//! The unit type **()** is [`Fixed0`].  There are types for structures with up
+//! to 16 fields.  For wider structures [`gv!`][crate::gv] and the other macros
+//! generate a type where they're used instead, with the same interface.
+//!
+//! All of these implement [`Structure`], see there for the rest of their
+//! interface.
+
+#[cfg(feature = "alloc")]
+use alloc::{borrow::ToOwned, boxed::Box};
+use core::{
+    fmt::Debug,
+    hash::{Hash, Hasher},
+};
+
+use ref_cast::RefCast;
+
+#[cfg(feature = "alloc")]
+use crate::builder;
+use crate::{
+    aligned_bytes::{self, AlignedSlice, Alignment, TryAsAligned, TryAsAlignedMut, A1},
+    calc_offsets,
+    casting::{self, AlignOf, AllBitPatternsValid},
+    endian::Endian,
+    normal_form,
+    variant_type::align,
+    write::{self, Write},
+    write_offsets, write_padding, Cast, DictEntry, SerializeTo, Structure,
+};
+
+// The alignment and fixed size of a field of type `T`
+const fn field_layout<T: Cast + ?Sized>() -> (usize, Option<usize>) {
+    (<T::AlignOf as Alignment>::ALIGNMENT, T::FIXED_SIZE)
+}
+
+// This is a straight port of the Python code from the GVariant paper section
+// 3.2.2 Computing the Table.  The offset of field n is:
+//
+//     let (i, a, b, c) = table[n];
+//     let off = align(frame_offset[i] + a, b) + c
+//
+// b and c are always aligned to the field's alignment.
+const fn generate_table<const N: usize>(
+    fields: [(usize, Option<usize>); N],
+) -> [(isize, usize, usize, usize); N] {
+    let (mut i, mut a, mut b, mut c) = (-1, 0, 1, 0);
+    let mut table = [(0, 0, 0, 0); N];
+    let mut n = 0;
+    while n < N {
+        let (alignment, size) = fields[n];
+        if alignment <= b {
+            // merge rule #1
+            c = align(c, alignment);
+        } else {
+            // merge rule #2
+            a += align(c, b);
+            b = alignment;
+            c = 0;
+        }
+        table[n] = (i, a, b, c);
+        match size {
+            // merge rule #3
+            Some(size) => c += size,
+            // item is not fixed-sized
+            None => {
+                i += 1;
+                a = 0;
+                b = 1;
+                c = 0;
+            }
+        }
+        n += 1;
+    }
+    table
+}
+
+// After all of the items have been added, a framing offset is appended, in
+// reverse order, for each non-fixed-sized item that is not the last item in
+// the structure.
+const fn n_frame_offsets<const N: usize>(fields: [(usize, Option<usize>); N]) -> usize {
+    let mut count = 0;
+    let mut n = 0;
+    while n + 1 < N {
+        if fields[n].1.is_none() {
+            count += 1;
+        }
+        n += 1;
+    }
+    count
+}
+
+// The padding after each field of a fixed size structure, and its size.  The
+// padding after the last field makes the size a multiple of the alignment of
+// the structure.
+const fn fixed_layout<const N: usize>(fields: [(usize, Option<usize>); N]) -> ([usize; N], usize) {
+    let mut padding = [0; N];
+    let mut alignment = 1;
+    let mut end = 0;
+    let mut n = 0;
+    while n < N {
+        let (field_alignment, size) = fields[n];
+        let start = align(end, field_alignment);
+        if n > 0 {
+            padding[n - 1] = start - end;
+        }
+        end = match size {
+            Some(size) => start + size,
+            None => panic!("Fixed size structures can only have fixed size fields"),
+        };
+        if field_alignment > alignment {
+            alignment = field_alignment;
+        }
+        n += 1;
+    }
+    let size = align(end, alignment);
+    if N > 0 {
+        padding[N - 1] = size - end;
+    }
+    (padding, size)
+}
+
+// A field of a non-fixed size structure, given its entry in the table from
+// `generate_table`
+fn field<T: Cast + ?Sized>(
+    data: &[u8],
+    (i, a, b, c): (isize, usize, usize, usize),
+    last_child: bool,
+    n_frame_offsets: usize,
+) -> &T {
+    // The data is aligned for the structure and `start` for the field, so
+    // `try_as_aligned` only fails if the framing offsets are nonsense
+    match calc_offsets(data, i, a, b, c, T::FIXED_SIZE, last_child, n_frame_offsets)
+        .and_then(|(start, end)| data[start..end].try_as_aligned().ok())
+    {
+        Some(x) => T::from_aligned_slice(x),
+        None => T::default_ref(),
+    }
+}
+
+fn field_mut<T: Cast>(
+    data: &mut [u8],
+    (i, a, b, c): (isize, usize, usize, usize),
+    last_child: bool,
+    n_frame_offsets: usize,
+) -> Option<&mut T> {
+    let (start, end) = calc_offsets(data, i, a, b, c, T::FIXED_SIZE, last_child, n_frame_offsets)?;
+    T::try_from_aligned_slice_mut(data[start..end].try_as_aligned_mut().ok()?).ok()
+}
+
+// The layout of a field, and a function that checks that its data is in normal
+// form
+type FieldCheck = ((usize, Option<usize>), fn(&[u8]) -> bool);
+
+fn is_normal_form(data: &[u8], fields: &[FieldCheck]) -> bool {
+    normal_form::structure(
+        data,
+        fields
+            .iter()
+            .map(|&((alignment, size), check)| (alignment, size, check)),
+        |check, x| check(x),
+    )
+}
+
+fn is_normal<T: Cast + ?Sized>(data: &[u8]) -> bool {
+    match data.try_as_aligned() {
+        Ok(x) => T::from_aligned_slice(x).is_normal_form(),
+        Err(_) => false,
+    }
+}
+
+// The alignment of a structure is the largest alignment of its fields
+macro_rules! max_align {
+    ($T:ident $($rest:ident)*) => {
+        <<$T as AlignOf>::AlignOf as Alignment>::Max<max_align!($($rest)*)>
+    };
+    () => {
+        A1
+    };
+}
+
+// The list of fields for `builder::StructureBuilder`
+#[cfg(feature = "alloc")]
+macro_rules! field_list {
+    ($T:ident $($rest:ident)*) => {
+        (builder::Field<$T>, field_list!($($rest)*))
+    };
+    () => {
+        ()
+    };
+}
+
+// Defines `$Fixed` and `$NonFixed` with a field for each `(n T S P field
+// field_mut padding)`.  `T` is the type of the field, `S` a type parameter for
+// serialising it and `P` the padding after it in fixed size structures.
+macro_rules! structure {
+    (
+        $(#[$fixed_attr:meta])* $Fixed:ident,
+        $(#[$non_fixed_attr:meta])* $NonFixed:ident,
+        $len:literal;
+        $(($n:tt $T:ident $S:ident $P:ident $field:ident $field_mut:ident $padding:ident))*
+    ) => {
+        $(#[$fixed_attr])*
+        #[derive(Copy, Clone)]
+        #[repr(C)]
+        pub struct $Fixed<$($T,)* $(const $P: usize),*> {
+            $(
+                pub $field: $T,
+                $padding: [u8; $P],
+            )*
+        }
+
+        impl<$($T: Cast + Copy,)* $(const $P: usize),*> $Fixed<$($T,)* $($P),*> {
+            // Checks that the padding parameters give the same layout as the
+            // GVariant data, so there's no uninitialised padding between the
+            // fields either.  This is evaluated at compile time wherever the
+            // type is used.
+            const SIZE: usize = {
+                let (padding, size) = fixed_layout([$(field_layout::<$T>()),*]);
+                assert!(
+                    $(padding[$n] == $P)&&*,
+                    "The padding parameters of this structure don't match its fields.  Use \
+                     gv_type! to name structure types"
+                );
+                assert!(size == core::mem::size_of::<Self>());
+                assert!(
+                    cfg!(feature = "alloc") || size <= aligned_bytes::MAX_ZEROED,
+                    "Fixed size structures can't be larger than 4096 bytes without the alloc \
+                     feature"
+                );
+                size
+            };
+
+            #[allow(clippy::too_many_arguments)]
+            pub const fn new($($field: $T),*) -> Self {
+                let _ = Self::SIZE;
+                $Fixed {
+                    $(
+                        $field,
+                        $padding: [0; $P],
+                    )*
+                }
+            }
+
+            $(
+                #[doc = concat!("Field ", stringify!($n))]
+                pub fn $field(&self) -> &$T {
+                    &self.$field
+                }
+            )*
+        }
+
+        unsafe impl<$($T: Cast + Copy,)* $(const $P: usize),*> AlignOf
+            for $Fixed<$($T,)* $($P),*>
+        {
+            type AlignOf = max_align!($($T)*);
+        }
+        unsafe impl<$($T: Cast + Copy,)* $(const $P: usize),*> AllBitPatternsValid
+            for $Fixed<$($T,)* $($P),*>
+        {
+        }
+
+        impl<$($T: Cast + Copy,)* $(const $P: usize),*> Cast for $Fixed<$($T,)* $($P),*> {
+            const FIXED_SIZE: Option<usize> = Some(Self::SIZE);
+            fn default_ref() -> &'static Self {
+                casting::try_cast_slice_to(aligned_bytes::zeroed(Self::SIZE))
+                    .expect("SIZE is the size of Self")
+            }
+            fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+                &casting::cast_to_slice(self)[..Self::SIZE]
+            }
+            fn is_normal_form(&self) -> bool {
+                is_normal_form(
+                    self.as_aligned_slice(),
+                    &[$((field_layout::<$T>(), is_normal::<$T>)),*],
+                )
+            }
+            fn try_from_aligned_slice(
+                slice: &AlignedSlice<Self::AlignOf>,
+            ) -> Result<&Self, casting::WrongSize> {
+                let _ = Self::SIZE;
+                casting::try_cast_slice_to::<Self>(slice)
+            }
+            fn try_from_aligned_slice_mut(
+                slice: &mut AlignedSlice<Self::AlignOf>,
+            ) -> Result<&mut Self, casting::WrongSize> {
+                let _ = Self::SIZE;
+                casting::try_cast_slice_to_mut::<Self>(slice)
+            }
+        }
+
+        impl<'a, $($T: Cast + Copy,)* $(const $P: usize),*> Structure<'a>
+            for $Fixed<$($T,)* $($P),*>
+        {
+            const N_FIELDS: usize = $len;
+            type RefTuple = ($(&'a $T,)*);
+            fn to_tuple(&'a self) -> Self::RefTuple {
+                ($(&self.$field,)*)
+            }
+        }
+
+        impl<'a, $($T: Cast + Copy,)* $(const $P: usize),*> From<&'a $Fixed<$($T,)* $($P),*>>
+            for ($(&'a $T,)*)
+        {
+            fn from(value: &'a $Fixed<$($T,)* $($P),*>) -> Self {
+                value.to_tuple()
+            }
+        }
+
+        impl<$($T: Cast + Copy + Default,)* $(const $P: usize),*> Default
+            for $Fixed<$($T,)* $($P),*>
+        {
+            fn default() -> Self {
+                Self::new($($T::default()),*)
+            }
+        }
+
+        // The standard library only implements traits for tuples of up to 12
+        // elements so these are implemented field by field rather than with
+        // `to_tuple()`.  The output matches the tuple implementations.
+        impl<$($T: Cast + Copy,)* $(const $P: usize),*> Debug for $Fixed<$($T,)* $($P),*> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.debug_tuple("")$(.field(&self.$field))*.finish()
+            }
+        }
+        impl<$($T: Cast + Copy,)* $(const $P: usize),*> PartialEq for $Fixed<$($T,)* $($P),*> {
+            fn eq(&self, other: &Self) -> bool {
+                $(self.$field == other.$field)&&*
+            }
+        }
+        impl<$($T: Cast + Copy + Eq,)* $(const $P: usize),*> Eq for $Fixed<$($T,)* $($P),*> {}
+        impl<$($T: Cast + Copy + Hash,)* $(const $P: usize),*> Hash for $Fixed<$($T,)* $($P),*> {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                $(self.$field.hash(state);)*
+            }
+        }
+
+        impl<$($T: Cast + Endian,)* $(const $P: usize),*> Endian for $Fixed<$($T,)* $($P),*> {
+            fn byteswap(self) -> Self {
+                Self::new($(self.$field.byteswap()),*)
+            }
+        }
+
+        impl<$($T: Cast + Copy, $S: SerializeTo<$T> + Copy,)* $(const $P: usize),*>
+            SerializeTo<$Fixed<$($T,)* $($P),*>> for &($($S,)*)
+        {
+            fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+                let mut off = 0;
+                $(
+                    off += write_padding::<$T::AlignOf, _>(off, f)?;
+                    off += self.$n.serialize(f)?;
+                )*
+                // Fixed size structures are padded out to a multiple of their
+                // alignment
+                off += write_padding::<max_align!($($T)*), _>(off, f)?;
+                Ok(off)
+            }
+        }
+        impl<'a, $($T: Cast + Copy,)* $(const $P: usize),*> SerializeTo<$Fixed<$($T,)* $($P),*>>
+            for &'a $Fixed<$($T,)* $($P),*>
+        where
+            $(for<'b> &'b $T: SerializeTo<$T>,)*
+        {
+            fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+                SerializeTo::<$Fixed<$($T,)* $($P),*>>::serialize(&self.to_tuple(), f)
+            }
+        }
+
+        #[cfg(feature = "alloc")]
+        impl<$($T: Cast + Copy,)* $(const $P: usize),*> builder::Container
+            for $Fixed<$($T,)* $($P),*>
+        {
+            type Builder<W: builder::Sink> =
+                builder::StructureBuilder<Self, W, field_list!($($T)*)>;
+            fn builder<W: builder::Sink>(parent: W) -> Self::Builder<W> {
+                builder::StructureBuilder::new(parent)
+            }
+        }
+
+        $(#[$non_fixed_attr])*
+        #[derive(RefCast)]
+        #[repr(transparent)]
+        pub struct $NonFixed<$($T: Cast + ?Sized),*> {
+            data: AlignedSlice<max_align!($($T)*)>,
+        }
+
+        impl<$($T: Cast + ?Sized),*> $NonFixed<$($T),*> {
+            const FIELDS: [(usize, Option<usize>); $len] = [$(field_layout::<$T>()),*];
+            const TABLE: [(isize, usize, usize, usize); $len] = generate_table(Self::FIELDS);
+            const N_FRAME_OFFSETS: usize = n_frame_offsets(Self::FIELDS);
+
+            $(
+                #[doc = concat!(
+                    "Field ",
+                    stringify!($n),
+                    ".  Unlike `to_tuple()` this only finds the offset of this one field."
+                )]
+                pub fn $field(&self) -> &$T {
+                    field(
+                        self.data.as_ref(),
+                        Self::TABLE[$n],
+                        $n == $len - 1,
+                        Self::N_FRAME_OFFSETS,
+                    )
+                }
+
+                #[doc = concat!(
+                    "Mutable access to field ",
+                    stringify!($n),
+                    " if it's fixed size, or `None` if the data is too short to contain it"
+                )]
+                pub fn $field_mut(&mut self) -> Option<&mut $T>
+                where
+                    $T: Sized,
+                {
+                    field_mut(
+                        self.data.as_mut(),
+                        Self::TABLE[$n],
+                        $n == $len - 1,
+                        Self::N_FRAME_OFFSETS,
+                    )
+                }
+            )*
+        }
+
+        #[cfg(feature = "alloc")]
+        impl<$($T: Cast + ?Sized),*> ToOwned for $NonFixed<$($T),*> {
+            type Owned = Box<Self>;
+            fn to_owned(&self) -> Self::Owned {
+                casting::ref_cast_box(self.data.to_owned())
+            }
+        }
+        unsafe impl<$($T: Cast + ?Sized),*> AlignOf for $NonFixed<$($T),*> {
+            type AlignOf = max_align!($($T)*);
+        }
+        unsafe impl<$($T: Cast + ?Sized),*> AllBitPatternsValid for $NonFixed<$($T),*> {}
+
+        impl<$($T: Cast + ?Sized),*> Cast for $NonFixed<$($T),*> {
+            const FIXED_SIZE: Option<usize> = None;
+            fn default_ref() -> &'static Self {
+                Self::ref_cast(aligned_bytes::empty_aligned())
+            }
+            fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+                &self.data
+            }
+            fn is_normal_form(&self) -> bool {
+                is_normal_form(self.data.as_ref(), &[$((field_layout::<$T>(), is_normal::<$T>)),*])
+            }
+            fn try_from_aligned_slice(
+                slice: &AlignedSlice<Self::AlignOf>,
+            ) -> Result<&Self, casting::WrongSize> {
+                Ok(Self::ref_cast(slice))
+            }
+            fn try_from_aligned_slice_mut(
+                slice: &mut AlignedSlice<Self::AlignOf>,
+            ) -> Result<&mut Self, casting::WrongSize> {
+                Ok(Self::ref_cast_mut(slice))
+            }
+        }
+
+        impl<'a, $($T: Cast + ?Sized),*> Structure<'a> for $NonFixed<$($T),*> {
+            const N_FIELDS: usize = $len;
+            type RefTuple = ($(&'a $T,)*);
+            fn to_tuple(&'a self) -> Self::RefTuple {
+                ($(self.$field(),)*)
+            }
+        }
+
+        impl<'a, $($T: Cast + ?Sized),*> From<&'a $NonFixed<$($T),*>> for ($(&'a $T,)*) {
+            fn from(value: &'a $NonFixed<$($T),*>) -> Self {
+                value.to_tuple()
+            }
+        }
+
+        impl<$($T: Cast + ?Sized),*> Debug for $NonFixed<$($T),*> {
+            fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+                f.debug_tuple("")$(.field(&self.$field()))*.finish()
+            }
+        }
+        impl<$($T: Cast + ?Sized),*> PartialEq for $NonFixed<$($T),*> {
+            fn eq(&self, other: &Self) -> bool {
+                $(self.$field() == other.$field())&&*
+            }
+        }
+        impl<$($T: Cast + Eq + ?Sized),*> Eq for $NonFixed<$($T),*> {}
+        impl<$($T: Cast + Hash + ?Sized),*> Hash for $NonFixed<$($T),*> {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                $(self.$field().hash(state);)*
+            }
+        }
+
+        impl<$($T: Cast + ?Sized, $S: SerializeTo<$T> + Copy),*> SerializeTo<$NonFixed<$($T),*>>
+            for &($($S,)*)
+        {
+            fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+                let mut off = 0;
+                let mut offsets = [0; $len];
+                let mut n_offsets = 0;
+                $(
+                    off += write_padding::<$T::AlignOf, _>(off, f)?;
+                    off += self.$n.serialize(f)?;
+                    if $T::FIXED_SIZE.is_none() && $n != $len - 1 {
+                        offsets[n_offsets] = off;
+                        n_offsets += 1;
+                    }
+                )*
+                offsets[..n_offsets].reverse();
+                write_offsets(off, &offsets[..n_offsets], f)
+            }
+        }
+        impl<'a, $($T: Cast + ?Sized),*> SerializeTo<$NonFixed<$($T),*>> for &'a $NonFixed<$($T),*>
+        where
+            $(for<'b> &'b $T: SerializeTo<$T>,)*
+        {
+            fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+                SerializeTo::<$NonFixed<$($T),*>>::serialize(&self.to_tuple(), f)
+            }
+        }
+
+        #[cfg(feature = "alloc")]
+        impl<$($T: Cast + ?Sized),*> builder::Container for $NonFixed<$($T),*> {
+            type Builder<W: builder::Sink> =
+                builder::StructureBuilder<Self, W, field_list!($($T)*)>;
+            fn builder<W: builder::Sink>(parent: W) -> Self::Builder<W> {
+                builder::StructureBuilder::new(parent)
+            }
+        }
+    };
+}
+
+// Defines the structures with 1 to 16 fields.  Each arity adds a field to the
+// ones before.
+macro_rules! structures {
+    ([$($fields:tt)*] ($Fixed:ident $NonFixed:ident $len:literal) $field:tt $($rest:tt)*) => {
+        structure! {
+            #[doc = concat!("A fixed size structure with ", stringify!($len), " fields")]
+            ///
+            /// See the [module documentation](self).
+            $Fixed,
+            #[doc = concat!("A non-fixed size structure with ", stringify!($len), " fields")]
+            ///
+            /// See the [module documentation](self).
+            $NonFixed,
+            $len;
+            $($fields)* $field
+        }
+        structures!([$($fields)* $field] $($rest)*);
+    };
+    ([$($fields:tt)*]) => {};
+}
+
+structures! {
+    []
+    (Fixed1 NonFixed1 1) (0 T0 S0 P0 field_0 field_0_mut _padding_0)
+    (Fixed2 NonFixed2 2) (1 T1 S1 P1 field_1 field_1_mut _padding_1)
+    (Fixed3 NonFixed3 3) (2 T2 S2 P2 field_2 field_2_mut _padding_2)
+    (Fixed4 NonFixed4 4) (3 T3 S3 P3 field_3 field_3_mut _padding_3)
+    (Fixed5 NonFixed5 5) (4 T4 S4 P4 field_4 field_4_mut _padding_4)
+    (Fixed6 NonFixed6 6) (5 T5 S5 P5 field_5 field_5_mut _padding_5)
+    (Fixed7 NonFixed7 7) (6 T6 S6 P6 field_6 field_6_mut _padding_6)
+    (Fixed8 NonFixed8 8) (7 T7 S7 P7 field_7 field_7_mut _padding_7)
+    (Fixed9 NonFixed9 9) (8 T8 S8 P8 field_8 field_8_mut _padding_8)
+    (Fixed10 NonFixed10 10) (9 T9 S9 P9 field_9 field_9_mut _padding_9)
+    (Fixed11 NonFixed11 11) (10 T10 S10 P10 field_10 field_10_mut _padding_10)
+    (Fixed12 NonFixed12 12) (11 T11 S11 P11 field_11 field_11_mut _padding_11)
+    (Fixed13 NonFixed13 13) (12 T12 S12 P12 field_12 field_12_mut _padding_12)
+    (Fixed14 NonFixed14 14) (13 T13 S13 P13 field_13 field_13_mut _padding_13)
+    (Fixed15 NonFixed15 15) (14 T14 S14 P14 field_14 field_14_mut _padding_14)
+    (Fixed16 NonFixed16 16) (15 T15 S15 P15 field_15 field_15_mut _padding_15)
+}
+
+structure! {
+    /// A fixed size dictionary entry **{KV}**
+    ///
+    /// `field_0` is the key and `field_1` the value.  See the [module
+    /// documentation](self).
+    FixedEntry,
+    /// A non-fixed size dictionary entry **{KV}**
+    ///
+    /// `field_0()` is the key and `field_1()` the value.  See the [module
+    /// documentation](self).
+    NonFixedEntry,
+    2;
+    (0 K S0 P0 field_0 field_0_mut _padding_0)
+    (1 V S1 P1 field_1 field_1_mut _padding_1)
+}
+
+impl<K: Cast + Copy, V: Cast + Copy, const P0: usize, const P1: usize> DictEntry
+    for FixedEntry<K, V, P0, P1>
+{
+    type Key = K;
+    type Value = V;
+    fn key(&self) -> &K {
+        &self.field_0
+    }
+    fn value(&self) -> &V {
+        &self.field_1
+    }
+}
+impl<K: Cast + ?Sized, V: Cast + ?Sized> DictEntry for NonFixedEntry<K, V> {
+    type Key = K;
+    type Value = V;
+    fn key(&self) -> &K {
+        self.field_0()
+    }
+    fn value(&self) -> &V {
+        self.field_1()
+    }
+}
+
+// Iterating over a `HashMap` or `BTreeMap` gives `(&K, &V)` tuples, so we
+// support serialising those for dictionaries
+impl<K: Cast + Copy, V: Cast + Copy, const P0: usize, const P1: usize, SK, SV>
+    SerializeTo<FixedEntry<K, V, P0, P1>> for (SK, SV)
+where
+    SK: SerializeTo<K> + Copy,
+    SV: SerializeTo<V> + Copy,
+{
+    // Serialising `self` rather than `&self` would call this again
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+        SerializeTo::<FixedEntry<K, V, P0, P1>>::serialize(&self, f)
+    }
+}
+impl<K: Cast + ?Sized, V: Cast + ?Sized, SK, SV> SerializeTo<NonFixedEntry<K, V>> for (SK, SV)
+where
+    SK: SerializeTo<K> + Copy,
+    SV: SerializeTo<V> + Copy,
+{
+    // Serialising `self` rather than `&self` would call this again
+    #[allow(clippy::needless_borrows_for_generic_args)]
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+        SerializeTo::<NonFixedEntry<K, V>>::serialize(&self, f)
+    }
+}
+
+/// The unit type **()**
+///
+/// This is serialised as a single zero byte.
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct Fixed0 {
+    padding: [u8; 1],
+}
+
+impl Fixed0 {
+    pub const fn new() -> Self {
+        Fixed0 { padding: [0] }
+    }
+}
+
+unsafe impl AlignOf for Fixed0 {
+    type AlignOf = A1;
+}
+unsafe impl AllBitPatternsValid for Fixed0 {}
+
+impl Cast for Fixed0 {
+    const FIXED_SIZE: Option<usize> = Some(1);
+    fn default_ref() -> &'static Self {
+        &Fixed0 { padding: [0] }
+    }
+    fn as_aligned_slice(&self) -> &AlignedSlice<Self::AlignOf> {
+        casting::cast_to_slice(self)
+    }
+    fn is_normal_form(&self) -> bool {
+        self.padding == [0]
+    }
+    fn try_from_aligned_slice(
+        slice: &AlignedSlice<Self::AlignOf>,
+    ) -> Result<&Self, casting::WrongSize> {
+        casting::try_cast_slice_to::<Self>(slice)
+    }
+    fn try_from_aligned_slice_mut(
+        slice: &mut AlignedSlice<Self::AlignOf>,
+    ) -> Result<&mut Self, casting::WrongSize> {
+        casting::try_cast_slice_to_mut::<Self>(slice)
+    }
+}
+
+impl<'a> Structure<'a> for Fixed0 {
+    const N_FIELDS: usize = 0;
+    type RefTuple = ();
+    fn to_tuple(&'a self) -> Self::RefTuple {}
+}
+
+impl From<&Fixed0> for () {
+    fn from(_: &Fixed0) -> Self {}
+}
+
+impl Debug for Fixed0 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("").finish()
+    }
+}
+impl PartialEq for Fixed0 {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+impl Eq for Fixed0 {}
+impl Hash for Fixed0 {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+impl Endian for Fixed0 {
+    fn byteswap(self) -> Self {
+        self
+    }
+}
+
+impl SerializeTo<Fixed0> for &() {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+        f.write_all(b"\0")?;
+        Ok(1)
+    }
+}
+impl SerializeTo<Fixed0> for &Fixed0 {
+    fn serialize(self, f: &mut impl Write) -> Result<usize, write::Error> {
+        SerializeTo::<Fixed0>::serialize(&(), f)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl builder::Container for Fixed0 {
+    type Builder<W: builder::Sink> = builder::StructureBuilder<Self, W, ()>;
+    fn builder<W: builder::Sink>(parent: W) -> Self::Builder<W> {
+        builder::StructureBuilder::new(parent)
+    }
+}
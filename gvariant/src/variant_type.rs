@@ -0,0 +1,874 @@
+//! Run-time representation of GVariant type strings
+//!
+//! Most of this crate deals with GVariant types at compile time via the
+//! [`gv!`][crate::gv] macro.  Sometimes the type of some data isn't known until
+//! run-time though.  For example: the type string embedded in a [`Variant`],
+//! D-Bus signatures or type strings read from configuration files.  This
+//! module provides [`VariantTypeStr`] - a validated, borrowed GVariant type
+//! string - and its owned equivalent [`VariantType`].  The relationship between
+//! them is the same as between `str` and `String`.
+//!
+//! ```rust
+//! # use gvariant::VariantTypeStr;
+//! let ty = VariantTypeStr::new("a{sv}").unwrap();
+//! assert!(ty.is_array());
+//! assert_eq!(ty.element().unwrap(), "{sv}");
+//! assert_eq!(ty.align_of(), 8);
+//! assert_eq!(ty.size_of(), None);
+//! ```
+//!
+//! The answers given by [`VariantTypeStr::size_of`] and
+//! [`VariantTypeStr::align_of`] are the same as those the `gv!` macro uses at
+//! compile time when laying out the types it generates.
+//!
+//! [`Variant`]: crate::Variant
+
+#[cfg(feature = "alloc")]
+use alloc::{borrow::ToOwned, boxed::Box, vec::Vec};
+use core::fmt::{Debug, Display};
+use ref_cast::{ref_cast_custom, RefCastCustom};
+
+/// Maximum nesting depth of containers within a type string
+///
+/// This is the same as GLib's `G_VARIANT_MAX_RECURSION_DEPTH`.  It exists to
+/// make it safe to process untrusted type strings recursively.
+pub const MAX_DEPTH: usize = 128;
+
+/// A validated GVariant type string
+///
+/// This is a borrowed type, typically created with [`VariantTypeStr::new`].
+/// It's guaranteed to contain exactly one complete type.  Use [`VariantType`]
+/// for the owned version.
+///
+/// Indefinite types (`*`, `?` and `r`) are supported as described in the GLib
+/// documentation for `GVariantType`.  They are mostly useful as the
+/// `supertype` argument to [`VariantTypeStr::is_subtype_of`].
+#[derive(RefCastCustom, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(transparent)]
+pub struct VariantTypeStr([u8]);
+
+/// Error returned when parsing an invalid GVariant type string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidTypeStr {
+    position: usize,
+    reason: &'static str,
+}
+impl InvalidTypeStr {
+    /// The byte offset into the type string where the problem was found
+    pub fn position(&self) -> usize {
+        self.position
+    }
+    /// A human readable description of the problem
+    pub fn reason(&self) -> &'static str {
+        self.reason
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidTypeStr {}
+impl Display for InvalidTypeStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "Invalid GVariant type string at position {}: {}",
+            self.position, self.reason
+        )
+    }
+}
+
+fn is_basic_char(c: u8) -> bool {
+    b"bynqiuhxtdsog?".contains(&c)
+}
+
+// This is a port of GLib's `variant_type_string_scan_internal`.  It returns the
+// position after the end of the type starting at `pos`.  Recursion is bounded
+// by `depth_limit`.
+fn scan(s: &[u8], mut pos: usize, depth_limit: usize) -> Result<usize, InvalidTypeStr> {
+    let err = |position, reason| Err(InvalidTypeStr { position, reason });
+    let c = match s.get(pos) {
+        Some(c) => *c,
+        None => return err(pos, "Unexpected end of type string"),
+    };
+    let start = pos;
+    pos += 1;
+    match c {
+        b'b' | b'y' | b'n' | b'q' | b'i' | b'u' | b'h' | b'x' | b't' | b'd' | b's' | b'o'
+        | b'g' | b'v' | b'r' | b'*' | b'?' => Ok(pos),
+        b'a' | b'm' => {
+            if depth_limit == 0 {
+                return err(start, "Type nesting is too deep");
+            }
+            scan(s, pos, depth_limit - 1)
+        }
+        b'(' => {
+            loop {
+                match s.get(pos) {
+                    Some(b')') => break,
+                    None => return err(pos, "Unterminated tuple"),
+                    Some(_) => {}
+                }
+                if depth_limit == 0 {
+                    return err(start, "Type nesting is too deep");
+                }
+                pos = scan(s, pos, depth_limit - 1)?;
+            }
+            Ok(pos + 1)
+        }
+        b'{' => {
+            if depth_limit == 0 {
+                return err(start, "Type nesting is too deep");
+            }
+            match s.get(pos) {
+                Some(c) if is_basic_char(*c) => {}
+                Some(_) => return err(pos, "Dict entry key must be a basic type"),
+                None => return err(pos, "Unterminated dict entry"),
+            }
+            pos = scan(s, pos + 1, depth_limit - 1)?;
+            match s.get(pos) {
+                Some(b'}') => Ok(pos + 1),
+                Some(_) => err(pos, "Dict entry must contain exactly two types"),
+                None => err(pos, "Unterminated dict entry"),
+            }
+        }
+        _ => err(start, "Unexpected character in type string"),
+    }
+}
+
+// Returns the length of the first type in `s`.  `s` must start with a valid
+// type string.  Unlike `scan` this doesn't recurse.
+fn type_len(s: &[u8]) -> usize {
+    let mut pos = 0;
+    while s[pos] == b'a' || s[pos] == b'm' {
+        pos += 1;
+    }
+    let mut depth = 0usize;
+    loop {
+        match s[pos] {
+            b'(' | b'{' => depth += 1,
+            b')' | b'}' => depth -= 1,
+            _ => {}
+        }
+        pos += 1;
+        if depth == 0 {
+            return pos;
+        }
+    }
+}
+
+pub(crate) const fn align(off: usize, alignment: usize) -> usize {
+    (off + alignment - 1) & !(alignment - 1)
+}
+
+impl VariantTypeStr {
+    // `s` must be a valid type string.  This isn't `unsafe` in the memory
+    // safety sense, but the methods below will give nonsense results or panic
+    // if it's not.
+    #[ref_cast_custom]
+    pub(crate) fn from_bytes_unchecked(s: &[u8]) -> &VariantTypeStr;
+
+    /// Validate `s` as a GVariant type string
+    ///
+    /// `s` must contain exactly one complete type.
+    ///
+    ///     # use gvariant::VariantTypeStr;
+    ///     assert!(VariantTypeStr::new("(is)").is_ok());
+    ///     assert!(VariantTypeStr::new("is").is_err());
+    ///     assert!(VariantTypeStr::new("{vs}").is_err());
+    pub fn new(s: &(impl AsRef<[u8]> + ?Sized)) -> Result<&VariantTypeStr, InvalidTypeStr> {
+        let s = s.as_ref();
+        let end = scan(s, 0, MAX_DEPTH)?;
+        if end != s.len() {
+            return Err(InvalidTypeStr {
+                position: end,
+                reason: "Trailing data after type string",
+            });
+        }
+        Ok(Self::from_bytes_unchecked(s))
+    }
+
+    /// Parse the first complete type from `s`, returning it and the remainder
+    ///
+    /// This is useful for type strings that are embedded in other data, or for
+    /// D-Bus signatures which are a sequence of complete types:
+    ///
+    ///     # use gvariant::VariantTypeStr;
+    ///     let (first, rest) = VariantTypeStr::split_first("a{sv}ias").unwrap();
+    ///     assert_eq!(first, "a{sv}");
+    ///     assert_eq!(rest, b"ias");
+    pub fn split_first(
+        s: &(impl AsRef<[u8]> + ?Sized),
+    ) -> Result<(&VariantTypeStr, &[u8]), InvalidTypeStr> {
+        let s = s.as_ref();
+        let end = scan(s, 0, MAX_DEPTH)?;
+        let (ty, rest) = s.split_at(end);
+        Ok((Self::from_bytes_unchecked(ty), rest))
+    }
+
+    /// The type string as bytes
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// The type string as a `&str`
+    ///
+    /// Valid type strings are always ASCII, so this is free.
+    pub fn as_str(&self) -> &str {
+        // We've validated that this only contains ASCII characters
+        core::str::from_utf8(&self.0).unwrap()
+    }
+
+    fn first_char(&self) -> u8 {
+        self.0[0]
+    }
+
+    /// Returns `true` for the basic types **b**, **y**, **n**, **q**, **i**,
+    /// **u**, **h**, **x**, **t**, **d**, **s**, **o** and **g** and for the
+    /// indefinite type **?**.
+    ///
+    /// These are the types that are permitted as dict entry keys.
+    pub fn is_basic(&self) -> bool {
+        is_basic_char(self.first_char())
+    }
+    /// Returns `true` for arrays, maybes, tuples, dict entries and variants
+    pub fn is_container(&self) -> bool {
+        matches!(self.first_char(), b'a' | b'm' | b'r' | b'(' | b'{' | b'v')
+    }
+    /// Returns `true` if this is an array type **a**
+    pub fn is_array(&self) -> bool {
+        self.first_char() == b'a'
+    }
+    /// Returns `true` if this is a maybe type **m**
+    pub fn is_maybe(&self) -> bool {
+        self.first_char() == b'm'
+    }
+    /// Returns `true` if this is a tuple type, including the indefinite tuple
+    /// type **r**
+    pub fn is_tuple(&self) -> bool {
+        matches!(self.first_char(), b'(' | b'r')
+    }
+    /// Returns `true` if this is a dict entry type like **{sv}**
+    pub fn is_dict_entry(&self) -> bool {
+        self.first_char() == b'{'
+    }
+    /// Returns `true` if this is the variant type **v**
+    pub fn is_variant(&self) -> bool {
+        self.first_char() == b'v'
+    }
+    /// Returns `true` if this type doesn't contain any of the indefinite types
+    /// **\***, **?** or **r**.
+    ///
+    /// Only definite types can be the type of a value.
+    pub fn is_definite(&self) -> bool {
+        !self.0.iter().any(|c| matches!(c, b'*' | b'?' | b'r'))
+    }
+
+    /// The element type of an array or maybe type
+    ///
+    /// Returns `None` for all other types.
+    ///
+    ///     # use gvariant::VariantTypeStr;
+    ///     let ty = VariantTypeStr::new("ma(is)").unwrap();
+    ///     assert_eq!(ty.element().unwrap(), "a(is)");
+    ///     assert_eq!(ty.element().unwrap().element().unwrap(), "(is)");
+    pub fn element(&self) -> Option<&VariantTypeStr> {
+        match self.first_char() {
+            b'a' | b'm' => Some(Self::from_bytes_unchecked(&self.0[1..])),
+            _ => None,
+        }
+    }
+
+    /// Iterate over the item types of a tuple or dict entry
+    ///
+    /// For all other types this will be empty.
+    ///
+    ///     # use gvariant::VariantTypeStr;
+    ///     let ty = VariantTypeStr::new("(ia{sv}s)").unwrap();
+    ///     let items: Vec<_> = ty.items().map(|x| x.as_str()).collect();
+    ///     assert_eq!(items, ["i", "a{sv}", "s"]);
+    pub fn items(&self) -> Items<'_> {
+        Items {
+            remaining: match self.first_char() {
+                b'(' | b'{' => &self.0[1..self.0.len() - 1],
+                _ => b"",
+            },
+        }
+    }
+
+    /// The number of items in a tuple or dict entry type.
+    ///
+    /// This will be 0 for all other types.
+    pub fn n_items(&self) -> usize {
+        self.items().count()
+    }
+
+    /// The key type of a dict entry type
+    ///
+    /// Returns `None` for all other types.
+    pub fn key(&self) -> Option<&VariantTypeStr> {
+        if self.is_dict_entry() {
+            self.items().next()
+        } else {
+            None
+        }
+    }
+
+    /// The value type of a dict entry type
+    ///
+    /// Returns `None` for all other types.
+    pub fn value(&self) -> Option<&VariantTypeStr> {
+        if self.is_dict_entry() {
+            self.items().nth(1)
+        } else {
+            None
+        }
+    }
+
+    /// The alignment of this type in the GVariant serialisation format
+    ///
+    /// This will be 1, 2, 4 or 8.  Indefinite types report the most
+    /// conservative alignment of 8.
+    pub fn align_of(&self) -> usize {
+        match self.first_char() {
+            b'b' | b'y' => 1,
+            // Each integer type has alignment equal to its fixed size.
+            b'n' | b'q' => 2,
+            b'i' | b'u' | b'h' => 4,
+            b'x' | b't' | b'd' => 8,
+            // Including object paths and signature strings, strings are not
+            // fixed-sized and have an alignment of 1.
+            b's' | b'o' | b'g' => 1,
+            // The alignment of a maybe or array type is always equal to the
+            // alignment of its element type.
+            b'a' | b'm' => self.element().unwrap().align_of(),
+            // The alignment of a container type is equal to the largest
+            // alignment of any potential child of that container.
+            b'(' | b'{' => self.items().map(|x| x.align_of()).max().unwrap_or(1),
+            // This means that the variant type has an alignment of 8 (since it
+            // could potentially contain a value of any other type and the
+            // maximum alignment is 8).
+            _ => 8,
+        }
+    }
+
+    /// The size of this type if it is fixed-size, otherwise `None`
+    ///
+    /// Indefinite types are never fixed-size.
+    pub fn size_of(&self) -> Option<usize> {
+        match self.first_char() {
+            b'b' | b'y' => Some(1),
+            b'n' | b'q' => Some(2),
+            b'i' | b'u' | b'h' => Some(4),
+            b'x' | b't' | b'd' => Some(8),
+            b'(' | b'{' => {
+                // the fixed size must be non-zero. This case would only occur
+                // for structures of the unit type or structures containing
+                // only such structures (recursively). This problem is solved
+                // by arbitrary declaring that the serialised encoding of an
+                // instance of the unit type is a single zero byte (size 1).
+                if self.0.len() == 2 {
+                    return Some(1);
+                }
+                let mut pos = 0;
+                for child in self.items() {
+                    pos = align(pos, child.align_of()) + child.size_of()?;
+                }
+                // the fixed sized must be a multiple of the alignment of the
+                // structure.
+                Some(align(pos, self.align_of()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if values of this type always have the same size
+    pub fn is_fixed_size(&self) -> bool {
+        self.size_of().is_some()
+    }
+
+    /// Check if this type is a subtype of `supertype`
+    ///
+    /// All types are subtypes of themselves.  The indefinite types **\***,
+    /// **?** and **r** in `supertype` match any type, any basic type and any
+    /// tuple type respectively:
+    ///
+    ///     # use gvariant::VariantTypeStr;
+    ///     let t = |s| VariantTypeStr::new(s).unwrap();
+    ///     assert!(t("a{sv}").is_subtype_of(t("a{?*}")));
+    ///     assert!(t("(ii)").is_subtype_of(t("r")));
+    ///     assert!(!t("a(ii)").is_subtype_of(t("a?")));
+    pub fn is_subtype_of(&self, supertype: &VariantTypeStr) -> bool {
+        // This is a port of GLib's `g_variant_type_is_subtype_of`.  We know
+        // that both type strings are well-formed, so it's safe to treat this
+        // merely as a text processing problem.
+        let ty = &self.0;
+        let mut pos = 0;
+        for &supertype_char in supertype.0.iter() {
+            let c = ty[pos];
+            if supertype_char == c {
+                pos += 1;
+            } else if c == b')' {
+                return false;
+            } else {
+                let target = Self::from_bytes_unchecked(&ty[pos..pos + type_len(&ty[pos..])]);
+                let matches = match supertype_char {
+                    b'r' => target.is_tuple(),
+                    b'*' => true,
+                    b'?' => target.is_basic(),
+                    _ => false,
+                };
+                if !matches {
+                    return false;
+                }
+                pos += target.0.len();
+            }
+        }
+        true
+    }
+
+    // The nesting depth of this type as GLib counts it.  Basic types and **v**
+    // have depth 1.
+    pub(crate) fn depth(&self) -> usize {
+        match self.first_char() {
+            b'a' | b'm' => 1 + self.element().unwrap().depth(),
+            b'(' | b'{' => 1 + self.items().map(|x| x.depth()).max().unwrap_or(0),
+            _ => 1,
+        }
+    }
+
+    /// The layout of the children of a tuple or dict entry type
+    ///
+    /// This is the table described in the GVariant paper section 3.2.2
+    /// "Computing the Table" and is the same as the table that `gv!` uses for
+    /// the structures it generates.  Each child type is yielded with a tuple
+    /// `(i, a, b, c)` where the start of the child in the serialised data is:
+    ///
+    /// ```text
+    /// align(frame_offset[i] + a, b) | c
+    /// ```
+    ///
+    /// `frame_offset[-1]` is 0.
+    ///
+    ///     # use gvariant::VariantTypeStr;
+    ///     let ty = VariantTypeStr::new("(sy)").unwrap();
+    ///     let table: Vec<_> = ty.table().map(|(_, x)| x).collect();
+    ///     assert_eq!(table, [(-1, 0, 1, 0), (0, 0, 1, 0)]);
+    pub fn table(&self) -> Table<'_> {
+        Table {
+            items: self.items(),
+            i: -1,
+            a: 0,
+            b: 1,
+            c: 0,
+        }
+    }
+}
+
+/// Iterator over the item types of a tuple or dict entry type
+///
+/// Created by [`VariantTypeStr::items`].
+#[derive(Clone)]
+pub struct Items<'a> {
+    remaining: &'a [u8],
+}
+impl<'a> Iterator for Items<'a> {
+    type Item = &'a VariantTypeStr;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining.is_empty() {
+            None
+        } else {
+            let (item, rest) = self.remaining.split_at(type_len(self.remaining));
+            self.remaining = rest;
+            Some(VariantTypeStr::from_bytes_unchecked(item))
+        }
+    }
+}
+
+/// Iterator over the layout of the children of a tuple or dict entry type
+///
+/// Created by [`VariantTypeStr::table`].
+#[derive(Clone)]
+pub struct Table<'a> {
+    items: Items<'a>,
+    i: isize,
+    a: usize,
+    b: usize,
+    c: usize,
+}
+impl<'a> Iterator for Table<'a> {
+    type Item = (&'a VariantTypeStr, (isize, usize, usize, usize));
+    fn next(&mut self) -> Option<Self::Item> {
+        let child = self.items.next()?;
+        let al = child.align_of();
+        if al <= self.b {
+            // merge rule #1
+            self.c = align(self.c, al);
+        } else {
+            // merge rule #2
+            self.a += align(self.c, self.b);
+            self.b = al;
+            self.c = 0;
+        }
+        let out = (self.i, self.a, self.b, self.c);
+        if let Some(size) = child.size_of() {
+            // merge rule #3
+            self.c += size;
+        } else {
+            // item is not fixed-sized
+            self.i += 1;
+            self.a = 0;
+            self.b = 1;
+            self.c = 0;
+        }
+        Some((child, out))
+    }
+}
+
+impl Display for VariantTypeStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+impl Debug for VariantTypeStr {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+impl AsRef<[u8]> for VariantTypeStr {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+impl AsRef<VariantTypeStr> for VariantTypeStr {
+    fn as_ref(&self) -> &VariantTypeStr {
+        self
+    }
+}
+impl PartialEq<str> for VariantTypeStr {
+    fn eq(&self, other: &str) -> bool {
+        &self.0 == other.as_bytes()
+    }
+}
+impl PartialEq<&str> for VariantTypeStr {
+    fn eq(&self, other: &&str) -> bool {
+        &self.0 == other.as_bytes()
+    }
+}
+impl PartialEq<[u8]> for VariantTypeStr {
+    fn eq(&self, other: &[u8]) -> bool {
+        &self.0 == other
+    }
+}
+impl<'a> core::convert::TryFrom<&'a str> for &'a VariantTypeStr {
+    type Error = InvalidTypeStr;
+    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
+        VariantTypeStr::new(value)
+    }
+}
+impl<'a> core::convert::TryFrom<&'a [u8]> for &'a VariantTypeStr {
+    type Error = InvalidTypeStr;
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        VariantTypeStr::new(value)
+    }
+}
+
+/// An owned GVariant type string
+///
+/// This is to [`VariantTypeStr`] what `String` is to `str`.  It derefs to
+/// [`VariantTypeStr`] so all of its methods are available.  In addition it
+/// provides constructors for building container types:
+///
+///     # use gvariant::{VariantType, VariantTypeStr};
+///     let s = VariantTypeStr::new("s").unwrap();
+///     let v = VariantTypeStr::new("v").unwrap();
+///     let dict = VariantType::new_array(&VariantType::new_dict_entry(s, v)?)?;
+///     assert_eq!(dict.as_str(), "a{sv}");
+///     # Ok::<(), gvariant::variant_type::InvalidTypeStr>(())
+#[cfg(feature = "alloc")]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VariantType(Box<[u8]>);
+
+#[cfg(feature = "alloc")]
+impl VariantType {
+    /// Validate `s` and make an owned copy of it
+    pub fn new(s: &(impl AsRef<[u8]> + ?Sized)) -> Result<VariantType, InvalidTypeStr> {
+        Ok(VariantTypeStr::new(s)?.to_owned())
+    }
+    fn from_vec(v: Vec<u8>) -> Result<VariantType, InvalidTypeStr> {
+        VariantTypeStr::new(&v)?;
+        Ok(VariantType(v.into_boxed_slice()))
+    }
+    /// Construct the array type with elements of type `element`
+    ///
+    /// Fails if the resulting type would be nested too deeply.
+    pub fn new_array(element: &VariantTypeStr) -> Result<VariantType, InvalidTypeStr> {
+        let mut v = Vec::with_capacity(element.0.len() + 1);
+        v.push(b'a');
+        v.extend_from_slice(&element.0);
+        Self::from_vec(v)
+    }
+    /// Construct the maybe type with elements of type `element`
+    ///
+    /// Fails if the resulting type would be nested too deeply.
+    pub fn new_maybe(element: &VariantTypeStr) -> Result<VariantType, InvalidTypeStr> {
+        let mut v = Vec::with_capacity(element.0.len() + 1);
+        v.push(b'm');
+        v.extend_from_slice(&element.0);
+        Self::from_vec(v)
+    }
+    /// Construct a tuple type from its item types
+    ///
+    /// Fails if the resulting type would be nested too deeply.
+    pub fn new_tuple<'a>(
+        items: impl IntoIterator<Item = &'a VariantTypeStr>,
+    ) -> Result<VariantType, InvalidTypeStr> {
+        let mut v = Vec::new();
+        v.push(b'(');
+        for item in items {
+            v.extend_from_slice(&item.0);
+        }
+        v.push(b')');
+        Self::from_vec(v)
+    }
+    /// Construct a dict entry type
+    ///
+    /// Fails if `key` isn't a basic type or if the resulting type would be
+    /// nested too deeply.
+    pub fn new_dict_entry(
+        key: &VariantTypeStr,
+        value: &VariantTypeStr,
+    ) -> Result<VariantType, InvalidTypeStr> {
+        let mut v = Vec::with_capacity(key.0.len() + value.0.len() + 2);
+        v.push(b'{');
+        v.extend_from_slice(&key.0);
+        v.extend_from_slice(&value.0);
+        v.push(b'}');
+        Self::from_vec(v)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl ToOwned for VariantTypeStr {
+    type Owned = VariantType;
+    fn to_owned(&self) -> Self::Owned {
+        VariantType(self.0.into())
+    }
+}
+#[cfg(feature = "alloc")]
+impl core::ops::Deref for VariantType {
+    type Target = VariantTypeStr;
+    fn deref(&self) -> &Self::Target {
+        VariantTypeStr::from_bytes_unchecked(&self.0)
+    }
+}
+#[cfg(feature = "alloc")]
+impl core::borrow::Borrow<VariantTypeStr> for VariantType {
+    fn borrow(&self) -> &VariantTypeStr {
+        self
+    }
+}
+#[cfg(feature = "alloc")]
+impl AsRef<VariantTypeStr> for VariantType {
+    fn as_ref(&self) -> &VariantTypeStr {
+        self
+    }
+}
+#[cfg(feature = "alloc")]
+impl AsRef<[u8]> for VariantType {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+#[cfg(feature = "alloc")]
+impl core::str::FromStr for VariantType {
+    type Err = InvalidTypeStr;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s)
+    }
+}
+#[cfg(feature = "alloc")]
+impl Display for VariantType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Display::fmt(&**self, f)
+    }
+}
+#[cfg(feature = "alloc")]
+impl Debug for VariantType {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+#[cfg(feature = "alloc")]
+impl PartialEq<VariantTypeStr> for VariantType {
+    fn eq(&self, other: &VariantTypeStr) -> bool {
+        **self == *other
+    }
+}
+#[cfg(feature = "alloc")]
+impl PartialEq<VariantType> for VariantTypeStr {
+    fn eq(&self, other: &VariantType) -> bool {
+        *self == **other
+    }
+}
+#[cfg(feature = "alloc")]
+impl PartialEq<str> for VariantType {
+    fn eq(&self, other: &str) -> bool {
+        **self == *other
+    }
+}
+#[cfg(feature = "alloc")]
+impl PartialEq<&str> for VariantType {
+    fn eq(&self, other: &&str) -> bool {
+        **self == **other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn t(s: &str) -> &VariantTypeStr {
+        VariantTypeStr::new(s).unwrap()
+    }
+
+    #[test]
+    fn test_parsing() {
+        for valid in &[
+            "b",
+            "(bynqiuxtdsogvaamb(abi)m(yy)a{si})",
+            "()",
+            "a{sv}",
+            "a{s(ii)}",
+            "{?*}",
+            "r",
+            "ma(s)",
+            "a{hs}",
+        ] {
+            assert_eq!(t(valid), *valid);
+        }
+        for (invalid, position) in &[
+            ("", 0),
+            ("a", 1),
+            ("(", 1),
+            ("(ii", 3),
+            ("{s}", 2),
+            ("{sii}", 3),
+            ("{vs}", 1),
+            ("{(i)s}", 1),
+            ("ii", 1),
+            ("z", 0),
+            ("{ah}", 1),
+            (")", 0),
+            ("a{sv", 4),
+        ] {
+            let err = VariantTypeStr::new(invalid).unwrap_err();
+            assert_eq!(err.position(), *position, "{:?}: {}", invalid, err);
+        }
+
+        let deep = "a".repeat(MAX_DEPTH) + "i";
+        assert!(VariantTypeStr::new(&deep).is_ok());
+        let too_deep = "a".repeat(MAX_DEPTH + 1) + "i";
+        assert!(VariantTypeStr::new(&too_deep).is_err());
+        let too_deep = "(".repeat(MAX_DEPTH + 1) + "i" + &")".repeat(MAX_DEPTH + 1);
+        assert!(VariantTypeStr::new(&too_deep).is_err());
+
+        let (first, rest) = VariantTypeStr::split_first(b"(si)a{sv}").unwrap();
+        assert_eq!(first, "(si)");
+        assert_eq!(rest, b"a{sv}");
+    }
+
+    #[test]
+    fn test_accessors() {
+        assert_eq!(t("ai").element(), Some(t("i")));
+        assert_eq!(t("m(ss)").element(), Some(t("(ss)")));
+        assert_eq!(t("(ss)").element(), None);
+        assert_eq!(
+            t("(ia(sv)m{sv}v)").items().collect::<Vec<_>>(),
+            [t("i"), t("a(sv)"), t("m{sv}"), t("v")]
+        );
+        assert_eq!(t("()").n_items(), 0);
+        assert_eq!(t("a(ii)").n_items(), 0);
+        assert_eq!(t("{sa{sv}}").key(), Some(t("s")));
+        assert_eq!(t("{sa{sv}}").value(), Some(t("a{sv}")));
+        assert_eq!(t("(sa{sv})").key(), None);
+
+        assert!(t("s").is_basic());
+        assert!(t("h").is_basic());
+        assert!(!t("v").is_basic());
+        assert!(t("v").is_container());
+        assert!(t("r").is_tuple());
+        assert!(t("()").is_tuple());
+        assert!(!t("{ss}").is_tuple());
+        assert!(t("a{?*}").is_array());
+        assert!(!t("a{?*}").is_definite());
+        assert!(t("a{sv}").is_definite());
+    }
+
+    #[test]
+    fn test_size_and_alignment() {
+        for (ty, size, alignment) in &[
+            ("s", None, 1),
+            ("i", Some(4), 4),
+            ("h", Some(4), 4),
+            ("a(uu)", None, 4),
+            ("(uu)", Some(8), 4),
+            ("(uy)", Some(8), 4),
+            ("(ti)", Some(16), 8),
+            ("()", Some(1), 1),
+            ("(()())", Some(2), 1),
+            ("{yq}", Some(4), 2),
+            ("(yv)", None, 8),
+            ("mt", None, 8),
+        ] {
+            assert_eq!(t(ty).size_of(), *size, "{}", ty);
+            assert_eq!(t(ty).align_of(), *alignment, "{}", ty);
+            assert_eq!(t(ty).is_fixed_size(), size.is_some());
+        }
+    }
+
+    #[test]
+    fn test_table() {
+        // From the GVariant paper, section 3.2.3 "Example"
+        let table: Vec<_> = t("(syus(xi)as)").table().map(|x| x.1).collect();
+        assert_eq!(
+            table,
+            [
+                (-1, 0, 1, 0),
+                (0, 0, 1, 0),
+                (0, 1, 4, 0),
+                (0, 1, 4, 4),
+                (1, 0, 8, 0),
+                (1, 0, 8, 16)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subtype() {
+        assert!(t("i").is_subtype_of(t("i")));
+        assert!(t("i").is_subtype_of(t("?")));
+        assert!(t("i").is_subtype_of(t("*")));
+        assert!(!t("i").is_subtype_of(t("r")));
+        assert!(t("(is)").is_subtype_of(t("r")));
+        assert!(t("(is)").is_subtype_of(t("(*?)")));
+        assert!(!t("(is)").is_subtype_of(t("(*)")));
+        assert!(!t("(i)").is_subtype_of(t("(**)")));
+        assert!(t("a{s(ii)}").is_subtype_of(t("a{?r}")));
+        assert!(!t("a{s(ii)}").is_subtype_of(t("a{??}")));
+        assert!(!t("*").is_subtype_of(t("i")));
+    }
+
+    #[test]
+    fn test_owned() {
+        let ty: VariantType = "a{sv}".parse().unwrap();
+        assert_eq!(ty, *t("a{sv}"));
+        assert_eq!(ty.to_string(), "a{sv}");
+        assert_eq!(format!("{:?}", ty), "\"a{sv}\"");
+        assert_eq!(
+            VariantType::new_tuple([t("i"), t("as")].iter().copied()).unwrap(),
+            "(ias)"
+        );
+        assert_eq!(VariantType::new_maybe(t("s")).unwrap(), "ms");
+        assert!(VariantType::new_dict_entry(t("v"), t("s")).is_err());
+        let deep = VariantType::new(&("a".repeat(MAX_DEPTH) + "i")).unwrap();
+        assert!(VariantType::new_array(&deep).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! Output for serialisation that works without `std`
+//!
+//! [`SerializeTo`] writes to anything that implements this module's [`Write`]
+//! trait rather than `std::io::Write`, so serialisation is available in
+//! `no_std` environments too.  [`Write`] is implemented for:
+//!
+//! * `&mut [u8]` - like `std::io::Write` the slice is advanced past the bytes
+//!   that have been written.
+//! * [`Cursor`] - for writing into an [`AlignedSlice`], so the result can be
+//!   cast without copying.
+//! * `Vec<u8>` - with the `alloc` feature.
+//! * [`AlignedVec`] - also with the `alloc` feature, a growable buffer that
+//!   keeps the data aligned.
+//! * [`IoWrite`] - an adapter for any `std::io::Write`, with the `std` feature.
+//!
+//! Serialising into a fixed size buffer in `no_std` code looks like:
+//!
+//! ```rust
+//! # use gvariant::aligned_bytes::{AsAligned, TryAsAlignedMut, A8};
+//! # use gvariant::{gv, write::Cursor, Marker, Structure};
+//! #[repr(align(8))]
+//! struct Buffer([u8; 32]);
+//!
+//! let mut buf = Buffer([0; 32]);
+//! let mut cursor = Cursor::<A8>::new(buf.0.try_as_aligned_mut().unwrap());
+//! gv!("(ys)").write(&(5u8, "hello"), &mut cursor)?;
+//! let value = gv!("(ys)").cast(cursor.written().as_aligned());
+//! let (y, s) = value.to_tuple();
+//! assert_eq!((*y, s.to_str()), (5, "hello"));
+//! # Ok::<(), gvariant::write::Error>(())
+//! ```
+//!
+//! [`Marker::serialize`] takes a `std::io::Write` as before, wrapping it in an
+//! [`IoWrite`] for you.
+//!
+//! [`SerializeTo`]: crate::SerializeTo
+//! [`AlignedVec`]: crate::aligned_bytes::AlignedVec
+//! [`Marker::serialize`]: crate::Marker::serialize
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+use core::fmt::Display;
+
+use crate::aligned_bytes::{AlignedSlice, Alignment};
+
+/// A destination for serialised GVariant data
+///
+/// This is the subset of `std::io::Write` that serialisation needs.
+pub trait Write {
+    /// Write all of `buf`, or fail
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error>;
+}
+
+/// Error returned when serialisation fails
+#[derive(Debug)]
+pub enum Error {
+    /// There wasn't enough space left in the output for the data.  The data
+    /// that did fit may have been written.
+    BufferFull,
+    /// The value can't be represented as the GVariant type it's being
+    /// serialised as, for example a string containing a NUL byte
+    InvalidInput(&'static str),
+    /// An error reported by the underlying `std::io::Write`
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+}
+impl Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::BufferFull => write!(f, "Not enough space in output buffer"),
+            Error::InvalidInput(msg) => f.write_str(msg),
+            #[cfg(feature = "std")]
+            Error::Io(e) => Display::fmt(e, f),
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+#[cfg(feature = "std")]
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+#[cfg(feature = "std")]
+impl From<Error> for std::io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::BufferFull => std::io::ErrorKind::WriteZero.into(),
+            Error::InvalidInput(msg) => std::io::Error::new(std::io::ErrorKind::InvalidInput, msg),
+            Error::Io(e) => e,
+        }
+    }
+}
+
+impl<W: Write + ?Sized> Write for &mut W {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        (**self).write_all(buf)
+    }
+}
+
+impl Write for &mut [u8] {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        if buf.len() > self.len() {
+            return Err(Error::BufferFull);
+        }
+        let (head, tail) = core::mem::take(self).split_at_mut(buf.len());
+        head.copy_from_slice(buf);
+        *self = tail;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Write for Vec<u8> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+/// Adapter to serialise to a `std::io::Write`
+///
+///     # use gvariant::{gv, write::IoWrite, SerializeTo};
+///     # fn m(myfile: impl std::io::Write) -> std::io::Result<()> {
+///     SerializeTo::<[u32]>::serialize(&[1, 2, 3], &mut IoWrite(myfile))?;
+///     # Ok(())
+///     # }
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct IoWrite<W>(pub W);
+
+#[cfg(feature = "std")]
+impl<W: std::io::Write> Write for IoWrite<W> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        Ok(self.0.write_all(buf)?)
+    }
+}
+
+/// Writes into an [`AlignedSlice`] from the beginning
+///
+/// The data written so far is available as an [`AlignedSlice`] with the same
+/// alignment from [`Cursor::written`], ready to be cast.
+#[derive(Debug)]
+pub struct Cursor<'a, A: Alignment> {
+    buf: &'a mut AlignedSlice<A>,
+    pos: usize,
+}
+
+impl<'a, A: Alignment> Cursor<'a, A> {
+    /// Create a `Cursor` that will write into `buf`
+    pub fn new(buf: &'a mut AlignedSlice<A>) -> Self {
+        Cursor { buf, pos: 0 }
+    }
+    /// The number of bytes written so far
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+    /// The data written so far
+    pub fn written(&self) -> &AlignedSlice<A> {
+        &self.buf[..self.pos]
+    }
+    /// Consume the `Cursor`, returning the data written
+    pub fn into_written(self) -> &'a mut AlignedSlice<A> {
+        &mut self.buf[..self.pos]
+    }
+}
+
+impl<A: Alignment> Write for Cursor<'_, A> {
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
+        let mut rest = &mut self.buf.as_mut()[self.pos..];
+        rest.write_all(buf)?;
+        self.pos += buf.len();
+        Ok(())
+    }
+}
+
+// Throws the data away, for when we only want to know how long it is
+pub(crate) struct Discard;
+
+impl Write for Discard {
+    fn write_all(&mut self, _: &[u8]) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aligned_bytes::{alloc_aligned, A8};
+    use crate::{gv, Marker};
+
+    #[test]
+    fn test_slice() {
+        let mut buf = [0u8; 6];
+        let mut out = &mut buf[..];
+        assert_eq!(gv!("au").write([1u32], &mut out).unwrap(), 4);
+        assert_eq!(out.len(), 2);
+        // Nothing is written if there isn't space
+        assert!(matches!(
+            gv!("u").write(3, &mut out),
+            Err(Error::BufferFull)
+        ));
+        assert_eq!(out.len(), 2);
+        assert_eq!(buf, [1, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_cursor() {
+        let mut buf = alloc_aligned::<A8>(16);
+        let mut cursor = Cursor::new(&mut buf);
+        gv!("s").write("hi", &mut cursor).unwrap();
+        gv!("y").write(3, &mut cursor).unwrap();
+        assert_eq!(cursor.position(), 4);
+        assert_eq!(&**cursor.written(), b"hi\0\x03");
+        let err = gv!("at").write([1u64, 2, 3], &mut cursor).unwrap_err();
+        assert!(matches!(err, Error::BufferFull));
+        // The elements that fit have been written
+        assert_eq!(&**cursor.into_written(), b"hi\0\x03\x01\0\0\0\0\0\0\0");
+    }
+
+    #[test]
+    fn test_io_error() {
+        let mut out = IoWrite(&mut [0u8; 1][..]);
+        let err: std::io::Error = gv!("q").write(5, &mut out).unwrap_err().into();
+        assert_eq!(err.kind(), std::io::ErrorKind::WriteZero);
+        let err: std::io::Error = gv!("s").write("a\0b", &mut vec![]).unwrap_err().into();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+}
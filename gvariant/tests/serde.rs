@@ -0,0 +1,319 @@
+#![cfg(feature = "serde")]
+use gvariant::aligned_bytes::copy_to_align;
+use gvariant::de::{from_slice, from_variant, Deserializer};
+use gvariant::ser::{infer_type, to_vec, Error};
+use gvariant::{gv, Marker, VariantTypeStr, VariantWrap};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+fn t(s: &str) -> &VariantTypeStr {
+    VariantTypeStr::new(s).unwrap()
+}
+
+fn ser<T: Serialize + ?Sized>(ty: &str, value: &T) -> Vec<u8> {
+    to_vec(value, Some(t(ty))).unwrap()
+}
+
+fn infer<T: Serialize + ?Sized>(value: &T) -> String {
+    infer_type(value).unwrap().as_str().to_string()
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Person {
+    name: String,
+    age: u32,
+    nicknames: Vec<String>,
+    email: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+enum Shape {
+    Point,
+    Circle(f64),
+    Rect(i32, i32),
+    Labelled { label: String, size: u8 },
+}
+
+#[test]
+fn test_basic_types() {
+    assert_eq!(ser("b", &true), gv!("b").serialize_to_vec(&true));
+    assert_eq!(ser("y", &5u8), [5]);
+    assert_eq!(ser("i", &-3i32), gv!("i").serialize_to_vec(-3));
+    assert_eq!(ser("t", &7u64), gv!("t").serialize_to_vec(7));
+    assert_eq!(ser("d", &1.5f32), gv!("d").serialize_to_vec(1.5));
+    assert_eq!(ser("s", "hello"), b"hello\0");
+    assert_eq!(ser("s", &'c'), b"c\0");
+    assert_eq!(ser("o", "/a/b"), b"/a/b\0");
+    assert_eq!(ser("g", "a{sv}"), b"a{sv}\0");
+    assert_eq!(ser("()", &()), b"\0");
+
+    // Integers can be written as any type that can hold them
+    assert_eq!(ser("q", &300i64), gv!("q").serialize_to_vec(300));
+    assert_eq!(ser("x", &3u8), gv!("x").serialize_to_vec(3));
+
+    for (ty, value) in &[("y", -1i32), ("q", 70000), ("n", 40000)] {
+        assert!(matches!(to_vec(value, Some(t(ty))), Err(Error::Type(_))));
+    }
+    assert!(to_vec("not a path", Some(t("o"))).is_err());
+    assert!(to_vec("a{", Some(t("g"))).is_err());
+    assert!(to_vec("a\0b", Some(t("s"))).is_err());
+    assert!(to_vec(&true, Some(t("i"))).is_err());
+    assert!(to_vec(&1, Some(t("s"))).is_err());
+    assert!(to_vec(&1, Some(t("a*"))).is_err());
+}
+
+#[test]
+fn test_containers() {
+    let william = Person {
+        name: "William".into(),
+        age: 34,
+        nicknames: vec!["Will".into(), "Bill".into()],
+        email: Some("w@example.com".into()),
+    };
+    let expected = gv!("(suasms)").serialize_to_vec(&(
+        &william.name,
+        &william.age,
+        &william.nicknames,
+        &william.email,
+    ));
+    assert_eq!(infer(&william), "(suasms)");
+    assert_eq!(to_vec(&william, None).unwrap(), expected);
+    assert_eq!(ser("(suasms)", &william), expected);
+
+    // A struct as a bag of properties
+    let properties = ser("a{sv}", &william);
+    let data = copy_to_align(&properties);
+    let m = gv!("a{sv}");
+    assert_eq!(
+        m.print(m.cast(data.as_ref()), false).to_string(),
+        "{'name': <'William'>, 'age': <uint32 34>, 'nicknames': <['Will', 'Bill']>, \
+         'email': <@ms 'w@example.com'>}"
+    );
+
+    // Fixed size
+    assert_eq!(
+        ser("a(yi)", &[(1u8, 2i32), (3, 4)]),
+        gv!("a(yi)").serialize_to_vec(&[(1u8, 2i32), (3u8, 4i32)])
+    );
+    assert_eq!(ser("ay", &[1u8, 2, 3]), [1, 2, 3]);
+    assert_eq!(ser("mi", &Some(5)), gv!("mi").serialize_to_vec(&Some(5)));
+    assert_eq!(ser("mi", &None::<i32>), b"");
+    assert_eq!(ser("ms", &Some("a")), b"a\0\0");
+
+    // Big enough to need 2 byte offsets
+    let strings: Vec<String> = (0..100).map(|x| format!("string {}", x)).collect();
+    assert_eq!(ser("as", &strings), gv!("as").serialize_to_vec(&strings));
+
+    let mut map = BTreeMap::new();
+    map.insert("a", 1);
+    map.insert("b", 2);
+    assert_eq!(infer(&map), "a{si}");
+    assert_eq!(
+        to_vec(&map, None).unwrap(),
+        gv!("a{si}").serialize_to_vec(&[("a", 1), ("b", 2)])
+    );
+    let nested = vec![vec![Some("x")], vec![None, Some("y")], vec![]];
+    assert_eq!(infer(&nested), "aams");
+    assert_eq!(
+        to_vec(&nested, None).unwrap(),
+        gv!("aams").serialize_to_vec(&nested)
+    );
+}
+
+#[test]
+fn test_variants() {
+    let mut map = BTreeMap::new();
+    map.insert("a", vec![1u16]);
+    let expected = gv!("a{sv}").serialize_to_vec(&[("a", VariantWrap(gv!("aq"), &[1u16]))]);
+    assert_eq!(ser("a{sv}", &map), expected);
+    assert_eq!(
+        ser("v", &5i32),
+        gv!("v").serialize_to_vec(VariantWrap(gv!("i"), &5))
+    );
+
+    assert_eq!(infer(&Shape::Circle(1.0)), "(sv)");
+    assert_eq!(
+        to_vec(&Shape::Circle(1.0), None).unwrap(),
+        gv!("(sv)").serialize_to_vec(&("Circle", VariantWrap(gv!("d"), &1.0)))
+    );
+    assert_eq!(
+        ser("(uv)", &Shape::Rect(1, 2)),
+        gv!("(uv)").serialize_to_vec(&(2u32, VariantWrap(gv!("(ii)"), &(1, 2))))
+    );
+    assert_eq!(
+        ser(
+            "(sv)",
+            &Shape::Labelled {
+                label: "x".into(),
+                size: 3
+            }
+        ),
+        gv!("(sv)").serialize_to_vec(&("Labelled", VariantWrap(gv!("(sy)"), &("x", 3u8))))
+    );
+    assert_eq!(
+        ser("(sv)", &Shape::Point),
+        gv!("(sv)").serialize_to_vec(&("Point", VariantWrap(gv!("()"), &())))
+    );
+    assert_eq!(ser("s", &Shape::Point), b"Point\0");
+    assert_eq!(ser("u", &Shape::Point), gv!("u").serialize_to_vec(0));
+    assert!(to_vec(&Shape::Circle(1.), Some(t("s"))).is_err());
+
+    // In a variant just the contents are written
+    assert_eq!(
+        ser("v", &Shape::Rect(1, 2)),
+        gv!("v").serialize_to_vec(VariantWrap(gv!("(ii)"), &(1, 2)))
+    );
+    let (_, expected) = gvariant::parse::parse(Some(t("av")), "[<()>, <2.0>]").unwrap();
+    assert_eq!(ser("av", &[Shape::Point, Shape::Circle(2.0)]), &**expected);
+}
+
+#[test]
+fn test_inference_errors() {
+    assert!(infer_type(&Vec::<String>::new()).is_err());
+    assert!(infer_type(&None::<i32>).is_err());
+    assert!(infer_type(&vec![vec![1i32], vec![]]).is_ok());
+    assert!(infer_type(&(1, vec![None::<u8>])).is_err());
+    let mut map = BTreeMap::new();
+    map.insert((1, 2), 3);
+    assert!(infer_type(&map).is_err());
+    assert!(to_vec(&map, None).is_err());
+}
+
+fn roundtrip<T>(ty: &str, value: &T) -> T
+where
+    T: Serialize + for<'de> Deserialize<'de>,
+{
+    let bytes = ser(ty, value);
+    let data = copy_to_align(&bytes);
+    from_slice(t(ty), &data).unwrap()
+}
+
+#[test]
+fn test_deserialize() {
+    let william = Person {
+        name: "William".into(),
+        age: 34,
+        nicknames: vec!["Will".into(), "Bill".into()],
+        email: None,
+    };
+    assert_eq!(roundtrip("(suasms)", &william), william);
+    let william = Person {
+        email: Some("w@example.com".into()),
+        ..william
+    };
+    assert_eq!(roundtrip("a{sv}", &william), william);
+
+    // Missing properties are None, unknown ones are ignored
+    let (_, data) = gvariant::parse::parse(
+        Some(t("a{sv}")),
+        "{'name': <'Bill'>, 'colour': <'blue'>, 'nicknames': <@as []>, 'age': <byte 7>}",
+    )
+    .unwrap();
+    let bill: Person = from_slice(t("a{sv}"), &data).unwrap();
+    assert_eq!(bill.age, 7);
+    assert_eq!(bill.email, None);
+
+    // Integers are converted if they fit
+    let bytes = gv!("i").serialize_to_vec(-3);
+    let data = copy_to_align(&bytes);
+    assert_eq!(from_slice::<i64>(t("i"), &data).unwrap(), -3);
+    assert!(from_slice::<u32>(t("i"), &data).is_err());
+
+    for shape in [
+        Shape::Point,
+        Shape::Circle(2.5),
+        Shape::Rect(1, 2),
+        Shape::Labelled {
+            label: "x".into(),
+            size: 3,
+        },
+    ] {
+        assert_eq!(roundtrip("(sv)", &shape), shape);
+        assert_eq!(roundtrip("(uv)", &shape), shape);
+    }
+    assert_eq!(roundtrip("s", &Shape::Point), Shape::Point);
+    assert_eq!(roundtrip("u", &Shape::Point), Shape::Point);
+    let data = copy_to_align(b"Square\0");
+    assert!(from_slice::<Shape>(t("s"), &data).is_err());
+    assert!(from_slice::<Shape>(t("ay"), &data).is_err());
+
+    let mut map = BTreeMap::new();
+    map.insert("a".to_string(), (1i32, Some(2u64)));
+    map.insert("b".to_string(), (3i32, None));
+    assert_eq!(roundtrip("a{s(imt)}", &map), map);
+    assert!(from_slice::<u32>(t("a{sv}"), &copy_to_align(b"")).is_err());
+    assert!(Deserializer::new(t("a*"), &copy_to_align(b"")).is_err());
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(untagged)]
+enum Property<'a> {
+    Bool(bool),
+    Int(i64),
+    Str(&'a str),
+    Bytes(Vec<u8>),
+}
+
+#[test]
+fn test_deserialize_borrowed() {
+    let data = copy_to_align(b"William\0\x22\x00\x00\x00\x08");
+    let (name, age): (&str, u32) = from_slice(t("(su)"), &data).unwrap();
+    assert_eq!((name, age), ("William", 34));
+    // The string is borrowed from the data
+    assert!(data.as_ptr_range().contains(&name.as_ptr()));
+
+    let data = copy_to_align(b"abc");
+    let bytes: &[u8] = from_slice(t("ay"), &data).unwrap();
+    assert_eq!(bytes, b"abc");
+    let data = copy_to_align(b"abc\0");
+    let bytes: &[u8] = Deserialize::deserialize(Deserializer::for_marker(gv!("s"), &data)).unwrap();
+    assert_eq!(bytes, b"abc");
+
+    // The contents of variants are dispatched on their type
+    let (_, data) = gvariant::parse::parse(
+        Some(t("a{sv}")),
+        "{'enabled': <true>, 'count': <int64 -5>, 'path': <objectpath '/org/example'>, \
+          'mac': <[byte 1, 2]>}",
+    )
+    .unwrap();
+    let props: BTreeMap<&str, Property> = from_slice(t("a{sv}"), &data).unwrap();
+    assert_eq!(props["enabled"], Property::Bool(true));
+    assert_eq!(props["count"], Property::Int(-5));
+    assert_eq!(props["path"], Property::Str("/org/example"));
+    assert_eq!(props["mac"], Property::Bytes(vec![1, 2]));
+
+    let bytes = gv!("v").serialize_to_vec(VariantWrap(gv!("s"), "hello"));
+    let data = copy_to_align(&bytes);
+    let v = gv!("v").cast(&data);
+    assert_eq!(from_variant::<&str>(v).unwrap(), "hello");
+}
+
+#[test]
+fn test_deserialize_malformed() {
+    // Non-normal data is read the same way as with `cast`
+    for bytes in [
+        b"".as_ref(),
+        b"ab\0",
+        b"ab\0cd\0\x03\x06",
+        b"ab\0cd\0\x03\x09",
+        b"abcd\x07\x03",
+    ] {
+        let data = copy_to_align(bytes);
+        let actual: Vec<&str> = from_slice(t("as"), &data).unwrap();
+        let expected: Vec<&str> = gv!("as")
+            .cast((*data).as_ref())
+            .into_iter()
+            .map(|s| s.to_str())
+            .collect();
+        assert_eq!(actual, expected);
+    }
+
+    // Fixed size values that are the wrong size are all zeros
+    let data = copy_to_align(b"\x01\x02\x03");
+    assert_eq!(from_slice::<(u8, u32)>(t("(yu)"), &data).unwrap(), (0, 0));
+
+    // A variant with an invalid type contains ()
+    let data = copy_to_align(b"\x01\0z");
+    let v = gv!("v").cast(&data);
+    assert!(from_variant::<()>(v).is_ok());
+}
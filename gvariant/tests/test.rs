@@ -1,5 +1,8 @@
-use gvariant::aligned_bytes::{copy_to_align, empty_aligned, AsAligned};
-use gvariant::{gv, Marker, Structure};
+use gvariant::aligned_bytes::{
+    copy_to_align, empty_aligned, AlignedSlice, Alignment, AsAligned, A8,
+};
+use gvariant::casting::AlignOf;
+use gvariant::{gv, Cast, DynValue, Marker, Structure, VariantTypeStr};
 use ref_cast::RefCast;
 use std::collections::HashMap;
 
@@ -12,6 +15,7 @@ fn test_basic_types() {
 }
 
 #[test]
+#[allow(clippy::unit_cmp, clippy::let_unit_value)]
 fn test_struct_into_tuple() {
     let t = gv!("()").cast(empty_aligned()).to_tuple();
     assert_eq!(t, ());
@@ -32,6 +36,37 @@ fn test_struct_into_tuple() {
     assert_eq!(t.1, "super");
 }
 
+#[test]
+fn test_field_accessors() {
+    fn n_fields<'a, T: Structure<'a> + ?Sized>(_: &T) -> usize {
+        T::N_FIELDS
+    }
+
+    let s = gv!("()").cast(empty_aligned());
+    assert_eq!(n_fields(s), 0);
+
+    let buf = copy_to_align(b"\x06\x00\x00\x00\x03\x00\x00\x00");
+    let s = gv!("(ii)").cast(buf.as_ref());
+    assert_eq!(n_fields(s), 2);
+    assert_eq!((s.field_0(), s.field_1()), s.to_tuple());
+
+    let data = gv!("(a{sv}aya(say)sstayay)")
+        .parse("(@a{sv} {}, @ay [], @a(say) [], 'subject', 'body', uint64 5, @ay [], @ay [1])")
+        .unwrap();
+    let commit = gv!("(a{sv}aya(say)sstayay)").cast(data.as_ref());
+    assert_eq!(n_fields(commit), 8);
+    assert_eq!(commit.field_3(), "subject");
+    assert_eq!(commit.field_4(), "body");
+    assert_eq!(*commit.field_5(), 5);
+    assert_eq!(commit.field_7(), &[1]);
+
+    // Fields that don't fit in the data have their default value
+    let s = gv!("(sis)").cast(empty_aligned());
+    assert_eq!(s.field_0(), "");
+    assert_eq!(*s.field_1(), 0);
+    assert_eq!(s.field_2(), "");
+}
+
 #[test]
 fn test_struct_serialisation() {
     // () - Unit type
@@ -123,6 +158,351 @@ fn test_struct_serialisation() {
 }
 
 #[test]
+#[allow(clippy::type_complexity)]
+fn test_wide_structures() {
+    use std::collections::HashSet;
+
+    // More fields than the standard library implements tuple traits for
+    let m = gv!("(yyyyyyyyyyyyyy)");
+    let data = m.serialize_to_vec(&(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14));
+    assert_eq!(
+        &*data,
+        b"\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e"
+    );
+    let s = m.cast(data.as_aligned());
+    assert_eq!(*s.to_tuple().13, 14);
+    assert_eq!(s, m.cast(data.as_aligned()));
+    assert_eq!(
+        format!("{:?}", s),
+        "(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14)"
+    );
+    assert_eq!(&*m.serialize_to_vec(s), &*data);
+
+    let m = gv!("(sayyyyyyyyyyyyyay)");
+    let data = m
+        .parse("('a', [1], 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, [14, 15])")
+        .unwrap();
+    let s = m.cast(data.as_aligned());
+    assert_eq!(s.to_tuple().0, "a");
+    assert_eq!(s.to_tuple().14.len(), 2);
+    assert_eq!(
+        format!("{:?}", s),
+        r#"("a", [1], 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, [14, 15])"#
+    );
+    assert_eq!(&*m.serialize_to_vec(s), &**data);
+
+    let other = m
+        .parse("('a', [1], 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, [14])")
+        .unwrap();
+    let other = m.cast(other.as_aligned());
+    assert_ne!(s, other);
+    let set: HashSet<_> = [s, s, other].iter().copied().collect();
+    assert_eq!(set.len(), 2);
+
+    // 17 fields is the narrowest structure that is generated rather than
+    // one of the generic types in `gvariant::structure`
+    let m = gv!("(yyyyyyyyyyyyyyyyy)");
+    let data = m.serialize_to_vec(&(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17));
+    assert_eq!(
+        &*data,
+        &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17]
+    );
+    let s = m.cast(data.as_aligned());
+    assert_eq!(s.field_16, 17);
+    let (first, .., last): (
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+    ) = s.into();
+    assert_eq!((*first, *last), (1, 17));
+    assert_eq!(s, m.cast(data.as_aligned()));
+    assert_eq!(
+        format!("{:?}", s),
+        "(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17)"
+    );
+    assert_eq!(&*m.serialize_to_vec(s), &*data);
+
+    let m = gv!("(syyyyyyyyyyyyyyyay)");
+    let data = m
+        .parse("('a', 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, [17])")
+        .unwrap();
+    let s = m.cast(data.as_aligned());
+    assert_eq!(s.field_0(), "a");
+    assert_eq!(*s.field_15(), 16);
+    let (first, .., last): (
+        &gvariant::Str,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &u8,
+        &[u8],
+    ) = s.into();
+    assert_eq!(first, "a");
+    assert_eq!(last, [17]);
+    assert_eq!(
+        format!("{:?}", s),
+        "(\"a\", 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, [17])"
+    );
+    assert_eq!(&*m.serialize_to_vec(s), &**data);
+
+    // 20 fields with padding between them
+    let m = gv!("(yiyiyiyiyiyiyiyiyiyi)");
+    let data = m.serialize_to_vec(&(
+        1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20,
+    ));
+    assert_eq!(data.len(), 80);
+    let aligned = copy_to_align(&data);
+    let s = m.cast(aligned.as_ref());
+    assert_eq!((s.field_18, s.field_19), (19, 20));
+    let (first, .., last): (
+        &u8,
+        &i32,
+        &u8,
+        &i32,
+        &u8,
+        &i32,
+        &u8,
+        &i32,
+        &u8,
+        &i32,
+        &u8,
+        &i32,
+        &u8,
+        &i32,
+        &u8,
+        &i32,
+        &u8,
+        &i32,
+        &u8,
+        &i32,
+    ) = s.into();
+    assert_eq!((*first, *last), (1, 20));
+    assert_eq!(
+        format!("{:?}", s),
+        "(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20)"
+    );
+    assert_eq!(&*m.serialize_to_vec(s), &*data);
+    let mut other = data.clone();
+    other[76] = 21;
+    let other = copy_to_align(&other);
+    let other = m.cast(other.as_ref());
+    assert_ne!(s, other);
+    let set: HashSet<_> = [s, s, other].iter().copied().collect();
+    assert_eq!(set.len(), 2);
+
+    let m = gv!("(sysysysysysysysysysy)");
+    let data = m
+        .parse("('a', 0, 'b', 1, 'c', 2, 'd', 3, 'e', 4, 'f', 5, 'g', 6, 'h', 7, 'i', 8, 'j', 9)")
+        .unwrap();
+    let s = m.cast(data.as_aligned());
+    assert_eq!((s.field_18().to_str(), *s.field_19()), ("j", 9));
+    let (first, .., last): (
+        &gvariant::Str,
+        &u8,
+        &gvariant::Str,
+        &u8,
+        &gvariant::Str,
+        &u8,
+        &gvariant::Str,
+        &u8,
+        &gvariant::Str,
+        &u8,
+        &gvariant::Str,
+        &u8,
+        &gvariant::Str,
+        &u8,
+        &gvariant::Str,
+        &u8,
+        &gvariant::Str,
+        &u8,
+        &gvariant::Str,
+        &u8,
+    ) = s.into();
+    assert_eq!((first.to_str(), *last), ("a", 9));
+    assert_eq!(format!("{:?}", s), "(\"a\", 0, \"b\", 1, \"c\", 2, \"d\", 3, \"e\", 4, \"f\", 5, \"g\", 6, \"h\", 7, \"i\", 8, \"j\", 9)");
+    assert_eq!(&*m.serialize_to_vec(s), &**data);
+
+    // 32 fields, including generic structures nested inside a generated one
+    let m = gv!("(bq(ii)tbq(ii)tbq(ii)tbq(ii)tbq(ii)tbq(ii)tbq(ii)tbq(ii)t)");
+    let data = m.serialize_to_vec(&(
+        &false,
+        0,
+        &(0, 0),
+        0,
+        &true,
+        1,
+        &(1, -1),
+        1000,
+        &false,
+        2,
+        &(2, -2),
+        2000,
+        &true,
+        3,
+        &(3, -3),
+        3000,
+        &false,
+        4,
+        &(4, -4),
+        4000,
+        &true,
+        5,
+        &(5, -5),
+        5000,
+        &false,
+        6,
+        &(6, -6),
+        6000,
+        &true,
+        7,
+        &(7, -7),
+        7000,
+    ));
+    assert_eq!(data.len(), 8 * 24);
+    let aligned = copy_to_align(&data);
+    let s = m.cast(aligned.as_ref());
+    assert_eq!(s.field_30.to_tuple(), (&7, &-7));
+    assert_eq!(s.field_31, 7000);
+    let (first, .., last): (
+        &gvariant::Bool,
+        &u16,
+        &gvariant::gv_type!("(ii)"),
+        &u64,
+        &gvariant::Bool,
+        &u16,
+        &gvariant::gv_type!("(ii)"),
+        &u64,
+        &gvariant::Bool,
+        &u16,
+        &gvariant::gv_type!("(ii)"),
+        &u64,
+        &gvariant::Bool,
+        &u16,
+        &gvariant::gv_type!("(ii)"),
+        &u64,
+        &gvariant::Bool,
+        &u16,
+        &gvariant::gv_type!("(ii)"),
+        &u64,
+        &gvariant::Bool,
+        &u16,
+        &gvariant::gv_type!("(ii)"),
+        &u64,
+        &gvariant::Bool,
+        &u16,
+        &gvariant::gv_type!("(ii)"),
+        &u64,
+        &gvariant::Bool,
+        &u16,
+        &gvariant::gv_type!("(ii)"),
+        &u64,
+    ) = s.into();
+    assert_eq!((first.to_bool(), *last), (false, 7000));
+    assert_eq!(format!("{:?}", s), "(false, 0, (0, 0), 0, true, 1, (1, -1), 1000, false, 2, (2, -2), 2000, true, 3, (3, -3), 3000, false, 4, (4, -4), 4000, true, 5, (5, -5), 5000, false, 6, (6, -6), 6000, true, 7, (7, -7), 7000)");
+    assert_eq!(&*m.serialize_to_vec(s), &*data);
+    assert_eq!(s, m.cast(copy_to_align(&data).as_ref()));
+
+    let m = gv!("(saysaysaysaysaysaysaysaysaysaysaysaysaysaysaysay)");
+    let data = m.parse("('a', [0], 'b', [1], 'c', [2], 'd', [3], 'e', [4], 'f', [5], 'g', [6], 'h', [7], 'i', [8], 'j', [9], 'k', [10], 'l', [11], 'm', [12], 'n', [13], 'o', [14], 'p', [15])").unwrap();
+    let s = m.cast(data.as_aligned());
+    assert_eq!((s.field_30().to_str(), s.field_31()), ("p", &[15][..]));
+    let (first, .., last): (
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+        &gvariant::Str,
+        &[u8],
+    ) = s.into();
+    assert_eq!((first.to_str(), last), ("a", &[15][..]));
+    assert_eq!(format!("{:?}", s), "(\"a\", [0], \"b\", [1], \"c\", [2], \"d\", [3], \"e\", [4], \"f\", [5], \"g\", [6], \"h\", [7], \"i\", [8], \"j\", [9], \"k\", [10], \"l\", [11], \"m\", [12], \"n\", [13], \"o\", [14], \"p\", [15])");
+    assert_eq!(&*m.serialize_to_vec(s), &**data);
+    let other = m.parse("('a', [0], 'b', [1], 'c', [2], 'd', [3], 'e', [4], 'f', [5], 'g', [6], 'h', [7], 'i', [8], 'j', [9], 'k', [10], 'l', [11], 'm', [12], 'n', [13], 'o', [14], 'p', [16])").unwrap();
+    let other = m.cast(other.as_aligned());
+    assert_ne!(s, other);
+    let set: HashSet<_> = [s, s, other].iter().copied().collect();
+    assert_eq!(set.len(), 2);
+
+    // Wide structures within arrays and generic structures
+    let m = gv!("(sa(yyyyyyyyyyyyyyyyy))");
+    let data = m.serialize_to_vec(&(
+        "x",
+        [&(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17)],
+    ));
+    let s = m.cast(data.as_aligned());
+    assert_eq!(s.field_1()[0].field_16, 17);
+}
+
+#[test]
+fn test_large_fixed_structure() {
+    // 6144 bytes, which is too large to take its default value from a static
+    // buffer of zeroes
+    let m = gv!("(((tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt))((tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt))((tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)(tttttttttttttttt)))");
+    let s = m.cast(empty_aligned());
+    assert_eq!(s.as_aligned_slice().len(), 6144);
+    assert!(s.as_aligned_slice().iter().all(|x| *x == 0));
+    assert_eq!(s.field_2.field_15.field_15, 0);
+    assert_eq!(*s, Default::default());
+
+    let data = copy_to_align(&[1; 6144]);
+    let s = m.cast(data.as_ref());
+    assert_eq!(s.field_2.field_15.field_15, 0x0101010101010101);
+    assert!(s.is_normal_form());
+}
+
+#[test]
+#[allow(clippy::type_complexity)]
 fn test_complex_types() {
     // Data created in Python with:
     //
@@ -272,7 +652,7 @@ fn test_non_normal_values() {
 
     // Wrong Size for Fixed Size Value
     assert_eq!(
-        *gv!("i").cast(&*copy_to_align(&[0x7u8, 0x33, 0x90]).as_ref()),
+        *gv!("i").cast(copy_to_align(&[0x7u8, 0x33, 0x90]).as_ref()),
         0
     );
 
@@ -357,3 +737,977 @@ fn test_non_normal_values() {
         )
     );
 }
+
+fn runtime_type<M: Marker>(_: M) -> &'static VariantTypeStr {
+    VariantTypeStr::new(M::TYPESTR).unwrap()
+}
+
+fn align_of<M: Marker>(_: M) -> usize {
+    <<M::Type as AlignOf>::AlignOf as Alignment>::ALIGNMENT
+}
+
+fn size_of<M: Marker>(_: M) -> usize
+where
+    M::Type: Sized,
+{
+    std::mem::size_of::<M::Type>()
+}
+
+#[test]
+fn test_runtime_type_matches_macro() {
+    macro_rules! check_fixed {
+        ($($typestr:literal),*) => {$(
+            let ty = runtime_type(gv!($typestr));
+            assert_eq!(ty.size_of(), Some(size_of(gv!($typestr))), "{}", ty);
+            assert_eq!(ty.align_of(), align_of(gv!($typestr)), "{}", ty);
+        )*};
+    }
+    macro_rules! check_non_fixed {
+        ($($typestr:literal),*) => {$(
+            let ty = runtime_type(gv!($typestr));
+            assert_eq!(ty.size_of(), None, "{}", ty);
+            assert_eq!(ty.align_of(), align_of(gv!($typestr)), "{}", ty);
+        )*};
+    }
+    check_fixed!(
+        "b", "y", "n", "q", "i", "u", "x", "t", "d", "()", "(yi)", "(iy)", "(yt)", "(ty)", "(qyu)",
+        "{yt}", "{qy}"
+    );
+    check_non_fixed!(
+        "s",
+        "o",
+        "g",
+        "v",
+        "ay",
+        "at",
+        "a(ty)",
+        "my",
+        "mt",
+        "m(u)",
+        "(ys)",
+        "(ts)",
+        "(ayn)",
+        "{sv}",
+        "a{sv}",
+        "(sa{sv}t)",
+        "maai",
+        "(i(yv))"
+    );
+}
+
+#[test]
+fn test_dyn_value_non_normal() {
+    // Compare against the statically typed implementation
+    fn bytes_of(v: Option<DynValue>) -> Vec<u8> {
+        match v {
+            Some(DynValue::Array(a)) => a
+                .iter()
+                .map(|x| match x {
+                    DynValue::Byte(x) => x,
+                    _ => panic!(),
+                })
+                .collect(),
+            _ => panic!(),
+        }
+    }
+    for bytes in [
+        b"foo\0bar\0baz\0\x04\x10\x0c".as_ref(),
+        b"foo\0bar\0baz\0\x04\x00\x04\x0c",
+        b"\x03\x02\x01",
+        b"",
+        b"\x01",
+    ] {
+        let data = copy_to_align(bytes);
+        let dynamic = match DynValue::new(VariantTypeStr::new("as").unwrap(), &data).unwrap() {
+            DynValue::Array(a) => a,
+            _ => panic!(),
+        };
+        let statically = gv!("as").cast(data.as_aligned());
+        assert_eq!(dynamic.len(), statically.len());
+        for (d, s) in dynamic.iter().zip(statically) {
+            assert!(matches!(d, DynValue::Str(x) if x == s));
+        }
+
+        let dynamic = match DynValue::new(VariantTypeStr::new("(ayays)").unwrap(), &data).unwrap() {
+            DynValue::Tuple(t) => t,
+            _ => panic!(),
+        };
+        let (a, b, c) = gv!("(ayays)").cast(data.as_aligned()).to_tuple();
+        assert_eq!(bytes_of(dynamic.get(0)), a);
+        assert_eq!(bytes_of(dynamic.get(1)), b);
+        assert!(matches!(dynamic.get(2), Some(DynValue::Str(x)) if x == c));
+    }
+}
+
+#[test]
+fn test_print() {
+    let m = gv!("(sasmqt)");
+    let data = m.serialize_to_vec(&("Hello", &[] as &[&str], &Some(7u16), 5u64));
+    let data = copy_to_align(&data);
+    let value = m.cast(data.as_ref());
+    assert_eq!(m.print(value, false).to_string(), "('Hello', [], 7, 5)");
+    assert_eq!(
+        m.print(value, true).to_string(),
+        "('Hello', @as [], @mq 7, uint64 5)"
+    );
+
+    let data = copy_to_align(b"(y(yq))\0");
+    let value = gv!("s").cast(data.as_ref());
+    assert_eq!(gv!("s").print(value, true).to_string(), "'(y(yq))'");
+
+    let data = copy_to_align(b"\x05\0\0\0\0u");
+    let v = gv!("v").cast(data.as_ref());
+    assert_eq!(v.print(false).to_string(), "<uint32 5>");
+    assert_eq!(gv!("v").print(v, false).to_string(), "<uint32 5>");
+}
+
+#[test]
+fn test_parse() {
+    let m = gv!("(sa{sv}mqt)");
+    let data = m
+        .parse("('Hello', {'a': <1>, 'b': <@as []>}, nothing, 5)")
+        .unwrap();
+    let value = m.cast(data.as_ref());
+    let (s, dict, maybe, t) = value.to_tuple();
+    assert_eq!(s.to_str(), "Hello");
+    assert_eq!(dict.len(), 2);
+    assert_eq!(dict[1].to_tuple().0.to_str(), "b");
+    assert_eq!(dict[0].to_tuple().1.get(gv!("i")), Some(&1));
+    assert_eq!(maybe.to_option(), None);
+    assert_eq!(*t, 5);
+    assert_eq!(
+        m.print(value, true).to_string(),
+        "('Hello', {'a': <1>, 'b': <@as []>}, @mq nothing, uint64 5)"
+    );
+
+    // Parsed data is in normal form.  This is the same as GLib produces:
+    let m = gv!("a(si)");
+    let data = m.parse("[('a', 1), ('bc', -2)]").unwrap();
+    assert_eq!(
+        &**data,
+        b"a\0\0\0\x01\0\0\0\x02\0\0\0bc\0\0\xfe\xff\xff\xff\x03\x09\x15"
+    );
+
+    let err = m.parse("[('a', 1), ('bc', 'd')]").unwrap_err();
+    assert_eq!(err.range(), 18..21);
+    assert_eq!(err.to_string(), "18-21:can not parse as value of type 'i'");
+}
+
+#[test]
+fn test_is_normal_form() {
+    let m = gv!("(sa{sv}mqt)");
+    let data = m
+        .parse("('Hello', {'a': <1>, 'b': <@as []>}, nothing, 5)")
+        .unwrap();
+    assert!(m.is_normal_form(data.as_ref()));
+    assert!(m.cast(data.as_ref()).is_normal_form());
+
+    // Fixed size structures
+    let m = gv!("(yyi)");
+    let data = copy_to_align(b"\x01\x01\0\0\x02\0\0\0");
+    assert!(m.is_normal_form(data.as_ref()));
+    assert!(m.cast(data.as_ref()).is_normal_form());
+    let data = copy_to_align(b"\x01\x01\x03\0\x02\0\0\0");
+    assert!(!m.cast(data.as_ref()).is_normal_form());
+    // Data of the wrong size is replaced by the default value
+    let data = copy_to_align(b"\x01\x01\0\0\x02\0\0\0\0");
+    assert!(!m.is_normal_form(data.as_ref()));
+    assert!(m.cast(data.as_ref()).is_normal_form());
+
+    let data = copy_to_align(b"\x01\x00\x02");
+    assert!(!gv!("ab").cast(data.as_ref()).is_normal_form());
+    assert!(gv!("ab").cast(&data[..2]).is_normal_form());
+
+    // Non-fixed size structures within arrays
+    let m = gv!("a(si)");
+    let data = copy_to_align(b"a\0\0\0\x01\0\0\0\x02\0\0\0bc\0\x01\xfe\xff\xff\xff\x03\x09\x15");
+    let value = m.cast(data.as_ref());
+    assert!(value[0].is_normal_form());
+    assert!(!value[1].is_normal_form());
+    assert!(!value.is_normal_form());
+    assert!(!m.is_normal_form(data.as_ref()));
+
+    // Variants check their contents
+    let m = gv!("v");
+    let data = copy_to_align(b"\x01\0\x03\0\x02\0\0\0\0(yyi)");
+    assert!(!m.cast(data.as_ref()).is_normal_form());
+    let data = copy_to_align(b"\x01\x01\0\0\x02\0\0\0\0(yyi)");
+    assert!(m.cast(data.as_ref()).is_normal_form());
+}
+
+#[test]
+fn test_normalize() {
+    // Data that is already in normal form is unchanged by normalisation
+    fn check<M: Marker>(m: M, text: &str)
+    where
+        for<'a> &'a M::Type: gvariant::SerializeTo<M::Type>,
+        AlignedSlice<A8>: AsAligned<<M::Type as AlignOf>::AlignOf>,
+    {
+        let data = m.parse(text).unwrap();
+        let normal = m.normalize(m.cast(data.as_aligned()));
+        assert_eq!(&**normal, &**data, "{}", text);
+    }
+    check(gv!("a(si)"), "[('a', 1), ('bc', -2)]");
+    check(gv!("a(yi)"), "[(1, 2), (3, 4)]");
+    check(gv!("aas"), "[['a', 'bc'], [], ['d']]");
+    check(
+        gv!("a{sv}"),
+        "{'a': <1>, 'b': <@as ['x']>, 'c': <(byte 1, 2)>}",
+    );
+    check(gv!("m(si)"), "just ('a', 1)");
+    check(gv!("m(yi)"), "just (1, 2)");
+    check(gv!("ams"), "[just 'a', nothing, just '']");
+    check(
+        gv!("(sa{sv}mqt)"),
+        "('Hello', {'a': <@mmb just nothing>}, 7, 5)",
+    );
+    check(gv!("(yyi)"), "(1, 2, 3)");
+
+    // Non-normal data
+    let m = gv!("a(si)");
+    let data = copy_to_align(b"a\0\0\0\x01\0\0\0\x02\x07\0\0bc\0\x01\xfe\xff\xff\xff\x03\x09\x15");
+    assert!(!m.is_normal_form(data.as_ref()));
+    let normal = m.normalize(m.cast(data.as_ref()));
+    assert_eq!(
+        &**normal,
+        b"a\0\0\0\x01\0\0\0\x02\0\0\0bc\0\0\xfe\xff\xff\xff\x03\x09\x15"
+    );
+
+    let data = copy_to_align(b"\x01\x02\x00\x07");
+    assert_eq!(
+        &**gv!("ab").normalize(gv!("ab").cast(data.as_ref())),
+        b"\x01\x01\x00\x01"
+    );
+
+    // The contents of variants are normalised too, including invalid strings
+    let m = gv!("av");
+    let data = m.parse("[<('a', 1)>, <@o '/a'>]").unwrap();
+    let mut bad = data.to_vec();
+    bad[2] = 5;
+    bad[17] = b'!';
+    let bad = copy_to_align(&bad);
+    assert!(!m.is_normal_form(bad.as_ref()));
+    let normal = m.normalize(m.cast(bad.as_ref()));
+    assert!(m.is_normal_form(normal.as_aligned()));
+    assert_eq!(
+        m.print(m.cast(normal.as_aligned()), false).to_string(),
+        "[<('a', 1)>, <objectpath '/'>]"
+    );
+}
+
+#[test]
+fn test_byte_order() {
+    use gvariant::endian::{ByteOrder, Endian};
+
+    // Fixed size structures swap each of their fields
+    let m = gv!("(yqu)");
+    let data = copy_to_align(b"\x01\0\x00\x02\x00\x00\x00\x03");
+    let value = m.cast(data.as_ref()).to_native(ByteOrder::Big);
+    assert_eq!(value.to_tuple(), (&1, &2, &3));
+    assert_eq!(value.byteswap().byteswap(), value);
+
+    let swapped = m.byteswap(m.cast(data.as_ref()));
+    assert_eq!(&**swapped, b"\x01\0\x02\x00\x03\x00\x00\x00");
+    assert_eq!(m.cast(swapped.as_aligned()).to_tuple(), (&1, &2, &3));
+
+    // Arrays
+    let m = gv!("at");
+    let be_bytes = [7u64.to_be_bytes(), 8u64.to_be_bytes()].concat();
+    let data = copy_to_align(&be_bytes);
+    let value = m.cast(data.as_ref());
+    let be: Vec<u64> = value.iter().map(|x| x.to_native(ByteOrder::Big)).collect();
+    assert_eq!(be, [7, 8]);
+    assert_eq!(m.cast(m.byteswap(value).as_aligned()), &[7, 8]);
+
+    // Numbers within variants are swapped too, and the result is normal form
+    let m = gv!("(sv)");
+    let data = m.parse("('a', <(int16 1, uint32 2)>)").unwrap();
+    let swapped = m.byteswap(m.cast(data.as_aligned()));
+    assert!(m.is_normal_form(swapped.as_aligned()));
+    assert_eq!(&**m.byteswap(m.cast(swapped.as_aligned())), &**data);
+    let (_, v) = m.cast(swapped.as_aligned()).to_tuple();
+    let (n, u) = v.get(gv!("(nu)")).unwrap().to_tuple();
+    assert_eq!((*n, *u), (1i16.swap_bytes(), 2u32.swap_bytes()));
+}
+
+#[test]
+fn test_variant_map_keys() {
+    use std::collections::HashSet;
+    // (yi) containing 1 and 5, with zeroed and non-zero padding
+    let normal = copy_to_align(b"\x01\0\0\0\x05\0\0\0\0(yi)");
+    let padded = copy_to_align(b"\x01\xff\xff\xff\x05\0\0\0\0(yi)");
+    let other = copy_to_align(b"\x01\0\0\0\x06\0\0\0\0(yi)");
+    let v = gv!("v");
+    let (normal, padded, other) = (v.cast(&normal), v.cast(&padded), v.cast(&other));
+    assert_eq!(normal, padded);
+    assert_ne!(normal, other);
+
+    let set: HashSet<_> = [normal, padded, other].iter().copied().collect();
+    assert_eq!(set.len(), 2);
+    assert!(set.contains(padded));
+
+    // Structures containing variants can be keys too
+    let data = copy_to_align(b"a\0\0\0\0\0\0\0\x01\0\0\0\x05\0\0\0\0(yi)\x02");
+    let padded_data = copy_to_align(b"a\0\xff\xff\0\0\0\0\x01\xff\xff\xff\x05\0\0\0\0(yi)\x02");
+    let other_data = copy_to_align(b"b\0\0\0\0\0\0\0\x01\0\0\0\x05\0\0\0\0(yi)\x02");
+    let m = gv!("(sv)");
+    let mut map = HashMap::new();
+    map.insert(m.cast(&data), 1);
+    assert_eq!(map.get(m.cast(&padded_data)), Some(&1));
+    assert_eq!(map.get(m.cast(&other_data)), None);
+    assert_eq!(m.cast(&data).to_tuple().1, normal);
+}
+
+#[test]
+fn test_object_paths_and_signatures() {
+    let m = gv!("(ogas)");
+    let serialized = m.serialize_to_vec(&("/org/example/Foo", "a{sv}", ["a", "b"].as_ref()));
+    let data = copy_to_align(&serialized);
+    let (path, sig, _) = m.cast(data.as_ref()).to_tuple();
+    assert_eq!(path, "/org/example/Foo");
+    assert_eq!(sig, "a{sv}");
+    let s: &str = path.into();
+    assert_eq!(s, "/org/example/Foo");
+
+    // Invalid input is rejected rather than producing non-normal data
+    let mut out = vec![];
+    assert!(m
+        .serialize(&("/org/", "", [""].as_ref()), &mut out)
+        .is_err());
+    assert!(m.serialize(&("/", "ms", [""].as_ref()), &mut out).is_err());
+
+    // Invalid data is read as the default value
+    let data = copy_to_align(b"org\0ms\0\x04");
+    let (path, sig) = gv!("(og)").cast(data.as_ref()).to_tuple();
+    assert_eq!((path.to_str(), sig.to_str()), ("/", ""));
+    assert!(!gv!("(og)").is_normal_form(data.as_ref()));
+}
+
+#[test]
+fn test_dict_lookup() {
+    use gvariant::Dict;
+
+    let m = gv!("a{sa{sv}}");
+    let data = m
+        .parse("{'b': {'x': <1>, 'y': <'why'>}, 'a': {}, 'b': {'x': <2>}}")
+        .unwrap();
+    let dict = m.cast(data.as_aligned());
+    let b = dict.lookup("b").unwrap();
+    assert_eq!(b.n_entries(), 2);
+    // The first entry wins if there are duplicates
+    assert_eq!(*b.lookup("x").unwrap().get(gv!("i")).unwrap(), 1);
+    assert_eq!(b.lookup("y").unwrap().get(gv!("s")).unwrap(), "why");
+    assert!(b.lookup("z").is_none());
+    assert_eq!(dict.lookup("a").unwrap().n_entries(), 0);
+    assert!(dict.lookup("c").is_none());
+
+    // Other basic key types, including fixed size entries
+    let m = gv!("a{oi}");
+    let data = m.parse("{'/a': 1, '/a/b': 2}").unwrap();
+    assert_eq!(m.cast(data.as_aligned()).lookup("/a/b"), Some(&2));
+    let m = gv!("a{yq}");
+    let data = m.parse("{1: 10, 3: 30}").unwrap();
+    let dict = m.cast(data.as_aligned());
+    assert_eq!(dict.lookup(&3), Some(&30));
+    assert_eq!(dict.lookup_sorted(&1), Some(&10));
+    assert_eq!(dict.lookup_sorted(&2), None);
+
+    // Binary search over a sorted dictionary
+    let m = gv!("a{ss}");
+    let keys: Vec<String> = (0..100).map(|n| format!("key{:03}", n)).collect();
+    let entries: Vec<String> = keys.iter().map(|k| format!("'{}': 'v{}'", k, k)).collect();
+    let data = m.parse(&format!("{{{}}}", entries.join(", "))).unwrap();
+    let dict = m.cast(data.as_aligned());
+    for k in &keys {
+        assert_eq!(dict.lookup_sorted(k.as_str()).unwrap(), &*format!("v{}", k));
+        assert_eq!(dict.lookup_sorted(k.as_str()), dict.lookup(k.as_str()));
+    }
+    assert!(dict.lookup_sorted("key").is_none());
+    assert!(dict.lookup_sorted("key100").is_none());
+    assert!(dict.lookup_sorted("").is_none());
+}
+
+#[test]
+fn test_derive() {
+    use gvariant::{Dict, GVariant, VariantWrap};
+
+    #[derive(GVariant)]
+    struct Person {
+        name: String,
+        age: u32,
+        nicknames: Vec<String>,
+        email: Option<String>,
+    }
+    let william = Person {
+        name: "William".into(),
+        age: 34,
+        nicknames: vec!["Will".into(), "Bill".into()],
+        email: None,
+    };
+    let bytes = Person::marker().serialize_to_vec(&william);
+    assert_eq!(
+        bytes,
+        gv!("(suasms)").serialize_to_vec(&(
+            &william.name,
+            &william.age,
+            &william.nicknames,
+            &william.email
+        ))
+    );
+    let data = copy_to_align(&bytes);
+    let view: &PersonView = Person::marker().cast(data.as_ref());
+    assert_eq!(view.name(), "William");
+    assert_eq!(*view.age(), 34);
+    assert_eq!(view.nicknames(), ["Will", "Bill"].as_ref());
+    assert_eq!(view.email().to_option(), None);
+    assert_eq!(
+        Person::marker().print(view, false).to_string(),
+        "('William', 34, ['Will', 'Bill'], nothing)"
+    );
+
+    // Derived types can be used within variants like any other
+    let v = gv!("v").serialize_to_vec(VariantWrap(Person::marker(), &william));
+    let v = copy_to_align(&v);
+    let v = gv!("v").cast(v.as_ref()).get(Person::marker()).unwrap();
+    assert_eq!(v.nicknames()[1].to_str(), "Bill");
+
+    // Fixed size structures
+    #[derive(GVariant)]
+    pub(crate) struct Point {
+        x: i32,
+        y: u8,
+    }
+    let bytes = Point::marker().serialize_to_vec(&Point { x: -1, y: 2 });
+    assert_eq!(bytes, b"\xff\xff\xff\xff\x02\0\0\0");
+    let data = copy_to_align(&bytes);
+    let p: &PointView = Point::marker().cast(data.as_ref());
+    assert_eq!((*p.x(), *p.y()), (-1, 2));
+
+    // With an explicit type and borrowed fields
+    #[derive(GVariant)]
+    #[gvariant(type = "(ou)")]
+    struct Object<'a> {
+        path: &'a str,
+        id: u32,
+    }
+    let bytes = Object::marker().serialize_to_vec(&Object { path: "/a", id: 7 });
+    let data = copy_to_align(&bytes);
+    let o = Object::marker().cast(data.as_ref());
+    assert_eq!(o.path(), "/a");
+    assert_eq!(*o.id(), 7);
+    let mut out = vec![];
+    assert!(Object::marker()
+        .serialize(&Object { path: "a", id: 7 }, &mut out)
+        .is_err());
+
+    // With the type of one field given and a map
+    #[derive(GVariant)]
+    struct Node {
+        #[gvariant(type = "o")]
+        path: String,
+        counts: std::collections::BTreeMap<String, u32>,
+    }
+    let node = Node {
+        path: "/b".into(),
+        counts: [("x".to_string(), 1), ("y".to_string(), 2)].into(),
+    };
+    let bytes = Node::marker().serialize_to_vec(&node);
+    assert_eq!(
+        bytes,
+        gv!("(oa{su})").serialize_to_vec(&("/b", [("x", 1u32), ("y", 2)].as_ref()))
+    );
+    let data = copy_to_align(&bytes);
+    let n = Node::marker().cast(data.as_ref());
+    assert_eq!(n.path(), "/b");
+    assert_eq!(n.counts().lookup("y"), Some(&2));
+
+    // With the path to the crate given, as when it's re-exported
+    #[derive(GVariant)]
+    #[gvariant(crate = "crate::reexport::gvariant")]
+    struct Range {
+        start: i32,
+        end: i32,
+    }
+    let bytes = Range::marker().serialize_to_vec(&Range { start: 1, end: -1 });
+    assert_eq!(bytes, gv!("(ii)").serialize_to_vec(&(1, -1)));
+}
+
+mod reexport {
+    pub use gvariant;
+}
+
+#[test]
+fn test_derive_enum() {
+    use gvariant::{GVariant, UnknownVariant, Variant, VariantWrap};
+    use std::convert::TryFrom;
+
+    #[derive(GVariant)]
+    enum Shape<'a> {
+        Point,
+        Circle(f64),
+        #[gvariant(type = "(ii)")]
+        Rect((i32, i32)),
+        Label(&'a str),
+        Path(#[gvariant(type = "o")] String),
+    }
+    let bytes = Shape::marker().serialize_to_vec(&Shape::Circle(2.5));
+    assert_eq!(
+        bytes,
+        gv!("v").serialize_to_vec(VariantWrap(gv!("d"), &2.5f64))
+    );
+    let data = copy_to_align(&bytes);
+    let v: &Variant = Shape::marker().cast(data.as_ref());
+    assert_eq!(ShapeView::try_from(v), Ok(ShapeView::Circle(&2.5)));
+
+    for (shape, expected) in &[
+        (Shape::Point, "<()>"),
+        (Shape::Rect((1, 2)), "<(1, 2)>"),
+        (Shape::Label("hello"), "<'hello'>"),
+    ] {
+        let bytes = Shape::marker().serialize_to_vec(shape);
+        let data = copy_to_align(&bytes);
+        let v = Shape::marker().cast(data.as_ref());
+        assert_eq!(v.print(false).to_string(), *expected);
+        let view = ShapeView::try_from(v).unwrap();
+        match (shape, view) {
+            (Shape::Point, ShapeView::Point) => {}
+            (Shape::Rect(r), ShapeView::Rect(x)) => assert_eq!(x.to_tuple(), (&r.0, &r.1)),
+            (Shape::Label(s), ShapeView::Label(x)) => assert_eq!(x, *s),
+            _ => panic!("Wrong variant"),
+        }
+    }
+
+    let bytes = Shape::marker().serialize_to_vec(&Shape::Path("/c".into()));
+    let data = copy_to_align(&bytes);
+    let v = Shape::marker().cast(data.as_ref());
+    assert_eq!(v.print(false).to_string(), "<objectpath '/c'>");
+    match ShapeView::try_from(v) {
+        Ok(ShapeView::Path(x)) => assert_eq!(x, "/c"),
+        _ => panic!("Wrong variant"),
+    }
+
+    let bytes = gv!("v").serialize_to_vec(VariantWrap(gv!("u"), &3u32));
+    let data = copy_to_align(&bytes);
+    assert_eq!(
+        ShapeView::try_from(gv!("v").cast(data.as_ref())),
+        Err(UnknownVariant::Type(b"u"))
+    );
+
+    // Tagged with the name of the variant
+    #[derive(GVariant)]
+    #[gvariant(type = "(sv)")]
+    enum Named {
+        #[gvariant(name = "int")]
+        Int(i32),
+        Text(String),
+    }
+    let bytes = Named::marker().serialize_to_vec(&Named::Int(-3));
+    let data = copy_to_align(&bytes);
+    let s = Named::marker().cast(data.as_ref());
+    assert_eq!(Named::marker().print(s, false).to_string(), "('int', <-3>)");
+    assert_eq!(NamedView::try_from(s), Ok(NamedView::Int(&-3)));
+
+    let bytes = Named::marker().serialize_to_vec(&Named::Text("hi".into()));
+    let data = copy_to_align(&bytes);
+    match NamedView::try_from(Named::marker().cast(data.as_ref())) {
+        Ok(NamedView::Text(x)) => assert_eq!(x, "hi"),
+        _ => panic!("Wrong variant"),
+    }
+
+    let bytes = gv!("(sv)").serialize_to_vec(&("Int", VariantWrap(gv!("i"), &1i32)));
+    let data = copy_to_align(&bytes);
+    assert_eq!(
+        NamedView::try_from(Named::marker().cast(data.as_ref())),
+        Err(UnknownVariant::Name("Int"))
+    );
+    let bytes = gv!("(sv)").serialize_to_vec(&("int", VariantWrap(gv!("s"), "1")));
+    let data = copy_to_align(&bytes);
+    assert_eq!(
+        NamedView::try_from(Named::marker().cast(data.as_ref())),
+        Err(UnknownVariant::Type(b"s"))
+    );
+
+    // Tagged with the index of the variant
+    #[derive(GVariant)]
+    #[gvariant(type = "(uv)")]
+    enum Indexed {
+        First,
+        Second(Vec<u8>),
+    }
+    let bytes = Indexed::marker().serialize_to_vec(&Indexed::Second(vec![1, 2]));
+    let data = copy_to_align(&bytes);
+    let s = Indexed::marker().cast(data.as_ref());
+    assert_eq!(
+        Indexed::marker().print(s, false).to_string(),
+        "(1, <[byte 0x01, 0x02]>)"
+    );
+    assert_eq!(
+        IndexedView::try_from(s),
+        Ok(IndexedView::Second([1u8, 2].as_ref()))
+    );
+    let bytes = Indexed::marker().serialize_to_vec(&Indexed::First);
+    let data = copy_to_align(&bytes);
+    assert_eq!(
+        IndexedView::try_from(Indexed::marker().cast(data.as_ref())),
+        Ok(IndexedView::First)
+    );
+    let bytes = gv!("(uv)").serialize_to_vec(&(2u32, VariantWrap(gv!("()"), &())));
+    let data = copy_to_align(&bytes);
+    assert_eq!(
+        IndexedView::try_from(Indexed::marker().cast(data.as_ref())),
+        Err(UnknownVariant::Index(2))
+    );
+}
+
+#[test]
+fn test_serialize_to_aligned() {
+    use gvariant::aligned_bytes::alloc_aligned;
+    use gvariant::{write, SerializeTo};
+
+    fn check<M: Marker, T: SerializeTo<M::Type> + Copy>(m: M, value: T) {
+        let expected = m.serialize_to_vec(value);
+        assert_eq!(value.serialized_size().unwrap(), expected.len());
+        let aligned = m.serialize_to_aligned(value).unwrap();
+        assert_eq!(&**aligned, expected.as_slice());
+    }
+    check(gv!("(yi)"), &(6u8, 87654321i32));
+    check(gv!("as"), &["a", "bc", ""]);
+    check(gv!("a{sv}"), &[("a", gvariant::VariantWrap(gv!("s"), "x"))]);
+    check(gv!("(sasmi)"), &("hello", ["world"], &Some(5)));
+    // Nothing to allocate
+    check(gv!("mi"), &None::<i32>);
+
+    // The result can be cast directly
+    let data = gv!("(say)")
+        .serialize_to_aligned(&("hello", [1u8, 2]))
+        .unwrap();
+    let (s, ay) = gv!("(say)").cast(data.as_aligned()).to_tuple();
+    assert_eq!((s.to_str(), ay), ("hello", [1u8, 2].as_ref()));
+
+    let mut buf = alloc_aligned::<A8>(4);
+    assert_eq!(gv!("ay").serialize_into([1u8, 2, 3], &mut buf).unwrap(), 3);
+    assert!(matches!(
+        gv!("s").serialize_into("hello", &mut buf),
+        Err(write::Error::BufferFull)
+    ));
+    assert!(SerializeTo::<gvariant::Str>::serialized_size("a\0").is_err());
+}
+
+#[test]
+fn test_builder() {
+    // Leaf values pushed one at a time
+    let mut out = vec![];
+    let mut b = gv!("a(yi)").builder(&mut out);
+    b.push(&(1u8, 2i32)).unwrap();
+    b.push(&(3u8, 4i32)).unwrap();
+    b.finish().unwrap();
+    assert_eq!(
+        out,
+        gv!("a(yi)").serialize_to_vec([&(1u8, 2i32), &(3u8, 4i32)])
+    );
+
+    // Fixed size structures are padded, non-fixed ones get framing offsets
+    let mut out = vec![];
+    let mut b = gv!("a(yi)").builder(&mut out);
+    b.open()
+        .unwrap()
+        .field(5u8)
+        .unwrap()
+        .field(6)
+        .unwrap()
+        .finish()
+        .unwrap();
+    b.finish().unwrap();
+    assert_eq!(out, gv!("a(yi)").serialize_to_vec([&(5u8, 6i32)]));
+
+    let mut out = vec![];
+    let mut s = gv!("(asyas)").builder(&mut out).open().unwrap();
+    s.push("a").unwrap();
+    let mut s = s.finish().unwrap().field(7u8).unwrap().open().unwrap();
+    s.push("bc").unwrap();
+    s.finish().unwrap().finish().unwrap();
+    assert_eq!(out, gv!("(asyas)").serialize_to_vec(&(["a"], 7u8, ["bc"])));
+
+    // Nested arrays
+    let mut out = vec![];
+    let mut outer = gv!("aas").builder(&mut out);
+    for inner in [&["a", "b"][..], &[], &["cde"]] {
+        let mut b = outer.open().unwrap();
+        for s in inner {
+            b.push(*s).unwrap();
+        }
+        b.finish().unwrap();
+    }
+    outer.finish().unwrap();
+    assert_eq!(
+        out,
+        gv!("aas").serialize_to_vec([&["a", "b"][..], &[], &["cde"]])
+    );
+
+    // Dictionaries of variants
+    let mut out = vec![];
+    let mut dict = gv!("a{sv}").builder(&mut out);
+    dict.open()
+        .unwrap()
+        .field("n")
+        .unwrap()
+        .open()
+        .unwrap()
+        .value(gv!("i"), 5)
+        .unwrap()
+        .finish()
+        .unwrap();
+    let mut v = dict.open().unwrap().field("s").unwrap().open().unwrap();
+    v = v.open(gv!("ms")).unwrap().some("x").unwrap();
+    v.finish().unwrap().finish().unwrap();
+    dict.finish().unwrap();
+    let expected = gv!("a{sv}").parse("{'n': <5>, 's': <@ms 'x'>}").unwrap();
+    assert_eq!(out, &**expected);
+
+    // Maybes
+    let mut out = vec![];
+    gv!("mi").builder(&mut out).some(3).unwrap();
+    gv!("mi").builder(&mut out).none().unwrap();
+    gv!("mas")
+        .builder(&mut out)
+        .open()
+        .unwrap()
+        .finish()
+        .unwrap()
+        .finish()
+        .unwrap();
+    let mut expected = gv!("mi").serialize_to_vec(&Some(3));
+    expected.extend(gv!("mas").serialize_to_vec(Some([] as [&str; 0])));
+    assert_eq!(out, expected);
+
+    // Variants must have contents
+    let mut out = vec![];
+    assert!(matches!(
+        gv!("v").builder(&mut out).finish(),
+        Err(gvariant::write::Error::InvalidInput(_))
+    ));
+
+    // Builders write through to a std::io::Write as they go
+    let mut file = std::io::Cursor::new(vec![]);
+    let mut b = gv!("as").builder(gvariant::write::IoWrite(&mut file));
+    b.push("hi").unwrap();
+    assert_eq!(b.position(), 3);
+    let gvariant::write::IoWrite(file) = b.finish().unwrap();
+    assert_eq!(file.position(), 4);
+    assert_eq!(file.get_ref(), b"hi\0\x03");
+}
+
+#[test]
+fn test_cast_mut() {
+    use gvariant::aligned_bytes::{alloc_aligned, AsAligned, AsAlignedMut, A4};
+
+    let mut data = alloc_aligned::<A8>(16);
+    gv!("at").try_cast_mut(&mut data).unwrap()[1] = 7;
+    assert_eq!(gv!("at").cast(&data), &[0, 7]);
+    gv!("ad").try_cast_mut(&mut data).unwrap()[0] = 1.5;
+    assert_eq!(gv!("ad").cast(&data)[0], 1.5);
+    gv!("ay")
+        .try_cast_mut(data.as_aligned_mut())
+        .unwrap()
+        .fill(1);
+    assert_eq!(
+        gv!("ab")
+            .cast(data.as_aligned())
+            .iter()
+            .filter(|b| b.to_bool())
+            .count(),
+        16
+    );
+    assert!(gv!("ab").try_cast_mut(data.as_aligned_mut()).unwrap()[3].to_bool());
+
+    // Arrays of fixed size structures
+    let mut data = alloc_aligned::<A4>(16);
+    let a = gv!("a(yi)").try_cast_mut(&mut data).unwrap();
+    a[1].field_0 = 3;
+    a[1].field_1 = -5;
+    assert_eq!(gv!("a(yi)").cast(&data)[1].to_tuple(), (&3u8, &-5i32));
+    // Doesn't divide evenly into elements
+    assert!(gv!("a(yi)").try_cast_mut(&mut data[..12]).is_err());
+    assert!(gv!("u").try_cast_mut(&mut data[..2]).is_err());
+
+    // Maybes of fixed size types
+    let mut data = alloc_aligned::<A4>(4);
+    *gv!("mu")
+        .try_cast_mut(&mut data)
+        .unwrap()
+        .to_option_mut()
+        .unwrap() = 9;
+    assert_eq!(gv!("mu").cast(&data).to_option(), Some(&9));
+    assert_eq!(
+        gv!("mu")
+            .try_cast_mut(&mut data[..0])
+            .unwrap()
+            .to_option_mut(),
+        None
+    );
+
+    // Fixed size fields of non-fixed size structures
+    let mut data = gv!("(sta{sv}y)")
+        .parse("('counter', 5, {'x': <1>}, 2)")
+        .unwrap();
+    let s = gv!("(sta{sv}y)").try_cast_mut(&mut data).unwrap();
+    *s.field_1_mut().unwrap() += 1;
+    *s.field_3_mut().unwrap() = 4;
+    let (name, count, dict, y) = gv!("(sta{sv}y)").cast(&data).to_tuple();
+    assert_eq!(
+        (name.to_str(), *count, dict.len(), *y),
+        ("counter", 6, 1, 4)
+    );
+
+    // The data is too short to contain the field
+    let mut data = alloc_aligned::<A8>(2);
+    assert_eq!(
+        gv!("(st)").try_cast_mut(&mut data).unwrap().field_1_mut(),
+        None
+    );
+}
+
+#[test]
+fn test_handle() {
+    use gvariant::Handle;
+
+    let seven = 7i32.to_ne_bytes();
+    let data = copy_to_align(&seven);
+    let h = gv!("h").cast(data.as_ref());
+    assert_eq!(*h, Handle(7));
+    assert_eq!(i32::from(*h), 7);
+    assert_eq!(gv!("h").serialize_to_vec(Handle(7)), &**data);
+
+    let m = gv!("a{hs}");
+    let data = m.parse("{handle 0: 'stdin', 2: 'stderr'}").unwrap();
+    let fds = m.cast(data.as_aligned());
+    let (h, name) = fds.first().unwrap().to_tuple();
+    assert_eq!((*h, name.to_str()), (Handle(0), "stdin"));
+    assert_eq!(*fds[1].to_tuple().0, Handle(2));
+    assert_eq!(
+        gv!("(hs)").serialize_to_vec(&(Handle(2), "stderr")),
+        &**fds[1].as_aligned_slice()
+    );
+    assert_eq!(
+        m.print(fds, true).to_string(),
+        "{handle 0: 'stdin', 2: 'stderr'}"
+    );
+}
+
+gvariant::gv_type! {
+    /// An ostree commit
+    pub type Commit = "(a{sv}aya(say)sstayay)";
+    pub(crate) type Point = "(ii)";
+    type Related = "(say)";
+    type Line = "((ii)(ii))";
+    #[cfg(any())]
+    type Disabled = "(s)";
+}
+
+static ORIGIN: Point = Point::new(0, 0);
+const POINT_SIZE: usize = std::mem::size_of::<Point>();
+
+fn commit_subject(commit: &Commit) -> &str {
+    commit.to_tuple().3.to_str()
+}
+
+fn related_name(related: &Related) -> &str {
+    related.to_tuple().0.to_str()
+}
+
+fn sum(xs: &gvariant::gv_type!("ai")) -> i32 {
+    xs.iter().sum()
+}
+
+#[test]
+fn test_repeated_structures() {
+    let data = gv!("((ii)(ii))").serialize_to_vec(&(&(1, 2), &(3, 4)));
+    let data = copy_to_align(&data);
+    let (a, b) = gv!("((ii)(ii))").cast(data.as_ref()).to_tuple();
+    assert_eq!(a.to_tuple(), (&1, &2));
+    assert_eq!(b.to_tuple(), (&3, &4));
+
+    let data = gv!("(a{sv}a{sv})").parse("({'a': <1>}, {})").unwrap();
+    let (a, b) = gv!("(a{sv}a{sv})").cast(data.as_ref()).to_tuple();
+    assert_eq!(a.len(), 1);
+    assert!(b.is_empty());
+}
+
+#[test]
+fn test_gv_type() {
+    struct Cache {
+        commit: Box<Commit>,
+    }
+
+    let data = Commit
+        .parse("(@a{sv} {}, @ay [], @a(say) [], 'subject', '', uint64 5, @ay [], @ay [])")
+        .unwrap();
+    let cache = Cache {
+        commit: Commit.cast(data.as_ref()).to_owned(),
+    };
+    assert_eq!(commit_subject(&cache.commit), "subject");
+    assert_eq!(*cache.commit.to_tuple().5, 5);
+
+    assert_eq!(POINT_SIZE, 8);
+    assert_eq!(&*Point.serialize_to_vec(&ORIGIN), &[0; 8]);
+    let data = Point.serialize_to_vec(&(1, 2));
+    let data = copy_to_align(&data);
+    let p: &Point = Point.cast(data.as_ref());
+    assert_eq!(p.to_tuple(), (&1, &2));
+
+    // A structure type string names the same type wherever it appears
+    let data = Commit
+        .parse("(@a{sv} {}, @ay [], [('related', @ay [])], '', '', uint64 0, @ay [], @ay [])")
+        .unwrap();
+    let commit = Commit.cast(data.as_ref());
+    assert_eq!(related_name(&commit.to_tuple().2[0]), "related");
+    let line = Line.serialize_to_vec(&(&ORIGIN, &(1, 2)));
+    let line = copy_to_align(&line);
+    let (start, end): (&Point, &Point) = Line.cast(line.as_ref()).into();
+    assert_eq!(start, &ORIGIN);
+    assert_eq!(end.to_tuple(), (&1, &2));
+
+    let data = gv!("ai").serialize_to_vec(&[1, 2, 3][..]);
+    let data = copy_to_align(&data);
+    assert_eq!(sum(gv!("ai").cast(data.as_ref())), 6);
+}
+
+fn cast_is(data: &AlignedSlice<A8>) -> &gvariant::gv_type!("(is)") {
+    gv!("(is)").cast(data.as_aligned())
+}
+
+fn is_name(x: &gvariant::gv_type!("(is)")) -> &str {
+    x.to_tuple().1.to_str()
+}
+
+fn with_type_of<M: Marker>(_: M, x: &M::Type) -> &M::Type {
+    x
+}
+
+#[test]
+fn test_canonical_structures() {
+    let data = gv!("(is)").serialize_to_vec(&(3, "three"));
+    let data = copy_to_align(&data);
+    let x = with_type_of(gv!("(is)"), cast_is(data.as_ref()));
+    assert_eq!(is_name(x), "three");
+    assert_eq!(*x.field_0(), 3);
+}
+
+struct RelatedCache {
+    related: Box<gvariant::gv_type!("a(say)")>,
+}
+
+fn related_names(related: &gvariant::gv_type!("a(say)")) -> Vec<&str> {
+    related.iter().map(|x| x.field_0().to_str()).collect()
+}
+
+#[test]
+fn test_gv_type_structures() {
+    let data = gv!("a(say)").serialize_to_vec(&[("a", b"x"), ("b", b"y")]);
+    let data = copy_to_align(&data);
+    let cache = RelatedCache {
+        related: gv!("a(say)").cast(data.as_ref()).to_owned(),
+    };
+    assert_eq!(related_names(&cache.related), ["a", "b"]);
+    assert_eq!(cache.related[1].field_1(), b"y");
+}